@@ -0,0 +1,64 @@
+// Compiles the Jack OS of `src/project12` to optimized VM code, which `src/os.rs`
+// embeds.
+
+use std::env;
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[allow(dead_code, unused_imports)]
+#[path = "src"]
+mod nand2tetris {
+    pub mod jack;
+    pub mod source_map;
+    pub mod vm;
+}
+
+// The `crate::source_map` and `crate::vm` the Jack compiler refers to.
+use nand2tetris::{source_map, vm};
+
+use nand2tetris::jack::{tokenize, Parser, VmGen};
+use vm::{optimize, print_vm};
+
+const OS_DIR: &str = "src/project12";
+
+fn main() {
+    for path in &[
+        OS_DIR,
+        "src/jack",
+        "src/jack.rs",
+        "src/source_map.rs",
+        "src/vm",
+        "src/vm.rs",
+    ] {
+        println!("cargo:rerun-if-changed={}", path);
+    }
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    let mut paths: Vec<_> = Path::new(OS_DIR)
+        .read_dir()
+        .unwrap()
+        .map(|p| p.unwrap().path())
+        .filter(|p| p.extension().is_some_and(|ext| ext == "jack"))
+        .collect();
+    paths.sort();
+
+    let mut classes = String::from("&[\n");
+    for path in paths {
+        let name = path.file_stem().unwrap().to_str().unwrap();
+        let src = fs::read_to_string(&path).unwrap();
+        let class = tokenize(src.chars())
+            .map_err(String::from)
+            .and_then(|tokens| Parser::new(tokens.into_iter()).parse())
+            .unwrap_or_else(|e| panic! {"{}: {}", path.display(), e});
+        let commands = VmGen::new()
+            .gen(class)
+            .unwrap_or_else(|e| panic! {"{}: {}", path.display(), e})
+            .to_vec();
+        let commands = optimize(commands);
+        let vm_path = out_dir.join(format! {"{}.vm", name});
+        fs::write(&vm_path, print_vm(&commands)).unwrap();
+        writeln!(classes, "    ({:?}, include_str!({:?})),", name, vm_path).unwrap();
+    }
+    classes.push(']');
+    fs::write(out_dir.join("os.rs"), classes).unwrap();
+}
@@ -1,22 +1,92 @@
-mod parser;
-
 use std::borrow::Cow;
 use std::fs::File;
-use std::io::{BufReader, BufWriter, Write};
+use std::io::{BufWriter, Write};
 use std::path::Path;
 
-use parser::{Op::*, Segment::*, *};
+use crate::source_map::{SourceLoc, SourceMap};
+use crate::vm::{load_vm_file, Command, Op, Op::*, Segment, Segment::*};
+
+/// Error code written to `StackCheck::error_addr` when a push exceeds the upper bound.
+pub const STACK_OVERFLOW_CODE: u16 = 1;
+/// Error code written to `StackCheck::error_addr` when a pop goes below the lower bound.
+pub const STACK_UNDERFLOW_CODE: u16 = 2;
+
+// Largest value an A-instruction loads.
+const MAX_CONSTANT: u16 = 32767;
+
+/// Bounds used by the stack guards emitted in debug mode.
+/// SP must stay within `lower..=upper`, otherwise the program jumps to
+/// `STACK_OVERFLOW` or `STACK_UNDERFLOW`, stores the error code at `error_addr` and halts.
+/// All of them are loaded by A-instructions, so they can't exceed 32767.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StackCheck {
+    pub lower: u16,
+    pub upper: u16,
+    pub error_addr: u16,
+}
+
+impl Default for StackCheck {
+    fn default() -> Self {
+        Self {
+            lower: 256,
+            upper: 2047,
+            error_addr: 15,
+        }
+    }
+}
+
+impl StackCheck {
+    fn validate(&self) -> Result<(), String> {
+        for (name, value) in [
+            ("stack-lower", self.lower),
+            ("stack-upper", self.upper),
+            ("error-addr", self.error_addr),
+        ] {
+            if value > MAX_CONSTANT {
+                return Err(
+                    format! {"Error: {} {} is out of range (0 to {})", name, value, MAX_CONSTANT},
+                );
+            }
+        }
+        if self.lower > self.upper {
+            return Err(
+                format! {"Error: stack-lower {} is above stack-upper {}", self.lower, self.upper},
+            );
+        }
+        Ok(())
+    }
+}
 
 pub struct AsmGenerator {
     asm: Vec<Cow<'static, str>>,
     label_count: u16,
+    // Function being translated, which scopes its labels.
+    function: String,
+    // Index in `asm` where the translation of each VM command with a known source
+    // starts, and the command's location.
+    sources: Vec<(usize, SourceLoc)>,
+    stack_check: Option<StackCheck>,
 }
 
 impl AsmGenerator {
     pub fn new(no_init: bool) -> Self {
+        Self::init(no_init, None)
+    }
+
+    /// Generator guarding every push and pop, or an error if the bounds of
+    /// `stack_check` are out of order or can't be loaded by an A-instruction.
+    pub fn with_stack_check(no_init: bool, stack_check: StackCheck) -> Result<Self, String> {
+        stack_check.validate()?;
+        Ok(Self::init(no_init, Some(stack_check)))
+    }
+
+    fn init(no_init: bool, stack_check: Option<StackCheck>) -> Self {
         let mut generator = Self {
             asm: Vec::new(),
             label_count: 0,
+            function: String::new(),
+            sources: Vec::new(),
+            stack_check,
         };
         if no_init {
             return generator;
@@ -30,34 +100,91 @@ impl AsmGenerator {
     }
 
     pub fn gen(&mut self, path: impl AsRef<Path>) -> Result<(), String> {
-        let path = path.as_ref().to_str().ok_or("Invalid file path")?;
+        let file = load_vm_file(path.as_ref())?;
+        let source = path.as_ref().display().to_string();
+        let lines: Vec<_> = (1..=file.commands.len()).collect();
+        self.gen_commands_with_source(&file.name, file.commands, &source, &lines);
+        Ok(())
+    }
 
-        let file = File::open(path).map_err(|_| format! {"Can't open file: {}", path})?;
-        let commands = parse_vm(BufReader::new(file))?;
+    /// Translates commands of the VM file `name`, which prefixes its static variables.
+    pub fn gen_commands(&mut self, name: &str, commands: Vec<Command>) {
         for com in commands {
-            match com {
-                Command::Arithmetic(op) => match op {
-                    Add | Sub | Eq_ | Gt | Lt | And | Or => self.binop(op),
-                    Not | Neg => self.uniop(op),
-                },
-                Command::Push(seg, offset) => self.push(seg, offset, path),
-                Command::Pop(seg, offset) => self.pop(seg, offset, path),
-                Command::Label(label) => self.label(&label),
-                Command::Goto(label) => self.goto(&label),
-                Command::IfGoto(label) => self.if_goto(&label),
-                Command::Function(label, n_locs) => self.function(&label, n_locs),
-                Command::Call(label, arity) => self.call(&label, arity),
-                Command::Return => self.return_(),
+            self.gen_command(name, com);
+        }
+    }
+
+    /// Translates commands like `gen_commands`, where the commands are at `lines` of the
+    /// file at the path `source`, for `source_map`.
+    pub fn gen_commands_with_source(
+        &mut self,
+        name: &str,
+        commands: Vec<Command>,
+        source: &str,
+        lines: &[usize],
+    ) {
+        for (com, &line) in commands.into_iter().zip(lines) {
+            let function = match &com {
+                Command::Function(label, _) => label,
+                _ => &self.function,
+            };
+            let function = Some(function.as_str()).filter(|f| !f.is_empty());
+            let loc = SourceLoc::new(source, line, function);
+            self.sources.push((self.asm.len(), loc));
+            self.gen_command(name, com);
+        }
+    }
+
+    fn gen_command(&mut self, name: &str, com: Command) {
+        match com {
+            Command::Arithmetic(op) => match op {
+                Add | Sub | Eq_ | Gt | Lt | And | Or => self.binop(op),
+                Not | Neg => self.uniop(op),
+            },
+            Command::Push(seg, offset) => self.push(seg, offset, name),
+            Command::Pop(seg, offset) => self.pop(seg, offset, name),
+            Command::Label(label) => self.label(&label),
+            Command::Goto(label) => self.goto(&label),
+            Command::IfGoto(label) => self.if_goto(&label),
+            Command::Function(label, n_locs) => self.function(&label, n_locs),
+            Command::Call(label, arity) => self.call(&label, arity),
+            Command::Return => self.return_(),
+        }
+    }
+
+    /// Generated assembly, an instruction or label per line.
+    pub fn lines(&self) -> Vec<Cow<'_, str>> {
+        self.asm
+            .iter()
+            .map(|line| Cow::Borrowed(line.as_ref()))
+            .chain(self.stack_error_handlers())
+            .collect()
+    }
+
+    /// Where each line of `lines` comes from, for the commands translated with a
+    /// source.
+    pub fn source_map(&self) -> SourceMap {
+        let mut locs = Vec::new();
+        let mut sources = self.sources.iter().peekable();
+        let mut loc = None;
+        for i in 0..self.lines().len() {
+            while let Some((_, next)) = sources.next_if(|&&(start, _)| start <= i) {
+                loc = Some(next.clone());
             }
+            // Code after the last command, such as the stack error handlers, has no source.
+            if i >= self.asm.len() {
+                loc = None;
+            }
+            locs.push(loc.clone());
         }
-        Ok(())
+        SourceMap::new(locs)
     }
 
     pub fn flush(&self, path: impl AsRef<Path>) -> Result<(), String> {
         let f = File::create(path.as_ref())
             .map_err(|_| format! {"Invalid file path: {:?}", path.as_ref()})?;
         let mut writer = BufWriter::new(f);
-        for line in &self.asm {
+        for line in &self.lines() {
             writer
                 .write_all(line.as_bytes())
                 .map_err(|_| "Can't write file")?;
@@ -68,8 +195,35 @@ impl AsmGenerator {
         Ok(())
     }
 
+    fn stack_error_handlers(&self) -> Vec<Cow<'static, str>> {
+        let check = match self.stack_check {
+            Some(check) => check,
+            None => return Vec::new(),
+        };
+        let mut handlers = Vec::new();
+        for (label, code) in [
+            ("STACK_OVERFLOW", STACK_OVERFLOW_CODE),
+            ("STACK_UNDERFLOW", STACK_UNDERFLOW_CODE),
+        ]
+        .iter()
+        {
+            handlers.push(Cow::Owned(format! {"({})", label}));
+            handlers.push(Cow::Owned(format! {"@{}", code}));
+            handlers.push(Cow::Borrowed("D=A"));
+            handlers.push(Cow::Owned(format! {"@{}", check.error_addr}));
+            handlers.push(Cow::Borrowed("M=D"));
+            handlers.push(Cow::Borrowed("@STACK_ERROR_HALT"));
+            handlers.push(Cow::Borrowed("0;JMP"));
+        }
+        handlers.push(Cow::Borrowed("(STACK_ERROR_HALT)"));
+        handlers.push(Cow::Borrowed("@STACK_ERROR_HALT"));
+        handlers.push(Cow::Borrowed("0;JMP"));
+        handlers
+    }
+
     fn binop(&mut self, op: Op) {
-        self.pop_dreg();
+        self.check_underflow(2);
+        self.pop_dreg_unchecked();
         self.asm.push(Cow::Borrowed("@SP"));
         self.asm.push(Cow::Borrowed("M=M-1"));
         self.asm.push(Cow::Borrowed("A=M"));
@@ -121,7 +275,7 @@ impl AsmGenerator {
         self.push_dreg();
     }
 
-    fn push(&mut self, segment: Segment, offset: u16, path: impl AsRef<Path>) {
+    fn push(&mut self, segment: Segment, offset: u16, name: &str) {
         let reg = match segment {
             Argument => "@ARG",
             Local => "@LCL",
@@ -129,7 +283,7 @@ impl AsmGenerator {
             That => "@THAT",
             Temp => "@R5",
             Pointer => "@THIS",
-            Static => return self.push_static(offset, path),
+            Static => return self.push_static(offset, name),
             Constant => return self.push_const(offset),
         };
         self.asm.push(Cow::Owned(format! {"@{}", offset}));
@@ -144,7 +298,7 @@ impl AsmGenerator {
         self.push_dreg();
     }
 
-    fn pop(&mut self, segment: Segment, offset: u16, path: impl AsRef<Path>) {
+    fn pop(&mut self, segment: Segment, offset: u16, name: &str) {
         let reg = match segment {
             Argument => "@ARG",
             Local => "@LCL",
@@ -152,7 +306,7 @@ impl AsmGenerator {
             That => "@THAT",
             Temp => "@R5",
             Pointer => "@THIS",
-            Static => return self.pop_static(offset, path),
+            Static => return self.pop_static(offset, name),
             Constant => unreachable!(),
         };
         self.asm.push(Cow::Owned(format! {"@{}", offset}));
@@ -171,25 +325,36 @@ impl AsmGenerator {
         self.asm.push(Cow::Borrowed("M=D"));
     }
 
+    // Labels are local to their function, so `FunctionName$label` in assembly.
     fn label(&mut self, label: &str) {
-        self.asm.push(Cow::Owned(format! {"({})", label}));
+        self.asm
+            .push(Cow::Owned(format! {"({}${})", self.function, label}));
     }
 
     fn goto(&mut self, label: &str) {
-        self.asm.push(Cow::Owned(format! {"@{}", label}));
+        self.asm
+            .push(Cow::Owned(format! {"@{}${}", self.function, label}));
         self.asm.push(Cow::Borrowed("0;JMP"));
     }
 
     fn if_goto(&mut self, label: &str) {
         self.pop_dreg();
-        self.asm.push(Cow::Owned(format! {"@{}", label}));
+        self.asm
+            .push(Cow::Owned(format! {"@{}${}", self.function, label}));
         self.asm.push(Cow::Borrowed("D;JNE"));
     }
 
     fn function(&mut self, label: &str, n_locs: u16) {
-        self.label(label);
+        self.function = label.to_string();
+        self.asm.push(Cow::Owned(format! {"({})", label}));
         self.asm.push(Cow::Borrowed("D=0"));
-        (0..n_locs).for_each(|_| self.push_dreg());
+        for _ in 0..n_locs {
+            self.push_dreg();
+            // Overflow guard clobbers D.
+            if self.stack_check.is_some() {
+                self.asm.push(Cow::Borrowed("D=0"));
+            }
+        }
     }
 
     fn call(&mut self, label: &str, arity: u16) {
@@ -215,7 +380,7 @@ impl AsmGenerator {
         self.asm.push(Cow::Borrowed("M=D"));
         self.asm.push(Cow::Owned(format! {"@{}", label}));
         self.asm.push(Cow::Borrowed("0;JMP"));
-        self.label(&return_label);
+        self.asm.push(Cow::Owned(format! {"({})", return_label}));
     }
 
     fn return_(&mut self) {
@@ -272,10 +437,8 @@ impl AsmGenerator {
         self.asm.push(Cow::Borrowed("0;JMP"));
     }
 
-    fn push_static(&mut self, val: u16, path: impl AsRef<Path>) {
-        self.asm.push(Cow::Owned(
-            format! {"@{}.{}", path.as_ref().file_stem().unwrap().to_str().unwrap(), val},
-        ));
+    fn push_static(&mut self, val: u16, name: &str) {
+        self.asm.push(Cow::Owned(format! {"@{}.{}", name, val}));
         self.asm.push(Cow::Borrowed("D=M"));
         self.push_dreg();
     }
@@ -286,26 +449,143 @@ impl AsmGenerator {
         self.asm.push(Cow::Borrowed("M=D"));
         self.asm.push(Cow::Borrowed("@SP"));
         self.asm.push(Cow::Borrowed("M=M+1"));
+        self.check_overflow();
     }
 
-    fn pop_static(&mut self, val: u16, path: impl AsRef<Path>) {
+    fn pop_static(&mut self, val: u16, name: &str) {
         self.pop_dreg();
-        self.asm.push(Cow::Owned(
-            format! {"@{}.{}", path.as_ref().file_stem().unwrap().to_str().unwrap(), val},
-        ));
+        self.asm.push(Cow::Owned(format! {"@{}.{}", name, val}));
         self.asm.push(Cow::Borrowed("M=D"));
     }
 
     fn pop_dreg(&mut self) {
+        self.check_underflow(1);
+        self.pop_dreg_unchecked();
+    }
+
+    fn pop_dreg_unchecked(&mut self) {
         self.asm.push(Cow::Borrowed("@SP"));
         self.asm.push(Cow::Borrowed("M=M-1"));
         self.asm.push(Cow::Borrowed("A=M"));
         self.asm.push(Cow::Borrowed("D=M"));
     }
 
+    // Jumps to STACK_OVERFLOW if SP > upper. Clobbers D.
+    fn check_overflow(&mut self) {
+        let upper = match self.stack_check {
+            Some(check) => check.upper,
+            None => return,
+        };
+        self.asm.push(Cow::Borrowed("@SP"));
+        self.asm.push(Cow::Borrowed("D=M"));
+        self.asm.push(Cow::Owned(format! {"@{}", upper}));
+        self.asm.push(Cow::Borrowed("D=D-A"));
+        self.asm.push(Cow::Borrowed("@STACK_OVERFLOW"));
+        self.asm.push(Cow::Borrowed("D;JGT"));
+    }
+
+    // Jumps to STACK_UNDERFLOW if popping `n` values would move SP below lower. Clobbers D.
+    fn check_underflow(&mut self, n: u16) {
+        let lower = match self.stack_check {
+            Some(check) => check.lower,
+            None => return,
+        };
+        // SP - n < lower as SP - lower - (n - 1) <= 0, which doesn't add to `lower`.
+        self.asm.push(Cow::Borrowed("@SP"));
+        self.asm.push(Cow::Borrowed("D=M"));
+        self.asm.push(Cow::Owned(format! {"@{}", lower}));
+        self.asm.push(Cow::Borrowed("D=D-A"));
+        for _ in 1..n {
+            self.asm.push(Cow::Borrowed("D=D-1"));
+        }
+        self.asm.push(Cow::Borrowed("@STACK_UNDERFLOW"));
+        self.asm.push(Cow::Borrowed("D;JLE"));
+    }
+
     fn label_count(&mut self) -> u16 {
         let count = self.label_count;
         self.label_count += 1;
         count
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emu::{assemble, Cpu, SP};
+    use crate::vm::parse_vm;
+
+    #[test]
+    fn test_label_scope() {
+        let sys = "function Sys.init 0
+call A.f 0
+pop temp 0
+call B.f 0
+pop temp 1
+label END
+goto END
+";
+        let class = |name: &str, value: u16| {
+            let vm = format! {"function {}.f 0\ngoto SKIP\npush constant 0\nreturn\nlabel SKIP\npush constant {}\nreturn\n", name, value};
+            parse_vm(vm.as_bytes()).unwrap()
+        };
+        let mut gen = AsmGenerator::new(false);
+        gen.gen_commands("Sys", parse_vm(sys.as_bytes()).unwrap());
+        gen.gen_commands("A", class("A", 1));
+        gen.gen_commands("B", class("B", 2));
+        // Both classes define `SKIP`, which only jumps within its function.
+        let program = assemble(gen.asm.join("\n").as_bytes()).unwrap();
+        let mut cpu = Cpu::new(&program);
+        for _ in 0..1000 {
+            cpu.step();
+        }
+        assert_eq!(cpu.ram[5..7], [1, 2]);
+    }
+
+    // Runs `vm` translated with the default stack check until it halts.
+    fn run_checked(no_init: bool, vm: &str) -> Cpu {
+        let mut gen = AsmGenerator::with_stack_check(no_init, StackCheck::default()).unwrap();
+        gen.gen_commands("Sys", parse_vm(vm.as_bytes()).unwrap());
+        let program = assemble(gen.lines().join("\n").as_bytes()).unwrap();
+        let mut cpu = Cpu::new(&program);
+        cpu.ram[SP] = 256;
+        for _ in 0..100_000 {
+            if cpu.is_halted() {
+                break;
+            }
+            cpu.step();
+        }
+        assert!(cpu.is_halted());
+        cpu
+    }
+
+    #[test]
+    fn test_stack_check() {
+        // Infinite recursion overflows.
+        let cpu = run_checked(false, "function Sys.init 0\ncall Sys.init 0\n");
+        assert_eq!(cpu.ram[15], STACK_OVERFLOW_CODE);
+        assert_eq!(cpu.ram[SP], 2048);
+
+        // The binary operation pops two values, but only one is there.
+        let vm = "push constant 5\npop temp 0\npush constant 7\nadd\npop temp 1\n";
+        let cpu = run_checked(true, vm);
+        assert_eq!(cpu.ram[15], STACK_UNDERFLOW_CODE);
+        assert_eq!(cpu.ram[5..7], [5, 0]);
+
+        // Values an A-instruction can't load, or bounds out of order.
+        for (lower, upper, error_addr) in [(256, 40000, 15), (256, 2047, 32768), (300, 299, 15)] {
+            let check = StackCheck {
+                lower,
+                upper,
+                error_addr,
+            };
+            assert!(AsmGenerator::with_stack_check(false, check).is_err());
+        }
+        let check = StackCheck {
+            lower: 32767,
+            upper: 32767,
+            error_addr: 15,
+        };
+        assert!(AsmGenerator::with_stack_check(false, check).is_ok());
+    }
+}
@@ -1,8 +1,11 @@
+use std::fs;
 use std::path::Path;
+use std::process;
 
 use clap::{App, Arg};
 
-use nand2tetris::AsmGenerator;
+use nand2tetris::vm::{load_vm_file, remove_dead_functions, Command};
+use nand2tetris::{AsmGenerator, StackCheck};
 
 fn main() {
     let args = App::new("code_gen")
@@ -24,28 +27,136 @@ fn main() {
                 .help("Compile VM codes in project07 and in first half of project08")
                 .long("no-init"),
         )
+        .arg(
+            Arg::with_name("stack-check")
+                .help("Emit stack overflow and underflow guards")
+                .long("stack-check"),
+        )
+        .arg(
+            Arg::with_name("stack-lower")
+                .help("Lowest valid SP value for --stack-check (default: 256)")
+                .long("stack-lower")
+                .takes_value(true)
+                .requires("stack-check"),
+        )
+        .arg(
+            Arg::with_name("stack-upper")
+                .help("Highest valid SP value for --stack-check (default: 2047)")
+                .long("stack-upper")
+                .takes_value(true)
+                .requires("stack-check"),
+        )
+        .arg(
+            Arg::with_name("error-addr")
+                .help("RAM address receiving the stack error code (default: 15)")
+                .long("error-addr")
+                .takes_value(true)
+                .requires("stack-check"),
+        )
+        .arg(
+            Arg::with_name("remove-dead-functions")
+                .help("Drop functions unreachable from the entry function and report them")
+                .long("remove-dead-functions"),
+        )
+        .arg(
+            Arg::with_name("source-map")
+                .help("Also write the VM line of each asm line to the output path plus .map")
+                .long("source-map"),
+        )
+        .arg(
+            Arg::with_name("entry")
+                .help("Entry function for --remove-dead-functions (default: Sys.init)")
+                .long("entry")
+                .takes_value(true)
+                .requires("remove-dead-functions"),
+        )
         .get_matches();
 
     let no_init = args.occurrences_of("no-init") > 0;
-    let mut gen = AsmGenerator::new(no_init);
+    let mut gen = if args.occurrences_of("stack-check") > 0 {
+        let mut check = StackCheck::default();
+        for (name, value) in [
+            ("stack-lower", &mut check.lower),
+            ("stack-upper", &mut check.upper),
+            ("error-addr", &mut check.error_addr),
+        ] {
+            if let Some(arg) = args.value_of(name) {
+                *value = arg.parse().unwrap_or_else(|_| {
+                    eprintln!("Error: Invalid {}: {}", name, arg);
+                    process::exit(1);
+                });
+            }
+        }
+        AsmGenerator::with_stack_check(no_init, check).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            process::exit(1);
+        })
+    } else {
+        AsmGenerator::new(no_init)
+    };
     let input_path = Path::new(args.value_of("INPUT").unwrap());
-    if input_path.is_dir() {
-        for path in input_path
+    let paths: Vec<_> = if input_path.is_dir() {
+        input_path
             .read_dir()
             .unwrap()
             .map(|p| p.unwrap().path())
             .filter(|p| p.is_file() && p.to_str().unwrap().ends_with("vm"))
-        {
-            gen.gen(path).unwrap();
-        }
+            .collect()
     } else {
         if !input_path.to_str().unwrap().ends_with("vm") {
             panic!("vm file is expected");
         }
-        gen.gen(input_path).unwrap();
+        vec![input_path.to_path_buf()]
+    };
+
+    if args.occurrences_of("remove-dead-functions") > 0 {
+        let mut files: Vec<_> = paths.iter().map(|p| load_vm_file(p).unwrap()).collect();
+        let lines: Vec<_> = files.iter().map(|file| file.commands.clone()).collect();
+        let entry = args.value_of("entry").unwrap_or("Sys.init");
+        let removed = remove_dead_functions(&mut files, entry).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            process::exit(1);
+        });
+        println!(
+            "Removed {} function(s) unreachable from {}:",
+            removed.len(),
+            entry
+        );
+        for name in &removed {
+            println!("  {}", name);
+        }
+        for ((file, commands), path) in files.into_iter().zip(lines).zip(&paths) {
+            let lines = kept_lines(&commands, &removed);
+            let source = path.display().to_string();
+            gen.gen_commands_with_source(&file.name, file.commands, &source, &lines);
+        }
+    } else {
+        for path in paths {
+            gen.gen(path).unwrap();
+        }
     }
 
     let default_out = format! {"{}.asm", input_path.file_stem().unwrap().to_str().unwrap()};
     let out_path = args.value_of("out").unwrap_or(&default_out);
     gen.flush(out_path).unwrap();
+    if args.occurrences_of("source-map") > 0 {
+        let map_path = format! {"{}.map", out_path};
+        fs::write(&map_path, gen.source_map().to_string())
+            .unwrap_or_else(|_| panic! {"Can't write file: {:?}", map_path});
+    }
+}
+
+// Lines of the `commands` of a file that `remove_dead_functions` keeps, as it drops the
+// `removed` functions whole.
+fn kept_lines(commands: &[Command], removed: &[String]) -> Vec<usize> {
+    let mut alive = true;
+    (1..)
+        .zip(commands)
+        .filter_map(|(line, command)| {
+            if let Command::Function(name, _) = command {
+                alive = !removed.contains(name);
+            }
+            Some(line).filter(|_| alive)
+        })
+        .collect()
 }
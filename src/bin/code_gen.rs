@@ -1,10 +1,11 @@
-use std::fs::File;
+use std::fs::{self, File};
 use std::io::{BufReader, BufWriter, Write};
 use std::path::Path;
 
 use clap::{App, Arg};
 
-use nand2tetris::gen_code;
+use nand2tetris::code_gen::gen_code_with_symbols;
+use nand2tetris::source_map::{SourceLoc, SourceMap};
 
 fn main() {
     let args = App::new("code_gen")
@@ -21,21 +22,39 @@ fn main() {
                 .takes_value(true)
                 .required(false),
         )
+        .arg(
+            Arg::with_name("source-map")
+                .long("source-map")
+                .help("Also write the asm line of each instruction to the output path plus .map"),
+        )
         .get_matches();
 
     let asm_path = Path::new(args.value_of("INPUT").unwrap());
     let strm = BufReader::new(
-        File::open(asm_path).expect(&format! {"Can't open asm file: {:?}", asm_path}),
+        File::open(asm_path).unwrap_or_else(|_| panic! {"Can't open asm file: {:?}", asm_path}),
     );
-    let code = gen_code(strm).unwrap();
+    let (code, symbols) = gen_code_with_symbols(strm).unwrap();
 
     let default_out = format! {"{}.hack", asm_path.file_stem().unwrap().to_str().unwrap()};
     let out_path = args.value_of("out").unwrap_or(&default_out);
     let mut writer = BufWriter::new(
-        File::create(out_path).expect(&format! {"Can't open output file: {:?}", out_path}),
+        File::create(out_path)
+            .unwrap_or_else(|_| panic! {"Can't open output file: {:?}", out_path}),
     );
     for line in code {
         writer.write_all(line.as_bytes()).unwrap();
         writer.write_all("\n".as_bytes()).unwrap();
     }
+
+    if args.occurrences_of("source-map") > 0 {
+        let asm_file = asm_path.display().to_string();
+        let locs = symbols
+            .lines
+            .iter()
+            .map(|&line| Some(SourceLoc::new(&asm_file, line, None)))
+            .collect();
+        let map_path = format! {"{}.map", out_path};
+        fs::write(&map_path, SourceMap::new(locs).to_string())
+            .unwrap_or_else(|_| panic! {"Can't write file: {:?}", map_path});
+    }
 }
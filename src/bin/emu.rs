@@ -0,0 +1,283 @@
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use clap::{App, Arg};
+
+use nand2tetris::code_gen::Symbols;
+use nand2tetris::emu::{
+    assemble_with_symbols, parse_hack, rom_source_map, screen_png, screen_ppm, Cpu, KeyScript,
+    Profiler, Vm, KBD, SP,
+};
+use nand2tetris::source_map::{SourceLoc, SourceMap, SourceResolver};
+
+// Program loaded on the emulator its file calls for, with what finds the sources of its
+// code: the resolved map and the symbols of the ROM, or the directory of the VM files.
+enum Machine {
+    Cpu(Cpu, SourceMap, Symbols),
+    Vm(Vm, PathBuf, SourceResolver),
+}
+
+impl Machine {
+    fn load(path: &Path) -> Result<Self, String> {
+        let ext = path.extension().and_then(|ext| ext.to_str());
+        if ext == Some("hack") || ext == Some("asm") {
+            let file = File::open(path).map_err(|_| format! {"Can't open file: {:?}", path})?;
+            let (program, symbols) = if ext == Some("hack") {
+                (parse_hack(BufReader::new(file))?, Symbols::default())
+            } else {
+                assemble_with_symbols(BufReader::new(file))?
+            };
+            let sources = rom_source_map(path, &symbols);
+            Ok(Machine::Cpu(Cpu::new(&program), sources, symbols))
+        } else {
+            let mut vm = Vm::load(path)?;
+            // Where the bootstrap code of the VM translator starts the stack.
+            vm.ram[SP] = 256;
+            let dir = if path.is_dir() {
+                path
+            } else {
+                path.parent().unwrap_or_else(|| Path::new(""))
+            };
+            Ok(Machine::Vm(vm, dir.to_path_buf(), SourceResolver::new()))
+        }
+    }
+
+    // Sources of the next instruction or command and of the calls leading to it,
+    // innermost first. `None` for code without a source.
+    fn backtrace(&mut self) -> Vec<Option<SourceLoc>> {
+        match self {
+            Machine::Cpu(cpu, sources, _) => cpu
+                .backtrace()
+                .into_iter()
+                .map(|addr| sources.get(addr as usize).cloned())
+                .collect(),
+            Machine::Vm(vm, dir, resolver) => vm
+                .backtrace()
+                .into_iter()
+                .map(|pc| {
+                    let mut loc = vm.source(pc)?;
+                    loc.file = dir.join(&loc.file).display().to_string();
+                    Some(resolver.resolve(loc))
+                })
+                .collect(),
+        }
+    }
+
+    fn profiler(&self) -> Profiler {
+        match self {
+            // A .hack file has no labels, but its source map may tell the functions.
+            Machine::Cpu(_, sources, symbols) if symbols.labels.is_empty() => {
+                let mut functions: Vec<(usize, String)> = Vec::new();
+                for addr in 0..sources.len() {
+                    let function = sources.get(addr).and_then(|loc| loc.function.as_ref());
+                    let last = functions.last().map(|(_, name)| name);
+                    if let Some(function) = function.filter(|&function| Some(function) != last) {
+                        functions.push((addr, function.clone()));
+                    }
+                }
+                Profiler::new(&functions)
+            }
+            Machine::Cpu(_, _, symbols) => Profiler::with_labels(&symbols.labels),
+            Machine::Vm(vm, _, _) => Profiler::with_vm(vm),
+        }
+    }
+
+    fn pc(&self) -> usize {
+        match self {
+            Machine::Cpu(cpu, _, _) => cpu.pc as usize,
+            Machine::Vm(vm, _, _) => vm.pc(),
+        }
+    }
+
+    fn step(&mut self) -> Result<(), String> {
+        match self {
+            Machine::Cpu(cpu, _, _) => cpu.step(),
+            Machine::Vm(vm, _, _) => vm.step()?,
+        }
+        Ok(())
+    }
+
+    fn is_halted(&self) -> bool {
+        match self {
+            Machine::Cpu(cpu, _, _) => cpu.is_halted(),
+            Machine::Vm(vm, _, _) => vm.is_halted(),
+        }
+    }
+
+    fn ram(&self) -> &[u16] {
+        match self {
+            Machine::Cpu(cpu, _, _) => &cpu.ram,
+            Machine::Vm(vm, _, _) => &vm.ram,
+        }
+    }
+
+    fn ram_mut(&mut self) -> &mut [u16] {
+        match self {
+            Machine::Cpu(cpu, _, _) => &mut cpu.ram,
+            Machine::Vm(vm, _, _) => &mut vm.ram,
+        }
+    }
+}
+
+fn app() -> App<'static, 'static> {
+    App::new("emu")
+        .arg(
+            Arg::with_name("INPUT")
+                .help(".hack or .asm file for the CPU emulator, or .vm file or dir for the VM emulator")
+                .required(true)
+                .index(1),
+        )
+        .arg(
+            Arg::with_name("cycles")
+                .long("cycles")
+                .help("Stop after this many instructions or VM commands instead of when the program halts")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("screen")
+                .long("screen")
+                .help("Write the screen to this .png or .ppm file when stopping")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("keys")
+                .long("keys")
+                .help("Keyboard script of KEY PRESS RELEASE lines driving the keyboard register")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("profile")
+                .long("profile")
+                .help("Write a profile of the instructions or VM commands executed to this file")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("profile-format")
+                .long("profile-format")
+                .help("Profile per function (flat), per call path (tree), as folded stacks for flame graphs (folded) or per address (addresses)")
+                .takes_value(true)
+                .possible_values(&["flat", "tree", "folded", "addresses"])
+                .requires("profile"),
+        )
+}
+
+fn main() {
+    let args = app().get_matches();
+
+    let cycles = args
+        .value_of("cycles")
+        .map(|cycles| cycles.parse::<u64>().expect("Invalid cycles"));
+    let screen = args.value_of("screen").map(Path::new);
+    let screen_format = screen.map(|path| match path.extension().and_then(|ext| ext.to_str()) {
+        Some("png") => screen_png,
+        Some("ppm") => screen_ppm,
+        _ => panic! {"Screen file must be .png or .ppm: {:?}", path},
+    });
+
+    let mut keys = args.value_of("keys").map(|keys| {
+        let file = File::open(keys).unwrap_or_else(|_| panic! {"Can't open file: {:?}", keys});
+        KeyScript::parse(BufReader::new(file)).unwrap_or_else(|e| {
+            eprintln!("{}: {}", keys, e);
+            process::exit(1);
+        })
+    });
+
+    let path = Path::new(args.value_of("INPUT").unwrap());
+    let mut machine = Machine::load(path).unwrap_or_else(|e| {
+        eprintln!("{}: {}", path.display(), e);
+        process::exit(1);
+    });
+    let mut profiler = args.value_of("profile").map(|_| machine.profiler());
+    let mut cycle = 0;
+    while cycles.is_none_or(|cycles| cycle < cycles) && !machine.is_halted() {
+        if let Some(keys) = &mut keys {
+            machine.ram_mut()[KBD] = keys.key(cycle);
+        }
+        if let Some(profiler) = &mut profiler {
+            profiler.record(machine.pc(), machine.ram());
+        }
+        if let Err(e) = machine.step() {
+            eprintln!("{}: {} (cycle {})", path.display(), e, cycle);
+            print_backtrace(&machine.backtrace());
+            process::exit(1);
+        }
+        cycle += 1;
+    }
+    let backtrace = machine.backtrace();
+    let failed = machine.is_halted()
+        && backtrace
+            .iter()
+            .flatten()
+            .any(|loc| loc.function.as_deref() == Some("Sys.error"));
+    if failed {
+        eprintln!("{}: Sys.error called (cycle {})", path.display(), cycle);
+        print_backtrace(&backtrace);
+    } else if machine.is_halted() {
+        println!("Halted after {} cycles", cycle);
+    } else {
+        println!("Stopped after {} cycles", cycle);
+        if let Some(Some(loc)) = backtrace.first() {
+            println!("at {}", loc);
+        }
+    }
+
+    if let (Some(screen), Some(format)) = (screen, screen_format) {
+        fs::write(screen, format(machine.ram()))
+            .unwrap_or_else(|_| panic! {"Can't write file: {:?}", screen});
+    }
+    if let (Some(profile), Some(profiler)) = (args.value_of("profile"), profiler) {
+        let text = match args.value_of("profile-format").unwrap_or("flat") {
+            "tree" => profiler.tree(),
+            "folded" => profiler.folded(),
+            "addresses" => profiler.addresses(),
+            _ => profiler.flat(),
+        };
+        fs::write(profile, text).unwrap_or_else(|_| panic! {"Can't write file: {:?}", profile});
+    }
+    if failed {
+        process::exit(1);
+    }
+}
+
+fn print_backtrace(backtrace: &[Option<SourceLoc>]) {
+    for loc in backtrace {
+        match loc {
+            Some(loc) => eprintln!("    at {}", loc),
+            None => eprintln!("    at <no source>"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_args() {
+        let args = app()
+            .get_matches_from_safe(["emu", "--screen", "out.png", "--keys", "keys.txt", "a.vm"])
+            .unwrap();
+        assert_eq!(args.value_of("screen"), Some("out.png"));
+        assert_eq!(args.value_of("profile"), None);
+        let args = app()
+            .get_matches_from_safe(["emu", "--profile", "profile.txt", "a.vm"])
+            .unwrap();
+        assert_eq!(args.value_of("profile-format"), None);
+        let args = app()
+            .get_matches_from_safe([
+                "emu",
+                "--profile",
+                "p.txt",
+                "--profile-format",
+                "tree",
+                "a.vm",
+            ])
+            .unwrap();
+        assert_eq!(args.value_of("profile-format"), Some("tree"));
+        assert!(app()
+            .get_matches_from_safe(["emu", "--profile-format", "tree", "a.vm"])
+            .is_err());
+    }
+}
@@ -0,0 +1,66 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+use std::process;
+
+use clap::{App, Arg};
+
+use nand2tetris::code_gen::Symbols;
+use nand2tetris::emu::{assemble_with_symbols, parse_hack, rom_source_map, Debugger};
+
+fn main() {
+    let args = App::new("hack_dbg")
+        .arg(
+            Arg::with_name("INPUT")
+                .help(".asm file, whose labels and variables the debugger names, or .hack file")
+                .required(true)
+                .index(1),
+        )
+        .get_matches();
+
+    let path = Path::new(args.value_of("INPUT").unwrap());
+    let file = File::open(path).unwrap_or_else(|_| panic! {"Can't open file: {:?}", path});
+    let loaded = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("asm") => assemble_with_symbols(BufReader::new(file)),
+        Some("hack") => {
+            parse_hack(BufReader::new(file)).map(|program| (program, Symbols::default()))
+        }
+        _ => Err("Input must be a .asm or .hack file".to_string()),
+    };
+    let (program, symbols) = loaded.unwrap_or_else(|e| {
+        eprintln!("{}: {}", path.display(), e);
+        process::exit(1);
+    });
+
+    let sources = rom_source_map(path, &symbols);
+    let mut dbg = Debugger::new(&program, symbols);
+    dbg.set_source_map(sources);
+    println!("{}", dbg.command("disas 0 1").unwrap());
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    let mut last = String::new();
+    loop {
+        print!("(dbg) ");
+        io::stdout().flush().unwrap();
+        let line = match lines.next() {
+            Some(Ok(line)) => line,
+            _ => break,
+        };
+        // An empty line repeats the last command, as in gdb.
+        let line = if line.trim().is_empty() {
+            last.clone()
+        } else {
+            line
+        };
+        match line.trim() {
+            "quit" | "q" => break,
+            "" => continue,
+            _ => {}
+        }
+        match dbg.command(&line) {
+            Ok(out) => println!("{}", out),
+            Err(e) => eprintln!("{}", e),
+        }
+        last = line;
+    }
+}
@@ -0,0 +1,416 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+
+use clap::{App, Arg};
+
+use nand2tetris::jack::ide::{Completion, DocumentSymbol, SymbolKind, Workspace};
+use nand2tetris::jack::{Json, Pos, Span};
+
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+struct Server {
+    ws: Workspace,
+    // URIs of the documents the editor has open; other documents are
+    // `.jack` files read from the directories of the open ones.
+    open: BTreeSet<String>,
+    shutdown: bool,
+}
+
+impl Server {
+    fn new(extended: bool) -> Self {
+        Self {
+            ws: if extended {
+                Workspace::extended()
+            } else {
+                Workspace::new()
+            },
+            open: BTreeSet::new(),
+            shutdown: false,
+        }
+    }
+
+    // Handles one message, writing its response and any notifications to `out`.
+    fn handle(&mut self, msg: &Json, out: &mut impl Write) -> io::Result<()> {
+        let method = msg.get("method").and_then(Json::as_str).unwrap_or("");
+        let params = msg.get("params").unwrap_or(&Json::Null);
+        let result = match method {
+            "initialize" => Ok(capabilities()),
+            "shutdown" => {
+                self.shutdown = true;
+                Ok(Json::Null)
+            }
+            "exit" => process::exit(if self.shutdown { 0 } else { 1 }),
+            "textDocument/didOpen" => {
+                let doc = params.get("textDocument");
+                if let (Some(uri), Some(text)) = (
+                    doc.and_then(|d| d.get("uri")).and_then(Json::as_str),
+                    doc.and_then(|d| d.get("text")).and_then(Json::as_str),
+                ) {
+                    self.open.insert(uri.to_string());
+                    self.ws.update(uri, text.to_string());
+                    self.load_siblings(uri);
+                    self.publish_all(out)?;
+                }
+                return Ok(());
+            }
+            "textDocument/didChange" => {
+                let text = params
+                    .get("contentChanges")
+                    .and_then(Json::as_array)
+                    .and_then(|changes| changes.last())
+                    .and_then(|change| change.get("text"))
+                    .and_then(Json::as_str);
+                if let (Some(uri), Some(text)) = (doc_uri(params), text) {
+                    self.ws.update(uri, text.to_string());
+                    self.publish_all(out)?;
+                }
+                return Ok(());
+            }
+            "textDocument/didClose" => {
+                if let Some(uri) = doc_uri(params) {
+                    self.open.remove(uri);
+                    // Fall back to the saved file, which other documents may still use.
+                    match uri_to_path(uri).and_then(|p| fs::read(p).ok()) {
+                        Some(text) => self
+                            .ws
+                            .update(uri, String::from_utf8_lossy(&text).into_owned()),
+                        None => self.ws.remove(uri),
+                    }
+                    publish(out, uri, Vec::new())?;
+                    self.publish_all(out)?;
+                }
+                return Ok(());
+            }
+            "textDocument/definition" => self.at_position(params, |ws, uri, pos| {
+                ws.definition(uri, pos).map_or(Json::Null, |loc| {
+                    Json::object(vec![
+                        ("uri", Json::String(loc.uri)),
+                        ("range", range(loc.span)),
+                    ])
+                })
+            }),
+            "textDocument/hover" => self.at_position(params, |ws, uri, pos| {
+                ws.hover(uri, pos).map_or(Json::Null, |(text, span)| {
+                    Json::object(vec![
+                        (
+                            "contents",
+                            Json::object(vec![
+                                ("kind", Json::String("markdown".to_string())),
+                                ("value", Json::String(format! {"```jack\n{}\n```", text})),
+                            ]),
+                        ),
+                        ("range", range(span)),
+                    ])
+                })
+            }),
+            "textDocument/documentSymbol" => match doc_uri(params) {
+                Some(uri) => Ok(Json::Array(
+                    self.ws
+                        .document_symbols(uri)
+                        .into_iter()
+                        .map(document_symbol)
+                        .collect(),
+                )),
+                None => Err((INVALID_PARAMS, "Missing textDocument".to_string())),
+            },
+            "textDocument/completion" => self.at_position(params, |ws, uri, pos| {
+                Json::Array(
+                    ws.completions(uri, pos)
+                        .into_iter()
+                        .map(completion)
+                        .collect(),
+                )
+            }),
+            _ => Err((METHOD_NOT_FOUND, format! {"Unknown method {}", method})),
+        };
+
+        // Notifications have no id and get no response.
+        let id = match msg.get("id") {
+            Some(id) => id.clone(),
+            None => return Ok(()),
+        };
+        let mut response = vec![("jsonrpc", Json::String("2.0".to_string())), ("id", id)];
+        match result {
+            Ok(result) => response.push(("result", result)),
+            Err((code, message)) => response.push((
+                "error",
+                Json::object(vec![
+                    ("code", Json::Number(code)),
+                    ("message", Json::String(message)),
+                ]),
+            )),
+        }
+        write_message(out, &Json::object(response))
+    }
+
+    fn at_position(
+        &self,
+        params: &Json,
+        f: impl FnOnce(&Workspace, &str, Pos) -> Json,
+    ) -> Result<Json, (i64, String)> {
+        let position = params.get("position");
+        let line = position.and_then(|p| p.get("line")).and_then(Json::as_i64);
+        let character = position
+            .and_then(|p| p.get("character"))
+            .and_then(Json::as_i64);
+        match (doc_uri(params), line, character) {
+            (Some(uri), Some(line), Some(character)) => {
+                let pos = Pos {
+                    line: line as usize + 1,
+                    col: character as usize + 1,
+                };
+                Ok(f(&self.ws, uri, pos))
+            }
+            _ => Err((
+                INVALID_PARAMS,
+                "Missing textDocument or position".to_string(),
+            )),
+        }
+    }
+
+    // Reads the other `.jack` files next to `uri` so their classes can be resolved.
+    fn load_siblings(&mut self, uri: &str) {
+        let dir = match uri_to_path(uri).as_ref().and_then(|p| p.parent()) {
+            Some(dir) => dir.to_path_buf(),
+            None => return,
+        };
+        let entries = match dir.read_dir() {
+            Ok(entries) => entries,
+            Err(_) => return,
+        };
+        for path in entries
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .filter(|p| p.is_file() && p.extension().is_some_and(|e| e == "jack"))
+        {
+            let sibling = path_to_uri(&path);
+            if !self.ws.contains(&sibling) {
+                if let Ok(text) = fs::read(&path) {
+                    self.ws
+                        .update(&sibling, String::from_utf8_lossy(&text).into_owned());
+                }
+            }
+        }
+    }
+
+    // Diagnostics of one document can change with any other, so all are sent again.
+    fn publish_all(&self, out: &mut impl Write) -> io::Result<()> {
+        for uri in &self.open {
+            publish(out, uri, self.ws.diagnostics(uri))?;
+        }
+        Ok(())
+    }
+}
+
+fn capabilities() -> Json {
+    Json::object(vec![
+        (
+            "capabilities",
+            Json::object(vec![
+                // Full text on every change.
+                ("textDocumentSync", Json::Number(1)),
+                ("definitionProvider", Json::Bool(true)),
+                ("hoverProvider", Json::Bool(true)),
+                ("documentSymbolProvider", Json::Bool(true)),
+                (
+                    "completionProvider",
+                    Json::object(vec![(
+                        "triggerCharacters",
+                        Json::Array(vec![Json::String(".".to_string())]),
+                    )]),
+                ),
+            ]),
+        ),
+        (
+            "serverInfo",
+            Json::object(vec![("name", Json::String("jack_lsp".to_string()))]),
+        ),
+    ])
+}
+
+fn publish(
+    out: &mut impl Write,
+    uri: &str,
+    diagnostics: Vec<nand2tetris::jack::Diagnostic>,
+) -> io::Result<()> {
+    let diagnostics = diagnostics
+        .into_iter()
+        .map(|diag| {
+            Json::object(vec![
+                ("range", range(diag.span)),
+                // Error
+                ("severity", Json::Number(1)),
+                ("source", Json::String("jack".to_string())),
+                ("message", Json::String(diag.message)),
+            ])
+        })
+        .collect();
+    write_message(
+        out,
+        &Json::object(vec![
+            ("jsonrpc", Json::String("2.0".to_string())),
+            (
+                "method",
+                Json::String("textDocument/publishDiagnostics".to_string()),
+            ),
+            (
+                "params",
+                Json::object(vec![
+                    ("uri", Json::String(uri.to_string())),
+                    ("diagnostics", Json::Array(diagnostics)),
+                ]),
+            ),
+        ]),
+    )
+}
+
+fn doc_uri(params: &Json) -> Option<&str> {
+    params
+        .get("textDocument")
+        .and_then(|doc| doc.get("uri"))
+        .and_then(Json::as_str)
+}
+
+// LSP positions are 0-based.
+fn position(pos: Pos) -> Json {
+    Json::object(vec![
+        ("line", Json::Number(pos.line as i64 - 1)),
+        ("character", Json::Number(pos.col as i64 - 1)),
+    ])
+}
+
+fn range(span: Span) -> Json {
+    Json::object(vec![
+        ("start", position(span.start)),
+        ("end", position(span.end)),
+    ])
+}
+
+fn document_symbol(symbol: DocumentSymbol) -> Json {
+    // LSP SymbolKind
+    let kind = match symbol.kind {
+        SymbolKind::Class => 5,
+        SymbolKind::Method => 6,
+        SymbolKind::Field => 8,
+        SymbolKind::Constructor => 9,
+        SymbolKind::Function => 12,
+        SymbolKind::Static | SymbolKind::Argument | SymbolKind::Var => 13,
+    };
+    Json::object(vec![
+        ("name", Json::String(symbol.name)),
+        ("detail", Json::String(symbol.detail)),
+        ("kind", Json::Number(kind)),
+        ("range", range(symbol.span)),
+        ("selectionRange", range(symbol.selection)),
+        (
+            "children",
+            Json::Array(symbol.children.into_iter().map(document_symbol).collect()),
+        ),
+    ])
+}
+
+fn completion(completion: Completion) -> Json {
+    // LSP CompletionItemKind
+    let kind = match completion.kind {
+        SymbolKind::Method => 2,
+        SymbolKind::Function => 3,
+        SymbolKind::Constructor => 4,
+        SymbolKind::Field => 5,
+        SymbolKind::Static | SymbolKind::Argument | SymbolKind::Var => 6,
+        SymbolKind::Class => 7,
+    };
+    Json::object(vec![
+        ("label", Json::String(completion.label)),
+        ("kind", Json::Number(kind)),
+        ("detail", Json::String(completion.detail)),
+    ])
+}
+
+fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    let path = uri.strip_prefix("file://")?;
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = || u8::from_str_radix(path.get(i + 1..i + 3)?, 16).ok();
+        match (bytes[i], hex()) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    Some(PathBuf::from(
+        String::from_utf8_lossy(&decoded).into_owned(),
+    ))
+}
+
+fn path_to_uri(path: &Path) -> String {
+    let mut uri = "file://".to_string();
+    for &byte in path.to_string_lossy().as_bytes() {
+        if byte.is_ascii_alphanumeric() || b"/-_.~".contains(&byte) {
+            uri.push(byte as char);
+        } else {
+            uri.push_str(&format! {"%{:02X}", byte});
+        }
+    }
+    uri
+}
+
+// Reads one `Content-Length`-framed message; `None` at the end of input.
+fn read_message(input: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut len = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some(value) = line.strip_prefix("Content-Length:") {
+            len = value.trim().parse::<usize>().ok();
+        }
+    }
+    let len = len.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "No Content-Length"))?;
+    let mut body = vec![0; len];
+    input.read_exact(&mut body)?;
+    Ok(Some(String::from_utf8_lossy(&body).into_owned()))
+}
+
+fn write_message(out: &mut impl Write, msg: &Json) -> io::Result<()> {
+    let body = msg.to_compact_string();
+    write!(out, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    out.flush()
+}
+
+fn main() {
+    let args = App::new("jack_lsp")
+        .about("Language server for Jack, speaking LSP over stdin and stdout")
+        .arg(
+            Arg::with_name("extended")
+                .long("extended")
+                .help("Accept 'c' char literals, backslash escapes and 0x/0b integers"),
+        )
+        .get_matches();
+
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let mut server = Server::new(args.occurrences_of("extended") > 0);
+    while let Some(body) = read_message(&mut input).expect("Can't read message") {
+        match body.parse::<Json>() {
+            Ok(msg) => server.handle(&msg, &mut out).expect("Can't write message"),
+            Err(e) => eprintln!("{}", e),
+        }
+    }
+}
@@ -1,12 +1,18 @@
-use std::fs::File;
+use std::fs::{self, File};
 use std::io::{BufRead, BufReader, BufWriter, Write};
 use std::path::Path;
+use std::process;
 
 use clap::{App, Arg};
 
-use nand2tetris::jack::{tokenize, Parser, VmGen};
+use nand2tetris::jack::{check_returns, tokenize, tokenize_extended, Parser, VmGen};
+use nand2tetris::os::os_dependencies;
+use nand2tetris::vm::{load_vm_file, optimize, print_vm, Command, VmFile};
 
-fn to_xml(p: impl AsRef<Path>) {
+// Writes the VM code of `p` next to it, with its source map if `source_map`, and returns
+// it. Returns `None`, after printing every error, if `p` doesn't tokenize or parse or has
+// invalid returns.
+fn compile(p: impl AsRef<Path>, opt: bool, extended: bool, source_map: bool) -> Option<VmFile> {
     let f = File::open(p.as_ref()).expect("Can't open file");
     let mut reader = BufReader::new(f);
     let mut s = String::new();
@@ -16,18 +22,74 @@ fn to_xml(p: impl AsRef<Path>) {
         buf.clear();
     }
 
-    let tokens = tokenize(s.chars()).unwrap();
-    let mut parser = Parser::new(tokens.into_iter());
-    let ast = parser.parse().unwrap();
+    let tokens = if extended {
+        tokenize_extended(s.chars())
+    } else {
+        tokenize(s.chars())
+    };
+    let ast = match tokens
+        .map_err(String::from)
+        .and_then(|tokens| Parser::new(tokens.into_iter()).parse())
+        .and_then(|ast| {
+            let diags = check_returns(&ast);
+            if diags.is_empty() {
+                Ok(ast)
+            } else {
+                Err(diags
+                    .iter()
+                    .map(|diag| diag.to_string())
+                    .collect::<Vec<_>>()
+                    .join("\n"))
+            }
+        }) {
+        Ok(ast) => ast,
+        Err(errors) => {
+            for error in errors.lines() {
+                eprintln!("{}: {}", p.as_ref().display(), error);
+            }
+            return None;
+        }
+    };
 
     let mut vm_gen = VmGen::new();
-    let vm = vm_gen.gen(ast).unwrap();
-
+    let mut commands = vm_gen.gen(ast).unwrap().to_vec();
+    if opt {
+        commands = optimize(commands);
+    }
+    let name = p.as_ref().file_stem().unwrap().to_str().unwrap();
     let dir = p.as_ref().parent().unwrap();
-    let file_name = format!("{}.vm", p.as_ref().file_stem().unwrap().to_str().unwrap());
-    let out_path = dir.join(file_name);
+    write_vm(dir, name, &commands);
+    if source_map {
+        let map = vm_gen.source_map(&p.as_ref().display().to_string());
+        let map_path = dir.join(format!("{}.vm.map", name));
+        fs::write(&map_path, map.to_string())
+            .unwrap_or_else(|_| panic! {"Can't write file: {:?}", map_path});
+    }
+    Some(VmFile {
+        name: name.to_string(),
+        commands,
+    })
+}
+
+fn write_vm(dir: &Path, name: &str, commands: &[Command]) {
+    let out_path = dir.join(format!("{}.vm", name));
     let mut writer = BufWriter::new(File::create(out_path).unwrap());
-    writer.write_all(vm.as_bytes()).unwrap();
+    writer.write_all(print_vm(commands).as_bytes()).unwrap();
+}
+
+// Writes the bundled OS classes that `files` need to `dir`, unless it already has
+// their VM code, which then replaces the bundled one.
+fn link_os(dir: &Path, mut files: Vec<VmFile>) {
+    for path in dir.read_dir().unwrap().map(|p| p.unwrap().path()) {
+        let is_vm = path.extension().is_some_and(|ext| ext == "vm");
+        let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("");
+        if is_vm && files.iter().all(|file| file.name != stem) {
+            files.push(load_vm_file(&path).unwrap());
+        }
+    }
+    for file in os_dependencies(&files) {
+        write_vm(dir, &file.name, &file.commands);
+    }
 }
 
 fn main() {
@@ -38,19 +100,61 @@ fn main() {
                 .required(true)
                 .index(1),
         )
+        .arg(
+            Arg::with_name("optimize")
+                .short("O")
+                .long("optimize")
+                .help("Optimize generated VM code"),
+        )
+        .arg(
+            Arg::with_name("no-os")
+                .long("no-os")
+                .help("Don't write the bundled OS classes the code calls"),
+        )
+        .arg(
+            Arg::with_name("source-map")
+                .long("source-map")
+                .conflicts_with("optimize")
+                .help("Also write the Jack line of each VM command to a .vm.map file"),
+        )
+        .arg(
+            Arg::with_name("extended")
+                .long("extended")
+                .help("Accept 'c' char literals, backslash escapes and 0x/0b integers"),
+        )
         .get_matches();
 
+    let opt = args.occurrences_of("optimize") > 0;
+    let extended = args.occurrences_of("extended") > 0;
+    let no_os = args.occurrences_of("no-os") > 0;
+    let source_map = args.occurrences_of("source-map") > 0;
+
     let input_path = Path::new(args.value_of("INPUT").unwrap());
-    if input_path.is_dir() {
-        for path in input_path
+    let (dir, paths): (_, Vec<_>) = if input_path.is_dir() {
+        let paths = input_path
             .read_dir()
             .unwrap()
             .map(|p| p.unwrap().path())
             .filter(|p| p.is_file() && p.to_str().unwrap().ends_with("jack"))
-        {
-            to_xml(path);
-        }
+            .collect();
+        (input_path, paths)
     } else if input_path.is_file() && input_path.to_str().unwrap().ends_with("jack") {
-        to_xml(input_path);
+        let dir = input_path.parent().filter(|dir| dir != &Path::new(""));
+        (
+            dir.unwrap_or_else(|| Path::new(".")),
+            vec![input_path.to_path_buf()],
+        )
+    } else {
+        return;
+    };
+    let files: Vec<_> = paths
+        .into_iter()
+        .map(|path| compile(path, opt, extended, source_map))
+        .collect();
+    if files.iter().any(Option::is_none) {
+        process::exit(1);
+    }
+    if !no_os {
+        link_os(dir, files.into_iter().flatten().collect());
     }
 }
@@ -0,0 +1,81 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use clap::{App, Arg};
+
+use nand2tetris::jack::pretty::{format_source, format_source_extended};
+
+// Formats `p` in place, or with `check` only reports whether it is formatted.
+// Returns false if the file is not formatted or can't be parsed.
+fn format_file(p: &Path, check: bool, extended: bool) -> bool {
+    let src = String::from_utf8_lossy(&fs::read(p).expect("Can't open file")).into_owned();
+    let formatted = if extended {
+        format_source_extended(&src)
+    } else {
+        format_source(&src)
+    };
+    let formatted = match formatted {
+        Ok(formatted) => formatted,
+        Err(errors) => {
+            for error in errors.lines() {
+                eprintln!("{}: {}", p.display(), error);
+            }
+            return false;
+        }
+    };
+    if formatted == src {
+        return true;
+    }
+    if check {
+        println!("{} is not formatted", p.display());
+        false
+    } else {
+        fs::write(p, formatted).unwrap();
+        true
+    }
+}
+
+fn main() {
+    let args = App::new("jackfmt")
+        .arg(
+            Arg::with_name("INPUT")
+                .help(".jack file or dir path containing .jack file")
+                .required(true)
+                .index(1),
+        )
+        .arg(
+            Arg::with_name("check")
+                .long("check")
+                .help("Don't write files; exit with 1 if any file is not formatted"),
+        )
+        .arg(
+            Arg::with_name("extended")
+                .long("extended")
+                .help("Accept 'c' char literals, backslash escapes and 0x/0b integers"),
+        )
+        .get_matches();
+
+    let check = args.occurrences_of("check") > 0;
+    let extended = args.occurrences_of("extended") > 0;
+
+    let input_path = Path::new(args.value_of("INPUT").unwrap());
+    let paths: Vec<PathBuf> = if input_path.is_dir() {
+        input_path
+            .read_dir()
+            .unwrap()
+            .map(|p| p.unwrap().path())
+            .filter(|p| p.is_file() && p.to_str().unwrap().ends_with("jack"))
+            .collect()
+    } else {
+        vec![input_path.to_path_buf()]
+    };
+
+    let mut ok = true;
+    for path in paths {
+        ok &= format_file(&path, check, extended);
+    }
+    if !ok {
+        process::exit(1);
+    }
+}
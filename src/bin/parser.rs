@@ -4,9 +4,9 @@ use std::path::Path;
 
 use clap::{App, Arg};
 
-use nand2tetris::jack::{tokenize, Parser};
+use nand2tetris::jack::{tokenize, tokens_to_xml, Parser, ToJson};
 
-fn to_xml(p: impl AsRef<Path>) {
+fn parse(p: impl AsRef<Path>, tokens_only: bool, json: bool) {
     let f = File::open(p.as_ref()).expect("Can't open file");
     let mut reader = BufReader::new(f);
     let mut s = String::new();
@@ -16,15 +16,30 @@ fn to_xml(p: impl AsRef<Path>) {
         buf.clear();
     }
     let tokens = tokenize(s.chars()).unwrap();
-    let mut parser = Parser::new(tokens.into_iter());
-    let ast = parser.parse().unwrap();
-    let xml = ast.to_xml();
+    let (out, suffix) = if tokens_only && json {
+        (tokens.to_json().to_string() + "\n", "T")
+    } else if tokens_only {
+        (tokens_to_xml(tokens.iter().map(|(t, _)| t)), "T")
+    } else {
+        let mut parser = Parser::new(tokens.into_iter());
+        let ast = parser.parse().unwrap();
+        if json {
+            (ast.to_json().to_string() + "\n", "")
+        } else {
+            (ast.to_xml(), "")
+        }
+    };
 
     let dir = p.as_ref().parent().unwrap();
-    let file_name = format!("{}.xml", p.as_ref().file_stem().unwrap().to_str().unwrap());
+    let file_name = format!(
+        "{}{}.{}",
+        p.as_ref().file_stem().unwrap().to_str().unwrap(),
+        suffix,
+        if json { "json" } else { "xml" }
+    );
     let out_path = dir.join(file_name);
     let mut writer = BufWriter::new(File::create(out_path).unwrap());
-    writer.write_all(xml.as_bytes()).unwrap();
+    writer.write_all(out.as_bytes()).unwrap();
 }
 
 fn main() {
@@ -35,8 +50,24 @@ fn main() {
                 .required(true)
                 .index(1),
         )
+        .arg(
+            Arg::with_name("tokens")
+                .long("tokens")
+                .help("Write the token stream to xxxT.xml instead of the parse tree"),
+        )
+        .arg(
+            Arg::with_name("format")
+                .long("format")
+                .takes_value(true)
+                .possible_values(&["xml", "json"])
+                .default_value("xml")
+                .help("Output format; json writes xxx.json (or xxxT.json with --tokens)"),
+        )
         .get_matches();
 
+    let tokens_only = args.occurrences_of("tokens") > 0;
+    let json = args.value_of("format") == Some("json");
+
     let input_path = Path::new(args.value_of("INPUT").unwrap());
     if input_path.is_dir() {
         for path in input_path
@@ -45,9 +76,9 @@ fn main() {
             .map(|p| p.unwrap().path())
             .filter(|p| p.is_file() && p.to_str().unwrap().ends_with("jack"))
         {
-            to_xml(path);
+            parse(path, tokens_only, json);
         }
     } else if input_path.is_file() && input_path.to_str().unwrap().ends_with("jack") {
-        to_xml(input_path);
+        parse(input_path, tokens_only, json);
     }
 }
@@ -0,0 +1,54 @@
+use std::fs;
+use std::path::Path;
+use std::process;
+
+use clap::{App, Arg};
+
+use nand2tetris::tst::TstRunner;
+
+fn main() {
+    let args = App::new("tst_run")
+        .arg(
+            Arg::with_name("INPUT")
+                .help("Test script (.tst) path")
+                .required(true)
+                .index(1),
+        )
+        .arg(
+            Arg::with_name("lib")
+                .short("L")
+                .long("lib")
+                .help("Directory of .hdl files for chips the script directory doesn't have")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .get_matches();
+
+    let path = Path::new(args.value_of("INPUT").unwrap());
+    let script =
+        fs::read_to_string(path).unwrap_or_else(|_| panic! {"Can't open file: {:?}", path});
+    let mut runner = TstRunner::new(path.parent().unwrap_or_else(|| Path::new(".")));
+    for dir in args.values_of("lib").into_iter().flatten() {
+        runner.lib(dir);
+    }
+    let result = runner.run(&script);
+
+    if let Some(out_path) = runner.output_file() {
+        let mut out = runner.output().join("\n");
+        out.push('\n');
+        fs::write(out_path, out).unwrap_or_else(|_| panic! {"Can't write file: {:?}", out_path});
+    }
+    if let Some(echo) = runner.echo() {
+        println!("{}", echo);
+    }
+    match result {
+        Ok(()) => println!("End of script - Comparison ended successfully"),
+        Err(errors) => {
+            for error in errors.lines() {
+                eprintln!("{}: {}", path.display(), error);
+            }
+            process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::process;
+
+use clap::{App, Arg};
+
+use nand2tetris::vm::{
+    load_vm_file, stack_depths, stack_usage, CallGraph, Command, StackUsage, FRAME_SIZE,
+};
+
+fn main() {
+    let args = App::new("vm_analyze")
+        .arg(
+            Arg::with_name("INPUT")
+                .help("VM file or dir path")
+                .required(true)
+                .index(1),
+        )
+        .arg(
+            Arg::with_name("dot")
+                .long("dot")
+                .help("Output path of the call graph in Graphviz DOT format")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("entry")
+                .long("entry")
+                .help("Entry function for the worst-case estimate (default: Sys.init)")
+                .takes_value(true),
+        )
+        .get_matches();
+
+    let input_path = Path::new(args.value_of("INPUT").unwrap());
+    let commands = load(input_path).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(1);
+    });
+
+    let graph = CallGraph::new(&commands);
+    let stats = stack_depths(&commands).unwrap_or_else(|e| {
+        eprintln!("{}: {}", input_path.display(), e);
+        process::exit(1);
+    });
+
+    if let Some(dot_path) = args.value_of("dot") {
+        let mut writer = BufWriter::new(
+            File::create(dot_path)
+                .unwrap_or_else(|_| panic! {"Can't open output file: {:?}", dot_path}),
+        );
+        writer.write_all(graph.to_dot().as_bytes()).unwrap();
+    }
+
+    let cycles = graph.cycles();
+    if cycles.is_empty() {
+        println!("No recursion cycles");
+    } else {
+        println!("Recursion cycles:");
+        for cycle in &cycles {
+            println!("  {}", cycle.join(" -> "));
+        }
+    }
+
+    println!();
+    println!(
+        "{:<40} {:>6} {:>9} {:>10}",
+        "function", "locals", "max depth", "worst case"
+    );
+    for (name, func) in &stats {
+        let worst = match stack_usage(&graph, &stats, name) {
+            StackUsage::Bounded(words) => words.to_string(),
+            StackUsage::Recursive => "recursive".to_string(),
+        };
+        println!(
+            "{:<40} {:>6} {:>9} {:>10}",
+            name, func.n_locals, func.max_depth, worst
+        );
+    }
+
+    let entry = args.value_of("entry").unwrap_or("Sys.init");
+    println!();
+    match stack_usage(&graph, &stats, entry) {
+        StackUsage::Bounded(words) => println!(
+            "Worst-case stack from {}: {} words (SP <= {} with the bootstrap at 256)",
+            entry,
+            FRAME_SIZE + words,
+            256 + FRAME_SIZE + words
+        ),
+        StackUsage::Recursive => println!(
+            "Worst-case stack from {}: unbounded, a recursion cycle is reachable",
+            entry
+        ),
+    }
+}
+
+// Commands of a .vm file, or of the .vm files of a directory in name order.
+fn load(path: &Path) -> Result<Vec<Command>, String> {
+    let load_file = |path: &Path| {
+        load_vm_file(path)
+            .map(|file| file.commands)
+            .map_err(|e| format! {"{}: {}", path.display(), e})
+    };
+    if !path.is_dir() {
+        return load_file(path);
+    }
+    let mut paths: Vec<_> = path
+        .read_dir()
+        .map_err(|_| format! {"Can't open directory: {:?}", path})?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|p| p.is_file() && p.extension().is_some_and(|ext| ext == "vm"))
+        .collect();
+    paths.sort();
+    let mut commands = Vec::new();
+    for path in paths {
+        commands.extend(load_file(&path)?);
+    }
+    Ok(commands)
+}
@@ -0,0 +1,39 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::Path;
+
+use clap::{App, Arg};
+
+use nand2tetris::vm::{optimize, parse_vm, print_vm};
+
+fn main() {
+    let args = App::new("vmopt")
+        .arg(
+            Arg::with_name("INPUT")
+                .help("VM file path")
+                .required(true)
+                .index(1),
+        )
+        .arg(
+            Arg::with_name("out")
+                .short("o")
+                .help("Output VM file path")
+                .takes_value(true)
+                .required(false),
+        )
+        .get_matches();
+
+    let input_path = Path::new(args.value_of("INPUT").unwrap());
+    let strm = BufReader::new(
+        File::open(input_path).unwrap_or_else(|_| panic! {"Can't open vm file: {:?}", input_path}),
+    );
+    let commands = optimize(parse_vm(strm).unwrap());
+
+    let default_out = format! {"{}.opt.vm", input_path.file_stem().unwrap().to_str().unwrap()};
+    let out_path = args.value_of("out").unwrap_or(&default_out);
+    let mut writer = BufWriter::new(
+        File::create(out_path)
+            .unwrap_or_else(|_| panic! {"Can't open output file: {:?}", out_path}),
+    );
+    writer.write_all(print_vm(&commands).as_bytes()).unwrap();
+}
@@ -1,15 +1,32 @@
-mod parser;
+pub mod parser;
 mod symbol;
 
+use std::collections::BTreeMap;
 use std::io::BufRead;
 
 use parser::*;
 use symbol::SymbolTable;
+pub use symbol::PREDEFINED_SYMBOLS;
 
 const MAXIMUM_ADDR: u16 = 32767;
 
+/// Addresses the assembler gave to the symbols of a program: labels in the ROM, and
+/// variables in the RAM. `lines` has the source line of the instruction at each ROM
+/// address.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Symbols {
+    pub labels: BTreeMap<String, u16>,
+    pub variables: BTreeMap<String, u16>,
+    pub lines: Vec<usize>,
+}
+
 pub fn gen_code(strm: impl BufRead) -> Result<Vec<String>, String> {
-    let ops = parse_asm(strm)?;
+    gen_code_with_symbols(strm).map(|(code, _)| code)
+}
+
+/// Assembles like `gen_code`, also returning the symbol map.
+pub fn gen_code_with_symbols(strm: impl BufRead) -> Result<(Vec<String>, Symbols), String> {
+    let (lines, ops): (Vec<_>, Vec<_>) = parse_asm_lines(strm)?.into_iter().unzip();
     let mut sym_table = SymbolTable::new(&ops);
 
     let mut ret = Vec::new();
@@ -28,7 +45,79 @@ pub fn gen_code(strm: impl BufRead) -> Result<Vec<String>, String> {
             Op::Label(_) => {}
         }
     }
-    Ok(ret)
+
+    let mut symbols = Symbols::default();
+    for (op, line) in ops.iter().zip(lines) {
+        match op {
+            Op::Label(label) => {
+                symbols
+                    .labels
+                    .insert(label.clone(), symbols.lines.len() as u16);
+            }
+            _ => symbols.lines.push(line),
+        }
+    }
+    for (name, addr) in sym_table.variables() {
+        symbols.variables.insert(name.to_string(), addr);
+    }
+    Ok((ret, symbols))
+}
+
+/// Instruction that assembles to `word`, or `None` for a C-instruction whose bits
+/// don't match any assembly mnemonic.
+pub fn decode(word: u16) -> Option<Op> {
+    if word >> 15 == 0 {
+        return Some(Op::Addr(Address::Immediate(word)));
+    }
+    if word >> 13 != 0b111 {
+        return None;
+    }
+    let dest = match word >> 3 & 0b111 {
+        0b000 => Dest::Null,
+        0b001 => Dest::M,
+        0b010 => Dest::D,
+        0b011 => Dest::MD,
+        0b100 => Dest::A,
+        0b101 => Dest::AM,
+        0b110 => Dest::AD,
+        _ => Dest::AMD,
+    };
+
+    let use_m = word >> 12 & 1 == 1;
+    let op = match (word >> 6 & 0b111111, use_m) {
+        (0b101010, false) => CompOp::Zero,
+        (0b111111, false) => CompOp::One,
+        (0b111010, false) => CompOp::NegOne,
+        (0b001100, false) => CompOp::D,
+        (0b110000, _) => CompOp::AM(use_m),
+        (0b001101, false) => CompOp::NotD,
+        (0b110001, _) => CompOp::NotAM(use_m),
+        (0b001111, false) => CompOp::NegD,
+        (0b110011, _) => CompOp::NegAM(use_m),
+        (0b011111, false) => CompOp::IncD,
+        (0b110111, _) => CompOp::IncAM(use_m),
+        (0b001110, false) => CompOp::DecD,
+        (0b110010, _) => CompOp::DecAM(use_m),
+        (0b000010, _) => CompOp::DPlusAM(use_m),
+        (0b010011, _) => CompOp::DMinusAM(use_m),
+        (0b000111, _) => CompOp::AMMinusD(use_m),
+        (0b000000, _) => CompOp::DAndAM(use_m),
+        (0b010101, _) => CompOp::DOrAM(use_m),
+        _ => return None,
+    };
+
+    let jmp = match word & 0b111 {
+        0b000 => Jmp::Null,
+        0b001 => Jmp::Jgt,
+        0b010 => Jmp::Jeq,
+        0b011 => Jmp::Jge,
+        0b100 => Jmp::Jlt,
+        0b101 => Jmp::Jne,
+        0b110 => Jmp::Jle,
+        _ => Jmp::Jmp,
+    };
+
+    Some(Op::Comp(Comp { dest, op, jmp }))
 }
 
 fn gen_addressing(addr: u16) -> String {
@@ -51,25 +140,25 @@ fn gen_comp(dest: &Dest, op: &CompOp, jmp: &Jmp) -> String {
         Dest::AMD => 0b111,
     };
 
-    let (op, use_m) = match op {
-        &CompOp::Zero => (0b101010, false),
-        &CompOp::One => (0b111111, false),
-        &CompOp::NegOne => (0b111010, false),
-        &CompOp::D => (0b001100, false),
-        &CompOp::AM(use_m) => (0b110000, use_m),
-        &CompOp::NotD => (0b001101, false),
-        &CompOp::NotAM(use_m) => (0b110001, use_m),
-        &CompOp::NegD => (0b001111, false),
-        &CompOp::NegAM(use_m) => (0b110011, use_m),
-        &CompOp::IncD => (0b011111, false),
-        &CompOp::IncAM(use_m) => (0b110111, use_m),
-        &CompOp::DecD => (0b001110, false),
-        &CompOp::DecAM(use_m) => (0b110010, use_m),
-        &CompOp::DPlusAM(use_m) => (0b000010, use_m),
-        &CompOp::DMinusAM(use_m) => (0b010011, use_m),
-        &CompOp::AMMinusD(use_m) => (0b000111, use_m),
-        &CompOp::DAndAM(use_m) => (0b000000, use_m),
-        &CompOp::DOrAM(use_m) => (0b010101, use_m),
+    let (op, use_m) = match *op {
+        CompOp::Zero => (0b101010, false),
+        CompOp::One => (0b111111, false),
+        CompOp::NegOne => (0b111010, false),
+        CompOp::D => (0b001100, false),
+        CompOp::AM(use_m) => (0b110000, use_m),
+        CompOp::NotD => (0b001101, false),
+        CompOp::NotAM(use_m) => (0b110001, use_m),
+        CompOp::NegD => (0b001111, false),
+        CompOp::NegAM(use_m) => (0b110011, use_m),
+        CompOp::IncD => (0b011111, false),
+        CompOp::IncAM(use_m) => (0b110111, use_m),
+        CompOp::DecD => (0b001110, false),
+        CompOp::DecAM(use_m) => (0b110010, use_m),
+        CompOp::DPlusAM(use_m) => (0b000010, use_m),
+        CompOp::DMinusAM(use_m) => (0b010011, use_m),
+        CompOp::AMMinusD(use_m) => (0b000111, use_m),
+        CompOp::DAndAM(use_m) => (0b000000, use_m),
+        CompOp::DOrAM(use_m) => (0b010101, use_m),
     };
 
     let use_m = if use_m { 0b1 } else { 0b0 };
@@ -86,3 +175,32 @@ fn gen_comp(dest: &Dest, op: &CompOp, jmp: &Jmp) -> String {
 
     format!("111{:b}{:06b}{:03b}{:03b}", use_m, op, dest, jmp)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        let asm = include_str!("project04/mult/Mult.asm");
+        let (code, symbols) = gen_code_with_symbols(asm.as_bytes()).unwrap();
+        assert_eq!(symbols.labels["LOOP"], 4);
+        assert_eq!(symbols.labels["END"] as usize, code.len() - 1);
+        let (_, vars) = gen_code_with_symbols("@sum\nM=0\n@i\nM=1\n@R1".as_bytes()).unwrap();
+        assert_eq!(vars.variables["sum"], 16);
+        assert_eq!(vars.variables["i"], 17);
+        assert_eq!(vars.variables.len(), 2);
+
+        // Every instruction disassembles to assembly of the same word.
+        let disassembly: Vec<_> = code
+            .iter()
+            .map(|word| {
+                decode(u16::from_str_radix(word, 2).unwrap())
+                    .unwrap()
+                    .to_string()
+            })
+            .collect();
+        assert_eq!(gen_code(disassembly.join("\n").as_bytes()).unwrap(), code);
+        assert_eq!(decode(0b1110_1111_1000_0000), None);
+    }
+}
@@ -1,9 +1,11 @@
+use std::fmt;
 use std::io::BufRead;
 use std::str;
 
 use lazy_static::lazy_static;
 use regex::Regex;
 
+#[allow(clippy::upper_case_acronyms)]
 #[derive(PartialEq, Eq, Debug)]
 pub enum Dest {
     Null,
@@ -38,6 +40,7 @@ pub enum CompOp {
     DOrAM(bool),
 }
 
+#[allow(clippy::enum_variant_names)]
 #[derive(PartialEq, Eq, Debug)]
 pub enum Jmp {
     Null,
@@ -70,7 +73,62 @@ pub enum Op {
     Label(String),
 }
 
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Op::Addr(Address::Symbol(symbol)) => write!(f, "@{}", symbol),
+            Op::Addr(Address::Immediate(num)) => write!(f, "@{}", num),
+            Op::Label(label) => write!(f, "({})", label),
+            Op::Comp(Comp { dest, op, jmp }) => {
+                if *dest != Dest::Null {
+                    write!(f, "{:?}=", dest)?;
+                }
+                let (op, use_m) = match op {
+                    CompOp::Zero => ("0", false),
+                    CompOp::One => ("1", false),
+                    CompOp::NegOne => ("-1", false),
+                    CompOp::D => ("D", false),
+                    CompOp::AM(use_m) => ("A", *use_m),
+                    CompOp::NotD => ("!D", false),
+                    CompOp::NotAM(use_m) => ("!A", *use_m),
+                    CompOp::NegD => ("-D", false),
+                    CompOp::NegAM(use_m) => ("-A", *use_m),
+                    CompOp::IncD => ("D+1", false),
+                    CompOp::IncAM(use_m) => ("A+1", *use_m),
+                    CompOp::DecD => ("D-1", false),
+                    CompOp::DecAM(use_m) => ("A-1", *use_m),
+                    CompOp::DPlusAM(use_m) => ("D+A", *use_m),
+                    CompOp::DMinusAM(use_m) => ("D-A", *use_m),
+                    CompOp::AMMinusD(use_m) => ("A-D", *use_m),
+                    CompOp::DAndAM(use_m) => ("D&A", *use_m),
+                    CompOp::DOrAM(use_m) => ("D|A", *use_m),
+                };
+                if use_m {
+                    write!(f, "{}", op.replace('A', "M"))?;
+                } else {
+                    write!(f, "{}", op)?;
+                }
+                if *jmp != Jmp::Null {
+                    write!(f, ";{}", format! {"{:?}", jmp}.to_uppercase())?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
 pub fn parse_asm<T>(strm: T) -> Result<Vec<Op>, String>
+where
+    T: BufRead,
+{
+    Ok(parse_asm_lines(strm)?
+        .into_iter()
+        .map(|(_, op)| op)
+        .collect())
+}
+
+/// Parses like `parse_asm`, pairing each op with its 1-based line number.
+pub fn parse_asm_lines<T>(strm: T) -> Result<Vec<(usize, Op)>, String>
 where
     T: BufRead,
 {
@@ -82,7 +140,8 @@ where
         if s.is_empty() {
             continue;
         }
-        ops.push(parse_line(&s).map_err(|e| format!("Error: line {}; {}", line_num, e))?);
+        let op = parse_line(s).map_err(|e| format!("Error: line {}; {}", line_num, e))?;
+        ops.push((line_num, op));
     }
     Ok(ops)
 }
@@ -97,7 +156,7 @@ fn parse_line(s: &str) -> Result<Op, &'static str> {
     }
 
     let op_matches: Vec<_> = OP_PAT.captures_iter(s).collect();
-    if 0 < op_matches.len() && op_matches.len() < 2 {
+    if op_matches.len() == 1 {
         let dest = op_matches[0].get(1).map(|s| s.as_str());
         let op = op_matches[0]
             .get(2)
@@ -108,13 +167,13 @@ fn parse_line(s: &str) -> Result<Op, &'static str> {
     }
 
     let addr_matches: Vec<_> = ADDR_PAT.captures_iter(s).collect();
-    if 0 < addr_matches.len() && addr_matches.len() < 2 {
+    if addr_matches.len() == 1 {
         let addr = addr_matches[0].get(1).map(|s| s.as_str()).unwrap();
         return parse_addr(addr);
     }
 
     let label_matches: Vec<_> = LABEL_PAT.captures_iter(s).collect();
-    if 0 < label_matches.len() && label_matches.len() < 2 {
+    if label_matches.len() == 1 {
         let label = label_matches[0].get(1).map(|s| s.as_str()).unwrap();
         return parse_label(label);
     }
@@ -217,7 +276,6 @@ fn parse_label(label: &str) -> Result<Op, &'static str> {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::io::BufReader;
 
     #[test]
     fn test_addr() {
@@ -4,37 +4,43 @@ use super::parser::*;
 
 const MAX_FREE_RAM_ADDR: u16 = 16384;
 
+/// Symbols every Hack program has, with their RAM addresses.
+pub const PREDEFINED_SYMBOLS: &[(&str, u16)] = &[
+    ("SP", 0),
+    ("LCL", 1),
+    ("ARG", 2),
+    ("THIS", 3),
+    ("THAT", 4),
+    ("R0", 0),
+    ("R1", 1),
+    ("R2", 2),
+    ("R3", 3),
+    ("R4", 4),
+    ("R5", 5),
+    ("R6", 6),
+    ("R7", 7),
+    ("R8", 8),
+    ("R9", 9),
+    ("R10", 10),
+    ("R11", 11),
+    ("R12", 12),
+    ("R13", 13),
+    ("R14", 14),
+    ("R15", 15),
+    ("SCREEN", 16384),
+    ("KBD", 24576),
+];
+
 pub struct SymbolTable<'a> {
     table: HashMap<&'a str, u16>,
+    // Variables in allocation order.
+    variables: Vec<&'a str>,
     next_addr: u16,
 }
 
 impl<'a> SymbolTable<'a> {
     pub fn new(v: &'a Vec<Op>) -> Self {
-        let mut table = HashMap::new();
-        table.insert("SP", 0);
-        table.insert("LCL", 1);
-        table.insert("ARG", 2);
-        table.insert("THIS", 3);
-        table.insert("THAT", 4);
-        table.insert("R0", 0);
-        table.insert("R1", 1);
-        table.insert("R2", 2);
-        table.insert("R3", 3);
-        table.insert("R4", 4);
-        table.insert("R5", 5);
-        table.insert("R6", 6);
-        table.insert("R7", 7);
-        table.insert("R8", 8);
-        table.insert("R9", 9);
-        table.insert("R10", 10);
-        table.insert("R11", 11);
-        table.insert("R12", 12);
-        table.insert("R13", 13);
-        table.insert("R14", 14);
-        table.insert("R15", 15);
-        table.insert("SCREEN", 16384);
-        table.insert("KBD", 24576);
+        let mut table: HashMap<_, _> = PREDEFINED_SYMBOLS.iter().copied().collect();
 
         let mut line = 0;
         for op in v {
@@ -50,6 +56,7 @@ impl<'a> SymbolTable<'a> {
         }
         Self {
             table,
+            variables: Vec::new(),
             next_addr: 16,
         }
     }
@@ -62,9 +69,15 @@ impl<'a> SymbolTable<'a> {
                     panic!("RAM Address overflow");
                 }
                 self.table.insert(s, self.next_addr);
+                self.variables.push(s);
                 self.next_addr += 1;
                 self.table[s]
             }
         }
     }
+
+    /// Variables with their RAM addresses.
+    pub fn variables(&self) -> impl Iterator<Item = (&'a str, u16)> + '_ {
+        self.variables.iter().map(move |&s| (s, self.table[s]))
+    }
 }
@@ -0,0 +1,17 @@
+mod cpu;
+mod debugger;
+mod keyboard;
+mod native;
+mod profile;
+mod screen;
+mod vm;
+
+pub(crate) use cpu::{alu, jumps};
+pub use cpu::{
+    assemble, assemble_with_symbols, parse_hack, rom_source_map, Cpu, KBD, MEMORY_SIZE, SCREEN,
+};
+pub use debugger::Debugger;
+pub use keyboard::{KeyEvent, KeyScript};
+pub use profile::Profiler;
+pub use screen::{screen_png, screen_ppm, SCREEN_HEIGHT, SCREEN_WIDTH};
+pub use vm::{Function, Vm, ARG, LCL, SP, THAT, THIS};
@@ -0,0 +1,183 @@
+use std::io::BufRead;
+use std::path::Path;
+
+use super::vm::return_addresses;
+use crate::code_gen::{gen_code_with_symbols, Symbols};
+use crate::source_map::{SourceLoc, SourceMap, SourceResolver};
+
+/// Words of the Hack ROM and RAM.
+pub const MEMORY_SIZE: usize = 32768;
+/// Address of the first word of the screen memory map.
+pub const SCREEN: usize = 16384;
+/// Address of the keyboard memory map.
+pub const KBD: usize = 24576;
+
+/// Hack computer running a program from its ROM, one instruction per `step`.
+pub struct Cpu {
+    pub rom: Vec<u16>,
+    pub ram: Vec<u16>,
+    pub a: u16,
+    pub d: u16,
+    pub pc: u16,
+}
+
+impl Cpu {
+    /// Computer with `program` at the start of the ROM and cleared RAM and registers.
+    pub fn new(program: &[u16]) -> Self {
+        let mut rom = vec![0; MEMORY_SIZE];
+        rom[..program.len()].copy_from_slice(program);
+        Self {
+            rom,
+            ram: vec![0; MEMORY_SIZE],
+            a: 0,
+            d: 0,
+            pc: 0,
+        }
+    }
+
+    /// Executes the instruction at `pc`.
+    pub fn step(&mut self) {
+        let instruction = self.rom[self.pc as usize];
+        if instruction >> 15 == 0 {
+            self.a = instruction;
+            self.pc = self.pc.wrapping_add(1);
+            return;
+        }
+        let address = self.a as usize % MEMORY_SIZE;
+        let y = if instruction >> 12 & 1 == 1 {
+            self.ram[address]
+        } else {
+            self.a
+        };
+        let out = alu(self.d, y, instruction >> 6 & 0x3f);
+        let jump = jumps(out, instruction);
+        if instruction >> 3 & 1 == 1 {
+            self.ram[address] = out;
+        }
+        // The PC loads the address A had before this instruction.
+        let target = self.a;
+        if instruction >> 5 & 1 == 1 {
+            self.a = out;
+        }
+        if instruction >> 4 & 1 == 1 {
+            self.d = out;
+        }
+        self.pc = if jump {
+            target
+        } else {
+            self.pc.wrapping_add(1)
+        } % MEMORY_SIZE as u16;
+    }
+
+    /// Whether the program is at the `@LOOP` of a `(LOOP) @LOOP 0;JMP` loop, as ends a
+    /// program or `Sys.halt`, so it will never change anything again.
+    pub fn is_halted(&self) -> bool {
+        let pc = self.pc as usize;
+        self.rom[pc] == pc as u16
+            && self
+                .rom
+                .get(pc + 1)
+                .is_some_and(|&jump| jump >> 13 == 0b111 && jump & 0b111 == 0b111)
+    }
+
+    /// ROM addresses of `pc` and of the calls of the VM functions running, innermost
+    /// first, following the frames that code from `AsmGenerator` keeps in the RAM.
+    pub fn backtrace(&self) -> Vec<u16> {
+        let calls = return_addresses(&self.ram).into_iter().map(|ret| ret - 1);
+        std::iter::once(self.pc).chain(calls).collect()
+    }
+}
+
+/// Reads a `.hack` file of 16-character binary lines.
+pub fn parse_hack(strm: impl BufRead) -> Result<Vec<u16>, String> {
+    let mut program = Vec::new();
+    for (line_num, line) in strm.lines().enumerate() {
+        let line = line.map_err(|e| format! {"Error: line {}; {:?}", line_num + 1, e})?;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let word = match u16::from_str_radix(line, 2) {
+            Ok(word) if line.len() == 16 => word,
+            _ => return Err(format! {"Error: line {}; Invalid instruction", line_num + 1}),
+        };
+        program.push(word);
+    }
+    if program.len() > MEMORY_SIZE {
+        return Err("Error: Program too large".to_string());
+    }
+    Ok(program)
+}
+
+/// Assembles a `.asm` file into the words of its `.hack` file.
+pub fn assemble(strm: impl BufRead) -> Result<Vec<u16>, String> {
+    assemble_with_symbols(strm).map(|(program, _)| program)
+}
+
+/// Assembles like `assemble`, also returning the symbol map.
+pub fn assemble_with_symbols(strm: impl BufRead) -> Result<(Vec<u16>, Symbols), String> {
+    let (code, symbols) = gen_code_with_symbols(strm)?;
+    Ok((parse_hack(code.join("\n").as_bytes())?, symbols))
+}
+
+/// Source of each ROM address of the program assembled from the `.asm` file at `path`
+/// with `symbols`, or read from the `.hack` file at `path` with its map, resolved
+/// through the maps of earlier stages. Empty if a `.hack` file has no map.
+pub fn rom_source_map(path: &Path, symbols: &Symbols) -> SourceMap {
+    let map = if path.extension().is_some_and(|ext| ext == "asm") {
+        let file = path.display().to_string();
+        let locs = symbols
+            .lines
+            .iter()
+            .map(|&line| Some(SourceLoc::new(&file, line, None)))
+            .collect();
+        SourceMap::new(locs)
+    } else {
+        SourceMap::load(format! {"{}.map", path.display()}).unwrap_or_default()
+    };
+    SourceResolver::new().resolve_map(&map)
+}
+
+/// Hack ALU output for inputs `x` and `y` and control bits `zx nx zy ny f no`.
+pub(crate) fn alu(x: u16, y: u16, control: u16) -> u16 {
+    let bit = |i: u16| control >> (5 - i) & 1 == 1;
+    let x = if bit(0) { 0 } else { x };
+    let x = if bit(1) { !x } else { x };
+    let y = if bit(2) { 0 } else { y };
+    let y = if bit(3) { !y } else { y };
+    let out = if bit(4) { x.wrapping_add(y) } else { x & y };
+    if bit(5) {
+        !out
+    } else {
+        out
+    }
+}
+
+/// Whether the jump bits of C-instruction `instruction` jump on ALU output `out`.
+pub(crate) fn jumps(out: u16, instruction: u16) -> bool {
+    let negative = (out as i16) < 0;
+    instruction & 0b100 != 0 && negative
+        || instruction & 0b010 != 0 && out == 0
+        || instruction & 0b001 != 0 && !negative && out != 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mult() {
+        let asm = include_str!("../project04/mult/Mult.asm");
+        let program = assemble(asm.as_bytes()).unwrap();
+        let hack = include_str!("../project04/mult/Mult.hack");
+        assert_eq!(program, parse_hack(hack.as_bytes()).unwrap());
+
+        let mut cpu = Cpu::new(&program);
+        cpu.ram[0] = 6;
+        cpu.ram[1] = 7;
+        for _ in 0..300 {
+            cpu.step();
+        }
+        assert_eq!(cpu.ram[2], 42);
+    }
+}
@@ -0,0 +1,429 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+use super::cpu::{Cpu, MEMORY_SIZE};
+use crate::code_gen::parser::Op;
+use crate::code_gen::{decode, Symbols, PREDEFINED_SYMBOLS};
+use crate::source_map::SourceMap;
+
+const HELP: &str = "\
+break|b LOC         Stop before executing the instruction at LOC
+delete|d LOC        Remove the breakpoint at LOC
+watch|w CELL        Stop after an instruction changes CELL
+unwatch CELL        Remove the watchpoint on CELL
+step|s [N]          Execute N instructions (default 1)
+continue|c [N]      Execute until a breakpoint, a watchpoint or a halt, or N instructions
+where|bt            Show the source location of PC and of the calls leading to it
+regs|r              Show A, D, M and PC
+print|p CELL [N]    Show N RAM cells from CELL (default 1)
+set CELL VALUE      Write VALUE to CELL
+disas|x [LOC] [N]   Disassemble N instructions from LOC (default PC and 10)
+info|i              List breakpoints and watchpoints
+reset               Restart the program with cleared RAM
+help|h              Show this help
+quit|q              Exit
+LOC is a ROM address, a label or a source FILE:LINE, CELL a RAM address or a symbol such as SP, R13 or a variable.";
+
+/// Debugger of a Hack program on the CPU emulator, driven by text commands.
+pub struct Debugger {
+    cpu: Cpu,
+    program: Vec<u16>,
+    symbols: Symbols,
+    // First label of each labelled ROM address, and variable of each RAM address.
+    labels: BTreeMap<u16, String>,
+    variables: BTreeMap<u16, String>,
+    // Resolved source of each ROM address.
+    sources: SourceMap,
+    breakpoints: BTreeSet<u16>,
+    // Watched RAM addresses with the values they had after the last instruction.
+    watchpoints: BTreeMap<u16, u16>,
+    cycles: u64,
+}
+
+impl Debugger {
+    /// Debugger of `program`, whose labels and variables `symbols` names.
+    pub fn new(program: &[u16], symbols: Symbols) -> Self {
+        let mut labels = BTreeMap::new();
+        for (label, &addr) in &symbols.labels {
+            labels.entry(addr).or_insert_with(|| label.clone());
+        }
+        let variables = symbols
+            .variables
+            .iter()
+            .map(|(name, &addr)| (addr, name.clone()))
+            .collect();
+        Self {
+            cpu: Cpu::new(program),
+            program: program.to_vec(),
+            symbols,
+            labels,
+            variables,
+            sources: SourceMap::default(),
+            breakpoints: BTreeSet::new(),
+            watchpoints: BTreeMap::new(),
+            cycles: 0,
+        }
+    }
+
+    /// Uses `sources`, resolved like `rom_source_map` does, to show source locations and
+    /// find the ROM address of `FILE:LINE`.
+    pub fn set_source_map(&mut self, sources: SourceMap) {
+        self.sources = sources;
+    }
+
+    pub fn cpu(&self) -> &Cpu {
+        &self.cpu
+    }
+
+    /// Instructions executed since the start.
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// Runs a command, returning what it prints.
+    pub fn command(&mut self, line: &str) -> Result<String, String> {
+        let words: Vec<_> = line.split_whitespace().collect();
+        let (name, args) = match words.split_first() {
+            Some((name, args)) => (*name, args),
+            None => return Ok(String::new()),
+        };
+        match (name, args) {
+            ("break" | "b", [loc]) => {
+                let addr = self.rom_address(loc)?;
+                self.breakpoints.insert(addr);
+                Ok(format! {"Breakpoint at {}", self.rom_name(addr)})
+            }
+            ("delete" | "d", [loc]) => {
+                let addr = self.rom_address(loc)?;
+                if self.breakpoints.remove(&addr) {
+                    Ok(format! {"Deleted breakpoint at {}", self.rom_name(addr)})
+                } else {
+                    Err(format! {"Error: No breakpoint at {}", self.rom_name(addr)})
+                }
+            }
+            ("watch" | "w", [cell]) => {
+                let addr = self.ram_address(cell)?;
+                self.watchpoints.insert(addr, self.cpu.ram[addr as usize]);
+                Ok(format! {"Watchpoint on {}", self.ram_name(addr)})
+            }
+            ("unwatch", [cell]) => {
+                let addr = self.ram_address(cell)?;
+                match self.watchpoints.remove(&addr) {
+                    Some(_) => Ok(format! {"Deleted watchpoint on {}", self.ram_name(addr)}),
+                    None => Err(format! {"Error: No watchpoint on {}", self.ram_name(addr)}),
+                }
+            }
+            ("step" | "s", []) => Ok(self.run(Some(1))),
+            ("step" | "s", [n]) => Ok(self.run(Some(count(n)?))),
+            ("continue" | "c", []) => Ok(self.run(None)),
+            ("continue" | "c", [n]) => Ok(self.run(Some(count(n)?))),
+            ("where" | "bt", []) => Ok(self.backtrace()),
+            ("regs" | "r", []) => Ok(self.regs()),
+            ("print" | "p", [cell]) => self.print(cell, "1"),
+            ("print" | "p", [cell, n]) => self.print(cell, n),
+            ("set", [cell, value]) => {
+                let addr = self.ram_address(cell)?;
+                let value = value
+                    .parse::<i32>()
+                    .ok()
+                    .filter(|value| (-32768..=65535).contains(value))
+                    .ok_or_else(|| format! {"Error: Invalid value {}", value})?;
+                self.cpu.ram[addr as usize] = value as u16;
+                self.watchpoints
+                    .entry(addr)
+                    .and_modify(|last| *last = value as u16);
+                Ok(self.cell(addr))
+            }
+            ("disas" | "x", []) => Ok(self.disassemble(self.cpu.pc, 10)),
+            ("disas" | "x", [loc]) => Ok(self.disassemble(self.rom_address(loc)?, 10)),
+            ("disas" | "x", [loc, n]) => Ok(self.disassemble(self.rom_address(loc)?, count(n)?)),
+            ("info" | "i", []) => Ok(self.info()),
+            ("reset", []) => {
+                self.cpu = Cpu::new(&self.program);
+                self.cycles = 0;
+                for (&addr, last) in &mut self.watchpoints {
+                    *last = self.cpu.ram[addr as usize];
+                }
+                Ok(self.disassemble(self.cpu.pc, 1))
+            }
+            ("help" | "h", []) => Ok(HELP.to_string()),
+            _ => Err(format! {"Error: Invalid command: {} (try help)", line.trim()}),
+        }
+    }
+
+    // Executes up to `limit` instructions, stopping early at a breakpoint, a changed
+    // watchpoint or a halt loop, and reports why it stopped and the next instruction.
+    fn run(&mut self, limit: Option<u64>) -> String {
+        let mut out = String::new();
+        let mut executed = 0;
+        while limit.is_none_or(|limit| executed < limit) {
+            self.cpu.step();
+            self.cycles += 1;
+            executed += 1;
+            let mut changed = false;
+            for (&addr, last) in &mut self.watchpoints {
+                let value = self.cpu.ram[addr as usize];
+                if value != *last {
+                    changed = true;
+                    let name = ram_name(&self.variables, addr);
+                    writeln!(
+                        out,
+                        "Watchpoint {}: {} -> {}",
+                        name, *last as i16, value as i16
+                    )
+                    .unwrap();
+                    *last = value;
+                }
+            }
+            if changed {
+                break;
+            }
+            if self.breakpoints.contains(&self.cpu.pc) {
+                writeln!(out, "Breakpoint at {}", self.rom_name(self.cpu.pc)).unwrap();
+                break;
+            }
+            if self.cpu.is_halted() {
+                writeln!(out, "Halted after {} cycles", self.cycles).unwrap();
+                break;
+            }
+        }
+        if let Some(loc) = self.sources.get(self.cpu.pc as usize) {
+            writeln!(out, "at {}", loc).unwrap();
+        }
+        out.push_str(&self.disassemble(self.cpu.pc, 1));
+        out
+    }
+
+    fn backtrace(&self) -> String {
+        if self.sources.is_empty() {
+            return "No source map".to_string();
+        }
+        let frames: Vec<_> = self
+            .cpu
+            .backtrace()
+            .into_iter()
+            .enumerate()
+            .map(|(i, addr)| match self.sources.get(addr as usize) {
+                Some(loc) => format! {"#{} {} in {}", i, self.rom_name(addr), loc},
+                None => format! {"#{} {}", i, self.rom_name(addr)},
+            })
+            .collect();
+        frames.join("\n")
+    }
+
+    fn regs(&self) -> String {
+        let a = self.cpu.a;
+        let m = self.cpu.ram[a as usize % MEMORY_SIZE];
+        format! {"A  = {} ({})\nD  = {}\nM  = {}\nPC = {}\ncycles = {}",
+        a as i16, self.ram_name(a % MEMORY_SIZE as u16), self.cpu.d as i16, m as i16,
+        self.rom_name(self.cpu.pc), self.cycles}
+    }
+
+    fn print(&self, cell: &str, n: &str) -> Result<String, String> {
+        let addr = self.ram_address(cell)? as usize;
+        let end = (addr + count(n)? as usize).min(MEMORY_SIZE);
+        let cells: Vec<_> = (addr..end).map(|addr| self.cell(addr as u16)).collect();
+        Ok(cells.join("\n"))
+    }
+
+    fn cell(&self, addr: u16) -> String {
+        format! {"{} = {}", self.ram_name(addr), self.cpu.ram[addr as usize] as i16}
+    }
+
+    fn info(&self) -> String {
+        let mut out = String::new();
+        for &addr in &self.breakpoints {
+            writeln!(out, "Breakpoint at {}", self.rom_name(addr)).unwrap();
+        }
+        for (&addr, &value) in &self.watchpoints {
+            writeln!(
+                out,
+                "Watchpoint on {} = {}",
+                self.ram_name(addr),
+                value as i16
+            )
+            .unwrap();
+        }
+        if out.is_empty() {
+            out.push_str("No breakpoints or watchpoints\n");
+        }
+        out.pop();
+        out
+    }
+
+    // Lines of `n` instructions from `start`, under the labels of their addresses. The
+    // next instruction is marked with `=>` and breakpoints with `*`.
+    fn disassemble(&self, start: u16, n: u64) -> String {
+        let mut lines = Vec::new();
+        let end = (start as u64 + n).min(MEMORY_SIZE as u64) as u16;
+        for addr in start..end {
+            for (label, &label_addr) in &self.symbols.labels {
+                if label_addr == addr {
+                    lines.push(format! {"{}:", label});
+                }
+            }
+            let word = self.cpu.rom[addr as usize];
+            let marker = if addr == self.cpu.pc { "=>" } else { "  " };
+            let breakpoint = if self.breakpoints.contains(&addr) {
+                '*'
+            } else {
+                ' '
+            };
+            let text = match decode(word) {
+                Some(op) => op.to_string(),
+                None => format! {"{:016b}", word},
+            };
+            // Name an address by the label it jumps to, or the RAM cell it selects.
+            let comment = match decode(word) {
+                Some(Op::Addr(_)) => {
+                    let next = self.cpu.rom.get(addr as usize + 1).copied().unwrap_or(0);
+                    if next >> 13 == 0b111 && next & 0b111 != 0 {
+                        self.labels.get(&word).cloned()
+                    } else {
+                        ram_symbol(&self.variables, word)
+                    }
+                }
+                _ => None,
+            };
+            let line = match comment {
+                Some(name) => {
+                    format! {"{}{}{:5}  {:<12}// {}", marker, breakpoint, addr, text, name}
+                }
+                None => format! {"{}{}{:5}  {}", marker, breakpoint, addr, text},
+            };
+            lines.push(line);
+        }
+        lines.join("\n")
+    }
+
+    fn rom_address(&self, loc: &str) -> Result<u16, String> {
+        loc.parse::<u16>()
+            .ok()
+            .filter(|&addr| (addr as usize) < MEMORY_SIZE)
+            .or_else(|| self.symbols.labels.get(loc).copied())
+            .or_else(|| self.source_address(loc))
+            .ok_or_else(|| format! {"Error: Unknown ROM address or label {}", loc})
+    }
+
+    fn ram_address(&self, cell: &str) -> Result<u16, String> {
+        cell.parse::<u16>()
+            .ok()
+            .filter(|&addr| (addr as usize) < MEMORY_SIZE)
+            .or_else(|| {
+                PREDEFINED_SYMBOLS
+                    .iter()
+                    .find(|(name, _)| *name == cell)
+                    .map(|&(_, addr)| addr)
+            })
+            .or_else(|| self.symbols.variables.get(cell).copied())
+            .ok_or_else(|| format! {"Error: Unknown RAM address or symbol {}", cell})
+    }
+
+    // First ROM address of the source line `FILE:LINE`, where `FILE` may leave out
+    // leading directories.
+    fn source_address(&self, loc: &str) -> Option<u16> {
+        let (file, line) = loc.rsplit_once(':')?;
+        let line = line.parse::<usize>().ok()?;
+        (0..self.sources.len()).find_map(|addr| {
+            let source = self.sources.get(addr)?;
+            let path = source.file.strip_suffix(file)?;
+            let matches = source.line == line && (path.is_empty() || path.ends_with('/'));
+            Some(addr as u16).filter(|_| matches)
+        })
+    }
+
+    // `addr`, with the label it's at or follows.
+    fn rom_name(&self, addr: u16) -> String {
+        match self.labels.range(..=addr).next_back() {
+            Some((&label_addr, label)) if label_addr == addr => format! {"{} ({})", addr, label},
+            Some((&label_addr, label)) => format! {"{} ({}+{})", addr, label, addr - label_addr},
+            None => addr.to_string(),
+        }
+    }
+
+    fn ram_name(&self, addr: u16) -> String {
+        ram_name(&self.variables, addr)
+    }
+}
+
+fn count(n: &str) -> Result<u64, String> {
+    n.parse()
+        .ok()
+        .filter(|&n| n > 0)
+        .ok_or_else(|| format! {"Error: Invalid count {}", n})
+}
+
+// Symbol of RAM address `addr`: its predefined symbols, such as `SP/R0`, or a variable.
+fn ram_symbol(variables: &BTreeMap<u16, String>, addr: u16) -> Option<String> {
+    let names: Vec<_> = PREDEFINED_SYMBOLS
+        .iter()
+        .filter(|&&(_, predefined)| predefined == addr)
+        .map(|&(name, _)| name)
+        .collect();
+    if names.is_empty() {
+        variables.get(&addr).cloned()
+    } else {
+        Some(names.join("/"))
+    }
+}
+
+fn ram_name(variables: &BTreeMap<u16, String>, addr: u16) -> String {
+    match ram_symbol(variables, addr) {
+        Some(name) => format! {"RAM[{}] ({})", addr, name},
+        None => format! {"RAM[{}]", addr},
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emu::assemble_with_symbols;
+
+    #[test]
+    fn test_debugger() {
+        let asm = "@sum
+M=0
+@i
+M=1
+(LOOP)
+@i
+D=M
+@R0
+D=D-M
+@END
+D;JGT
+@i
+D=M
+@sum
+M=D+M
+@i
+M=M+1
+@LOOP
+0;JMP
+(END)
+@END
+0;JMP
+";
+        let (program, symbols) = assemble_with_symbols(asm.as_bytes()).unwrap();
+        let mut dbg = Debugger::new(&program, symbols);
+        let mut run = |line: &str| dbg.command(line).unwrap();
+        assert_eq!(run("set R0 4"), "RAM[0] (SP/R0) = 4");
+        assert_eq!(run("break LOOP"), "Breakpoint at 4 (LOOP)");
+        assert_eq!(
+            run("c"),
+            "Breakpoint at 4 (LOOP)\nLOOP:\n=>*    4  @17         // i"
+        );
+        assert_eq!(run("watch sum"), "Watchpoint on RAM[16] (sum)");
+        assert_eq!(run("d LOOP"), "Deleted breakpoint at 4 (LOOP)");
+        assert_eq!(
+            run("c"),
+            "Watchpoint RAM[16] (sum): 0 -> 1\n=>    14  @17         // i"
+        );
+        assert_eq!(run("s 2"), "=>    16  @4          // LOOP");
+        assert_eq!(run("unwatch sum"), "Deleted watchpoint on RAM[16] (sum)");
+        assert!(run("c").starts_with("Halted after"));
+        assert_eq!(run("p sum 2"), "RAM[16] (sum) = 10\nRAM[17] (i) = 5");
+        assert_eq!(run("r").lines().nth(3), Some("PC = 18 (END)"));
+        assert!(dbg.command("break NOWHERE").is_err());
+    }
+}
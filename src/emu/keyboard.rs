@@ -0,0 +1,183 @@
+use std::io::BufRead;
+
+/// A key held down from cycle `press` until cycle `release`, counted in instructions on
+/// the CPU emulator or commands on the VM emulator.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KeyEvent {
+    pub key: u16,
+    pub press: u64,
+    pub release: u64,
+}
+
+/// Contents of the keyboard register over time, following key events.
+pub struct KeyScript {
+    // Cycles when the register changes, in order, with its new value.
+    changes: Vec<(u64, u16)>,
+    next: usize,
+    key: u16,
+}
+
+impl KeyScript {
+    /// While several keys are held, the register holds the one pressed last.
+    pub fn new(events: &[KeyEvent]) -> Self {
+        let mut times: Vec<_> = events
+            .iter()
+            .flat_map(|event| vec![event.press, event.release])
+            .collect();
+        times.sort_unstable();
+        times.dedup();
+        let mut changes: Vec<(u64, u16)> = Vec::new();
+        for time in times {
+            let key = events
+                .iter()
+                .filter(|event| event.press <= time && time < event.release)
+                .max_by_key(|event| event.press)
+                .map_or(0, |event| event.key);
+            if changes.last().map_or(0, |&(_, last)| last) != key {
+                changes.push((time, key));
+            }
+        }
+        Self {
+            changes,
+            next: 0,
+            key: 0,
+        }
+    }
+
+    /// Reads a keyboard script of `KEY PRESS RELEASE` lines with `//` comments, where
+    /// `KEY` is a character, a key name such as `newline` or `f1`, or a key code of
+    /// several digits.
+    pub fn parse(strm: impl BufRead) -> Result<Self, String> {
+        let mut events = Vec::new();
+        for (line_num, line) in strm.lines().enumerate() {
+            let error = |message: &str| format! {"Error: line {}; {}", line_num + 1, message};
+            let line = line.map_err(|e| error(&format! {"{:?}", e}))?;
+            let line = line.split("//").next().unwrap_or_default();
+            let words: Vec<_> = line.split_whitespace().collect();
+            if words.is_empty() {
+                continue;
+            }
+            let [key, press, release] = words[..] else {
+                return Err(error("Expected KEY PRESS RELEASE"));
+            };
+            let key = key_code(key).ok_or_else(|| error(&format! {"Invalid key {}", key}))?;
+            let cycle = |word: &str| {
+                word.parse::<u64>()
+                    .map_err(|_| error(&format! {"Invalid cycle {}", word}))
+            };
+            let (press, release) = (cycle(press)?, cycle(release)?);
+            if release <= press {
+                return Err(error("Release must come after press"));
+            }
+            events.push(KeyEvent {
+                key,
+                press,
+                release,
+            });
+        }
+        Ok(Self::new(&events))
+    }
+
+    /// Key code in the keyboard register at `cycle`, which must not decrease between
+    /// calls.
+    pub fn key(&mut self, cycle: u64) -> u16 {
+        while let Some(&(time, key)) = self.changes.get(self.next) {
+            if time > cycle {
+                break;
+            }
+            self.key = key;
+            self.next += 1;
+        }
+        self.key
+    }
+}
+
+// Hack character set codes of the keys that aren't printable characters.
+const KEY_NAMES: &[(&str, u16)] = &[
+    ("space", 32),
+    ("newline", 128),
+    ("enter", 128),
+    ("backspace", 129),
+    ("left", 130),
+    ("up", 131),
+    ("right", 132),
+    ("down", 133),
+    ("home", 134),
+    ("end", 135),
+    ("pageup", 136),
+    ("pagedown", 137),
+    ("insert", 138),
+    ("delete", 139),
+    ("esc", 140),
+];
+
+fn key_code(word: &str) -> Option<u16> {
+    let lower = word.to_lowercase();
+    if let Some(&(_, code)) = KEY_NAMES.iter().find(|(name, _)| *name == lower) {
+        return Some(code);
+    }
+    if let Some(n) = lower.strip_prefix('f').and_then(|n| n.parse::<u16>().ok()) {
+        return Some(n).filter(|n| (1..=12).contains(n)).map(|n| 140 + n);
+    }
+    let mut chars = word.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if (' '..='~').contains(&c) => Some(c as u16),
+        _ => word.parse().ok(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emu::{Vm, KBD, SP};
+    use crate::vm::{parse_vm, VmFile};
+
+    #[test]
+    fn test_key_script() {
+        let script = "// key press release
+a 10 20
+b 15 30
+newline 40 50
+f12 50 51
+";
+        let mut keys = KeyScript::parse(script.as_bytes()).unwrap();
+        let timeline: Vec<_> = [0, 10, 14, 15, 25, 30, 40, 50, 51]
+            .iter()
+            .map(|&cycle| keys.key(cycle))
+            .collect();
+        assert_eq!(timeline, [0, 97, 97, 98, 98, 0, 128, 152, 0]);
+        assert!(KeyScript::parse("a 20 10".as_bytes()).is_err());
+        assert!(KeyScript::parse("f13 1 2".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_read_int() {
+        let main = "function Main.main 0
+push constant 0
+call String.new 1
+call Keyboard.readInt 1
+pop static 0
+push constant 0
+return
+";
+        let main = VmFile {
+            name: "Main".to_string(),
+            commands: parse_vm(main.as_bytes()).unwrap(),
+        };
+        let script = "4 1000 2000
+2 3000 4000
+newline 5000 6000
+";
+        let mut keys = KeyScript::parse(script.as_bytes()).unwrap();
+        let mut vm = Vm::with_os(vec![main]).unwrap();
+        vm.ram[SP] = 256;
+        let mut cycle = 0;
+        while !vm.is_halted() {
+            vm.ram[KBD] = keys.key(cycle);
+            vm.step().unwrap();
+            cycle += 1;
+        }
+        assert!(cycle > 5000);
+        assert_eq!(vm.ram[16], 42);
+    }
+}
@@ -0,0 +1,871 @@
+use std::collections::HashSet;
+
+use super::cpu::MEMORY_SIZE;
+use super::vm::{Function, Vm};
+use crate::os::{os_class, os_dependencies};
+use crate::vm::{parse_vm, VmFile};
+
+/// Rust implementation of an OS function, taking its arguments and returning its
+/// value, or `None` to run its VM code instead.
+pub(super) type Native = fn(&mut Vm, &[i16]) -> Option<i16>;
+
+/// Native implementations, with their number of arguments, of the functions of
+/// `functions` that belong to a class of `bundled` whose code only calls classes of
+/// `bundled` too, so a user-supplied class replaces the natives that would call it.
+pub(super) fn natives(
+    functions: &[Function],
+    bundled: &HashSet<String>,
+) -> Vec<Option<(u16, Native)>> {
+    let enabled: HashSet<&str> = bundled
+        .iter()
+        .filter(|class| {
+            let commands = os_class(class)
+                .and_then(|vm| parse_vm(vm.as_bytes()).ok())
+                .unwrap_or_default();
+            let file = VmFile {
+                name: class.to_string(),
+                commands,
+            };
+            os_dependencies(&[file])
+                .iter()
+                .all(|dependency| bundled.contains(&dependency.name))
+        })
+        .map(String::as_str)
+        .collect();
+    functions
+        .iter()
+        .map(|function| {
+            let class = function.name.split('.').next().unwrap_or_default();
+            NATIVES
+                .iter()
+                .find(|(name, _, _)| *name == function.name)
+                .filter(|_| function.start.is_some() && enabled.contains(class))
+                .map(|&(_, n_args, native)| (n_args, native))
+        })
+        .collect()
+}
+
+// Ports of the Jack code of `src/project12`, down to its overflows and its bugs, using
+// the same static variables, heap blocks and screen memory, so the RAM ends up as the
+// VM code leaves it but for the stack below `SP` and the `temp` segment. Functions that
+// wait for a key or never return (`Sys.init`, `Sys.halt`, `Sys.error`, and the `read`
+// functions of `Keyboard`) always run their VM code. A native gives up where the Jack
+// code would never return or calls `Sys.error`, letting the VM code do so.
+const NATIVES: &[(&str, u16, Native)] = &[
+    ("Array.new", 1, |vm, a| alloc(vm, a[0])),
+    ("Array.dispose", 1, |vm, a| dealloc(vm, a[0])),
+    ("Keyboard.init", 0, |vm, _| set(vm, "Keyboard", 0, 24576)),
+    ("Keyboard.keyPressed", 0, |vm, _| {
+        Some(read(vm, get(vm, "Keyboard", 0)))
+    }),
+    ("Math.init", 0, math_init),
+    ("Math.abs", 1, |_, a| Some(abs(a[0]))),
+    ("Math.multiply", 2, |vm, a| Some(multiply(vm, a[0], a[1]))),
+    ("Math.divide", 2, |vm, a| divide(vm, a[0], a[1])),
+    ("Math.sqrt", 1, |vm, a| {
+        Some(a[0]).filter(|&x| x >= 0).map(|x| sqrt(vm, x))
+    }),
+    ("Math.max", 2, |_, a| {
+        Some(if a[0] > a[1] { a[0] } else { a[1] })
+    }),
+    ("Math.min", 2, |_, a| {
+        Some(if a[0] < a[1] { a[0] } else { a[1] })
+    }),
+    ("Math.bit", 2, |vm, a| Some(bool_value(bit(vm, a[0], a[1])))),
+    ("Memory.init", 0, memory_init),
+    ("Memory.peek", 1, |vm, a| {
+        Some(read(vm, get(vm, "Memory", 0).wrapping_add(a[0])))
+    }),
+    ("Memory.poke", 2, |vm, a| {
+        write(vm, get(vm, "Memory", 0).wrapping_add(a[0]), a[1]);
+        Some(0)
+    }),
+    ("Memory.alloc", 1, |vm, a| alloc(vm, a[0])),
+    ("Memory.deAlloc", 1, |vm, a| dealloc(vm, a[0])),
+    ("Output.init", 0, output_init),
+    ("Output.initMap", 0, output_init_map),
+    ("Output.create", 12, |vm, a| create_char(vm, a[0], &a[1..])),
+    ("Output.getMap", 1, |vm, a| Some(char_map(vm, a[0]))),
+    ("Output.moveCursor", 2, |vm, a| {
+        if !(0..=22).contains(&a[0]) || !(0..=63).contains(&a[1]) {
+            return None;
+        }
+        set(vm, "Output", 1, a[0]);
+        set(vm, "Output", 2, a[1])
+    }),
+    ("Output.printChar", 1, |vm, a| print_char(vm, a[0])),
+    ("Output.printString", 1, |vm, a| print_string(vm, a[0])),
+    ("Output.printInt", 1, |vm, a| {
+        let s = string_new(vm, 6)?;
+        set_int(vm, s, a[0])?;
+        print_string(vm, s)
+    }),
+    ("Output.println", 0, |vm, _| Some(println(vm))),
+    ("Output.backSpace", 0, output_back_space),
+    ("Screen.init", 0, screen_init),
+    ("Screen.clearScreen", 0, |vm, _| {
+        let screen = get(vm, "Screen", 1);
+        for i in 0..8192 {
+            write(vm, screen.wrapping_add(i), 0);
+        }
+        Some(0)
+    }),
+    ("Screen.setColor", 1, |vm, a| set(vm, "Screen", 0, a[0])),
+    ("Screen.drawPixel", 2, |vm, a| draw_pixel(vm, a[0], a[1])),
+    ("Screen.drawLine", 4, |vm, a| {
+        draw_line(vm, a[0], a[1], a[2], a[3])
+    }),
+    ("Screen.drawVertical", 3, |vm, a| {
+        draw_vertical(vm, a[0], a[1], a[2])
+    }),
+    ("Screen.drawHorizontal", 3, |vm, a| {
+        draw_horizontal(vm, a[0], a[1], a[2])
+    }),
+    ("Screen.drawRectangle", 4, |vm, a| {
+        let (mut x1, y1, x2, y2) = (a[0], a[1], a[2], a[3]);
+        if x1 > x2 || y1 > y2 || !on_screen(x1, y1) || !on_screen(x2, y2) {
+            return None;
+        }
+        while x1 <= x2 {
+            draw_line(vm, x1, y1, x1, y2)?;
+            x1 = x1.wrapping_add(1);
+        }
+        Some(0)
+    }),
+    ("Screen.drawCircle", 3, |vm, a| {
+        let (x, y, r) = (a[0], a[1], a[2]);
+        if !on_screen(x, y)
+            || !(0..=181).contains(&r)
+            || !on_screen(x - r, y - r)
+            || !on_screen(x + r, y + r)
+        {
+            return None;
+        }
+        let mut dy = r.wrapping_neg().wrapping_add(1);
+        while dy < r {
+            let len = multiply(vm, r, r).wrapping_sub(multiply(vm, dy, dy));
+            let len = sqrt(vm, len);
+            draw_horizontal(
+                vm,
+                y.wrapping_add(dy),
+                x.wrapping_sub(len),
+                x.wrapping_add(len),
+            )?;
+            dy = dy.wrapping_add(1);
+        }
+        Some(0)
+    }),
+    ("String.new", 1, |vm, a| string_new(vm, a[0])),
+    ("String.dispose", 1, |vm, a| {
+        dealloc(vm, read(vm, a[0].wrapping_add(2)))?;
+        dealloc(vm, a[0])
+    }),
+    ("String.length", 1, |vm, a| Some(read(vm, a[0]))),
+    ("String.charAt", 2, |vm, a| {
+        Some(a[1])
+            .filter(|j| (0..read(vm, a[0])).contains(j))
+            .map(|j| char_at(vm, a[0], j))
+    }),
+    ("String.setCharAt", 3, |vm, a| {
+        if !(0..read(vm, a[0])).contains(&a[1]) {
+            return None;
+        }
+        let str = read(vm, a[0].wrapping_add(2));
+        write(vm, str.wrapping_add(a[1]), a[2]);
+        Some(0)
+    }),
+    ("String.appendChar", 2, |vm, a| append_char(vm, a[0], a[1])),
+    ("String.eraseLastChar", 1, |vm, a| {
+        let length = read(vm, a[0]);
+        if length == 0 {
+            return None;
+        }
+        write(vm, a[0], length - 1);
+        Some(0)
+    }),
+    ("String.intValue", 1, |vm, a| Some(int_value(vm, a[0]))),
+    ("String.setInt", 2, |vm, a| set_int(vm, a[0], a[1])),
+    ("String.setIntImpl", 2, |vm, a| set_int_impl(vm, a[0], a[1])),
+    ("String.clear", 1, |vm, a| {
+        write(vm, a[0], 0);
+        Some(0)
+    }),
+    ("String.newLine", 0, |_, _| Some(128)),
+    ("String.backSpace", 0, |_, _| Some(129)),
+    ("String.doubleQuote", 0, |_, _| Some(34)),
+    // Busy-waits without touching the RAM.
+    ("Sys.wait", 1, |_, a| Some(0).filter(|_| a[0] >= 0)),
+];
+
+// Characters and bitmaps passed to `Output.create`, in the order `Output.initMap`
+// creates them.
+const FONT: &[(i16, [i16; 11])] = &[
+    (0, [63, 63, 63, 63, 63, 63, 63, 63, 63, 0, 0]),
+    (32, [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]),
+    (33, [12, 30, 30, 30, 12, 12, 0, 12, 12, 0, 0]),
+    (34, [54, 54, 20, 0, 0, 0, 0, 0, 0, 0, 0]),
+    (35, [0, 18, 18, 63, 18, 18, 63, 18, 18, 0, 0]),
+    (36, [12, 30, 51, 3, 30, 48, 51, 30, 12, 12, 0]),
+    (37, [0, 0, 35, 51, 24, 12, 6, 51, 49, 0, 0]),
+    (38, [12, 30, 30, 12, 54, 27, 27, 27, 54, 0, 0]),
+    (39, [12, 12, 6, 0, 0, 0, 0, 0, 0, 0, 0]),
+    (40, [24, 12, 6, 6, 6, 6, 6, 12, 24, 0, 0]),
+    (41, [6, 12, 24, 24, 24, 24, 24, 12, 6, 0, 0]),
+    (42, [0, 0, 0, 51, 30, 63, 30, 51, 0, 0, 0]),
+    (43, [0, 0, 0, 12, 12, 63, 12, 12, 0, 0, 0]),
+    (44, [0, 0, 0, 0, 0, 0, 0, 12, 12, 6, 0]),
+    (45, [0, 0, 0, 0, 0, 63, 0, 0, 0, 0, 0]),
+    (46, [0, 0, 0, 0, 0, 0, 0, 12, 12, 0, 0]),
+    (47, [0, 0, 32, 48, 24, 12, 6, 3, 1, 0, 0]),
+    (48, [12, 30, 51, 51, 51, 51, 51, 30, 12, 0, 0]),
+    (49, [12, 14, 15, 12, 12, 12, 12, 12, 63, 0, 0]),
+    (50, [30, 51, 48, 24, 12, 6, 3, 51, 63, 0, 0]),
+    (51, [30, 51, 48, 48, 28, 48, 48, 51, 30, 0, 0]),
+    (52, [16, 24, 28, 26, 25, 63, 24, 24, 60, 0, 0]),
+    (53, [63, 3, 3, 31, 48, 48, 48, 51, 30, 0, 0]),
+    (54, [28, 6, 3, 3, 31, 51, 51, 51, 30, 0, 0]),
+    (55, [63, 49, 48, 48, 24, 12, 12, 12, 12, 0, 0]),
+    (56, [30, 51, 51, 51, 30, 51, 51, 51, 30, 0, 0]),
+    (57, [30, 51, 51, 51, 62, 48, 48, 24, 14, 0, 0]),
+    (58, [0, 0, 12, 12, 0, 0, 12, 12, 0, 0, 0]),
+    (59, [0, 0, 12, 12, 0, 0, 12, 12, 6, 0, 0]),
+    (60, [0, 0, 24, 12, 6, 3, 6, 12, 24, 0, 0]),
+    (61, [0, 0, 0, 63, 0, 0, 63, 0, 0, 0, 0]),
+    (62, [0, 0, 3, 6, 12, 24, 12, 6, 3, 0, 0]),
+    (64, [30, 51, 51, 59, 59, 59, 27, 3, 30, 0, 0]),
+    (63, [30, 51, 51, 24, 12, 12, 0, 12, 12, 0, 0]),
+    (65, [12, 30, 51, 51, 63, 51, 51, 51, 51, 0, 0]),
+    (66, [31, 51, 51, 51, 31, 51, 51, 51, 31, 0, 0]),
+    (67, [28, 54, 35, 3, 3, 3, 35, 54, 28, 0, 0]),
+    (68, [15, 27, 51, 51, 51, 51, 51, 27, 15, 0, 0]),
+    (69, [63, 51, 35, 11, 15, 11, 35, 51, 63, 0, 0]),
+    (70, [63, 51, 35, 11, 15, 11, 3, 3, 3, 0, 0]),
+    (71, [28, 54, 35, 3, 59, 51, 51, 54, 44, 0, 0]),
+    (72, [51, 51, 51, 51, 63, 51, 51, 51, 51, 0, 0]),
+    (73, [30, 12, 12, 12, 12, 12, 12, 12, 30, 0, 0]),
+    (74, [60, 24, 24, 24, 24, 24, 27, 27, 14, 0, 0]),
+    (75, [51, 51, 51, 27, 15, 27, 51, 51, 51, 0, 0]),
+    (76, [3, 3, 3, 3, 3, 3, 35, 51, 63, 0, 0]),
+    (77, [33, 51, 63, 63, 51, 51, 51, 51, 51, 0, 0]),
+    (78, [51, 51, 55, 55, 63, 59, 59, 51, 51, 0, 0]),
+    (79, [30, 51, 51, 51, 51, 51, 51, 51, 30, 0, 0]),
+    (80, [31, 51, 51, 51, 31, 3, 3, 3, 3, 0, 0]),
+    (81, [30, 51, 51, 51, 51, 51, 63, 59, 30, 48, 0]),
+    (82, [31, 51, 51, 51, 31, 27, 51, 51, 51, 0, 0]),
+    (83, [30, 51, 51, 6, 28, 48, 51, 51, 30, 0, 0]),
+    (84, [63, 63, 45, 12, 12, 12, 12, 12, 30, 0, 0]),
+    (85, [51, 51, 51, 51, 51, 51, 51, 51, 30, 0, 0]),
+    (86, [51, 51, 51, 51, 51, 30, 30, 12, 12, 0, 0]),
+    (87, [51, 51, 51, 51, 51, 63, 63, 63, 18, 0, 0]),
+    (88, [51, 51, 30, 30, 12, 30, 30, 51, 51, 0, 0]),
+    (89, [51, 51, 51, 51, 30, 12, 12, 12, 30, 0, 0]),
+    (90, [63, 51, 49, 24, 12, 6, 35, 51, 63, 0, 0]),
+    (91, [30, 6, 6, 6, 6, 6, 6, 6, 30, 0, 0]),
+    (92, [0, 0, 1, 3, 6, 12, 24, 48, 32, 0, 0]),
+    (93, [30, 24, 24, 24, 24, 24, 24, 24, 30, 0, 0]),
+    (94, [8, 28, 54, 0, 0, 0, 0, 0, 0, 0, 0]),
+    (95, [0, 0, 0, 0, 0, 0, 0, 0, 0, 63, 0]),
+    (96, [6, 12, 24, 0, 0, 0, 0, 0, 0, 0, 0]),
+    (97, [0, 0, 0, 14, 24, 30, 27, 27, 54, 0, 0]),
+    (98, [3, 3, 3, 15, 27, 51, 51, 51, 30, 0, 0]),
+    (99, [0, 0, 0, 30, 51, 3, 3, 51, 30, 0, 0]),
+    (100, [48, 48, 48, 60, 54, 51, 51, 51, 30, 0, 0]),
+    (101, [0, 0, 0, 30, 51, 63, 3, 51, 30, 0, 0]),
+    (102, [28, 54, 38, 6, 15, 6, 6, 6, 15, 0, 0]),
+    (103, [0, 0, 30, 51, 51, 51, 62, 48, 51, 30, 0]),
+    (104, [3, 3, 3, 27, 55, 51, 51, 51, 51, 0, 0]),
+    (105, [12, 12, 0, 14, 12, 12, 12, 12, 30, 0, 0]),
+    (106, [48, 48, 0, 56, 48, 48, 48, 48, 51, 30, 0]),
+    (107, [3, 3, 3, 51, 27, 15, 15, 27, 51, 0, 0]),
+    (108, [14, 12, 12, 12, 12, 12, 12, 12, 30, 0, 0]),
+    (109, [0, 0, 0, 29, 63, 43, 43, 43, 43, 0, 0]),
+    (110, [0, 0, 0, 29, 51, 51, 51, 51, 51, 0, 0]),
+    (111, [0, 0, 0, 30, 51, 51, 51, 51, 30, 0, 0]),
+    (112, [0, 0, 0, 30, 51, 51, 51, 31, 3, 3, 0]),
+    (113, [0, 0, 0, 30, 51, 51, 51, 62, 48, 48, 0]),
+    (114, [0, 0, 0, 29, 55, 51, 3, 3, 7, 0, 0]),
+    (115, [0, 0, 0, 30, 51, 6, 24, 51, 30, 0, 0]),
+    (116, [4, 6, 6, 15, 6, 6, 6, 54, 28, 0, 0]),
+    (117, [0, 0, 0, 27, 27, 27, 27, 27, 54, 0, 0]),
+    (118, [0, 0, 0, 51, 51, 51, 51, 30, 12, 0, 0]),
+    (119, [0, 0, 0, 51, 51, 51, 63, 63, 18, 0, 0]),
+    (120, [0, 0, 0, 51, 30, 12, 12, 30, 51, 0, 0]),
+    (121, [0, 0, 0, 51, 51, 51, 62, 48, 24, 15, 0]),
+    (122, [0, 0, 0, 63, 27, 12, 6, 51, 63, 0, 0]),
+    (123, [56, 12, 12, 12, 7, 12, 12, 12, 56, 0, 0]),
+    (124, [12, 12, 12, 12, 12, 12, 12, 12, 12, 0, 0]),
+    (125, [7, 12, 12, 12, 56, 12, 12, 12, 7, 0, 0]),
+    (126, [38, 45, 25, 0, 0, 0, 0, 0, 0, 0, 0]),
+];
+
+fn read(vm: &Vm, address: i16) -> i16 {
+    vm.ram[address as u16 as usize % MEMORY_SIZE] as i16
+}
+
+fn write(vm: &mut Vm, address: i16, value: i16) {
+    vm.ram[address as u16 as usize % MEMORY_SIZE] = value as u16;
+}
+
+// Static variable `index` of `class`.
+fn get(vm: &Vm, class: &str, index: i16) -> i16 {
+    read(vm, vm.static_base(class) as i16 + index)
+}
+
+fn set(vm: &mut Vm, class: &str, index: i16, value: i16) -> Option<i16> {
+    write(vm, vm.static_base(class) as i16 + index, value);
+    Some(0)
+}
+
+fn bool_value(b: bool) -> i16 {
+    if b {
+        -1
+    } else {
+        0
+    }
+}
+
+fn math_init(vm: &mut Vm, _: &[i16]) -> Option<i16> {
+    let mask = alloc(vm, 16)?;
+    set(vm, "Math", 0, mask);
+    write(vm, mask, 1);
+    for i in 1..16 {
+        let bit = read(vm, mask.wrapping_add(i - 1));
+        write(vm, mask.wrapping_add(i), bit.wrapping_add(bit));
+    }
+    Some(0)
+}
+
+fn abs(x: i16) -> i16 {
+    if x < 0 {
+        x.wrapping_neg()
+    } else {
+        x
+    }
+}
+
+fn bit(vm: &Vm, x: i16, i: i16) -> bool {
+    read(vm, get(vm, "Math", 0).wrapping_add(i)) & x != 0
+}
+
+fn multiply(vm: &Vm, x: i16, y: i16) -> i16 {
+    let mut sum = 0i16;
+    let mut shifted_x = x;
+    for i in 0..16 {
+        if bit(vm, y, i) {
+            sum = sum.wrapping_add(shifted_x);
+        }
+        shifted_x = shifted_x.wrapping_add(shifted_x);
+    }
+    sum
+}
+
+fn divide(vm: &Vm, x: i16, y: i16) -> Option<i16> {
+    if y == 0 {
+        return None;
+    }
+    let abs_x = abs(x);
+    let abs_y = abs(y);
+    if abs_y > abs_x || abs_y < 0 {
+        return Some(0);
+    }
+    let q = if abs_y > 16383 {
+        0
+    } else {
+        divide(vm, abs_x, multiply(vm, 2, abs_y))?
+    };
+    let ret = if abs_x.wrapping_sub(multiply(vm, multiply(vm, 2, q), abs_y)) < abs_y {
+        multiply(vm, 2, q)
+    } else {
+        multiply(vm, 2, q).wrapping_add(1)
+    };
+    Some(if (x < 0) == (y < 0) {
+        ret
+    } else {
+        ret.wrapping_neg()
+    })
+}
+
+fn sqrt(vm: &Vm, x: i16) -> i16 {
+    let mut ret = 0i16;
+    for j in (0..8).rev() {
+        let temp = ret.wrapping_add(read(vm, get(vm, "Math", 0).wrapping_add(j)));
+        let temp_pow = multiply(vm, temp, temp);
+        if temp_pow <= x && temp_pow > 0 {
+            ret = temp;
+        }
+    }
+    ret
+}
+
+fn memory_init(vm: &mut Vm, _: &[i16]) -> Option<i16> {
+    set(vm, "Memory", 0, 0);
+    set(vm, "Memory", 1, 2048);
+    let free_list = get(vm, "Memory", 1);
+    write(vm, free_list, 14335);
+    write(vm, free_list.wrapping_add(1), 0);
+    Some(0)
+}
+
+fn alloc(vm: &mut Vm, size: i16) -> Option<i16> {
+    if size <= 0 {
+        return None;
+    }
+    let mut segment = get(vm, "Memory", 1);
+    while segment != 0 {
+        let segment_size = read(vm, segment);
+        if segment_size.wrapping_sub(2) > size {
+            let ptr = segment.wrapping_add(segment_size.wrapping_sub(size));
+            write(vm, ptr.wrapping_sub(1), size.wrapping_add(1));
+            write(vm, segment, segment_size.wrapping_sub(size.wrapping_add(1)));
+            return Some(ptr);
+        }
+        let next = read(vm, segment.wrapping_add(1));
+        if next == segment {
+            return None;
+        }
+        segment = next;
+    }
+    None
+}
+
+fn dealloc(vm: &mut Vm, o: i16) -> Option<i16> {
+    let segment = o.wrapping_sub(1);
+    let free_list = get(vm, "Memory", 1);
+    write(vm, segment.wrapping_add(1), free_list);
+    set(vm, "Memory", 1, segment)
+}
+
+// Fields of a `String`: `length`, `capacity` and `str`.
+fn string_new(vm: &mut Vm, max_length: i16) -> Option<i16> {
+    if max_length < 0 {
+        return None;
+    }
+    let this = alloc(vm, 3)?;
+    write(vm, this, 0);
+    let capacity = if max_length == 0 {
+        max_length + 1
+    } else {
+        max_length
+    };
+    write(vm, this.wrapping_add(1), capacity);
+    let str = alloc(vm, capacity)?;
+    write(vm, this.wrapping_add(2), str);
+    Some(this)
+}
+
+fn char_at(vm: &Vm, this: i16, j: i16) -> i16 {
+    read(vm, read(vm, this.wrapping_add(2)).wrapping_add(j))
+}
+
+fn append_char(vm: &mut Vm, this: i16, c: i16) -> Option<i16> {
+    let length = read(vm, this);
+    if length == read(vm, this.wrapping_add(1)) {
+        return None;
+    }
+    let str = read(vm, this.wrapping_add(2));
+    write(vm, str.wrapping_add(length), c);
+    write(vm, this, length.wrapping_add(1));
+    Some(this)
+}
+
+fn int_value(vm: &Vm, this: i16) -> i16 {
+    let length = read(vm, this);
+    let mut cursor = 0i16;
+    let mut ret = 0i16;
+    let neg = length > 0 && char_at(vm, this, 0) == 45;
+    if neg {
+        cursor += 1;
+    }
+    while cursor < length {
+        let c = char_at(vm, this, cursor);
+        if !(48..=57).contains(&c) {
+            return ret;
+        }
+        ret = multiply(vm, ret, 10).wrapping_add(c - 48);
+        cursor += 1;
+    }
+    if neg {
+        ret.wrapping_neg()
+    } else {
+        ret
+    }
+}
+
+fn set_int(vm: &mut Vm, this: i16, mut val: i16) -> Option<i16> {
+    let mut digits = 1;
+    let mut rest = divide(vm, val, 10)?;
+    while rest != 0 {
+        digits += 1;
+        rest = divide(vm, rest, 10)?;
+    }
+    if val < 0 {
+        digits += 1;
+    }
+    if digits > read(vm, this.wrapping_add(1)) {
+        return None;
+    }
+    write(vm, this, 0);
+    if val < 0 {
+        append_char(vm, this, 45)?;
+        val = val.wrapping_neg();
+    }
+    set_int_impl(vm, this, val)
+}
+
+fn set_int_impl(vm: &mut Vm, this: i16, val: i16) -> Option<i16> {
+    if val < 10 {
+        append_char(vm, this, val.wrapping_add(48))?;
+    } else {
+        let next_num = divide(vm, val, 10)?;
+        set_int_impl(vm, this, next_num)?;
+        let c = val
+            .wrapping_sub(multiply(vm, next_num, 10))
+            .wrapping_add(48);
+        append_char(vm, this, c)?;
+    }
+    Some(0)
+}
+
+// Statics of `Output`: `charMaps`, `cursorRow`, `cursorCol` and `screen`.
+fn output_init(vm: &mut Vm, _: &[i16]) -> Option<i16> {
+    set(vm, "Output", 1, 0);
+    set(vm, "Output", 2, 0);
+    set(vm, "Output", 3, 16384);
+    output_init_map(vm, &[])
+}
+
+fn output_init_map(vm: &mut Vm, _: &[i16]) -> Option<i16> {
+    let char_maps = alloc(vm, 127)?;
+    set(vm, "Output", 0, char_maps);
+    for (index, map) in FONT {
+        create_char(vm, *index, map)?;
+    }
+    Some(0)
+}
+
+fn create_char(vm: &mut Vm, index: i16, values: &[i16]) -> Option<i16> {
+    let map = alloc(vm, 11)?;
+    write(vm, get(vm, "Output", 0).wrapping_add(index), map);
+    for (i, value) in (0..).zip(values) {
+        write(vm, map.wrapping_add(i), *value);
+    }
+    Some(0)
+}
+
+fn char_map(vm: &Vm, c: i16) -> i16 {
+    let c = if !(32..=126).contains(&c) { 0 } else { c };
+    read(vm, get(vm, "Output", 0).wrapping_add(c))
+}
+
+fn print_char(vm: &mut Vm, c: i16) -> Option<i16> {
+    let char_map = char_map(vm, c);
+    let row = get(vm, "Output", 1);
+    let col = get(vm, "Output", 2);
+    let screen = get(vm, "Output", 3);
+    let mut addr = multiply(vm, multiply(vm, row, 32), 11).wrapping_add(divide(vm, col, 2)?);
+    let is_odd_word = col & 1 == 1;
+    for i in 0..11 {
+        let word = read(vm, screen.wrapping_add(addr));
+        let bits = read(vm, char_map.wrapping_add(i));
+        let word = if is_odd_word {
+            word & 255 | multiply(vm, bits, 256)
+        } else {
+            word & multiply(vm, 255, 256) | bits
+        };
+        write(vm, screen.wrapping_add(addr), word);
+        addr = addr.wrapping_add(32);
+    }
+    if col == 63 {
+        println(vm);
+    } else {
+        set(vm, "Output", 2, col.wrapping_add(1));
+    }
+    Some(0)
+}
+
+fn print_string(vm: &mut Vm, s: i16) -> Option<i16> {
+    let length = read(vm, s);
+    for i in 0..length.max(0) {
+        print_char(vm, char_at(vm, s, i))?;
+    }
+    Some(0)
+}
+
+fn println(vm: &mut Vm) -> i16 {
+    let row = get(vm, "Output", 1);
+    let row = if row == 22 { 0 } else { row.wrapping_add(1) };
+    set(vm, "Output", 1, row);
+    set(vm, "Output", 2, 0);
+    0
+}
+
+fn output_back_space(vm: &mut Vm, _: &[i16]) -> Option<i16> {
+    let row = get(vm, "Output", 1);
+    let col = get(vm, "Output", 2);
+    if col != 0 {
+        set(vm, "Output", 2, col - 1);
+    } else if row != 0 {
+        set(vm, "Output", 1, row - 1);
+        set(vm, "Output", 2, 63);
+    }
+    Some(0)
+}
+
+// Statics of `Screen`: `black`, `screen` and `mask`.
+fn screen_init(vm: &mut Vm, _: &[i16]) -> Option<i16> {
+    set(vm, "Screen", 0, -1);
+    set(vm, "Screen", 1, 16384);
+    let mask = alloc(vm, 16)?;
+    set(vm, "Screen", 2, mask);
+    write(vm, mask, 1);
+    for i in 1..16 {
+        let bit = read(vm, mask.wrapping_add(i - 1));
+        write(vm, mask.wrapping_add(i), bit.wrapping_add(bit));
+    }
+    Some(0)
+}
+
+fn on_screen(x: i16, y: i16) -> bool {
+    (0..512).contains(&x) && (0..256).contains(&y)
+}
+
+fn draw_pixel(vm: &mut Vm, x: i16, y: i16) -> Option<i16> {
+    if !on_screen(x, y) {
+        return None;
+    }
+    let addr = divide(vm, x, 16)?.wrapping_add(multiply(vm, y, 32));
+    let bit_mask = read(vm, get(vm, "Screen", 2).wrapping_add(x & 15));
+    let address = get(vm, "Screen", 1).wrapping_add(addr);
+    let word = read(vm, address);
+    if get(vm, "Screen", 0) != 0 {
+        write(vm, address, word | bit_mask);
+    } else {
+        write(vm, address, word & !bit_mask);
+    }
+    Some(0)
+}
+
+fn draw_line(vm: &mut Vm, x1: i16, y1: i16, x2: i16, y2: i16) -> Option<i16> {
+    if !on_screen(x1, y1) || !on_screen(x2, y2) {
+        return None;
+    }
+    let (x1, y1, x2, y2) = if x1 > x2 {
+        (x2, y2, x1, y1)
+    } else {
+        (x1, y1, x2, y2)
+    };
+    let dx = x2.wrapping_sub(x1);
+    let dy = y2.wrapping_sub(y1);
+    if dx == 0 {
+        return draw_vertical(vm, x1, y1, y2);
+    }
+    if dy == 0 {
+        return draw_horizontal(vm, y1, x1, x2);
+    }
+    let mut judge = 0i16;
+    let mut a = 0i16;
+    let mut b = 0i16;
+    while a <= dx && (if dy > 0 { b <= dy } else { b >= dy }) {
+        draw_pixel(vm, x1.wrapping_add(a), y1.wrapping_add(b))?;
+        if judge > 0 {
+            judge = if dy > 0 {
+                judge.wrapping_sub(dy)
+            } else {
+                judge.wrapping_add(dy)
+            };
+            a = a.wrapping_add(1);
+        } else {
+            judge = judge.wrapping_add(dx);
+            b = if dy > 0 {
+                b.wrapping_add(1)
+            } else {
+                b.wrapping_sub(1)
+            };
+        }
+    }
+    Some(0)
+}
+
+fn draw_vertical(vm: &mut Vm, x: i16, y1: i16, y2: i16) -> Option<i16> {
+    let (ymin, ymax) = if y1 > y2 { (y2, y1) } else { (y1, y2) };
+    for y in ymin..=ymax {
+        draw_pixel(vm, x, y)?;
+    }
+    Some(0)
+}
+
+fn draw_horizontal(vm: &mut Vm, y: i16, x1: i16, x2: i16) -> Option<i16> {
+    let (xmin, xmax) = if x1 > x2 { (x2, x1) } else { (x1, x2) };
+    for x in xmin..=xmax {
+        draw_pixel(vm, x, y)?;
+    }
+    Some(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emu::{LCL, SP};
+    use crate::jack::{tokenize, Parser, VmGen};
+
+    const MAIN: &str = "
+class Main {
+    static int product, quotient, root, value, array, big;
+
+    function void main() {
+        var String s;
+        var Array a;
+        let product = 123 * -45;
+        let quotient = -5000 / 7;
+        let root = Math.sqrt(1000);
+        let s = \"-42\";
+        let value = s.intValue();
+        do Output.printString(\"Hello, world!\");
+        do Output.println();
+        do Output.printInt(-1234);
+        do Output.backSpace();
+        do Output.printChar(65);
+        let a = Array.new(10);
+        let array = Array.new(5);
+        do Screen.drawLine(10, 100, 200, 30);
+        do Screen.drawRectangle(300, 100, 310, 120);
+        do Screen.drawCircle(400, 200, 10);
+        do Screen.setColor(false);
+        do Screen.drawLine(0, 0, 60, 255);
+        do Memory.poke(8000, Memory.peek(array - 1));
+        do a.dispose();
+        let big = 30000 / 3;
+        return;
+    }
+}
+";
+
+    // Runs `vm` until it reaches `Sys.halt`, within 50M steps and with the stack below
+    // the heap, returning the number of steps.
+    fn run(vm: &mut Vm) -> usize {
+        let halt = vm
+            .functions()
+            .iter()
+            .find(|function| function.name == "Sys.halt")
+            .and_then(|function| function.start)
+            .unwrap();
+        vm.ram[SP] = 256;
+        let mut steps = 0;
+        while !(halt..halt + 4).contains(&vm.pc()) {
+            vm.step().unwrap();
+            steps += 1;
+            assert!(steps < 50_000_000, "Sys.halt not reached");
+            assert!(vm.ram[SP] < 2048, "Stack overflow");
+        }
+        steps
+    }
+
+    // `main` compiled with the OS classes it needs.
+    fn compile(main: &str) -> Vec<VmFile> {
+        let tokens = tokenize(main.chars()).unwrap();
+        let class = Parser::new(tokens.into_iter()).parse().unwrap();
+        let main = VmFile {
+            name: "Main".to_string(),
+            commands: VmGen::new().gen(class).unwrap().to_vec(),
+        };
+        let mut files = vec![main];
+        files.extend(os_dependencies(&files));
+        files
+    }
+
+    #[test]
+    fn test_natives() {
+        let files = compile(MAIN);
+        // The OS classes given as files are bundled as well.
+        let mut native = Vm::with_os(files.clone()).unwrap();
+        let mut vm = Vm::new(files).unwrap();
+        let native_steps = run(&mut native);
+        let steps = run(&mut vm);
+        assert!(native_steps * 100 < steps);
+        assert_eq!(&native.ram[16..19], &[-5535i16 as u16, -714i16 as u16, 31]);
+        assert_eq!(native.ram[19], -42i16 as u16);
+        assert_eq!(native.ram[21], 10000);
+        assert_eq!(&native.ram[16..256], &vm.ram[16..256]);
+        assert_eq!(&native.ram[2048..], &vm.ram[2048..]);
+    }
+
+    #[test]
+    fn test_errors() {
+        for (statement, code, function) in [
+            ("do Math.divide(1, 0);", 3, "Math.divide"),
+            ("do Math.sqrt(-4);", 4, "Math.sqrt"),
+            ("do Array.new(-1);", 2, "Array.new"),
+            ("do Memory.alloc(20000);", 6, "Memory.alloc"),
+            // `Sys.error` itself must not need the exhausted heap.
+            ("while (true) { do Array.new(1); }", 6, "Memory.alloc"),
+            ("do s.charAt(2);", 15, "String.charAt"),
+            ("do s.appendChar(99);", 17, "String.appendChar"),
+            ("do s.setInt(-100);", 19, "String.setInt"),
+            ("do Screen.drawPixel(512, 0);", 7, "Screen.drawPixel"),
+        ] {
+            let main = format! {
+                "class Main {{
+    function void main() {{
+        var String s;
+        let s = String.new(2);
+        do s.appendChar(97);
+        do s.appendChar(98);
+        {}
+        return;
+    }}
+}}",
+                statement
+            };
+            let files = compile(&main);
+            // The VM code first, as natives could loop for ever on a RAM that a failing
+            // `Sys.error` overwrote.
+            for mut vm in [Vm::new(files.clone()).unwrap(), Vm::with_os(files).unwrap()] {
+                run(&mut vm);
+                let functions: Vec<_> = vm
+                    .backtrace()
+                    .into_iter()
+                    .filter_map(|pc| vm.source(pc)?.function)
+                    .collect();
+                assert!(functions.iter().any(|f| f == function), "{}", statement);
+                assert!(functions.iter().any(|f| f == "Sys.error"), "{}", statement);
+                // `Sys.error` called `Sys.halt`, whose frame saved the `ARG` of the code.
+                let arg = vm.ram[vm.ram[LCL] as usize - 3];
+                assert_eq!(vm.ram[arg as usize], code, "{}", statement);
+            }
+        }
+    }
+
+    #[test]
+    fn test_override() {
+        let main = parse_vm(
+            "function Main.main 0
+push constant 6
+push constant 7
+call Math.multiply 2
+pop static 0
+push constant 0
+return
+"
+            .as_bytes(),
+        )
+        .unwrap();
+        let math = parse_vm(
+            "function Math.init 0
+push constant 0
+return
+function Math.multiply 0
+push constant 7
+return
+"
+            .as_bytes(),
+        )
+        .unwrap();
+        let file = |name: &str, commands| VmFile {
+            name: name.to_string(),
+            commands,
+        };
+        let mut vm = Vm::with_os(vec![file("Main", main), file("Math", math)]).unwrap();
+        run(&mut vm);
+        assert_eq!(vm.ram[16], 7);
+    }
+}
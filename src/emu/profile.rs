@@ -0,0 +1,416 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+
+use super::vm::{Vm, ARG, LCL, SP};
+
+// Name of the code outside of any function, such as the bootstrap code.
+const NO_FUNCTION: &str = "(no function)";
+
+// Function called in a call path, with the instructions executed in it on that path.
+struct Node {
+    function: Option<usize>,
+    parent: usize,
+    children: HashMap<usize, usize>,
+    count: u64,
+}
+
+/// Counts the instructions executed at each ROM address, or the commands executed at
+/// each index on the VM emulator, and the call paths of VM functions they run in.
+///
+/// A function is entered when execution reaches its start, and left when it reaches
+/// the return address the caller saved in the function's frame with `SP` just above
+/// its arguments, as `call` and `return` do in VM code and the assembly
+/// `AsmGenerator` translates it to.
+pub struct Profiler {
+    counts: Vec<u64>,
+    names: Vec<String>,
+    starts: HashMap<usize, usize>,
+    // Call tree with the path of the program at its root, and the node of each
+    // function running with its return address and `SP` once it returns.
+    nodes: Vec<Node>,
+    current: usize,
+    returns: Vec<(usize, u16)>,
+}
+
+impl Profiler {
+    /// Profiler of a program whose functions start at the addresses or indices of
+    /// `functions`.
+    pub fn new(functions: &[(usize, String)]) -> Self {
+        Self {
+            counts: Vec::new(),
+            names: functions.iter().map(|(_, name)| name.clone()).collect(),
+            starts: functions
+                .iter()
+                .enumerate()
+                .map(|(id, &(start, _))| (start, id))
+                .collect(),
+            nodes: vec![Node {
+                function: None,
+                parent: 0,
+                children: HashMap::new(),
+                count: 0,
+            }],
+            current: 0,
+            returns: Vec::new(),
+        }
+    }
+
+    /// Profiler of a Hack program with the labels `labels`, of which the `FunctionName`
+    /// labels of `AsmGenerator::function`, named `Class.function`, start functions.
+    pub fn with_labels(labels: &BTreeMap<String, u16>) -> Self {
+        let functions: Vec<_> = labels
+            .iter()
+            .filter(|(label, _)| label.contains('.') && !label.contains('$'))
+            .map(|(label, &addr)| (addr as usize, label.clone()))
+            .collect();
+        Self::new(&functions)
+    }
+
+    /// Profiler of the program of `vm`.
+    pub fn with_vm(vm: &Vm) -> Self {
+        let functions: Vec<_> = vm
+            .functions()
+            .iter()
+            .filter_map(|function| Some((function.start?, function.name.clone())))
+            .collect();
+        Self::new(&functions)
+    }
+
+    /// Counts the instruction or command at `pc` before it's executed, with `ram`
+    /// holding the VM registers.
+    pub fn record(&mut self, pc: usize, ram: &[u16]) {
+        // The return address may also start a function, such as the one following the
+        // bootstrap code, which `SP` tells from a call to it.
+        if self.returns.last() == Some(&(pc, ram[SP])) {
+            self.returns.pop();
+            self.current = self.nodes[self.current].parent;
+        }
+        if let Some(&function) = self.starts.get(&pc) {
+            let lcl = ram[LCL] as usize;
+            // `Sys.init` may start without a call, and so without a return address.
+            let ret = lcl
+                .checked_sub(5)
+                .map_or(usize::MAX, |frame| ram[frame] as usize);
+            self.returns.push((ret, ram[ARG].wrapping_add(1)));
+            self.current = self.child(self.current, function);
+        }
+        self.nodes[self.current].count += 1;
+        if self.counts.len() <= pc {
+            self.counts.resize(pc + 1, 0);
+        }
+        self.counts[pc] += 1;
+    }
+
+    /// Instructions or commands executed at each address or index.
+    pub fn counts(&self) -> &[u64] {
+        &self.counts
+    }
+
+    /// Instructions executed in each function itself and including the functions it
+    /// calls, most executed first.
+    pub fn flat(&self) -> String {
+        let mut totals = vec![(0, 0); self.names.len() + 1];
+        for (i, node) in self.nodes.iter().enumerate() {
+            totals[self.id(node.function)].0 += node.count;
+            let mut path: Vec<_> = self.path(i).into_iter().map(|f| self.id(f)).collect();
+            path.sort_unstable();
+            path.dedup();
+            for id in path {
+                totals[id].1 += node.count;
+            }
+        }
+        let mut totals: Vec<_> = totals
+            .into_iter()
+            .enumerate()
+            .filter(|&(_, (_, total))| total > 0)
+            .collect();
+        totals.sort_by_key(|&(id, (count, total))| (std::cmp::Reverse((count, total)), id));
+        let sum = self.total();
+        let mut out =
+            format! {"{:>12} {:>7} {:>12} {:>7}  function\n", "self", "self%", "total", "total%"};
+        for (id, (count, total)) in totals {
+            writeln!(
+                out,
+                "{:>12} {:>6.2}% {:>12} {:>6.2}%  {}",
+                count,
+                percent(count, sum),
+                total,
+                percent(total, sum),
+                self.name(id)
+            )
+            .unwrap();
+        }
+        out
+    }
+
+    /// Call tree of the functions, each with the instructions executed in it including
+    /// its calls and by itself, and its calls indented below it, most executed first.
+    pub fn tree(&self) -> String {
+        let totals = self.subtree_totals();
+        let sum = self.total();
+        let mut out = format! {"{:>12} {:>7} {:>12}  function\n", "total", "total%", "self"};
+        let mut stack = vec![(0, 0)];
+        while let Some((i, depth)) = stack.pop() {
+            let node = &self.nodes[i];
+            if i != 0 || node.count > 0 {
+                writeln!(
+                    out,
+                    "{:>12} {:>6.2}% {:>12}  {}{}",
+                    totals[i],
+                    percent(totals[i], sum),
+                    node.count,
+                    "  ".repeat(depth),
+                    self.name(self.id(node.function))
+                )
+                .unwrap();
+            }
+            // The root is only listed for code outside of functions, and its calls
+            // aren't indented under it.
+            let depth = if i == 0 { 0 } else { depth + 1 };
+            let mut children: Vec<_> = node.children.values().copied().collect();
+            children.sort_by_key(|&child| (totals[child], std::cmp::Reverse(child)));
+            stack.extend(children.into_iter().map(|child| (child, depth)));
+        }
+        out
+    }
+
+    /// A line per call path of `Outer;Inner COUNT`, as flame graph tools read.
+    pub fn folded(&self) -> String {
+        let mut lines: Vec<_> = self
+            .nodes
+            .iter()
+            .enumerate()
+            .filter(|(_, node)| node.count > 0)
+            .map(|(i, node)| {
+                let path: Vec<_> = self.path(i).into_iter().rev().collect();
+                let names: Vec<_> = if path.is_empty() {
+                    vec![NO_FUNCTION]
+                } else {
+                    path.into_iter().map(|f| self.name(self.id(f))).collect()
+                };
+                format! {"{} {}", names.join(";"), node.count}
+            })
+            .collect();
+        lines.sort();
+        lines.iter().map(|line| format! {"{}\n", line}).collect()
+    }
+
+    /// Executions of each address or index executed, with its function, most executed
+    /// first.
+    pub fn addresses(&self) -> String {
+        let mut starts: Vec<_> = self
+            .starts
+            .iter()
+            .map(|(&start, &id)| (start, id))
+            .collect();
+        starts.sort_unstable();
+        let mut counts: Vec<_> = self
+            .counts
+            .iter()
+            .enumerate()
+            .filter(|&(_, &count)| count > 0)
+            .collect();
+        counts.sort_by_key(|&(addr, &count)| (std::cmp::Reverse(count), addr));
+        let sum = self.total();
+        let mut out = format! {"{:>12} {:>7} {:>7}  function\n", "count", "count%", "address"};
+        for (addr, &count) in counts {
+            // Functions are contiguous, so an address belongs to the last one starting
+            // before it.
+            let function = match starts.partition_point(|&(start, _)| start <= addr) {
+                0 => NO_FUNCTION,
+                i => &self.names[starts[i - 1].1],
+            };
+            writeln!(
+                out,
+                "{:>12} {:>6.2}% {:>7}  {}",
+                count,
+                percent(count, sum),
+                addr,
+                function
+            )
+            .unwrap();
+        }
+        out
+    }
+
+    fn child(&mut self, parent: usize, function: usize) -> usize {
+        if let Some(&child) = self.nodes[parent].children.get(&function) {
+            return child;
+        }
+        self.nodes.push(Node {
+            function: Some(function),
+            parent,
+            children: HashMap::new(),
+            count: 0,
+        });
+        let child = self.nodes.len() - 1;
+        self.nodes[parent].children.insert(function, child);
+        child
+    }
+
+    // Functions from node `i` up to the root, innermost first.
+    fn path(&self, mut i: usize) -> Vec<Option<usize>> {
+        let mut path = Vec::new();
+        while i != 0 {
+            path.push(self.nodes[i].function);
+            i = self.nodes[i].parent;
+        }
+        path
+    }
+
+    // Instructions executed in each node including its descendants, which come after
+    // it in `nodes`.
+    fn subtree_totals(&self) -> Vec<u64> {
+        let mut totals: Vec<_> = self.nodes.iter().map(|node| node.count).collect();
+        for i in (1..self.nodes.len()).rev() {
+            totals[self.nodes[i].parent] += totals[i];
+        }
+        totals
+    }
+
+    fn total(&self) -> u64 {
+        self.nodes.iter().map(|node| node.count).sum()
+    }
+
+    // Index in `names`, or one past them for code outside of functions.
+    fn id(&self, function: Option<usize>) -> usize {
+        function.unwrap_or(self.names.len())
+    }
+
+    fn name(&self, id: usize) -> &str {
+        self.names.get(id).map_or(NO_FUNCTION, String::as_str)
+    }
+}
+
+fn percent(count: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        count as f64 * 100.0 / total as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emu::{assemble_with_symbols, Cpu, SP};
+    use crate::vm::{parse_vm, VmFile};
+    use crate::AsmGenerator;
+
+    #[test]
+    fn test_profile() {
+        let vm = "function Sys.init 0
+push constant 3
+call Main.fact 1
+pop temp 0
+label END
+goto END
+function Main.fact 0
+push argument 0
+if-goto REC
+push constant 1
+return
+label REC
+push argument 0
+push argument 0
+push constant 1
+sub
+call Main.fact 1
+call Main.mul 2
+return
+function Main.mul 0
+push argument 0
+push argument 1
+add
+return
+";
+        let commands = parse_vm(vm.as_bytes()).unwrap();
+        let file = VmFile {
+            name: "Main".to_string(),
+            commands: commands.clone(),
+        };
+        let mut vm = Vm::new(vec![file]).unwrap();
+        vm.ram[SP] = 256;
+        let mut profiler = Profiler::with_vm(&vm);
+        while !vm.is_halted() {
+            profiler.record(vm.pc(), &vm.ram);
+            vm.step().unwrap();
+        }
+        let folded = "Sys.init 4
+Sys.init;Main.fact 11
+Sys.init;Main.fact;Main.fact 11
+Sys.init;Main.fact;Main.fact;Main.fact 11
+Sys.init;Main.fact;Main.fact;Main.fact;Main.fact 5
+Sys.init;Main.fact;Main.fact;Main.fact;Main.mul 5
+Sys.init;Main.fact;Main.fact;Main.mul 5
+Sys.init;Main.fact;Main.mul 5
+";
+        assert_eq!(profiler.folded(), folded);
+        assert_eq!(
+            profiler.flat().lines().nth(1),
+            Some("          38  66.67%           53  92.98%  Main.fact")
+        );
+        assert_eq!(
+            profiler.tree().lines().nth(5),
+            Some("           5   8.77%            5          Main.fact")
+        );
+        assert_eq!(profiler.counts()[19..24], [3; 5]);
+
+        // The same program on the CPU runs in the same functions.
+        let mut gen = AsmGenerator::new(false);
+        gen.gen_commands("Main", commands);
+        let asm = gen.lines().join("\n");
+        let (program, symbols) = assemble_with_symbols(asm.as_bytes()).unwrap();
+        let mut cpu = Cpu::new(&program);
+        let mut profiler = Profiler::with_labels(&symbols.labels);
+        while !cpu.is_halted() {
+            profiler.record(cpu.pc as usize, &cpu.ram);
+            cpu.step();
+        }
+        let paths: Vec<_> = profiler
+            .folded()
+            .lines()
+            .map(|line| line.rsplit_once(' ').unwrap().0.to_string())
+            .collect();
+        let mut expected: Vec<_> = folded
+            .lines()
+            .map(|line| line.rsplit_once(' ').unwrap().0.to_string())
+            .collect();
+        expected.insert(0, NO_FUNCTION.to_string());
+        assert_eq!(paths, expected);
+    }
+
+    #[test]
+    fn test_call_at_return_address() {
+        // The return address of the bootstrap's call of `Sys.init` starts `Main.f`.
+        let vm = "function Main.f 0
+push constant 1
+return
+function Sys.init 0
+call Main.f 0
+pop temp 0
+label END
+goto END
+";
+        let mut gen = AsmGenerator::new(false);
+        gen.gen_commands("Main", parse_vm(vm.as_bytes()).unwrap());
+        let asm = gen.lines().join("\n");
+        let (program, symbols) = assemble_with_symbols(asm.as_bytes()).unwrap();
+        assert_eq!(
+            symbols.labels.get("FUNC_RETURN_0"),
+            symbols.labels.get("Main.f")
+        );
+        let mut cpu = Cpu::new(&program);
+        let mut profiler = Profiler::with_labels(&symbols.labels);
+        while !cpu.is_halted() {
+            profiler.record(cpu.pc as usize, &cpu.ram);
+            cpu.step();
+        }
+        let folded = profiler.folded();
+        let paths: Vec<_> = folded
+            .lines()
+            .map(|line| line.rsplit_once(' ').unwrap().0)
+            .collect();
+        assert_eq!(paths, [NO_FUNCTION, "Sys.init", "Sys.init;Main.f"]);
+    }
+}
@@ -0,0 +1,118 @@
+use super::cpu::SCREEN;
+
+/// Pixels of the Hack screen.
+pub const SCREEN_WIDTH: usize = 512;
+pub const SCREEN_HEIGHT: usize = 256;
+
+// Whether pixel (`x`, `y`) is black in the screen memory map of `ram`, where each row
+// is 32 words and bit 0 of a word is its leftmost pixel.
+fn pixel(ram: &[u16], x: usize, y: usize) -> bool {
+    ram[SCREEN + y * SCREEN_WIDTH / 16 + x / 16] >> (x % 16) & 1 == 1
+}
+
+/// Binary PPM image of the screen memory map of `ram`.
+pub fn screen_ppm(ram: &[u16]) -> Vec<u8> {
+    let mut image = format! {"P6\n{} {}\n255\n", SCREEN_WIDTH, SCREEN_HEIGHT}.into_bytes();
+    for y in 0..SCREEN_HEIGHT {
+        for x in 0..SCREEN_WIDTH {
+            let value = if pixel(ram, x, y) { 0 } else { 255 };
+            image.extend(&[value; 3]);
+        }
+    }
+    image
+}
+
+/// PNG image of the screen memory map of `ram`, a 1-bit grayscale image whose pixel
+/// data is stored in uncompressed deflate blocks.
+pub fn screen_png(ram: &[u16]) -> Vec<u8> {
+    // Each row starts with filter type 0, and the leftmost pixel is the high bit.
+    let mut rows = Vec::with_capacity(SCREEN_HEIGHT * (1 + SCREEN_WIDTH / 8));
+    for y in 0..SCREEN_HEIGHT {
+        rows.push(0);
+        for x in (0..SCREEN_WIDTH).step_by(8) {
+            let byte = (0..8).fold(0, |byte, i| byte << 1 | !pixel(ram, x + i, y) as u8);
+            rows.push(byte);
+        }
+    }
+
+    // zlib stream without compression.
+    let mut data = vec![0x78, 0x01];
+    let mut blocks = rows.chunks(0xffff).peekable();
+    while let Some(block) = blocks.next() {
+        data.push(blocks.peek().is_none() as u8);
+        let len = block.len() as u16;
+        data.extend(&len.to_le_bytes());
+        data.extend(&(!len).to_le_bytes());
+        data.extend(block);
+    }
+    data.extend(&adler32(&rows).to_be_bytes());
+
+    let mut header = Vec::new();
+    header.extend(&(SCREEN_WIDTH as u32).to_be_bytes());
+    header.extend(&(SCREEN_HEIGHT as u32).to_be_bytes());
+    // Bit depth 1, grayscale, default compression and filtering, no interlace.
+    header.extend(&[1, 0, 0, 0, 0]);
+
+    let mut image = b"\x89PNG\r\n\x1a\n".to_vec();
+    for (kind, chunk) in &[(b"IHDR", header), (b"IDAT", data), (b"IEND", Vec::new())] {
+        image.extend(&(chunk.len() as u32).to_be_bytes());
+        let start = image.len();
+        image.extend(*kind);
+        image.extend(chunk);
+        let crc = crc32(&image[start..]);
+        image.extend(&crc.to_be_bytes());
+    }
+    image
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0, |crc, &byte| {
+        (0..8).fold(crc ^ byte as u32, |crc, _| {
+            if crc & 1 == 1 {
+                crc >> 1 ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            }
+        })
+    })
+}
+
+fn adler32(bytes: &[u8]) -> u32 {
+    let (a, b) = bytes.iter().fold((1, 0), |(a, b), &byte| {
+        let a = (a + byte as u32) % 65521;
+        (a, (b + a) % 65521)
+    });
+    b << 16 | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emu::MEMORY_SIZE;
+
+    #[test]
+    fn test_screen() {
+        let mut ram = vec![0; MEMORY_SIZE];
+        // Pixels (0, 0), (17, 0) and (511, 255).
+        ram[SCREEN] = 1;
+        ram[SCREEN + 1] = 2;
+        ram[SCREEN + 8191] = 0x8000;
+
+        let ppm = screen_ppm(&ram);
+        let header = b"P6\n512 256\n255\n";
+        assert_eq!(&ppm[..header.len()], header);
+        let pixels = &ppm[header.len()..];
+        assert_eq!(pixels.len(), 512 * 256 * 3);
+        let black: Vec<_> = (0..512 * 256).filter(|i| pixels[i * 3] == 0).collect();
+        assert_eq!(black, [0, 17, 512 * 256 - 1]);
+
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+        let png = screen_png(&ram);
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+        assert_eq!(&png[png.len() - 12..], b"\0\0\0\0IEND\xae\x42\x60\x82");
+        // First row: filter type, then pixels 0 and 17 black.
+        let row = &png[33 + 8 + 2 + 5..][..65];
+        assert_eq!(&row[..4], &[0, 0x7f, 0xff, 0xbf]);
+    }
+}
@@ -0,0 +1,495 @@
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+use super::cpu::MEMORY_SIZE;
+use super::native::{natives, Native};
+use crate::os::{is_os_class, os_dependencies};
+use crate::source_map::SourceLoc;
+use crate::vm::{load_vm_file, Command, Op, Segment, VmFile};
+
+/// RAM addresses of the VM registers.
+pub const SP: usize = 0;
+pub const LCL: usize = 1;
+pub const ARG: usize = 2;
+pub const THIS: usize = 3;
+pub const THAT: usize = 4;
+const TEMP: usize = 5;
+const STATIC: usize = 16;
+const STATIC_END: usize = 256;
+// Bounds the frames `return_addresses` follows, as the stack ends at 2048.
+const MAX_FRAMES: usize = 2048 / 5;
+
+// Command with its labels and callee resolved. The index of `Push`/`Pop` of the
+// `Static` segment is already a RAM address.
+#[derive(Clone, Copy, Debug)]
+enum Instr {
+    Arithmetic(Op),
+    Push(Segment, u16),
+    Pop(Segment, u16),
+    Goto(usize),
+    IfGoto(usize),
+    Function(u16),
+    // Index in `Vm::functions`, and the number of arguments.
+    Call(usize, u16),
+    Return,
+}
+
+/// A function called by the program, with the index of its `function` command, or
+/// `None` if no loaded file defines it.
+#[derive(Clone, Debug)]
+pub struct Function {
+    pub name: String,
+    pub start: Option<usize>,
+}
+
+/// Interpreter of VM commands, keeping the stack and segments in the Hack RAM like
+/// the generated assembly does. Return addresses pushed by `call` are command indices.
+pub struct Vm {
+    pub ram: Vec<u16>,
+    commands: Vec<Command>,
+    instrs: Vec<Instr>,
+    functions: Vec<Function>,
+    // Index in `functions` of the function each command belongs to.
+    owners: Vec<Option<usize>>,
+    // Native implementation of each function, if any, with its number of arguments.
+    natives: Vec<Option<(u16, Native)>>,
+    static_bases: HashMap<String, u16>,
+    // Name of each file with the index of its first command, in order.
+    files: Vec<(String, usize)>,
+    pc: usize,
+}
+
+impl Vm {
+    /// Loads a `.vm` file, or every `.vm` file of a directory in name order, and links
+    /// the bundled OS like `with_os`.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        let mut paths = if path.is_dir() {
+            path.read_dir()
+                .map_err(|_| format! {"Can't open directory: {:?}", path})?
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| path.extension().is_some_and(|ext| ext == "vm"))
+                .collect()
+        } else {
+            vec![path.to_path_buf()]
+        };
+        paths.sort();
+        let files = paths
+            .iter()
+            .map(load_vm_file)
+            .collect::<Result<Vec<_>, _>>()?;
+        Self::with_os(files)
+    }
+
+    /// Links `files` like `new`, adding the bundled OS classes they need but don't
+    /// define. Calls to the functions of bundled classes, added or identical to the
+    /// bundled code as `jackc` writes it, run natively with the same effect on the
+    /// heap, the static variables and the screen.
+    pub fn with_os(mut files: Vec<VmFile>) -> Result<Self, String> {
+        let linked = os_dependencies(&files);
+        let bundled: HashSet<_> = files
+            .iter()
+            .filter(|file| is_os_class(file))
+            .chain(&linked)
+            .map(|file| file.name.clone())
+            .collect();
+        files.extend(linked);
+        let mut vm = Self::new(files)?;
+        vm.natives = natives(&vm.functions, &bundled);
+        Ok(vm)
+    }
+
+    /// Links `files`, each getting its own part of the static segment in order.
+    /// Execution starts at `Sys.init` if a file defines it, or else at the first command.
+    pub fn new(files: Vec<VmFile>) -> Result<Self, String> {
+        let mut commands = Vec::new();
+        let mut statics = Vec::new();
+        let mut static_bases = HashMap::new();
+        let mut static_base = STATIC;
+        let mut file_starts = Vec::new();
+        for file in files {
+            let size = file
+                .commands
+                .iter()
+                .filter_map(|command| match command {
+                    Command::Push(Segment::Static, i) | Command::Pop(Segment::Static, i) => {
+                        Some(*i as usize + 1)
+                    }
+                    _ => None,
+                })
+                .max()
+                .unwrap_or(0);
+            if static_base + size > STATIC_END {
+                return Err(format! {"Error: Too many static variables in {}", file.name});
+            }
+            statics.extend(file.commands.iter().map(|_| static_base as u16));
+            file_starts.push((file.name.clone(), commands.len()));
+            static_bases.insert(file.name, static_base as u16);
+            commands.extend(file.commands);
+            static_base += size;
+        }
+
+        let mut functions: Vec<Function> = Vec::new();
+        let mut function_ids = HashMap::new();
+        let mut labels = HashMap::new();
+        let mut function = "";
+        for (i, command) in commands.iter().enumerate() {
+            match command {
+                Command::Function(name, _) => {
+                    function = name;
+                    let id = *function_ids.entry(name.as_str()).or_insert_with(|| {
+                        functions.push(Function {
+                            name: name.clone(),
+                            start: None,
+                        });
+                        functions.len() - 1
+                    });
+                    if functions[id].start.replace(i).is_some() {
+                        return Err(format! {"Error: Function {} is defined twice", name});
+                    }
+                }
+                Command::Label(label) => {
+                    labels.insert((function, label.as_str()), i);
+                }
+                _ => {}
+            }
+        }
+
+        let mut instrs = Vec::with_capacity(commands.len());
+        let mut owners = Vec::with_capacity(commands.len());
+        let mut function = "";
+        let mut owner = None;
+        for (i, command) in commands.iter().enumerate() {
+            let label = |label: &str| {
+                labels
+                    .get(&(function, label))
+                    .copied()
+                    .ok_or_else(|| format! {"Error: Label {} not found in {}", label, function})
+            };
+            instrs.push(match command {
+                Command::Arithmetic(op) => Instr::Arithmetic(*op),
+                Command::Push(Segment::Static, index) => {
+                    Instr::Push(Segment::Static, statics[i] + index)
+                }
+                Command::Pop(Segment::Static, index) => {
+                    Instr::Pop(Segment::Static, statics[i] + index)
+                }
+                Command::Push(segment, index) => Instr::Push(*segment, *index),
+                Command::Pop(segment, index) => Instr::Pop(*segment, *index),
+                // A label needs no work, so it jumps to the next command.
+                Command::Label(_) => Instr::Goto(i + 1),
+                Command::Goto(name) => Instr::Goto(label(name)?),
+                Command::IfGoto(name) => Instr::IfGoto(label(name)?),
+                Command::Function(name, n_locals) => {
+                    function = name;
+                    owner = Some(function_ids[name.as_str()]);
+                    Instr::Function(*n_locals)
+                }
+                Command::Call(name, n_args) => {
+                    let id = *function_ids.entry(name.as_str()).or_insert_with(|| {
+                        functions.push(Function {
+                            name: name.clone(),
+                            start: None,
+                        });
+                        functions.len() - 1
+                    });
+                    Instr::Call(id, *n_args)
+                }
+                Command::Return => Instr::Return,
+            });
+            owners.push(owner);
+        }
+
+        let pc = function_ids
+            .get("Sys.init")
+            .and_then(|&id| functions[id].start)
+            .unwrap_or(0);
+        Ok(Self {
+            ram: vec![0; MEMORY_SIZE],
+            commands,
+            instrs,
+            owners,
+            natives: vec![None; functions.len()],
+            functions,
+            static_bases,
+            files: file_starts,
+            pc,
+        })
+    }
+
+    /// Loaded commands of every file, in order.
+    pub fn commands(&self) -> &[Command] {
+        &self.commands
+    }
+
+    /// Index of the next command to execute.
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    pub fn set_pc(&mut self, pc: usize) {
+        self.pc = pc;
+    }
+
+    /// Functions defined or called by the program.
+    pub fn functions(&self) -> &[Function] {
+        &self.functions
+    }
+
+    /// RAM address of the static segment of the file `name`.
+    pub(super) fn static_base(&self, name: &str) -> u16 {
+        self.static_bases
+            .get(name)
+            .copied()
+            .unwrap_or(STATIC as u16)
+    }
+
+    /// Name of the function the next command belongs to.
+    pub fn current_function(&self) -> Option<&str> {
+        self.function_at(self.pc)
+    }
+
+    /// Line of the command at `pc` in its file `NAME.vm`, as `print_vm` writes it, with
+    /// the function it belongs to.
+    pub fn source(&self, pc: usize) -> Option<SourceLoc> {
+        if pc >= self.commands.len() {
+            return None;
+        }
+        let (name, start) = self.files.iter().rev().find(|(_, start)| *start <= pc)?;
+        let file = format! {"{}.vm", name};
+        Some(SourceLoc::new(&file, pc - start + 1, self.function_at(pc)))
+    }
+
+    /// Indices of `pc` and of the calls of the functions running, innermost first.
+    pub fn backtrace(&self) -> Vec<usize> {
+        let calls = return_addresses(&self.ram)
+            .into_iter()
+            .map(|ret| ret as usize - 1)
+            .filter(|&call| call < self.commands.len());
+        std::iter::once(self.pc).chain(calls).collect()
+    }
+
+    fn function_at(&self, pc: usize) -> Option<&str> {
+        let owner = self.owners.get(pc).or_else(|| self.owners.last())?;
+        owner.map(|id| self.functions[id].name.as_str())
+    }
+
+    /// Whether the next command is part of a loop of `goto`s and labels, as ends
+    /// `Sys.halt`, so the program will never change anything again.
+    pub fn is_halted(&self) -> bool {
+        let mut pc = self.pc;
+        for _ in 0..2 {
+            match self.instrs.get(pc) {
+                Some(Instr::Goto(target)) => pc = *target,
+                _ => return false,
+            }
+            if pc == self.pc {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Executes the command at `pc`, which stays at the command if it fails.
+    pub fn step(&mut self) -> Result<(), String> {
+        let pc = self.pc;
+        let instr = *self
+            .instrs
+            .get(pc)
+            .ok_or("Error: Program counter is past the last command")?;
+        self.execute(instr).inspect_err(|_| self.pc = pc)
+    }
+
+    fn execute(&mut self, instr: Instr) -> Result<(), String> {
+        self.pc += 1;
+        match instr {
+            Instr::Arithmetic(op) => {
+                let y = self.pop();
+                let value = match op {
+                    Op::Neg => y.wrapping_neg(),
+                    Op::Not => !y,
+                    _ => {
+                        let x = self.pop();
+                        match op {
+                            Op::Add => x.wrapping_add(y),
+                            Op::Sub => x.wrapping_sub(y),
+                            Op::Eq_ => bool_value(x == y),
+                            Op::Gt => bool_value((x as i16) > (y as i16)),
+                            Op::Lt => bool_value((x as i16) < (y as i16)),
+                            Op::And => x & y,
+                            Op::Or => x | y,
+                            Op::Neg | Op::Not => unreachable!(),
+                        }
+                    }
+                };
+                self.push(value);
+            }
+            Instr::Push(Segment::Constant, value) => self.push(value),
+            Instr::Push(segment, index) => {
+                let address = self.address(segment, index)?;
+                self.push(self.ram[address]);
+            }
+            Instr::Pop(segment, index) => {
+                let address = self.address(segment, index)?;
+                self.ram[address] = self.pop();
+            }
+            Instr::Goto(target) => self.pc = target,
+            Instr::IfGoto(target) => {
+                if self.pop() != 0 {
+                    self.pc = target;
+                }
+            }
+            Instr::Function(n_locals) => {
+                for _ in 0..n_locals {
+                    self.push(0);
+                }
+            }
+            Instr::Call(id, n_args) => {
+                let arg = self.ram[SP].wrapping_sub(n_args);
+                if let Some((_, native)) = self.natives[id].filter(|&(n, _)| n == n_args) {
+                    let args: Vec<_> = (0..n_args)
+                        .map(|i| self.ram[arg.wrapping_add(i) as usize % MEMORY_SIZE] as i16)
+                        .collect();
+                    if let Some(value) = native(self, &args) {
+                        self.ram[SP] = arg;
+                        self.push(value as u16);
+                        return Ok(());
+                    }
+                }
+                let function = &self.functions[id];
+                let start = function
+                    .start
+                    .ok_or_else(|| format! {"Error: Function {} not found", function.name})?;
+                let sp = self.ram[SP];
+                self.push(self.pc as u16);
+                for reg in LCL..=THAT {
+                    self.push(self.ram[reg]);
+                }
+                self.ram[ARG] = sp.wrapping_sub(n_args);
+                self.ram[LCL] = self.ram[SP];
+                self.pc = start;
+            }
+            Instr::Return => {
+                let frame = self.ram[LCL] as usize;
+                let ret = self.ram[frame.wrapping_sub(5) % MEMORY_SIZE];
+                let value = self.pop();
+                let arg = self.ram[ARG];
+                self.ram[arg as usize % MEMORY_SIZE] = value;
+                self.ram[SP] = arg.wrapping_add(1);
+                for reg in LCL..=THAT {
+                    self.ram[reg] = self.ram[frame.wrapping_sub(5 - reg) % MEMORY_SIZE];
+                }
+                self.pc = ret as usize;
+            }
+        }
+        Ok(())
+    }
+
+    fn push(&mut self, value: u16) {
+        let sp = self.ram[SP];
+        self.ram[sp as usize % MEMORY_SIZE] = value;
+        self.ram[SP] = sp.wrapping_add(1);
+    }
+
+    fn pop(&mut self) -> u16 {
+        let sp = self.ram[SP].wrapping_sub(1);
+        self.ram[SP] = sp;
+        self.ram[sp as usize % MEMORY_SIZE]
+    }
+
+    // RAM address of `segment index`.
+    fn address(&self, segment: Segment, index: u16) -> Result<usize, String> {
+        let address = match segment {
+            Segment::Local => self.ram[LCL].wrapping_add(index),
+            Segment::Argument => self.ram[ARG].wrapping_add(index),
+            Segment::This => self.ram[THIS].wrapping_add(index),
+            Segment::That => self.ram[THAT].wrapping_add(index),
+            Segment::Pointer if index < 2 => THIS as u16 + index,
+            Segment::Temp if index < 8 => TEMP as u16 + index,
+            Segment::Static => index,
+            _ => return Err(format! {"Error: Invalid segment index {} {}", segment, index}),
+        };
+        Ok(address as usize % MEMORY_SIZE)
+    }
+}
+
+/// Return addresses in the frames of the functions running, innermost first, where
+/// LCL points after each frame's saved return address, LCL, ARG, THIS and THAT. The
+/// frames end at an LCL that can't follow a frame, as at the start of `Sys.init`.
+pub(super) fn return_addresses(ram: &[u16]) -> Vec<u16> {
+    let mut ret = Vec::new();
+    let mut lcl = ram[LCL] as usize;
+    while (5..MEMORY_SIZE).contains(&lcl) && ret.len() < MAX_FRAMES {
+        let addr = ram[lcl - 5];
+        if addr == 0 {
+            break;
+        }
+        ret.push(addr);
+        // Callers' frames are further down the stack.
+        let caller = ram[lcl - 4] as usize;
+        if caller >= lcl {
+            break;
+        }
+        lcl = caller;
+    }
+    ret
+}
+
+fn bool_value(b: bool) -> u16 {
+    if b {
+        0xffff
+    } else {
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::parse_vm;
+
+    #[test]
+    fn test_call() {
+        let file = |name: &str, vm: &str| VmFile {
+            name: name.to_string(),
+            commands: parse_vm(vm.as_bytes()).unwrap(),
+        };
+        let main = file(
+            "Main",
+            "function Main.double 0
+push argument 0
+push argument 0
+add
+pop static 0
+push static 0
+return
+",
+        );
+        let sys = file(
+            "Sys",
+            "function Sys.init 1
+push constant 21
+call Main.double 1
+pop local 0
+label END
+goto END
+",
+        );
+        let mut vm = Vm::new(vec![main, sys]).unwrap();
+        assert_eq!(vm.pc(), 7);
+        assert_eq!(vm.current_function(), Some("Sys.init"));
+        assert_eq!(
+            vm.source(0).unwrap().function.as_deref(),
+            Some("Main.double")
+        );
+        vm.ram[SP] = 256;
+        vm.ram[LCL] = 256;
+        for _ in 0..13 {
+            vm.step().unwrap();
+        }
+        assert_eq!(vm.current_function(), Some("Sys.init"));
+        assert_eq!(vm.ram[SP], 257);
+        assert_eq!(vm.ram[256], 42);
+        assert_eq!(vm.ram[STATIC], 42);
+    }
+}
@@ -0,0 +1,6 @@
+mod builtin;
+mod parser;
+mod sim;
+
+pub use parser::{parse_hdl, BusRef, ChipDef, Connection, Part, PinDecl, MAX_WIDTH};
+pub use sim::{Chip, ChipLoader};
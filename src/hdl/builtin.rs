@@ -0,0 +1,283 @@
+use std::slice;
+
+use crate::emu::{alu, jumps};
+
+/// Chip implemented in Rust. Outputs change with the inputs named in its `Spec::comb`
+/// when evaluated, and otherwise only with the clock: `tick` samples the inputs at
+/// the rising edge and `tock` shows the result at the falling one.
+pub(super) trait Builtin {
+    fn eval(&mut self, inputs: &[u16], outputs: &mut [u16]);
+
+    fn tick(&mut self, _inputs: &[u16]) {}
+
+    fn tock(&mut self) {}
+
+    /// Stored words, such as RAM contents or a register value.
+    fn memory(&mut self) -> &mut [u16] {
+        &mut []
+    }
+}
+
+/// Interface of a builtin chip, with pins in the order `Builtin` methods take them.
+#[derive(Clone, Copy)]
+pub(super) struct Spec {
+    pub inputs: &'static [(&'static str, usize)],
+    pub outputs: &'static [(&'static str, usize)],
+    // Inputs that outputs follow without the clock.
+    pub comb: &'static [&'static str],
+    pub new: fn() -> Box<dyn Builtin>,
+}
+
+const OUT16: &[(&str, usize)] = &[("out", 16)];
+
+/// Builtin chip `name`, if there is one.
+pub(super) fn builtin(name: &str) -> Option<Spec> {
+    let ram = |inputs, new| Spec {
+        inputs,
+        outputs: OUT16,
+        comb: &["address"],
+        new,
+    };
+    Some(match name {
+        "DFF" => Spec {
+            inputs: &[("in", 1)],
+            outputs: &[("out", 1)],
+            comb: &[],
+            new: || Box::new(Register::default()),
+        },
+        "Bit" => Spec {
+            inputs: &[("in", 1), ("load", 1)],
+            outputs: &[("out", 1)],
+            comb: &[],
+            new: || Box::new(Register::default()),
+        },
+        "Register" | "ARegister" | "DRegister" => Spec {
+            inputs: &[("in", 16), ("load", 1)],
+            outputs: OUT16,
+            comb: &[],
+            new: || Box::new(Register::default()),
+        },
+        "RAM8" => ram(&[("in", 16), ("load", 1), ("address", 3)], || Ram::boxed(8)),
+        "RAM64" => ram(&[("in", 16), ("load", 1), ("address", 6)], || {
+            Ram::boxed(64)
+        }),
+        "RAM512" => ram(&[("in", 16), ("load", 1), ("address", 9)], || {
+            Ram::boxed(512)
+        }),
+        "RAM4K" => ram(&[("in", 16), ("load", 1), ("address", 12)], || {
+            Ram::boxed(4096)
+        }),
+        "RAM16K" => ram(&[("in", 16), ("load", 1), ("address", 14)], || {
+            Ram::boxed(16384)
+        }),
+        "Screen" => ram(&[("in", 16), ("load", 1), ("address", 13)], || {
+            Ram::boxed(8192)
+        }),
+        "ROM32K" => Spec {
+            inputs: &[("address", 15)],
+            outputs: OUT16,
+            comb: &["address"],
+            new: || Box::new(Rom(vec![0; 32768])),
+        },
+        "Keyboard" => Spec {
+            inputs: &[],
+            outputs: OUT16,
+            comb: &[],
+            new: || Box::new(Keyboard(0)),
+        },
+        "PC" => Spec {
+            inputs: &[("in", 16), ("load", 1), ("inc", 1), ("reset", 1)],
+            outputs: OUT16,
+            comb: &[],
+            new: || Box::new(Pc::default()),
+        },
+        "CPU" => Spec {
+            inputs: &[("inM", 16), ("instruction", 16), ("reset", 1)],
+            outputs: &[("outM", 16), ("writeM", 1), ("addressM", 15), ("pc", 15)],
+            comb: &["inM", "instruction"],
+            new: || Box::new(Cpu::default()),
+        },
+        _ => return None,
+    })
+}
+
+// `DFF`, `Bit` and the 16-bit registers. A `DFF` has no `load` and always loads.
+#[derive(Default)]
+struct Register {
+    out: u16,
+    value: u16,
+}
+
+impl Builtin for Register {
+    fn eval(&mut self, _inputs: &[u16], outputs: &mut [u16]) {
+        outputs[0] = self.out;
+    }
+
+    fn tick(&mut self, inputs: &[u16]) {
+        if inputs.get(1).is_none_or(|&load| load == 1) {
+            self.value = inputs[0];
+        }
+    }
+
+    fn tock(&mut self) {
+        self.out = self.value;
+    }
+
+    fn memory(&mut self) -> &mut [u16] {
+        slice::from_mut(&mut self.value)
+    }
+}
+
+// `RAM*` and `Screen`, whose `out` follows `address` right away.
+struct Ram {
+    words: Vec<u16>,
+    write: Option<(usize, u16)>,
+}
+
+impl Ram {
+    fn boxed(size: usize) -> Box<dyn Builtin> {
+        Box::new(Self {
+            words: vec![0; size],
+            write: None,
+        })
+    }
+}
+
+impl Builtin for Ram {
+    fn eval(&mut self, inputs: &[u16], outputs: &mut [u16]) {
+        outputs[0] = self.words[inputs[2] as usize];
+    }
+
+    fn tick(&mut self, inputs: &[u16]) {
+        if inputs[1] == 1 {
+            self.write = Some((inputs[2] as usize, inputs[0]));
+        }
+    }
+
+    fn tock(&mut self) {
+        if let Some((address, value)) = self.write.take() {
+            self.words[address] = value;
+        }
+    }
+
+    fn memory(&mut self) -> &mut [u16] {
+        &mut self.words
+    }
+}
+
+struct Rom(Vec<u16>);
+
+impl Builtin for Rom {
+    fn eval(&mut self, inputs: &[u16], outputs: &mut [u16]) {
+        outputs[0] = self.0[inputs[0] as usize];
+    }
+
+    fn memory(&mut self) -> &mut [u16] {
+        &mut self.0
+    }
+}
+
+// Code of the key pressed, or 0, which is set through `memory`.
+struct Keyboard(u16);
+
+impl Builtin for Keyboard {
+    fn eval(&mut self, _inputs: &[u16], outputs: &mut [u16]) {
+        outputs[0] = self.0;
+    }
+
+    fn memory(&mut self) -> &mut [u16] {
+        slice::from_mut(&mut self.0)
+    }
+}
+
+#[derive(Default)]
+struct Pc {
+    out: u16,
+    value: u16,
+}
+
+impl Builtin for Pc {
+    fn eval(&mut self, _inputs: &[u16], outputs: &mut [u16]) {
+        outputs[0] = self.out;
+    }
+
+    fn tick(&mut self, inputs: &[u16]) {
+        self.value = match inputs {
+            [_, _, _, 1] => 0,
+            [value, 1, _, _] => *value,
+            [_, _, 1, _] => self.out.wrapping_add(1),
+            _ => self.out,
+        };
+    }
+
+    fn tock(&mut self) {
+        self.out = self.value;
+    }
+
+    fn memory(&mut self) -> &mut [u16] {
+        slice::from_mut(&mut self.value)
+    }
+}
+
+// Registers A, D and PC, as shown to the outputs and as sampled by `tick`.
+#[derive(Default)]
+struct Cpu {
+    out: [u16; 3],
+    regs: [u16; 3],
+}
+
+const A: usize = 0;
+const D: usize = 1;
+const PC: usize = 2;
+
+impl Cpu {
+    // ALU result of C-instruction `instruction`.
+    fn comp(&self, in_m: u16, instruction: u16) -> u16 {
+        let bit = |i: u16| instruction >> i & 1 == 1;
+        let y = if bit(12) { in_m } else { self.out[A] };
+        alu(self.out[D], y, instruction >> 6 & 0x3f)
+    }
+}
+
+impl Builtin for Cpu {
+    fn eval(&mut self, inputs: &[u16], outputs: &mut [u16]) {
+        let (in_m, instruction) = (inputs[0], inputs[1]);
+        let c_instruction = instruction >> 15 == 1;
+        outputs[0] = self.comp(in_m, instruction);
+        outputs[1] = (c_instruction && instruction >> 3 & 1 == 1) as u16;
+        outputs[2] = self.out[A] & 0x7fff;
+        outputs[3] = self.out[PC] & 0x7fff;
+    }
+
+    fn tick(&mut self, inputs: &[u16]) {
+        let (in_m, instruction, reset) = (inputs[0], inputs[1], inputs[2]);
+        let mut jump = false;
+        if instruction >> 15 == 0 {
+            self.regs[A] = instruction;
+        } else {
+            let out = self.comp(in_m, instruction);
+            if instruction >> 5 & 1 == 1 {
+                self.regs[A] = out;
+            }
+            if instruction >> 4 & 1 == 1 {
+                self.regs[D] = out;
+            }
+            jump = jumps(out, instruction);
+        }
+        self.regs[PC] = if reset == 1 {
+            0
+        } else if jump {
+            self.out[A]
+        } else {
+            self.out[PC].wrapping_add(1)
+        };
+    }
+
+    fn tock(&mut self) {
+        self.out = self.regs;
+    }
+
+    fn memory(&mut self) -> &mut [u16] {
+        &mut self.regs
+    }
+}
@@ -0,0 +1,349 @@
+use std::iter::Peekable;
+use std::str::Chars;
+
+/// Widest bus a pin can have.
+pub const MAX_WIDTH: usize = 16;
+
+/// Chip interface pin, `name` or `name[width]`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PinDecl {
+    pub name: String,
+    pub width: usize,
+}
+
+/// `name`, `name[i]` or `name[i..j]`, where `range` is `(i, j)`, both inclusive.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BusRef {
+    pub name: String,
+    pub range: Option<(usize, usize)>,
+}
+
+/// `pin=wire` in a part, where `wire` may also be `true` or `false`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Connection {
+    pub pin: BusRef,
+    pub wire: BusRef,
+}
+
+/// Use of a chip inside another, e.g. `Nand(a=a, b=b, out=out);`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Part {
+    pub chip: String,
+    pub conns: Vec<Connection>,
+    pub line: usize,
+}
+
+/// Chip definition of an `.hdl` file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChipDef {
+    pub name: String,
+    pub inputs: Vec<PinDecl>,
+    pub outputs: Vec<PinDecl>,
+    pub parts: Vec<Part>,
+    /// Builtin chip implementing this one, from `BUILTIN name;` in place of `PARTS:`.
+    pub builtin: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Tok {
+    Ident(String),
+    Num(usize),
+    Sym(char),
+    // `..` of a sub bus
+    DotDot,
+}
+
+/// Parses the chip definition of an `.hdl` file.
+pub fn parse_hdl(src: &str) -> Result<ChipDef, String> {
+    let mut parser = HdlParser {
+        toks: lex(src)?,
+        pos: 0,
+    };
+    parser.chip()
+}
+
+fn lex(src: &str) -> Result<Vec<(Tok, usize)>, String> {
+    let mut toks = Vec::new();
+    let mut line = 1;
+    let mut chars: Peekable<Chars> = src.chars().peekable();
+    while let Some(c) = chars.next() {
+        let tok = match c {
+            '\n' => {
+                line += 1;
+                continue;
+            }
+            c if c.is_whitespace() => continue,
+            '/' if chars.peek() == Some(&'/') => {
+                while chars.peek().is_some_and(|&c| c != '\n') {
+                    chars.next();
+                }
+                continue;
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let start = line;
+                let mut prev = ' ';
+                loop {
+                    match chars.next() {
+                        Some('/') if prev == '*' => break,
+                        Some(c) => {
+                            if c == '\n' {
+                                line += 1;
+                            }
+                            prev = c;
+                        }
+                        None => {
+                            return Err(format! {"Error: line {}; Unclosed comment", start});
+                        }
+                    }
+                }
+                continue;
+            }
+            '.' if chars.peek() == Some(&'.') => {
+                chars.next();
+                Tok::DotDot
+            }
+            c if c.is_ascii_digit() => {
+                let mut num = c.to_digit(10).unwrap() as usize;
+                while let Some(d) = chars.peek().and_then(|c| c.to_digit(10)) {
+                    chars.next();
+                    num = num.saturating_mul(10).saturating_add(d as usize);
+                }
+                Tok::Num(num)
+            }
+            c if c.is_ascii_alphabetic() || c == '_' => {
+                let mut s = c.to_string();
+                while let Some(&c) = chars.peek() {
+                    if c.is_ascii_alphanumeric() || c == '_' {
+                        s.push(c);
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                Tok::Ident(s)
+            }
+            '{' | '}' | '(' | ')' | '[' | ']' | ',' | ';' | '=' | ':' => Tok::Sym(c),
+            _ => return Err(format! {"Error: line {}; Unexpected character {:?}", line, c}),
+        };
+        toks.push((tok, line));
+    }
+    Ok(toks)
+}
+
+struct HdlParser {
+    toks: Vec<(Tok, usize)>,
+    pos: usize,
+}
+
+impl HdlParser {
+    fn chip(&mut self) -> Result<ChipDef, String> {
+        self.keyword("CHIP")?;
+        let name = self.ident()?;
+        self.sym('{')?;
+        let inputs = if self.at_keyword("IN") {
+            self.pin_decls()?
+        } else {
+            Vec::new()
+        };
+        let outputs = if self.at_keyword("OUT") {
+            self.pin_decls()?
+        } else {
+            Vec::new()
+        };
+        let mut parts = Vec::new();
+        let mut builtin = None;
+        if self.at_keyword("BUILTIN") {
+            self.next();
+            builtin = Some(self.ident()?);
+            self.sym(';')?;
+            // Builtins know which of their pins are clocked.
+            if self.at_keyword("CLOCKED") {
+                while !matches!(self.next(), Some(Tok::Sym(';')) | None) {}
+            }
+        } else {
+            self.keyword("PARTS")?;
+            self.sym(':')?;
+            while self.peek() != Some(&Tok::Sym('}')) {
+                parts.push(self.part()?);
+            }
+        }
+        self.sym('}')?;
+        if self.peek().is_some() {
+            return Err(self.error("Expected end of file"));
+        }
+        Ok(ChipDef {
+            name,
+            inputs,
+            outputs,
+            parts,
+            builtin,
+        })
+    }
+
+    // `IN` or `OUT` and the pins after it.
+    fn pin_decls(&mut self) -> Result<Vec<PinDecl>, String> {
+        self.next();
+        let mut pins = Vec::new();
+        loop {
+            let name = self.ident()?;
+            let width = if self.peek() == Some(&Tok::Sym('[')) {
+                self.next();
+                let width = self.num()?;
+                self.sym(']')?;
+                if width == 0 || width > MAX_WIDTH {
+                    return Err(self.error(&format! {"Bus width must be 1 to {}", MAX_WIDTH}));
+                }
+                width
+            } else {
+                1
+            };
+            pins.push(PinDecl { name, width });
+            if self.peek() != Some(&Tok::Sym(',')) {
+                break;
+            }
+            self.next();
+        }
+        self.sym(';')?;
+        Ok(pins)
+    }
+
+    fn part(&mut self) -> Result<Part, String> {
+        let line = self.line();
+        let chip = self.ident()?;
+        self.sym('(')?;
+        let mut conns = Vec::new();
+        loop {
+            let pin = self.bus_ref()?;
+            self.sym('=')?;
+            let wire = self.bus_ref()?;
+            conns.push(Connection { pin, wire });
+            if self.peek() != Some(&Tok::Sym(',')) {
+                break;
+            }
+            self.next();
+        }
+        self.sym(')')?;
+        self.sym(';')?;
+        Ok(Part { chip, conns, line })
+    }
+
+    fn bus_ref(&mut self) -> Result<BusRef, String> {
+        let name = self.ident()?;
+        let range = if self.peek() == Some(&Tok::Sym('[')) {
+            self.next();
+            let lo = self.num()?;
+            let hi = if self.peek() == Some(&Tok::DotDot) {
+                self.next();
+                self.num()?
+            } else {
+                lo
+            };
+            self.sym(']')?;
+            if lo > hi {
+                return Err(self.error("Sub bus must be written low..high"));
+            }
+            Some((lo, hi))
+        } else {
+            None
+        };
+        Ok(BusRef { name, range })
+    }
+
+    fn peek(&self) -> Option<&Tok> {
+        self.toks.get(self.pos).map(|(tok, _)| tok)
+    }
+
+    fn next(&mut self) -> Option<Tok> {
+        let tok = self.toks.get(self.pos).map(|(tok, _)| tok.clone());
+        self.pos += 1;
+        tok
+    }
+
+    // Line of the next token, or of the last one at the end of the file.
+    fn line(&self) -> usize {
+        self.toks
+            .get(self.pos)
+            .or_else(|| self.toks.last())
+            .map_or(1, |(_, line)| *line)
+    }
+
+    fn error(&self, message: &str) -> String {
+        format! {"Error: line {}; {}", self.line(), message}
+    }
+
+    fn at_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Tok::Ident(s)) if s == keyword)
+    }
+
+    fn keyword(&mut self, keyword: &str) -> Result<(), String> {
+        if self.at_keyword(keyword) {
+            self.next();
+            Ok(())
+        } else {
+            Err(self.error(&format! {"Expected {}", keyword}))
+        }
+    }
+
+    fn sym(&mut self, c: char) -> Result<(), String> {
+        if self.peek() == Some(&Tok::Sym(c)) {
+            self.next();
+            Ok(())
+        } else {
+            Err(self.error(&format! {"Expected '{}'", c}))
+        }
+    }
+
+    fn ident(&mut self) -> Result<String, String> {
+        match self.peek() {
+            Some(Tok::Ident(s)) => {
+                let s = s.clone();
+                self.next();
+                Ok(s)
+            }
+            _ => Err(self.error("Expected name")),
+        }
+    }
+
+    fn num(&mut self) -> Result<usize, String> {
+        match self.peek() {
+            Some(&Tok::Num(num)) => {
+                self.next();
+                Ok(num)
+            }
+            _ => Err(self.error("Expected number")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_hdl() {
+        let def = parse_hdl(include_str!("../project01/Mux4Way16.hdl")).unwrap();
+        assert_eq!(def.name, "Mux4Way16");
+        assert_eq!(def.inputs.len(), 5);
+        assert_eq!(
+            def.inputs[4],
+            PinDecl {
+                name: "sel".to_string(),
+                width: 2
+            }
+        );
+        assert_eq!(def.outputs[0].width, 16);
+        assert_eq!(def.parts.len(), 3);
+        assert_eq!(def.parts[0].line, 14);
+        assert_eq!(
+            def.parts[0].conns[2].wire,
+            BusRef {
+                name: "sel".to_string(),
+                range: Some((0, 0))
+            }
+        );
+
+        let err = parse_hdl("CHIP A {\n IN a[17];\n PARTS:\n}").unwrap_err();
+        assert_eq!(err, "Error: line 2; Bus width must be 1 to 16");
+    }
+}
@@ -0,0 +1,657 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use super::builtin::{builtin, Builtin, Spec};
+use super::parser::{parse_hdl, BusRef, ChipDef, PinDecl};
+
+// Nets of the constants `false` and `true`.
+const FALSE: usize = 0;
+const TRUE: usize = 1;
+
+/// Finds chip definitions by name as `<name>.hdl` in a list of directories,
+/// the first one that has it winning. Builtin chips stand in for those without one.
+pub struct ChipLoader {
+    dirs: Vec<PathBuf>,
+    defs: HashMap<String, Rc<ChipDef>>,
+}
+
+impl ChipLoader {
+    pub fn new<P: Into<PathBuf>>(dirs: impl IntoIterator<Item = P>) -> Self {
+        Self {
+            dirs: dirs.into_iter().map(Into::into).collect(),
+            defs: HashMap::new(),
+        }
+    }
+
+    /// Makes `def` the definition of its chip, instead of any `.hdl` file.
+    pub fn insert(&mut self, def: ChipDef) {
+        self.defs.insert(def.name.clone(), Rc::new(def));
+    }
+
+    pub fn def(&mut self, name: &str) -> Result<Rc<ChipDef>, String> {
+        self.find_def(name)?
+            .ok_or_else(|| format! {"Error: Chip {} not found", name})
+    }
+
+    fn find_def(&mut self, name: &str) -> Result<Option<Rc<ChipDef>>, String> {
+        if let Some(def) = self.defs.get(name) {
+            return Ok(Some(def.clone()));
+        }
+        let path = match self
+            .dirs
+            .iter()
+            .map(|dir| dir.join(format! {"{}.hdl", name}))
+            .find(|path| path.is_file())
+        {
+            Some(path) => path,
+            None => return Ok(None),
+        };
+        let src = fs::read_to_string(&path).map_err(|e| format! {"{}: {}", path.display(), e})?;
+        let def = parse_hdl(&src).map_err(|e| format! {"{}: {}", path.display(), e})?;
+        if def.name != name {
+            return Err(format! {"{}: Error: Chip is named {}", path.display(), def.name});
+        }
+        let def = Rc::new(def);
+        self.defs.insert(name.to_string(), def.clone());
+        Ok(Some(def))
+    }
+
+    fn resolve(&mut self, name: &str) -> Result<Resolved, String> {
+        if name == "Nand" {
+            return Ok(Resolved::Nand);
+        }
+        match self.find_def(name)? {
+            Some(def) => match &def.builtin {
+                Some(builtin_name) => builtin(builtin_name)
+                    .map(Resolved::Builtin)
+                    .ok_or_else(|| format! {"Error: No builtin chip {}", builtin_name}),
+                None => Ok(Resolved::Hdl(def)),
+            },
+            None => builtin(name)
+                .map(Resolved::Builtin)
+                .ok_or_else(|| format! {"Error: Chip {} not found", name}),
+        }
+    }
+
+    /// Builds chip `name` down to `Nand` gates and builtin chips.
+    pub fn load(&mut self, name: &str) -> Result<Chip, String> {
+        let (inputs, outputs) = self.interface(name)?;
+        let mut builder = Builder {
+            loader: self,
+            parent: vec![FALSE, TRUE],
+            gates: Vec::new(),
+            blocks: Vec::new(),
+            stack: Vec::new(),
+        };
+        let mut pins = HashMap::new();
+        let mut new_pins = |decls: Vec<PinDecl>, builder: &mut Builder| {
+            decls
+                .into_iter()
+                .map(|pin| {
+                    let nets = builder.new_nets(pin.width);
+                    pins.insert(pin.name.clone(), nets.clone());
+                    (pin.name, nets)
+                })
+                .collect::<Vec<_>>()
+        };
+        let inputs = new_pins(inputs, &mut builder);
+        let outputs = new_pins(outputs, &mut builder);
+        builder.instantiate(name, &pins)?;
+        builder.finish(name, inputs, outputs)
+    }
+
+    fn interface(&mut self, name: &str) -> Result<(Vec<PinDecl>, Vec<PinDecl>), String> {
+        let decls = |pins: &[(&str, usize)]| {
+            pins.iter()
+                .map(|&(name, width)| PinDecl {
+                    name: name.to_string(),
+                    width,
+                })
+                .collect()
+        };
+        Ok(match self.resolve(name)? {
+            Resolved::Nand => (decls(&[("a", 1), ("b", 1)]), decls(&[("out", 1)])),
+            Resolved::Builtin(spec) => (decls(spec.inputs), decls(spec.outputs)),
+            Resolved::Hdl(def) => (def.inputs.clone(), def.outputs.clone()),
+        })
+    }
+}
+
+enum Resolved {
+    Nand,
+    Builtin(Spec),
+    Hdl(Rc<ChipDef>),
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Gate {
+    Nand { a: usize, b: usize, out: usize },
+    // Index in `Chip::blocks`
+    Builtin(usize),
+}
+
+// Builtin chip with the nets of its pins.
+struct Block {
+    name: String,
+    chip: Box<dyn Builtin>,
+    inputs: Vec<Vec<usize>>,
+    outputs: Vec<Vec<usize>>,
+    // Nets of the inputs in `Spec::comb`
+    comb: Vec<usize>,
+}
+
+// Flattens a chip into `Nand` gates over nets, where each net is one bit that
+// connected pins share through union-find.
+struct Builder<'a> {
+    loader: &'a mut ChipLoader,
+    parent: Vec<usize>,
+    gates: Vec<Gate>,
+    blocks: Vec<Block>,
+    // Chips being instantiated, to catch ones that contain themselves.
+    stack: Vec<String>,
+}
+
+impl<'a> Builder<'a> {
+    fn new_nets(&mut self, width: usize) -> Vec<usize> {
+        let start = self.parent.len();
+        self.parent.extend(start..start + width);
+        (start..start + width).collect()
+    }
+
+    fn find(&mut self, mut net: usize) -> usize {
+        while self.parent[net] != net {
+            self.parent[net] = self.parent[self.parent[net]];
+            net = self.parent[net];
+        }
+        net
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let (a, b) = (self.find(a), self.find(b));
+        // Keep the constants as roots so that they stay recognizable.
+        if a < b {
+            self.parent[b] = a;
+        } else {
+            self.parent[a] = b;
+        }
+    }
+
+    // Adds the gates of chip `name`, whose pins are connected to `pins`.
+    fn instantiate(
+        &mut self,
+        name: &str,
+        pins: &HashMap<String, Vec<usize>>,
+    ) -> Result<(), String> {
+        let def = match self.loader.resolve(name)? {
+            Resolved::Nand => {
+                self.gates.push(Gate::Nand {
+                    a: pins["a"][0],
+                    b: pins["b"][0],
+                    out: pins["out"][0],
+                });
+                return Ok(());
+            }
+            Resolved::Builtin(spec) => {
+                let nets = |decls: &[(&str, usize)]| {
+                    decls.iter().map(|(pin, _)| pins[*pin].clone()).collect()
+                };
+                let comb = spec
+                    .comb
+                    .iter()
+                    .flat_map(|pin| pins[*pin].iter().copied())
+                    .collect();
+                self.gates.push(Gate::Builtin(self.blocks.len()));
+                self.blocks.push(Block {
+                    name: name.to_string(),
+                    chip: (spec.new)(),
+                    inputs: nets(spec.inputs),
+                    outputs: nets(spec.outputs),
+                    comb,
+                });
+                return Ok(());
+            }
+            Resolved::Hdl(def) => def,
+        };
+        if self.stack.iter().any(|chip| chip == name) {
+            return Err(format! {"Error: Chip {} contains itself", name});
+        }
+        self.stack.push(name.to_string());
+
+        let mut wires = pins.clone();
+        let mut internal = Vec::new();
+        // Bits of wires fed by part outputs, which each can be only once.
+        let mut fed = HashSet::new();
+        for part in &def.parts {
+            let err =
+                |message: String| format! {"{}: Error: line {}; {}", def.name, part.line, message};
+            let (inputs, outputs) = self.loader.interface(&part.chip)?;
+            let mut part_pins = HashMap::new();
+            for pin in inputs.iter().chain(&outputs) {
+                part_pins.insert(pin.name.clone(), self.new_nets(pin.width));
+            }
+            for conn in &part.conns {
+                let is_output = outputs.iter().any(|pin| pin.name == conn.pin.name);
+                let pin_nets = part_pins
+                    .get(&conn.pin.name)
+                    .ok_or_else(|| err(format! {"{} has no pin {}", part.chip, conn.pin.name}))?;
+                let pin_nets = slice(pin_nets, &conn.pin).map_err(err)?.to_vec();
+                let wire_nets = match conn.wire.name.as_str() {
+                    "true" | "false" if is_output => {
+                        return Err(err(
+                            format! {"Can't connect output {} to a constant", conn.pin.name},
+                        ));
+                    }
+                    "true" | "false" if conn.wire.range.is_some() => {
+                        return Err(err("Constants can't have a sub bus".to_string()));
+                    }
+                    "true" => vec![TRUE; pin_nets.len()],
+                    "false" => vec![FALSE; pin_nets.len()],
+                    name => {
+                        if !wires.contains_key(name) {
+                            if conn.wire.range.is_some() {
+                                return Err(err(
+                                    format! {"Internal pin {} can't have a sub bus", name},
+                                ));
+                            }
+                            wires.insert(name.to_string(), self.new_nets(pin_nets.len()));
+                            internal.push(name.to_string());
+                        } else if conn.wire.range.is_some() && !pins.contains_key(name) {
+                            return Err(err(
+                                format! {"Internal pin {} can't have a sub bus", name},
+                            ));
+                        }
+                        slice(&wires[name], &conn.wire).map_err(err)?.to_vec()
+                    }
+                };
+                if wire_nets.len() != pin_nets.len() {
+                    return Err(err(format! {
+                        "{} has width {}, but {} has width {}",
+                        conn.pin.name,
+                        pin_nets.len(),
+                        conn.wire.name,
+                        wire_nets.len()
+                    }));
+                }
+                if is_output {
+                    if def.inputs.iter().any(|pin| pin.name == conn.wire.name) {
+                        return Err(err(format! {"Can't feed input pin {}", conn.wire.name}));
+                    }
+                    let lo = conn.wire.range.map_or(0, |(lo, _)| lo);
+                    for i in lo..lo + wire_nets.len() {
+                        if !fed.insert((conn.wire.name.clone(), i)) {
+                            return Err(err(
+                                format! {"{} is fed by more than one part", conn.wire.name},
+                            ));
+                        }
+                    }
+                }
+                for (&pin, &wire) in pin_nets.iter().zip(&wire_nets) {
+                    self.union(pin, wire);
+                }
+            }
+            self.instantiate(&part.chip, &part_pins)?;
+        }
+        for name in internal {
+            if (0..wires[&name].len()).any(|i| !fed.contains(&(name.clone(), i))) {
+                return Err(
+                    format! {"{}: Error: Internal pin {} is not fed by any part", def.name, name},
+                );
+            }
+        }
+        self.stack.pop();
+        Ok(())
+    }
+
+    // Orders the gates so that each comes after those driving its inputs.
+    fn finish(
+        mut self,
+        name: &str,
+        inputs: Vec<(String, Vec<usize>)>,
+        outputs: Vec<(String, Vec<usize>)>,
+    ) -> Result<Chip, String> {
+        let gates = std::mem::take(&mut self.gates);
+        let mut blocks = std::mem::take(&mut self.blocks);
+        let gates: Vec<_> = gates
+            .into_iter()
+            .map(|gate| match gate {
+                Gate::Nand { a, b, out } => Gate::Nand {
+                    a: self.find(a),
+                    b: self.find(b),
+                    out: self.find(out),
+                },
+                builtin => builtin,
+            })
+            .collect();
+        for block in &mut blocks {
+            for net in block.inputs.iter_mut().chain(&mut block.outputs).flatten() {
+                *net = self.find(*net);
+            }
+            for net in &mut block.comb {
+                *net = self.find(*net);
+            }
+        }
+        // Nets a gate reads before it can be evaluated, and those it writes.
+        let deps = |gate: &Gate| -> (Vec<usize>, Vec<usize>) {
+            match *gate {
+                Gate::Nand { a, b, out } => (vec![a, b], vec![out]),
+                Gate::Builtin(i) => (
+                    blocks[i].comb.clone(),
+                    blocks[i].outputs.iter().flatten().copied().collect(),
+                ),
+            }
+        };
+        let mut driver = vec![None; self.parent.len()];
+        for (i, gate) in gates.iter().enumerate() {
+            for out in deps(gate).1 {
+                driver[out] = Some(i);
+            }
+        }
+        let mut users = vec![Vec::new(); gates.len()];
+        let mut pending = vec![0; gates.len()];
+        for (i, gate) in gates.iter().enumerate() {
+            for input in deps(gate).0 {
+                if let Some(d) = driver[input] {
+                    users[d].push(i);
+                    pending[i] += 1;
+                }
+            }
+        }
+        let mut ready: Vec<_> = (0..gates.len()).filter(|&i| pending[i] == 0).collect();
+        let mut order = Vec::with_capacity(gates.len());
+        while let Some(i) = ready.pop() {
+            order.push(gates[i]);
+            for &user in &users[i] {
+                pending[user] -= 1;
+                if pending[user] == 0 {
+                    ready.push(user);
+                }
+            }
+        }
+        if order.len() < gates.len() {
+            return Err(format! {"Error: Chip {} has a combinational loop", name});
+        }
+
+        let mut resolve = |pins: Vec<(String, Vec<usize>)>| {
+            pins.into_iter()
+                .map(|(name, nets)| (name, nets.into_iter().map(|n| self.find(n)).collect()))
+                .collect()
+        };
+        let inputs = resolve(inputs);
+        let outputs = resolve(outputs);
+        let mut values = vec![false; self.parent.len()];
+        values[TRUE] = true;
+        let mut chip = Chip {
+            name: name.to_string(),
+            inputs,
+            outputs,
+            gates: order,
+            blocks,
+            values,
+        };
+        chip.eval();
+        Ok(chip)
+    }
+}
+
+// Nets of `bus` within `nets`, all of them if it has no range.
+fn slice<'n>(nets: &'n [usize], bus: &BusRef) -> Result<&'n [usize], String> {
+    match bus.range {
+        None => Ok(nets),
+        Some((lo, hi)) if hi < nets.len() => Ok(&nets[lo..=hi]),
+        Some(_) => Err(format! {"Sub bus of {} is out of range 0..{}", bus.name, nets.len() - 1}),
+    }
+}
+
+/// Chip built from `Nand` gates and builtin chips. Input values are set with `set`,
+/// and outputs follow after `eval`, or for clocked chips after a `tick` and a `tock`.
+pub struct Chip {
+    name: String,
+    inputs: Vec<(String, Vec<usize>)>,
+    outputs: Vec<(String, Vec<usize>)>,
+    gates: Vec<Gate>,
+    blocks: Vec<Block>,
+    values: Vec<bool>,
+}
+
+impl Chip {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Names and widths of the input pins.
+    pub fn inputs(&self) -> impl Iterator<Item = (&str, usize)> {
+        self.inputs
+            .iter()
+            .map(|(name, nets)| (name.as_str(), nets.len()))
+    }
+
+    /// Names and widths of the output pins.
+    pub fn outputs(&self) -> impl Iterator<Item = (&str, usize)> {
+        self.outputs
+            .iter()
+            .map(|(name, nets)| (name.as_str(), nets.len()))
+    }
+
+    /// Sets input `pin`, bit `i` of the bus to bit `i` of `value`.
+    pub fn set(&mut self, pin: &str, value: u16) -> Result<(), String> {
+        let nets = self
+            .inputs
+            .iter()
+            .find(|(name, _)| name == pin)
+            .map(|(_, nets)| nets)
+            .ok_or_else(|| format! {"Error: {} has no input pin {}", self.name, pin})?;
+        for (i, &net) in nets.iter().enumerate() {
+            // Inputs tied to a constant or to each other keep their value.
+            if net > TRUE {
+                self.values[net] = value >> i & 1 == 1;
+            }
+        }
+        Ok(())
+    }
+
+    /// Value of input or output `pin`.
+    pub fn get(&self, pin: &str) -> Result<u16, String> {
+        let nets = self
+            .inputs
+            .iter()
+            .chain(&self.outputs)
+            .find(|(name, _)| name == pin)
+            .map(|(_, nets)| nets)
+            .ok_or_else(|| format! {"Error: {} has no pin {}", self.name, pin})?;
+        Ok(read(&self.values, nets))
+    }
+
+    /// Propagates the inputs through the gates.
+    pub fn eval(&mut self) {
+        let values = &mut self.values;
+        for gate in &self.gates {
+            match *gate {
+                Gate::Nand { a, b, out } => values[out] = !(values[a] && values[b]),
+                Gate::Builtin(i) => {
+                    let block = &mut self.blocks[i];
+                    let inputs: Vec<_> =
+                        block.inputs.iter().map(|nets| read(values, nets)).collect();
+                    let mut outputs = vec![0; block.outputs.len()];
+                    block.chip.eval(&inputs, &mut outputs);
+                    for (nets, &value) in block.outputs.iter().zip(&outputs) {
+                        write(values, nets, value);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Rising clock edge, when clocked chips sample their inputs.
+    pub fn tick(&mut self) {
+        self.eval();
+        let values = &self.values;
+        for block in &mut self.blocks {
+            let inputs: Vec<_> = block.inputs.iter().map(|nets| read(values, nets)).collect();
+            block.chip.tick(&inputs);
+        }
+    }
+
+    /// Falling clock edge, when clocked chips change their outputs.
+    pub fn tock(&mut self) {
+        for block in &mut self.blocks {
+            block.chip.tock();
+        }
+        self.eval();
+    }
+
+    /// Words stored in the first builtin part named `chip`, such as the contents of
+    /// a `RAM16K`, the program in a `ROM32K`, the key of a `Keyboard`, or the value
+    /// a `Register` takes at the next `tock`.
+    pub fn memory(&mut self, chip: &str) -> Option<&mut [u16]> {
+        self.blocks
+            .iter_mut()
+            .find(|block| block.name == chip)
+            .map(|block| block.chip.memory())
+    }
+}
+
+fn read(values: &[bool], nets: &[usize]) -> u16 {
+    nets.iter()
+        .enumerate()
+        .fold(0, |acc, (i, &net)| acc | (values[net] as u16) << i)
+}
+
+fn write(values: &mut [bool], nets: &[usize], value: u16) {
+    for (i, &net) in nets.iter().enumerate() {
+        values[net] = value >> i & 1 == 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::parse_hdl;
+    use super::*;
+
+    fn loader() -> ChipLoader {
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/src");
+        ChipLoader::new(vec![
+            PathBuf::from(dir).join("project02"),
+            PathBuf::from(dir).join("project01"),
+        ])
+    }
+
+    #[test]
+    fn test_mux4way16() {
+        let mut chip = loader().load("Mux4Way16").unwrap();
+        let inputs = [0x1234, 0xbeef, 0x0f0f, 0xffff];
+        for (pin, &value) in ["a", "b", "c", "d"].iter().zip(&inputs) {
+            chip.set(pin, value).unwrap();
+        }
+        for (sel, &value) in inputs.iter().enumerate() {
+            chip.set("sel", sel as u16).unwrap();
+            chip.eval();
+            assert_eq!(chip.get("out").unwrap(), value);
+        }
+    }
+
+    #[test]
+    fn test_alu() {
+        let mut chip = loader().load("ALU").unwrap();
+        let (x, y): (u16, u16) = (0x1234, 0x00ff);
+        // zx nx zy ny f no, and the result
+        let cases = [
+            (0b101010, 0),
+            (0b111111, 1),
+            (0b000010, x.wrapping_add(y)),
+            (0b010011, x.wrapping_sub(y)),
+            (0b000000, x & y),
+            (0b010101, x | y),
+            (0b001111, x.wrapping_neg()),
+        ];
+        chip.set("x", x).unwrap();
+        chip.set("y", y).unwrap();
+        for &(bits, out) in &cases {
+            for (i, pin) in ["no", "f", "ny", "zy", "nx", "zx"].iter().enumerate() {
+                chip.set(pin, bits >> i & 1).unwrap();
+            }
+            chip.eval();
+            assert_eq!(chip.get("out").unwrap(), out, "{:06b}", bits);
+            assert_eq!(chip.get("zr").unwrap(), (out == 0) as u16);
+            assert_eq!(chip.get("ng").unwrap(), out >> 15);
+        }
+    }
+
+    #[test]
+    fn test_bit() {
+        let mut loader = loader();
+        loader
+            .dirs
+            .push(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/project03/a"));
+        let mut chip = loader.load("Bit").unwrap();
+        let mut prev = 0;
+        // in, load, and out after the clock cycle
+        for &(input, load, out) in &[(1, 0, 0), (1, 1, 1), (0, 0, 1), (0, 1, 0)] {
+            chip.set("in", input).unwrap();
+            chip.set("load", load).unwrap();
+            chip.tick();
+            assert_eq!(chip.get("out").unwrap(), prev);
+            chip.tock();
+            assert_eq!(chip.get("out").unwrap(), out);
+            prev = out;
+        }
+    }
+
+    #[test]
+    fn test_computer() {
+        let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src");
+        let mut loader = ChipLoader::new(vec![
+            dir.join("project05"),
+            dir.join("project02"),
+            dir.join("project01"),
+        ]);
+        let mut chip = loader.load("Computer").unwrap();
+        let program = include_str!("../project05/Add.hack");
+        let rom = chip.memory("ROM32K").unwrap();
+        for (word, line) in rom.iter_mut().zip(program.lines()) {
+            *word = u16::from_str_radix(line.trim(), 2).unwrap();
+        }
+        for _ in 0..6 {
+            chip.tick();
+            chip.tock();
+        }
+        assert_eq!(chip.memory("RAM16K").unwrap()[0], 5);
+        assert_eq!(chip.memory("PC").unwrap()[0], 6);
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut loader = loader();
+        let def = |src: &str| parse_hdl(src).unwrap();
+        loader.insert(def(
+            "CHIP Loop { IN a; OUT out;\n PARTS:\n Nand(a=a, b=x, out=x); Not(in=x, out=out); }",
+        ));
+        loader.insert(def(
+            "CHIP Twice { IN a; OUT out;\n PARTS:\n Not(in=a, out=out);\n Not(in=a, out=out); }",
+        ));
+        loader.insert(def(
+            "CHIP Wide { IN a[2]; OUT out;\n PARTS:\n Not(in=a, out=out); }",
+        ));
+        loader.insert(def(
+            "CHIP Unfed { IN a; OUT out;\n PARTS:\n Nand(a=a, b=x, out=out); }",
+        ));
+        let err = |loader: &mut ChipLoader, name| loader.load(name).err().unwrap();
+        assert_eq!(
+            err(&mut loader, "Loop"),
+            "Error: Chip Loop has a combinational loop"
+        );
+        assert_eq!(
+            err(&mut loader, "Twice"),
+            "Twice: Error: line 4; out is fed by more than one part"
+        );
+        assert_eq!(
+            err(&mut loader, "Wide"),
+            "Wide: Error: line 3; in has width 1, but a has width 2"
+        );
+        assert_eq!(
+            err(&mut loader, "Unfed"),
+            "Unfed: Error: Internal pin x is not fed by any part"
+        );
+    }
+}
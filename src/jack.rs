@@ -1,10 +1,24 @@
-mod ast;
+pub mod ast;
+mod diagnostic;
+mod flow;
+pub mod fold;
+pub mod ide;
+mod json;
 mod lexer;
 mod parser;
+pub mod pretty;
 mod symbol_table;
 mod token;
+pub mod visit;
 mod vm_gen;
+mod xml;
 
-pub use lexer::tokenize;
+pub use diagnostic::Diagnostic;
+pub use flow::check_returns;
+pub use json::{Json, ToJson};
+pub use lexer::{
+    tokenize, tokenize_extended, tokenize_extended_with_comments, tokenize_with_comments,
+};
 pub use parser::Parser;
+pub use token::{tokens_to_xml, Comment, Keyword, Pos, Span, Symbol, Token};
 pub use vm_gen::VmGen;
@@ -1,38 +1,66 @@
 use std::rc::Rc;
 
-pub struct ClassDec {
+use super::token::Span;
+use super::xml::XmlWriter;
+
+/// Identifier together with where it appears in the source.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Ident {
     pub name: Rc<String>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClassDec {
+    pub name: Ident,
     pub var_decs: Vec<ClassVarDec>,
     pub subroutine_decs: Vec<SubRoutineDec>,
+    pub span: Span,
 }
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct ClassVarDec {
     pub var_ty: ClassVarType,
     pub ty: Type,
-    pub names: Vec<Rc<String>>,
+    pub names: Vec<Ident>,
+    pub span: Span,
 }
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct SubRoutineDec {
-    pub name: Rc<String>,
+    pub name: Ident,
     pub kind: SubRoutineKind,
     pub ret: Type,
-    pub args: Vec<(Type, Rc<String>)>,
+    pub args: Vec<(Type, Ident)>,
     pub body: SubRoutineBody,
+    pub span: Span,
 }
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct SubRoutineBody {
     pub var_decs: Vec<VarDec>,
     pub stmts: Vec<Stmt>,
+    pub span: Span,
 }
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct VarDec {
-    pub names: Vec<Rc<String>>,
+    pub names: Vec<Ident>,
     pub ty: Type,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stmt {
+    pub kind: StmtKind,
+    pub span: Span,
 }
 
-pub enum Stmt {
+#[derive(Debug, Clone, PartialEq)]
+#[allow(clippy::large_enum_variant)]
+pub enum StmtKind {
     Let {
-        name: Rc<String>,
+        name: Ident,
         idx: Option<Expr>,
         expr: Expr,
     },
@@ -49,50 +77,63 @@ pub enum Stmt {
     Return(Option<Expr>),
 }
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct Expr {
     pub lhs: Term,
-    pub cdr: Option<(Binop, Box<Term>)>,
+    /// Operators and terms after `lhs`, applied from left to right.
+    pub cdr: Vec<(Binop, Term)>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Term {
+    pub kind: TermKind,
+    pub span: Span,
 }
 
-pub enum Term {
+#[derive(Debug, Clone, PartialEq)]
+pub enum TermKind {
     IntegerConstant(u16),
     StringConstant(Rc<String>),
     KeywordConstant(KeywordConstant),
-    ValName(Rc<String>),
+    ValName(Ident),
     WithUnary(Unop, Box<Term>),
-    WithIdx(Rc<String>, Box<Expr>),
+    WithIdx(Ident, Box<Expr>),
     SubRoutineCall(SubRoutineCall),
     Expr(Box<Expr>),
 }
 
-#[derive(PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Copy)]
 pub enum ClassVarType {
     Static,
     Field,
 }
 
+#[derive(Debug, Clone, PartialEq)]
 pub enum Type {
-    Class(Rc<String>),
+    Class(Ident),
     Int,
     Char,
     Boolean,
     Void,
 }
 
-#[derive(PartialEq, Eq, Clone, Copy)]
+#[derive(Debug, Clone, PartialEq, Eq, Copy)]
 pub enum SubRoutineKind {
     Constructor,
     Function,
     Method,
 }
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct SubRoutineCall {
-    pub obj_name: Option<Rc<String>>,
-    pub routine_name: Rc<String>,
+    pub obj_name: Option<Ident>,
+    pub routine_name: Ident,
     pub args: Vec<Expr>,
+    pub span: Span,
 }
 
-#[derive(Clone, Copy)]
+#[derive(Debug, Clone, PartialEq, Eq, Copy)]
 pub enum Binop {
     Plus,
     Minus,
@@ -105,12 +146,13 @@ pub enum Binop {
     Equal,
 }
 
-#[derive(Clone, Copy)]
+#[derive(Debug, Clone, PartialEq, Eq, Copy)]
 pub enum Unop {
     Minus,
     BitNot,
 }
 
+#[derive(Debug, Clone, PartialEq, Eq, Copy)]
 pub enum KeywordConstant {
     True,
     False,
@@ -118,54 +160,46 @@ pub enum KeywordConstant {
     This,
 }
 
-macro_rules! symbol {
-    ($s:expr) => {
-        concat!("<symbol> ", $s, " </symbol>\n")
-    };
-}
-
-macro_rules! keyword {
-    ($s:expr) => {
-        concat!("<keyword> ", $s, " </keyword>\n");
-    };
-}
+impl Ident {
+    pub fn new(name: Rc<String>, span: Span) -> Self {
+        Self { name, span }
+    }
 
-macro_rules! ident {
-    ($s:expr) => {
-        format!("<identifier> {} </identifier>\n", $s)
-    };
+    pub fn as_str(&self) -> &str {
+        self.name.as_str()
+    }
 }
 
 impl ClassDec {
     pub fn new(
-        name: Rc<String>,
+        name: Ident,
         var_decs: Vec<ClassVarDec>,
         subroutine_decs: Vec<SubRoutineDec>,
+        span: Span,
     ) -> Self {
         Self {
             name,
             var_decs,
             subroutine_decs,
+            span,
         }
     }
 
     pub fn to_xml(&self) -> String {
-        let mut ret = String::new();
-        ret.push_str("<class>\n");
-        ret.push_str(keyword! {"class"});
-        ret.push_str(&ident! {self.name});
-        ret.push_str(symbol!("{"));
-        if !self.var_decs.is_empty() {
-            for var_dec in &self.var_decs {
-                var_dec.write(&mut ret);
-            }
+        let mut w = XmlWriter::new();
+        w.open("class");
+        w.keyword("class");
+        w.ident(self.name.as_str());
+        w.symbol("{");
+        for var_dec in &self.var_decs {
+            var_dec.write(&mut w);
         }
         for routine_dec in &self.subroutine_decs {
-            &routine_dec.write(&mut ret);
+            routine_dec.write(&mut w);
         }
-        ret.push_str(symbol! {"}"});
-        ret.push_str("</class>\n");
-        ret
+        w.symbol("}");
+        w.close("class");
+        w.finish()
     }
 
     pub fn name(&self) -> &str {
@@ -174,34 +208,33 @@ impl ClassDec {
 }
 
 impl ClassVarDec {
-    pub fn new(var_ty: ClassVarType, ty: Type, names: Vec<Rc<String>>) -> Self {
-        Self { var_ty, ty, names }
+    pub fn new(var_ty: ClassVarType, ty: Type, names: Vec<Ident>, span: Span) -> Self {
+        Self {
+            var_ty,
+            ty,
+            names,
+            span,
+        }
     }
 
-    fn write(&self, s: &mut String) {
-        s.push_str("<classVarDec>\n");
-        match self.var_ty {
-            ClassVarType::Field => s.push_str(keyword! {"field"}),
-            ClassVarType::Static => s.push_str(keyword! {"static"}),
-        }
-        self.ty.write(s);
-        s.push_str(&ident! {self.names[0]});
-        for name in self.names.iter().skip(1) {
-            s.push_str(symbol! {","});
-            s.push_str(&ident! {name});
-        }
-        s.push_str(symbol! {";"});
-        s.push_str("</classVarDec>\n");
+    fn write(&self, w: &mut XmlWriter) {
+        w.open("classVarDec");
+        w.keyword(self.var_ty.as_str());
+        self.ty.write(w);
+        write_names(&self.names, w);
+        w.symbol(";");
+        w.close("classVarDec");
     }
 }
 
 impl SubRoutineDec {
     pub fn new(
-        name: Rc<String>,
+        name: Ident,
         kind: SubRoutineKind,
         ret: Type,
-        args: Vec<(Type, Rc<String>)>,
+        args: Vec<(Type, Ident)>,
         body: SubRoutineBody,
+        span: Span,
     ) -> Self {
         Self {
             name,
@@ -209,258 +242,335 @@ impl SubRoutineDec {
             ret,
             args,
             body,
+            span,
         }
     }
 
-    fn write(&self, s: &mut String) {
-        s.push_str("<subroutineDec>\n");
-        match self.kind {
-            SubRoutineKind::Constructor => s.push_str(keyword! {"constructor"}),
-            SubRoutineKind::Function => s.push_str(keyword! { "function"}),
-            SubRoutineKind::Method => s.push_str(keyword! {"method"}),
-        }
-        &self.ret.write(s);
-        s.push_str(&ident! {self.name});
-        s.push_str(symbol! {"("});
-        s.push_str("<parameterList>\n");
+    fn write(&self, w: &mut XmlWriter) {
+        w.open("subroutineDec");
+        w.keyword(self.kind.as_str());
+        self.ret.write(w);
+        w.ident(self.name.as_str());
+        w.symbol("(");
+        w.open("parameterList");
         for (i, (ty, name)) in self.args.iter().enumerate() {
             if i > 0 {
-                s.push_str(symbol! {","});
+                w.symbol(",");
             }
-            ty.write(s);
-            s.push_str(&ident! {name});
+            ty.write(w);
+            w.ident(name.as_str());
         }
-        s.push_str("</parameterList>\n");
-        s.push_str(symbol! {")"});
-        self.body.write(s);
-        s.push_str("</subroutineDec>\n");
+        w.close("parameterList");
+        w.symbol(")");
+        self.body.write(w);
+        w.close("subroutineDec");
     }
 }
 
 impl SubRoutineBody {
-    pub fn new(var_decs: Vec<VarDec>, stmts: Vec<Stmt>) -> Self {
-        Self { var_decs, stmts }
+    pub fn new(var_decs: Vec<VarDec>, stmts: Vec<Stmt>, span: Span) -> Self {
+        Self {
+            var_decs,
+            stmts,
+            span,
+        }
     }
 
-    fn write(&self, s: &mut String) {
-        s.push_str("<subroutineBody>\n");
-        s.push_str(symbol! {"{"});
+    fn write(&self, w: &mut XmlWriter) {
+        w.open("subroutineBody");
+        w.symbol("{");
         for var_dec in &self.var_decs {
-            s.push_str("<varDec>\n");
-            var_dec.write(s);
-            s.push_str("</varDec>\n");
-        }
-        s.push_str("<statements>\n");
-        for stmt in &self.stmts {
-            stmt.write(s);
+            var_dec.write(w);
         }
-        s.push_str("</statements>\n");
-        s.push_str(symbol! {"}"});
-        s.push_str("</subroutineBody>\n");
+        write_stmts(&self.stmts, w);
+        w.symbol("}");
+        w.close("subroutineBody");
     }
 }
 
 impl VarDec {
-    pub fn new(names: Vec<Rc<String>>, ty: Type) -> Self {
-        Self { names, ty }
+    pub fn new(names: Vec<Ident>, ty: Type, span: Span) -> Self {
+        Self { names, ty, span }
     }
 
-    fn write(&self, s: &mut String) {
-        s.push_str(keyword! {"var"});
-        self.ty.write(s);
-        s.push_str(&ident!(self.names[0]));
-        for name in self.names.iter().skip(1) {
-            s.push_str(symbol!(","));
-            s.push_str(&ident! {name});
+    fn write(&self, w: &mut XmlWriter) {
+        w.open("varDec");
+        w.keyword("var");
+        self.ty.write(w);
+        write_names(&self.names, w);
+        w.symbol(";");
+        w.close("varDec");
+    }
+}
+
+fn write_names(names: &[Ident], w: &mut XmlWriter) {
+    for (i, name) in names.iter().enumerate() {
+        if i > 0 {
+            w.symbol(",");
         }
-        s.push_str(symbol!(";"));
+        w.ident(name.as_str());
     }
 }
 
+fn write_stmts(stmts: &[Stmt], w: &mut XmlWriter) {
+    w.open("statements");
+    for stmt in stmts {
+        stmt.write(w);
+    }
+    w.close("statements");
+}
+
 impl Stmt {
-    fn write(&self, s: &mut String) {
-        match self {
-            Stmt::Let { name, idx, expr } => {
-                s.push_str("<letStatement>\n");
-                s.push_str(keyword! {"let"});
-                s.push_str(&ident! {name});
-                if let Some(expr) = idx {
-                    s.push_str(symbol! {"["});
-                    expr.write(s);
-                    s.push_str(symbol! {"]"});
+    pub fn new(kind: StmtKind, span: Span) -> Self {
+        Self { kind, span }
+    }
+
+    fn write(&self, w: &mut XmlWriter) {
+        match &self.kind {
+            StmtKind::Let { name, idx, expr } => {
+                w.open("letStatement");
+                w.keyword("let");
+                w.ident(name.as_str());
+                if let Some(idx) = idx {
+                    w.symbol("[");
+                    idx.write(w);
+                    w.symbol("]");
                 }
-                s.push_str(symbol! {"="});
-                expr.write(s);
-                s.push_str(symbol! {";"});
-                s.push_str("</letStatement>\n");
+                w.symbol("=");
+                expr.write(w);
+                w.symbol(";");
+                w.close("letStatement");
             }
-            Stmt::If { test, then, else_ } => {
-                s.push_str("<ifStatement>\n");
-                s.push_str(keyword! {"if"});
-                s.push_str(symbol! {"("});
-                test.write(s);
-                s.push_str(symbol! {")"});
-                s.push_str(symbol! {"{"});
-                s.push_str("<statements>\n");
-                for stmt in then {
-                    stmt.write(s);
-                }
-                s.push_str("</statements>\n");
-                s.push_str(symbol! {"}"});
+            StmtKind::If { test, then, else_ } => {
+                w.open("ifStatement");
+                w.keyword("if");
+                w.symbol("(");
+                test.write(w);
+                w.symbol(")");
+                w.symbol("{");
+                write_stmts(then, w);
+                w.symbol("}");
                 if let Some(else_) = else_ {
-                    s.push_str(keyword!("else"));
-                    s.push_str(symbol! {"{"});
-                    s.push_str("<statements>\n");
-                    for stmt in else_ {
-                        stmt.write(s);
-                    }
-                    s.push_str("</statements>\n");
-                    s.push_str(symbol! {"}"});
+                    w.keyword("else");
+                    w.symbol("{");
+                    write_stmts(else_, w);
+                    w.symbol("}");
                 }
-                s.push_str("</ifStatement>\n");
+                w.close("ifStatement");
             }
-            Stmt::While { test, body } => {
-                s.push_str("<whileStatement>\n");
-                s.push_str(keyword! {"while"});
-                s.push_str(symbol! {"("});
-                test.write(s);
-                s.push_str(symbol! {")"});
-                s.push_str(symbol! {"{"});
-                s.push_str("<statements>\n");
-                for stmt in body {
-                    stmt.write(s);
-                }
-                s.push_str("</statements>\n");
-                s.push_str(symbol! {"}"});
-                s.push_str("</whileStatement>\n");
+            StmtKind::While { test, body } => {
+                w.open("whileStatement");
+                w.keyword("while");
+                w.symbol("(");
+                test.write(w);
+                w.symbol(")");
+                w.symbol("{");
+                write_stmts(body, w);
+                w.symbol("}");
+                w.close("whileStatement");
             }
-            Stmt::Do(subroutine_call) => {
-                s.push_str("<doStatement>\n");
-                s.push_str(keyword! {"do"});
-                subroutine_call.write(s);
-                s.push_str(symbol! {";"});
-                s.push_str("</doStatement>\n");
+            StmtKind::Do(subroutine_call) => {
+                w.open("doStatement");
+                w.keyword("do");
+                subroutine_call.write(w);
+                w.symbol(";");
+                w.close("doStatement");
             }
-            Stmt::Return(expr) => {
-                s.push_str("<returnStatement>\n");
-                s.push_str(keyword! {"return"});
+            StmtKind::Return(expr) => {
+                w.open("returnStatement");
+                w.keyword("return");
                 if let Some(expr) = expr {
-                    expr.write(s);
+                    expr.write(w);
                 }
-                s.push_str(symbol! {";"});
-                s.push_str("</returnStatement>\n");
+                w.symbol(";");
+                w.close("returnStatement");
             }
         }
     }
 }
 
 impl Expr {
-    pub fn new(lhs: Term, cdr: Option<(Binop, Box<Term>)>) -> Self {
-        Self { lhs, cdr }
+    pub fn new(lhs: Term, cdr: Vec<(Binop, Term)>, span: Span) -> Self {
+        Self { lhs, cdr, span }
     }
 
-    fn write(&self, s: &mut String) {
-        s.push_str("<expression>\n");
-        self.lhs.write(s);
-        if let Some((binop, term)) = &self.cdr {
-            match binop {
-                Binop::Plus => s.push_str(symbol! {"+"}),
-                Binop::Minus => s.push_str(symbol! {"-"}),
-                Binop::Mul => s.push_str(symbol! {"*"}),
-                Binop::Div => s.push_str(symbol! {"/"}),
-                Binop::And => s.push_str(symbol! {"&amp;"}),
-                Binop::Or => s.push_str(symbol! {"|"}),
-                Binop::Gt => s.push_str(symbol! {"&gt;"}),
-                Binop::Lt => s.push_str(symbol! {"&lt;"}),
-                Binop::Equal => s.push_str(symbol! {"="}),
-            }
-            term.write(s);
+    fn write(&self, w: &mut XmlWriter) {
+        w.open("expression");
+        self.lhs.write(w);
+        for (binop, term) in &self.cdr {
+            w.symbol(binop.as_str());
+            term.write(w);
         }
-        s.push_str("</expression>\n");
+        w.close("expression");
     }
 }
 
 impl Term {
-    fn write(&self, s: &mut String) {
-        s.push_str("<term>\n");
-        match self {
-            Term::IntegerConstant(num) => {
-                s.push_str(&format!("<integerConstant> {} </integerConstant>\n", num));
-            }
-            Term::StringConstant(cnstr) => {
-                s.push_str(&format!("<stringConstant> {} </stringConstant>\n", cnstr));
-            }
-            Term::KeywordConstant(kwd) => match kwd {
-                KeywordConstant::True => s.push_str(keyword! {"true"}),
-                KeywordConstant::False => s.push_str(keyword! {"false"}),
-                KeywordConstant::Null => s.push_str(keyword! {"null"}),
-                KeywordConstant::This => s.push_str(keyword! {"this"}),
-            },
-            Term::ValName(name) => {
-                s.push_str(&ident! {name});
-            }
-            Term::WithUnary(unop, term) => {
-                match unop {
-                    Unop::Minus => s.push_str(symbol!("-")),
-                    Unop::BitNot => s.push_str(symbol!("~")),
-                }
-                term.write(s);
-            }
-            Term::WithIdx(name, expr) => {
-                s.push_str(&ident! {name});
-                s.push_str(symbol!("["));
-                expr.write(s);
-                s.push_str(symbol!("]"));
+    pub fn new(kind: TermKind, span: Span) -> Self {
+        Self { kind, span }
+    }
+
+    fn write(&self, w: &mut XmlWriter) {
+        w.open("term");
+        match &self.kind {
+            TermKind::IntegerConstant(num) => w.leaf("integerConstant", &num.to_string()),
+            TermKind::StringConstant(s) => w.leaf("stringConstant", s),
+            TermKind::KeywordConstant(kwd) => w.keyword(kwd.as_str()),
+            TermKind::ValName(name) => w.ident(name.as_str()),
+            TermKind::WithUnary(unop, term) => {
+                w.symbol(unop.as_str());
+                term.write(w);
             }
-            Term::SubRoutineCall(call) => {
-                call.write(s);
+            TermKind::WithIdx(name, expr) => {
+                w.ident(name.as_str());
+                w.symbol("[");
+                expr.write(w);
+                w.symbol("]");
             }
-            Term::Expr(expr) => {
-                s.push_str(symbol! {"("});
-                expr.write(s);
-                s.push_str(symbol! {")"});
+            TermKind::SubRoutineCall(call) => call.write(w),
+            TermKind::Expr(expr) => {
+                w.symbol("(");
+                expr.write(w);
+                w.symbol(")");
             }
         }
-        s.push_str("</term>\n");
+        w.close("term");
     }
 }
 
 impl SubRoutineCall {
-    pub fn new(obj_name: Option<Rc<String>>, routine_name: Rc<String>, args: Vec<Expr>) -> Self {
+    pub fn new(obj_name: Option<Ident>, routine_name: Ident, args: Vec<Expr>, span: Span) -> Self {
         Self {
             obj_name,
             routine_name,
             args,
+            span,
         }
     }
 
-    fn write(&self, s: &mut String) {
+    fn write(&self, w: &mut XmlWriter) {
         if let Some(obj_name) = &self.obj_name {
-            s.push_str(&ident!(obj_name));
-            s.push_str(symbol!("."));
+            w.ident(obj_name.as_str());
+            w.symbol(".");
         }
-        s.push_str(&ident! {self.routine_name});
-        s.push_str(symbol! {"("});
-        s.push_str("<expressionList>\n");
+        w.ident(self.routine_name.as_str());
+        w.symbol("(");
+        w.open("expressionList");
         for (i, arg) in self.args.iter().enumerate() {
             if i > 0 {
-                s.push_str(symbol! {","});
+                w.symbol(",");
             }
-            arg.write(s);
+            arg.write(w);
         }
-        s.push_str("</expressionList>\n");
-        s.push_str(symbol! {")"});
+        w.close("expressionList");
+        w.symbol(")");
     }
 }
 
 impl Type {
-    fn write(&self, s: &mut String) {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Type::Class(name) => name.as_str(),
+            Type::Int => "int",
+            Type::Char => "char",
+            Type::Boolean => "boolean",
+            Type::Void => "void",
+        }
+    }
+
+    fn write(&self, w: &mut XmlWriter) {
+        match self {
+            Type::Class(name) => w.ident(name.as_str()),
+            _ => w.keyword(self.as_str()),
+        }
+    }
+}
+
+impl ClassVarType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ClassVarType::Static => "static",
+            ClassVarType::Field => "field",
+        }
+    }
+}
+
+impl SubRoutineKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SubRoutineKind::Constructor => "constructor",
+            SubRoutineKind::Function => "function",
+            SubRoutineKind::Method => "method",
+        }
+    }
+}
+
+impl Binop {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Binop::Plus => "+",
+            Binop::Minus => "-",
+            Binop::Mul => "*",
+            Binop::Div => "/",
+            Binop::And => "&",
+            Binop::Or => "|",
+            Binop::Gt => ">",
+            Binop::Lt => "<",
+            Binop::Equal => "=",
+        }
+    }
+}
+
+impl Unop {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Unop::Minus => "-",
+            Unop::BitNot => "~",
+        }
+    }
+}
+
+impl KeywordConstant {
+    pub fn as_str(&self) -> &'static str {
         match self {
-            Type::Class(ref name) => s.push_str(&ident! {name}),
-            Type::Int => s.push_str(keyword! {"int"}),
-            Type::Char => s.push_str(keyword! {"char"}),
-            Type::Boolean => s.push_str(keyword! {"boolean"}),
-            Type::Void => s.push_str(keyword! {"void"}),
+            KeywordConstant::True => "true",
+            KeywordConstant::False => "false",
+            KeywordConstant::Null => "null",
+            KeywordConstant::This => "this",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{tokenize, Parser};
+
+    #[test]
+    fn test_reference_xml() {
+        // Course programs, with the parse trees that `src/project10/reference_xml.py` writes
+        // in the format of project 10's compare files.
+        let cases = [
+            (
+                include_str!("../project09/Square/Main.jack"),
+                include_str!("../project10/Square/Main.xml"),
+            ),
+            (
+                include_str!("../project09/Square/Square.jack"),
+                include_str!("../project10/Square/Square.xml"),
+            ),
+            (
+                include_str!("../project09/Square/SquareGame.jack"),
+                include_str!("../project10/Square/SquareGame.xml"),
+            ),
+            (
+                include_str!("../project09/Average/Main.jack"),
+                include_str!("../project10/Average/Main.xml"),
+            ),
+        ];
+        for (jack, xml) in cases.iter() {
+            let tokens = tokenize(jack.chars()).unwrap();
+            let ast = Parser::new(tokens.into_iter()).parse().unwrap();
+            assert_eq!(ast.to_xml(), *xml);
         }
     }
 }
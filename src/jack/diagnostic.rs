@@ -0,0 +1,31 @@
+use std::fmt;
+
+use super::token::Span;
+
+/// Error in Jack source, located by its span.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub span: Span,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn new(span: Span, message: impl Into<String>) -> Self {
+        Self {
+            span,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write! {f, "Error: line {}; {}", self.span.start.line, self.message}
+    }
+}
+
+impl From<Diagnostic> for String {
+    fn from(diag: Diagnostic) -> Self {
+        diag.to_string()
+    }
+}
@@ -0,0 +1,123 @@
+use super::ast::*;
+use super::diagnostic::Diagnostic;
+use super::token::{Pos, Span};
+
+/// Checks the `return` statements of every subroutine in `class`: each path through
+/// the body must end in one, with a value unless the subroutine is `void`, and
+/// constructors must `return this`.
+pub fn check_returns(class: &ClassDec) -> Vec<Diagnostic> {
+    let mut diags = Vec::new();
+    for dec in &class.subroutine_decs {
+        check_stmts(dec, &dec.body.stmts, &mut diags);
+        if !always_returns(&dec.body.stmts) {
+            // The closing `}` of the body.
+            let end = dec.body.span.end;
+            let span = Span {
+                start: Pos {
+                    line: end.line,
+                    col: end.col - 1,
+                },
+                end,
+            };
+            diags.push(Diagnostic::new(
+                span,
+                format! {"{}.{} can end without return", class.name(), dec.name.as_str()},
+            ));
+        }
+    }
+    diags
+}
+
+fn check_stmts(dec: &SubRoutineDec, stmts: &[Stmt], diags: &mut Vec<Diagnostic>) {
+    for stmt in stmts {
+        let message = match &stmt.kind {
+            StmtKind::If { then, else_, .. } => {
+                check_stmts(dec, then, diags);
+                if let Some(else_) = else_ {
+                    check_stmts(dec, else_, diags);
+                }
+                continue;
+            }
+            StmtKind::While { body, .. } => {
+                check_stmts(dec, body, diags);
+                continue;
+            }
+            StmtKind::Let { .. } | StmtKind::Do(_) => continue,
+            StmtKind::Return(expr) if dec.kind == SubRoutineKind::Constructor => {
+                if expr.as_ref().is_some_and(is_this) {
+                    continue;
+                }
+                "Constructor must return this".to_string()
+            }
+            StmtKind::Return(Some(_)) if dec.ret == Type::Void => {
+                "Void subroutine can't return a value".to_string()
+            }
+            StmtKind::Return(None) if dec.ret != Type::Void => {
+                format! {"Return without value in subroutine returning {}", dec.ret.as_str()}
+            }
+            StmtKind::Return(_) => continue,
+        };
+        diags.push(Diagnostic::new(stmt.span, message));
+    }
+}
+
+// Whether every path through `stmts` ends in a `return`. A `while` body may not run
+// at all, so only `if`s with both branches returning count.
+fn always_returns(stmts: &[Stmt]) -> bool {
+    stmts.iter().any(|stmt| match &stmt.kind {
+        StmtKind::Return(_) => true,
+        StmtKind::If {
+            then,
+            else_: Some(else_),
+            ..
+        } => always_returns(then) && always_returns(else_),
+        _ => false,
+    })
+}
+
+fn is_this(expr: &Expr) -> bool {
+    expr.cdr.is_empty() && expr.lhs.kind == TermKind::KeywordConstant(KeywordConstant::This)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{tokenize, Parser};
+    use super::*;
+
+    #[test]
+    fn test_check_returns() {
+        let jack = "class Main {
+    constructor Main new() { return 1; }
+    function int f(int x) {
+        if (x) { return 1; } else { return; }
+    }
+    function void g(int x) {
+        while (x) { return 1; }
+    }
+    method int h(int x) {
+        if (x) { return 1; } else { let x = 0; }
+        return x;
+    }
+}
+";
+        let tokens = tokenize(jack.chars()).unwrap();
+        let class = Parser::new(tokens.into_iter()).parse().unwrap();
+        let diags: Vec<_> = check_returns(&class)
+            .into_iter()
+            .map(|diag| (diag.span.start.line, diag.span.start.col, diag.message))
+            .collect();
+        assert_eq!(
+            diags,
+            [
+                (2, 30, "Constructor must return this".to_string()),
+                (
+                    4,
+                    37,
+                    "Return without value in subroutine returning int".to_string()
+                ),
+                (7, 21, "Void subroutine can't return a value".to_string()),
+                (8, 5, "Main.g can end without return".to_string()),
+            ]
+        );
+    }
+}
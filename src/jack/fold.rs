@@ -0,0 +1,249 @@
+//! Owned, rebuilding traversal of `jack::ast`.
+//!
+//! Every `fold_*` method defaults to the matching `walk_*` function, which
+//! folds the node's children in source order and reassembles the node.
+//! Override a method to replace a kind of node.
+
+use super::ast::*;
+
+pub trait Fold {
+    fn fold_class(&mut self, class: ClassDec) -> ClassDec {
+        walk_class(self, class)
+    }
+
+    fn fold_class_var_dec(&mut self, dec: ClassVarDec) -> ClassVarDec {
+        walk_class_var_dec(self, dec)
+    }
+
+    fn fold_subroutine_dec(&mut self, dec: SubRoutineDec) -> SubRoutineDec {
+        walk_subroutine_dec(self, dec)
+    }
+
+    fn fold_subroutine_body(&mut self, body: SubRoutineBody) -> SubRoutineBody {
+        walk_subroutine_body(self, body)
+    }
+
+    fn fold_var_dec(&mut self, dec: VarDec) -> VarDec {
+        walk_var_dec(self, dec)
+    }
+
+    fn fold_stmt(&mut self, stmt: Stmt) -> Stmt {
+        walk_stmt(self, stmt)
+    }
+
+    fn fold_expr(&mut self, expr: Expr) -> Expr {
+        walk_expr(self, expr)
+    }
+
+    fn fold_term(&mut self, term: Term) -> Term {
+        walk_term(self, term)
+    }
+
+    fn fold_subroutine_call(&mut self, call: SubRoutineCall) -> SubRoutineCall {
+        walk_subroutine_call(self, call)
+    }
+
+    fn fold_type(&mut self, ty: Type) -> Type {
+        walk_type(self, ty)
+    }
+
+    /// Called for declared names and uses alike.
+    fn fold_ident(&mut self, ident: Ident) -> Ident {
+        ident
+    }
+}
+
+pub fn walk_class<F: Fold + ?Sized>(f: &mut F, class: ClassDec) -> ClassDec {
+    ClassDec {
+        name: f.fold_ident(class.name),
+        var_decs: class
+            .var_decs
+            .into_iter()
+            .map(|dec| f.fold_class_var_dec(dec))
+            .collect(),
+        subroutine_decs: class
+            .subroutine_decs
+            .into_iter()
+            .map(|dec| f.fold_subroutine_dec(dec))
+            .collect(),
+        span: class.span,
+    }
+}
+
+pub fn walk_class_var_dec<F: Fold + ?Sized>(f: &mut F, dec: ClassVarDec) -> ClassVarDec {
+    ClassVarDec {
+        var_ty: dec.var_ty,
+        ty: f.fold_type(dec.ty),
+        names: fold_idents(f, dec.names),
+        span: dec.span,
+    }
+}
+
+pub fn walk_subroutine_dec<F: Fold + ?Sized>(f: &mut F, dec: SubRoutineDec) -> SubRoutineDec {
+    let ret = f.fold_type(dec.ret);
+    let name = f.fold_ident(dec.name);
+    SubRoutineDec {
+        name,
+        kind: dec.kind,
+        ret,
+        args: dec
+            .args
+            .into_iter()
+            .map(|(ty, name)| (f.fold_type(ty), f.fold_ident(name)))
+            .collect(),
+        body: f.fold_subroutine_body(dec.body),
+        span: dec.span,
+    }
+}
+
+pub fn walk_subroutine_body<F: Fold + ?Sized>(f: &mut F, body: SubRoutineBody) -> SubRoutineBody {
+    SubRoutineBody {
+        var_decs: body
+            .var_decs
+            .into_iter()
+            .map(|dec| f.fold_var_dec(dec))
+            .collect(),
+        stmts: fold_stmts(f, body.stmts),
+        span: body.span,
+    }
+}
+
+pub fn walk_var_dec<F: Fold + ?Sized>(f: &mut F, dec: VarDec) -> VarDec {
+    let ty = f.fold_type(dec.ty);
+    VarDec {
+        names: fold_idents(f, dec.names),
+        ty,
+        span: dec.span,
+    }
+}
+
+pub fn walk_stmt<F: Fold + ?Sized>(f: &mut F, stmt: Stmt) -> Stmt {
+    let kind = match stmt.kind {
+        StmtKind::Let { name, idx, expr } => StmtKind::Let {
+            name: f.fold_ident(name),
+            idx: idx.map(|idx| f.fold_expr(idx)),
+            expr: f.fold_expr(expr),
+        },
+        StmtKind::If { test, then, else_ } => StmtKind::If {
+            test: f.fold_expr(test),
+            then: fold_stmts(f, then),
+            else_: else_.map(|else_| fold_stmts(f, else_)),
+        },
+        StmtKind::While { test, body } => StmtKind::While {
+            test: f.fold_expr(test),
+            body: fold_stmts(f, body),
+        },
+        StmtKind::Do(call) => StmtKind::Do(f.fold_subroutine_call(call)),
+        StmtKind::Return(expr) => StmtKind::Return(expr.map(|expr| f.fold_expr(expr))),
+    };
+    Stmt::new(kind, stmt.span)
+}
+
+pub fn walk_expr<F: Fold + ?Sized>(f: &mut F, expr: Expr) -> Expr {
+    Expr {
+        lhs: f.fold_term(expr.lhs),
+        cdr: expr
+            .cdr
+            .into_iter()
+            .map(|(op, rhs)| (op, f.fold_term(rhs)))
+            .collect(),
+        span: expr.span,
+    }
+}
+
+pub fn walk_term<F: Fold + ?Sized>(f: &mut F, term: Term) -> Term {
+    let kind = match term.kind {
+        kind @ TermKind::IntegerConstant(_)
+        | kind @ TermKind::StringConstant(_)
+        | kind @ TermKind::KeywordConstant(_) => kind,
+        TermKind::ValName(name) => TermKind::ValName(f.fold_ident(name)),
+        TermKind::WithUnary(op, term) => TermKind::WithUnary(op, Box::new(f.fold_term(*term))),
+        TermKind::WithIdx(name, idx) => {
+            let name = f.fold_ident(name);
+            TermKind::WithIdx(name, Box::new(f.fold_expr(*idx)))
+        }
+        TermKind::SubRoutineCall(call) => TermKind::SubRoutineCall(f.fold_subroutine_call(call)),
+        TermKind::Expr(expr) => TermKind::Expr(Box::new(f.fold_expr(*expr))),
+    };
+    Term::new(kind, term.span)
+}
+
+pub fn walk_subroutine_call<F: Fold + ?Sized>(f: &mut F, call: SubRoutineCall) -> SubRoutineCall {
+    SubRoutineCall {
+        obj_name: call.obj_name.map(|name| f.fold_ident(name)),
+        routine_name: f.fold_ident(call.routine_name),
+        args: call.args.into_iter().map(|arg| f.fold_expr(arg)).collect(),
+        span: call.span,
+    }
+}
+
+pub fn walk_type<F: Fold + ?Sized>(f: &mut F, ty: Type) -> Type {
+    match ty {
+        Type::Class(name) => Type::Class(f.fold_ident(name)),
+        ty => ty,
+    }
+}
+
+fn fold_stmts<F: Fold + ?Sized>(f: &mut F, stmts: Vec<Stmt>) -> Vec<Stmt> {
+    stmts.into_iter().map(|stmt| f.fold_stmt(stmt)).collect()
+}
+
+fn fold_idents<F: Fold + ?Sized>(f: &mut F, names: Vec<Ident>) -> Vec<Ident> {
+    names.into_iter().map(|name| f.fold_ident(name)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{tokenize, Parser};
+    use super::*;
+    use std::rc::Rc;
+
+    // Replaces `n + m` of two integer constants with their sum.
+    struct AddConstants;
+
+    impl Fold for AddConstants {
+        fn fold_expr(&mut self, expr: Expr) -> Expr {
+            let expr = walk_expr(self, expr);
+            match (&expr.lhs.kind, &expr.cdr[..]) {
+                (TermKind::IntegerConstant(lhs), [(Binop::Plus, rhs)]) => match rhs.kind {
+                    TermKind::IntegerConstant(rhs) => {
+                        let sum = Term::new(TermKind::IntegerConstant(lhs + rhs), expr.span);
+                        Expr::new(sum, Vec::new(), expr.span)
+                    }
+                    _ => expr,
+                },
+                _ => expr,
+            }
+        }
+    }
+
+    struct Rename;
+
+    impl Fold for Rename {
+        fn fold_ident(&mut self, ident: Ident) -> Ident {
+            if ident.as_str() == "x" {
+                Ident::new(Rc::new("y".to_string()), ident.span)
+            } else {
+                ident
+            }
+        }
+    }
+
+    fn parse(jack: &str) -> ClassDec {
+        let tokens = tokenize(jack.chars()).unwrap();
+        Parser::new(tokens.into_iter()).parse().unwrap()
+    }
+
+    #[test]
+    fn test_fold() {
+        let ast = parse("class Main { function int f() { var int x; let x = x + 1; return x; } }");
+        assert_eq!(AddConstants.fold_class(ast.clone()), ast);
+
+        let ast =
+            parse("class Main { function int f() { var int x; let x = (1 + 2) + x; return x; } }");
+        let folded = Rename.fold_class(AddConstants.fold_class(ast));
+        let expected =
+            parse("class Main { function int f() { var int y; let y = (3) + y; return y; } }");
+        assert_eq!(folded.to_xml(), expected.to_xml());
+    }
+}
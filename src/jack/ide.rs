@@ -0,0 +1,663 @@
+//! Editor features over a set of Jack documents: diagnostics, go-to-definition,
+//! hover, document symbols and completion.
+//!
+//! Positions are those of `tokenize`: 1-based lines and columns counted in characters.
+
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+use super::ast::*;
+use super::diagnostic::Diagnostic;
+use super::flow::check_returns;
+use super::lexer::{tokenize, tokenize_extended};
+use super::parser::Parser;
+use super::symbol_table::{SymKind, SymType, SymbolTable};
+use super::token::{Pos, Span};
+use super::visit::{self, Visitor};
+
+/// Place in a document of the workspace.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Location {
+    pub uri: String,
+    pub span: Span,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SymbolKind {
+    Class,
+    Static,
+    Field,
+    Argument,
+    Var,
+    Constructor,
+    Function,
+    Method,
+}
+
+/// Declaration in the outline of a document.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DocumentSymbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    /// Declaration without the body, e.g. `field int x`.
+    pub detail: String,
+    pub span: Span,
+    /// Span of the declared name.
+    pub selection: Span,
+    pub children: Vec<DocumentSymbol>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Completion {
+    pub label: String,
+    pub kind: SymbolKind,
+    pub detail: String,
+}
+
+struct Document {
+    text: String,
+    // AST of the text, without the parts with syntax errors. The last one is
+    // kept while even the class header is broken.
+    ast: Option<ClassDec>,
+    syntax_errors: Vec<Diagnostic>,
+}
+
+/// Documents keyed by URI. Classes are looked up by name across all of them.
+#[derive(Default)]
+pub struct Workspace {
+    docs: BTreeMap<String, Document>,
+    extended: bool,
+}
+
+impl Workspace {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Workspace of documents in the syntax of `tokenize_extended`.
+    pub fn extended() -> Self {
+        Self {
+            docs: BTreeMap::new(),
+            extended: true,
+        }
+    }
+
+    /// Adds the document or replaces its text.
+    pub fn update(&mut self, uri: &str, text: String) {
+        let (ast, syntax_errors) = parse(&text, self.extended);
+        let doc = self.docs.entry(uri.to_string()).or_insert(Document {
+            text: String::new(),
+            ast: None,
+            syntax_errors: Vec::new(),
+        });
+        doc.text = text;
+        if ast.is_some() {
+            doc.ast = ast;
+        }
+        doc.syntax_errors = syntax_errors;
+    }
+
+    pub fn remove(&mut self, uri: &str) {
+        self.docs.remove(uri);
+    }
+
+    pub fn contains(&self, uri: &str) -> bool {
+        self.docs.contains_key(uri)
+    }
+
+    /// Syntax errors, or if there are none, uses of undefined variables and subroutines.
+    pub fn diagnostics(&self, uri: &str) -> Vec<Diagnostic> {
+        let doc = match self.docs.get(uri) {
+            Some(doc) => doc,
+            None => return Vec::new(),
+        };
+        // Names declared in broken parts would be reported as undefined.
+        if !doc.syntax_errors.is_empty() {
+            return doc.syntax_errors.clone();
+        }
+        let mut diags: Vec<_> = self
+            .references(uri)
+            .into_iter()
+            .filter_map(|r| match r.target {
+                Target::Undefined(message) => Some(Diagnostic::new(r.span, message)),
+                _ => None,
+            })
+            .collect();
+        if let Some(class) = &doc.ast {
+            diags.extend(check_returns(class));
+        }
+        diags.sort_by_key(|diag| diag.span);
+        diags
+    }
+
+    pub fn definition(&self, uri: &str, pos: Pos) -> Option<Location> {
+        match self.reference_at(uri, pos)?.target {
+            Target::Variable { def, .. } => Some(Location {
+                uri: uri.to_string(),
+                span: def,
+            }),
+            Target::Subroutine { uri, dec, .. } => Some(Location {
+                uri: uri.to_string(),
+                span: dec.name.span,
+            }),
+            Target::Class { uri, class } => Some(Location {
+                uri: uri.to_string(),
+                span: class.name.span,
+            }),
+            Target::Undefined(_) | Target::Unknown => None,
+        }
+    }
+
+    /// Declaration of the name at `pos`, and the span of that name.
+    pub fn hover(&self, uri: &str, pos: Pos) -> Option<(String, Span)> {
+        let r = self.reference_at(uri, pos)?;
+        let text = match r.target {
+            Target::Variable { name, kind, ty, .. } => {
+                format! {"{} {} {}", kind.as_str(), ty.as_str(), name}
+            }
+            Target::Subroutine { class, dec, .. } => signature(class, dec),
+            Target::Class { class, .. } => format! {"class {}", class.name()},
+            Target::Undefined(_) | Target::Unknown => return None,
+        };
+        Some((text, r.span))
+    }
+
+    pub fn document_symbols(&self, uri: &str) -> Vec<DocumentSymbol> {
+        let class = match self.docs.get(uri).and_then(|doc| doc.ast.as_ref()) {
+            Some(class) => class,
+            None => return Vec::new(),
+        };
+        let mut children = Vec::new();
+        for dec in &class.var_decs {
+            let kind = match dec.var_ty {
+                ClassVarType::Static => SymbolKind::Static,
+                ClassVarType::Field => SymbolKind::Field,
+            };
+            for name in &dec.names {
+                children.push(DocumentSymbol {
+                    name: name.as_str().to_string(),
+                    kind,
+                    detail: format! {"{} {}", dec.var_ty.as_str(), dec.ty.as_str()},
+                    span: dec.span,
+                    selection: name.span,
+                    children: Vec::new(),
+                });
+            }
+        }
+        for dec in &class.subroutine_decs {
+            children.push(DocumentSymbol {
+                name: dec.name.as_str().to_string(),
+                kind: subroutine_kind(dec.kind),
+                detail: signature(class, dec),
+                span: dec.span,
+                selection: dec.name.span,
+                children: Vec::new(),
+            });
+        }
+        vec![DocumentSymbol {
+            name: class.name().to_string(),
+            kind: SymbolKind::Class,
+            detail: format! {"class {}", class.name()},
+            span: class.span,
+            selection: class.name.span,
+            children,
+        }]
+    }
+
+    /// Names that can be typed at `pos`: after `x.` the subroutines callable on `x`,
+    /// otherwise the variables in scope, the subroutines of the class and all class names.
+    pub fn completions(&self, uri: &str, pos: Pos) -> Vec<Completion> {
+        let doc = match self.docs.get(uri) {
+            Some(doc) => doc,
+            None => return Vec::new(),
+        };
+        let class = match &doc.ast {
+            Some(class) => class,
+            None => return Vec::new(),
+        };
+        let line = doc.text.lines().nth(pos.line - 1).unwrap_or("");
+        let before: String = line.chars().take(pos.col - 1).collect();
+        let before = before.trim_end_matches(is_ident_char);
+        let table = scope(class, pos);
+
+        if let Some(before_dot) = before.strip_suffix('.') {
+            let obj = &before_dot[before_dot.trim_end_matches(is_ident_char).len()..];
+            let (class_name, methods) = match table.get(&obj.to_string()) {
+                Some(entry) => match entry.ty() {
+                    SymType::Class(name) => (name.to_string(), true),
+                    _ => return Vec::new(),
+                },
+                None => (obj.to_string(), false),
+            };
+            let (_, class) = match self.class(&class_name) {
+                Some(class) => class,
+                None => return Vec::new(),
+            };
+            return class
+                .subroutine_decs
+                .iter()
+                .filter(|dec| (dec.kind == SubRoutineKind::Method) == methods)
+                .map(|dec| subroutine_completion(class, dec))
+                .collect();
+        }
+
+        let mut vars: Vec<_> = table
+            .iter()
+            .map(|(name, entry)| Completion {
+                label: name.to_string(),
+                kind: match entry.kind() {
+                    SymKind::Static => SymbolKind::Static,
+                    SymKind::Field => SymbolKind::Field,
+                    SymKind::Argument => SymbolKind::Argument,
+                    SymKind::Var => SymbolKind::Var,
+                },
+                detail: format! {"{} {}", entry.kind().as_str(), entry.ty().as_str()},
+            })
+            .collect();
+        vars.sort_by(|a, b| a.label.cmp(&b.label));
+        let subroutines = class
+            .subroutine_decs
+            .iter()
+            .map(|dec| subroutine_completion(class, dec));
+        let classes = self
+            .docs
+            .values()
+            .filter_map(|doc| doc.ast.as_ref())
+            .map(|class| Completion {
+                label: class.name().to_string(),
+                kind: SymbolKind::Class,
+                detail: format! {"class {}", class.name()},
+            });
+        vars.into_iter().chain(subroutines).chain(classes).collect()
+    }
+
+    fn class(&self, name: &str) -> Option<(&str, &ClassDec)> {
+        self.docs.iter().find_map(|(uri, doc)| match &doc.ast {
+            Some(class) if class.name() == name => Some((uri.as_str(), class)),
+            _ => None,
+        })
+    }
+
+    fn references(&self, uri: &str) -> Vec<Reference<'_>> {
+        let (uri, doc) = match self.docs.get_key_value(uri) {
+            Some(entry) => entry,
+            None => return Vec::new(),
+        };
+        let class = match &doc.ast {
+            Some(class) => class,
+            None => return Vec::new(),
+        };
+        let mut resolver = Resolver {
+            ws: self,
+            uri,
+            class,
+            table: SymbolTable::new(),
+            refs: Vec::new(),
+        };
+        resolver.visit_class(class);
+        resolver.refs
+    }
+
+    fn reference_at(&self, uri: &str, pos: Pos) -> Option<Reference<'_>> {
+        self.references(uri)
+            .into_iter()
+            .find(|r| r.span.contains(pos))
+    }
+}
+
+fn parse(text: &str, extended: bool) -> (Option<ClassDec>, Vec<Diagnostic>) {
+    let tokens = if extended {
+        tokenize_extended(text.chars())
+    } else {
+        tokenize(text.chars())
+    };
+    match tokens {
+        Ok(tokens) => Parser::new(tokens.into_iter()).parse_partial(),
+        Err(diag) => (None, vec![diag]),
+    }
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+fn subroutine_kind(kind: SubRoutineKind) -> SymbolKind {
+    match kind {
+        SubRoutineKind::Constructor => SymbolKind::Constructor,
+        SubRoutineKind::Function => SymbolKind::Function,
+        SubRoutineKind::Method => SymbolKind::Method,
+    }
+}
+
+fn subroutine_completion(class: &ClassDec, dec: &SubRoutineDec) -> Completion {
+    Completion {
+        label: dec.name.as_str().to_string(),
+        kind: subroutine_kind(dec.kind),
+        detail: signature(class, dec),
+    }
+}
+
+// e.g. `method void Square.moveTo(int x, int y)`
+fn signature(class: &ClassDec, dec: &SubRoutineDec) -> String {
+    let args: Vec<_> = dec
+        .args
+        .iter()
+        .map(|(ty, name)| format! {"{} {}", ty.as_str(), name.as_str()})
+        .collect();
+    format! {
+        "{} {} {}.{}({})",
+        dec.kind.as_str(),
+        dec.ret.as_str(),
+        class.name(),
+        dec.name.as_str(),
+        args.join(", ")
+    }
+}
+
+fn declare_class_vars(table: &mut SymbolTable, class: &ClassDec) {
+    for dec in &class.var_decs {
+        let kind = match dec.var_ty {
+            ClassVarType::Static => SymKind::Static,
+            ClassVarType::Field => SymKind::Field,
+        };
+        declare(table, &dec.ty, &dec.names, kind);
+    }
+}
+
+fn declare_locals(table: &mut SymbolTable, dec: &SubRoutineDec) {
+    table.next_scope();
+    for (ty, name) in &dec.args {
+        declare(table, ty, std::slice::from_ref(name), SymKind::Argument);
+    }
+    for var_dec in &dec.body.var_decs {
+        declare(table, &var_dec.ty, &var_dec.names, SymKind::Var);
+    }
+}
+
+fn declare(table: &mut SymbolTable, ty: &Type, names: &[Ident], kind: SymKind) {
+    if let Ok(ty) = SymType::from_astty(ty) {
+        for name in names {
+            table.insert(name.name.clone(), ty.clone(), kind, name.span);
+        }
+    }
+}
+
+// Variables visible at `pos` in `class`.
+fn scope(class: &ClassDec, pos: Pos) -> SymbolTable {
+    let mut table = SymbolTable::new();
+    declare_class_vars(&mut table, class);
+    let dec = class
+        .subroutine_decs
+        .iter()
+        .find(|dec| dec.span.contains(pos));
+    if let Some(dec) = dec {
+        declare_locals(&mut table, dec);
+    }
+    table
+}
+
+// Use or declaration of a name.
+struct Reference<'a> {
+    span: Span,
+    target: Target<'a>,
+}
+
+enum Target<'a> {
+    Variable {
+        name: Rc<String>,
+        kind: SymKind,
+        ty: SymType,
+        def: Span,
+    },
+    Subroutine {
+        uri: &'a str,
+        class: &'a ClassDec,
+        dec: &'a SubRoutineDec,
+    },
+    Class {
+        uri: &'a str,
+        class: &'a ClassDec,
+    },
+    Undefined(String),
+    // Declared outside the workspace, e.g. in the OS.
+    Unknown,
+}
+
+struct Resolver<'a> {
+    ws: &'a Workspace,
+    uri: &'a str,
+    class: &'a ClassDec,
+    table: SymbolTable,
+    refs: Vec<Reference<'a>>,
+}
+
+impl<'a> Resolver<'a> {
+    fn variable(&mut self, name: &Ident) {
+        let target = match self.table.get(&name.name) {
+            Some(entry) => Target::Variable {
+                name: name.name.clone(),
+                kind: entry.kind(),
+                ty: entry.ty().clone(),
+                def: entry.def(),
+            },
+            None => Target::Undefined(format! {"Undefined variable {}", name.as_str()}),
+        };
+        self.refs.push(Reference {
+            span: name.span,
+            target,
+        });
+    }
+
+    fn class_ref(&mut self, name: &Ident) {
+        let target = match self.ws.class(name.as_str()) {
+            Some((uri, class)) => Target::Class { uri, class },
+            None => Target::Unknown,
+        };
+        self.refs.push(Reference {
+            span: name.span,
+            target,
+        });
+    }
+}
+
+impl<'a> Visitor<'a> for Resolver<'a> {
+    fn visit_class(&mut self, class: &'a ClassDec) {
+        declare_class_vars(&mut self.table, class);
+        self.refs.push(Reference {
+            span: class.name.span,
+            target: Target::Class {
+                uri: self.uri,
+                class,
+            },
+        });
+        visit::walk_class(self, class);
+    }
+
+    fn visit_class_var_dec(&mut self, dec: &'a ClassVarDec) {
+        for name in &dec.names {
+            self.variable(name);
+        }
+        visit::walk_class_var_dec(self, dec);
+    }
+
+    fn visit_subroutine_dec(&mut self, dec: &'a SubRoutineDec) {
+        declare_locals(&mut self.table, dec);
+        self.refs.push(Reference {
+            span: dec.name.span,
+            target: Target::Subroutine {
+                uri: self.uri,
+                class: self.class,
+                dec,
+            },
+        });
+        for (_, name) in &dec.args {
+            self.variable(name);
+        }
+        visit::walk_subroutine_dec(self, dec);
+    }
+
+    fn visit_var_dec(&mut self, dec: &'a VarDec) {
+        for name in &dec.names {
+            self.variable(name);
+        }
+        visit::walk_var_dec(self, dec);
+    }
+
+    fn visit_stmt(&mut self, stmt: &'a Stmt) {
+        if let StmtKind::Let { name, .. } = &stmt.kind {
+            self.variable(name);
+        }
+        visit::walk_stmt(self, stmt);
+    }
+
+    fn visit_term(&mut self, term: &'a Term) {
+        match &term.kind {
+            TermKind::ValName(name) | TermKind::WithIdx(name, _) => self.variable(name),
+            _ => {}
+        }
+        visit::walk_term(self, term);
+    }
+
+    fn visit_subroutine_call(&mut self, call: &'a SubRoutineCall) {
+        let class_name = match &call.obj_name {
+            Some(obj) => match self.table.get(&obj.name) {
+                Some(entry) => {
+                    let class_name = match entry.ty() {
+                        SymType::Class(name) => Some(name.to_string()),
+                        _ => None,
+                    };
+                    self.variable(obj);
+                    class_name
+                }
+                None => {
+                    self.class_ref(obj);
+                    Some(obj.as_str().to_string())
+                }
+            },
+            None => Some(self.class.name().to_string()),
+        };
+        let name = &call.routine_name;
+        let target = match class_name.and_then(|c| self.ws.class(&c)) {
+            Some((uri, class)) => match class
+                .subroutine_decs
+                .iter()
+                .find(|dec| dec.name.name == name.name)
+            {
+                Some(dec) => Target::Subroutine { uri, class, dec },
+                None => Target::Undefined(
+                    format! {"Undefined subroutine {}.{}", class.name(), name.as_str()},
+                ),
+            },
+            None => Target::Unknown,
+        };
+        self.refs.push(Reference {
+            span: name.span,
+            target,
+        });
+        visit::walk_subroutine_call(self, call);
+    }
+
+    fn visit_type(&mut self, ty: &'a Type) {
+        if let Type::Class(name) = ty {
+            self.class_ref(name);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAIN: &str = "class Main {\n\
+                        \x20   field Point p;\n\
+                        \x20   method void run(int n) {\n\
+                        \x20       var int i;\n\
+                        \x20       let i = p.getX() + n;\n\
+                        \x20       do Point.new(i, y);\n\
+                        \x20       return;\n\
+                        \x20   }\n\
+                        }\n";
+    const POINT: &str = "class Point {\n\
+                         \x20   field int x;\n\
+                         \x20   constructor Point new(int ax, int ay) { let x = ax; return this; }\n\
+                         \x20   method int getX() { return x; }\n\
+                         }\n";
+
+    fn pos(line: usize, col: usize) -> Pos {
+        Pos { line, col }
+    }
+
+    fn workspace() -> Workspace {
+        let mut ws = Workspace::new();
+        ws.update("main", MAIN.to_string());
+        ws.update("point", POINT.to_string());
+        ws
+    }
+
+    #[test]
+    fn test_definition_and_hover() {
+        let ws = workspace();
+        // `i` in `let i`
+        let def = ws.definition("main", pos(5, 13)).unwrap();
+        assert_eq!((def.uri.as_str(), def.span.start), ("main", pos(4, 17)));
+        // `getX` in `p.getX()`
+        let def = ws.definition("main", pos(5, 19)).unwrap();
+        assert_eq!((def.uri.as_str(), def.span.start), ("point", pos(4, 16)));
+        // `Point` in `field Point p`
+        let def = ws.definition("main", pos(2, 11)).unwrap();
+        assert_eq!((def.uri.as_str(), def.span.start), ("point", pos(1, 7)));
+
+        assert_eq!(ws.hover("main", pos(5, 17)).unwrap().0, "field Point p");
+        assert_eq!(
+            ws.hover("main", pos(6, 19)).unwrap().0,
+            "constructor Point Point.new(int ax, int ay)"
+        );
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        let mut ws = workspace();
+        let diags = ws.diagnostics("main");
+        assert_eq!(diags.len(), 1);
+        assert_eq!(diags[0].message, "Undefined variable y");
+        assert_eq!(diags[0].span.start, pos(6, 25));
+
+        ws.update("main", MAIN.replace("return;", "return"));
+        let diags = ws.diagnostics("main");
+        assert_eq!(diags.len(), 1);
+        assert_eq!(diags[0].message, "Expected expression, found '}'");
+        assert_eq!(diags[0].span.start, pos(8, 5));
+        // The rest of the class still serves other requests.
+        assert!(ws.definition("main", pos(5, 13)).is_some());
+    }
+
+    #[test]
+    fn test_extended() {
+        let src = "class Main { function int f() { return 'a' + 0x10; } }";
+        let mut ws = Workspace::new();
+        ws.update("main", src.to_string());
+        let diags = ws.diagnostics("main");
+        assert_eq!(
+            diags[0].message,
+            "Character literals need the extended syntax"
+        );
+        let mut ws = Workspace::extended();
+        ws.update("main", src.to_string());
+        assert!(ws.diagnostics("main").is_empty());
+    }
+
+    #[test]
+    fn test_completions() {
+        let mut ws = workspace();
+        ws.update("main", MAIN.replace("p.getX()", "p.g"));
+        let labels = |completions: Vec<Completion>| -> Vec<String> {
+            completions.into_iter().map(|c| c.label).collect()
+        };
+        assert_eq!(labels(ws.completions("main", pos(5, 20))), ["getX"]);
+        assert_eq!(labels(ws.completions("main", pos(6, 18))), ["new"]);
+        assert_eq!(
+            labels(ws.completions("main", pos(6, 12))),
+            ["i", "n", "p", "run", "Main", "Point"]
+        );
+    }
+}
@@ -0,0 +1,669 @@
+use std::fmt;
+use std::iter::Peekable;
+use std::str::{Chars, FromStr};
+
+use super::ast::*;
+use super::token::{Pos, Span, Token};
+
+/// JSON value with object keys kept in insertion order.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(i64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+/// Conversion of AST nodes to JSON.
+/// Every node is an object whose `"node"` member names its kind and whose
+/// `"span"` member gives its source range. Primitive types and parameters
+/// have no span of their own; a parameter's name still carries one.
+pub trait ToJson {
+    fn to_json(&self) -> Json;
+}
+
+impl Json {
+    pub fn object(members: Vec<(&str, Json)>) -> Self {
+        Json::Object(
+            members
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value))
+                .collect(),
+        )
+    }
+
+    /// Member `key` of an object; `None` for other values.
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(members) => members.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Json::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Json::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    /// Serializes without any whitespace.
+    pub fn to_compact_string(&self) -> String {
+        let mut buf = String::new();
+        self.write_compact(&mut buf);
+        buf
+    }
+
+    fn write_compact(&self, buf: &mut String) {
+        match self {
+            Json::Array(items) => {
+                buf.push('[');
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        buf.push(',');
+                    }
+                    item.write_compact(buf);
+                }
+                buf.push(']');
+            }
+            Json::Object(members) => {
+                buf.push('{');
+                for (i, (key, value)) in members.iter().enumerate() {
+                    if i > 0 {
+                        buf.push(',');
+                    }
+                    write_str(key, buf);
+                    buf.push(':');
+                    value.write_compact(buf);
+                }
+                buf.push('}');
+            }
+            scalar => scalar.write(buf, 0),
+        }
+    }
+
+    fn is_scalar(&self) -> bool {
+        !matches!(self, Json::Array(_) | Json::Object(_))
+    }
+
+    fn write(&self, buf: &mut String, depth: usize) {
+        match self {
+            Json::Null => buf.push_str("null"),
+            Json::Bool(b) => buf.push_str(if *b { "true" } else { "false" }),
+            Json::Number(n) => buf.push_str(&n.to_string()),
+            Json::String(s) => write_str(s, buf),
+            Json::Array(items) if items.is_empty() => buf.push_str("[]"),
+            Json::Array(items) if items.iter().all(Json::is_scalar) => {
+                buf.push('[');
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        buf.push_str(", ");
+                    }
+                    item.write(buf, depth);
+                }
+                buf.push(']');
+            }
+            Json::Array(items) => {
+                buf.push('[');
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        buf.push(',');
+                    }
+                    newline(buf, depth + 1);
+                    item.write(buf, depth + 1);
+                }
+                newline(buf, depth);
+                buf.push(']');
+            }
+            Json::Object(members) if members.is_empty() => buf.push_str("{}"),
+            Json::Object(members) if members.iter().all(|(_, value)| value.is_scalar()) => {
+                buf.push_str("{ ");
+                for (i, (key, value)) in members.iter().enumerate() {
+                    if i > 0 {
+                        buf.push_str(", ");
+                    }
+                    write_str(key, buf);
+                    buf.push_str(": ");
+                    value.write(buf, depth);
+                }
+                buf.push_str(" }");
+            }
+            Json::Object(members) => {
+                buf.push('{');
+                for (i, (key, value)) in members.iter().enumerate() {
+                    if i > 0 {
+                        buf.push(',');
+                    }
+                    newline(buf, depth + 1);
+                    write_str(key, buf);
+                    buf.push_str(": ");
+                    value.write(buf, depth + 1);
+                }
+                newline(buf, depth);
+                buf.push('}');
+            }
+        }
+    }
+}
+
+/// Pretty-prints with two-space indentation, keeping arrays and objects
+/// that hold only scalars on a single line.
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut buf = String::new();
+        self.write(&mut buf, 0);
+        f.write_str(&buf)
+    }
+}
+
+/// Parses a JSON text. Numbers with a fraction or exponent are truncated to integers.
+impl FromStr for Json {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut reader = Reader {
+            chars: s.chars().peekable(),
+        };
+        let value = reader.value()?;
+        reader.skip_ws();
+        match reader.chars.next() {
+            None => Ok(value),
+            Some(c) => Err(format! {"Error: unexpected {:?} after JSON value", c}),
+        }
+    }
+}
+
+struct Reader<'a> {
+    chars: Peekable<Chars<'a>>,
+}
+
+impl<'a> Reader<'a> {
+    fn value(&mut self) -> Result<Json, String> {
+        self.skip_ws();
+        match self.chars.peek() {
+            Some('n') => self.keyword("null", Json::Null),
+            Some('t') => self.keyword("true", Json::Bool(true)),
+            Some('f') => self.keyword("false", Json::Bool(false)),
+            Some('"') => Ok(Json::String(self.string()?)),
+            Some('[') => {
+                self.chars.next();
+                let mut items = Vec::new();
+                self.skip_ws();
+                if self.chars.peek() == Some(&']') {
+                    self.chars.next();
+                    return Ok(Json::Array(items));
+                }
+                loop {
+                    items.push(self.value()?);
+                    self.skip_ws();
+                    match self.chars.next() {
+                        Some(',') => {}
+                        Some(']') => return Ok(Json::Array(items)),
+                        _ => return Err("Error: expected ',' or ']'".to_string()),
+                    }
+                }
+            }
+            Some('{') => {
+                self.chars.next();
+                let mut members = Vec::new();
+                self.skip_ws();
+                if self.chars.peek() == Some(&'}') {
+                    self.chars.next();
+                    return Ok(Json::Object(members));
+                }
+                loop {
+                    self.skip_ws();
+                    let key = self.string()?;
+                    self.skip_ws();
+                    if self.chars.next() != Some(':') {
+                        return Err("Error: expected ':'".to_string());
+                    }
+                    members.push((key, self.value()?));
+                    self.skip_ws();
+                    match self.chars.next() {
+                        Some(',') => {}
+                        Some('}') => return Ok(Json::Object(members)),
+                        _ => return Err("Error: expected ',' or '}'".to_string()),
+                    }
+                }
+            }
+            Some(c) if *c == '-' || c.is_ascii_digit() => self.number(),
+            _ => Err("Error: expected JSON value".to_string()),
+        }
+    }
+
+    fn keyword(&mut self, word: &str, value: Json) -> Result<Json, String> {
+        for expected in word.chars() {
+            if self.chars.next() != Some(expected) {
+                return Err(format! {"Error: expected {}", word});
+            }
+        }
+        Ok(value)
+    }
+
+    fn number(&mut self) -> Result<Json, String> {
+        let mut s = String::new();
+        while let Some(&c) = self.chars.peek() {
+            if c.is_ascii_digit() || "+-.eE".contains(c) {
+                s.push(c);
+                self.chars.next();
+            } else {
+                break;
+            }
+        }
+        s.parse::<i64>()
+            .or_else(|_| s.parse::<f64>().map(|f| f as i64))
+            .map(Json::Number)
+            .map_err(|_| format! {"Error: invalid number {}", s})
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        if self.chars.next() != Some('"') {
+            return Err("Error: expected string".to_string());
+        }
+        let mut s = String::new();
+        loop {
+            match self.chars.next() {
+                Some('"') => return Ok(s),
+                Some('\\') => match self.chars.next() {
+                    Some('n') => s.push('\n'),
+                    Some('r') => s.push('\r'),
+                    Some('t') => s.push('\t'),
+                    Some('b') => s.push('\u{8}'),
+                    Some('f') => s.push('\u{c}'),
+                    Some('u') => {
+                        let mut code = self.hex4()?;
+                        // Characters outside the BMP come as a UTF-16 surrogate pair.
+                        if (0xd800..0xdc00).contains(&code) {
+                            if self.chars.next() != Some('\\') || self.chars.next() != Some('u') {
+                                return Err("Error: unpaired surrogate".to_string());
+                            }
+                            let low = self.hex4()?;
+                            if !(0xdc00..0xe000).contains(&low) {
+                                return Err("Error: unpaired surrogate".to_string());
+                            }
+                            code = 0x10000 + ((code - 0xd800) << 10) + (low - 0xdc00);
+                        }
+                        s.push(std::char::from_u32(code).unwrap_or('\u{fffd}'));
+                    }
+                    Some(c) => s.push(c),
+                    None => return Err("Error: unterminated string".to_string()),
+                },
+                Some(c) => s.push(c),
+                None => return Err("Error: unterminated string".to_string()),
+            }
+        }
+    }
+
+    fn hex4(&mut self) -> Result<u32, String> {
+        let mut code = 0;
+        for _ in 0..4 {
+            let digit = self
+                .chars
+                .next()
+                .and_then(|c| c.to_digit(16))
+                .ok_or("Error: invalid \\u escape")?;
+            code = code * 16 + digit;
+        }
+        Ok(code)
+    }
+
+    fn skip_ws(&mut self) {
+        while let Some(c) = self.chars.peek() {
+            if c.is_ascii_whitespace() {
+                self.chars.next();
+            } else {
+                break;
+            }
+        }
+    }
+}
+
+fn newline(buf: &mut String, depth: usize) {
+    buf.push('\n');
+    for _ in 0..depth * 2 {
+        buf.push(' ');
+    }
+}
+
+fn write_str(s: &str, buf: &mut String) {
+    buf.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => buf.push_str("\\\""),
+            '\\' => buf.push_str("\\\\"),
+            '\n' => buf.push_str("\\n"),
+            '\r' => buf.push_str("\\r"),
+            '\t' => buf.push_str("\\t"),
+            c if (c as u32) < 0x20 => buf.push_str(&format! {"\\u{:04x}", c as u32}),
+            c => buf.push(c),
+        }
+    }
+    buf.push('"');
+}
+
+fn node(kind: &str, mut members: Vec<(&str, Json)>, span: Span) -> Json {
+    members.insert(0, ("node", str_json(kind)));
+    members.push(("span", span.to_json()));
+    Json::object(members)
+}
+
+fn str_json(s: &str) -> Json {
+    Json::String(s.to_string())
+}
+
+fn array<T: ToJson>(items: &[T]) -> Json {
+    Json::Array(items.iter().map(ToJson::to_json).collect())
+}
+
+fn option<T: ToJson>(item: &Option<T>) -> Json {
+    item.as_ref().map_or(Json::Null, ToJson::to_json)
+}
+
+impl ToJson for Pos {
+    fn to_json(&self) -> Json {
+        Json::object(vec![
+            ("line", Json::Number(self.line as i64)),
+            ("col", Json::Number(self.col as i64)),
+        ])
+    }
+}
+
+impl ToJson for Span {
+    fn to_json(&self) -> Json {
+        Json::object(vec![
+            ("start", self.start.to_json()),
+            ("end", self.end.to_json()),
+        ])
+    }
+}
+
+/// A token as produced by `tokenize`, tagged like the elements of `xxxT.xml`.
+impl ToJson for (Token, Span) {
+    fn to_json(&self) -> Json {
+        let (kind, value) = match &self.0 {
+            Token::Keyword(keyword) => ("keyword", str_json(keyword.as_str())),
+            Token::Symbol(symbol) => ("symbol", str_json(symbol.as_str())),
+            Token::IntegerConstant(num) => ("integerConstant", Json::Number(*num as i64)),
+            Token::StringConstant(s) => ("stringConstant", str_json(s)),
+            Token::Ident(name) => ("identifier", str_json(name)),
+        };
+        node(kind, vec![("value", value)], self.1)
+    }
+}
+
+impl<T: ToJson> ToJson for Vec<T> {
+    fn to_json(&self) -> Json {
+        array(self)
+    }
+}
+
+impl ToJson for Ident {
+    fn to_json(&self) -> Json {
+        node(
+            "identifier",
+            vec![("name", str_json(self.as_str()))],
+            self.span,
+        )
+    }
+}
+
+impl ToJson for ClassDec {
+    fn to_json(&self) -> Json {
+        node(
+            "class",
+            vec![
+                ("name", self.name.to_json()),
+                ("classVarDecs", array(&self.var_decs)),
+                ("subroutineDecs", array(&self.subroutine_decs)),
+            ],
+            self.span,
+        )
+    }
+}
+
+impl ToJson for ClassVarDec {
+    fn to_json(&self) -> Json {
+        node(
+            "classVarDec",
+            vec![
+                ("kind", str_json(self.var_ty.as_str())),
+                ("type", self.ty.to_json()),
+                ("names", array(&self.names)),
+            ],
+            self.span,
+        )
+    }
+}
+
+impl ToJson for SubRoutineDec {
+    fn to_json(&self) -> Json {
+        let params = self
+            .args
+            .iter()
+            .map(|(ty, name)| {
+                Json::object(vec![
+                    ("node", str_json("parameter")),
+                    ("type", ty.to_json()),
+                    ("name", name.to_json()),
+                ])
+            })
+            .collect();
+        node(
+            "subroutineDec",
+            vec![
+                ("kind", str_json(self.kind.as_str())),
+                ("returnType", self.ret.to_json()),
+                ("name", self.name.to_json()),
+                ("parameters", Json::Array(params)),
+                ("body", self.body.to_json()),
+            ],
+            self.span,
+        )
+    }
+}
+
+impl ToJson for SubRoutineBody {
+    fn to_json(&self) -> Json {
+        node(
+            "subroutineBody",
+            vec![
+                ("varDecs", array(&self.var_decs)),
+                ("statements", array(&self.stmts)),
+            ],
+            self.span,
+        )
+    }
+}
+
+impl ToJson for VarDec {
+    fn to_json(&self) -> Json {
+        node(
+            "varDec",
+            vec![("type", self.ty.to_json()), ("names", array(&self.names))],
+            self.span,
+        )
+    }
+}
+
+impl ToJson for Type {
+    fn to_json(&self) -> Json {
+        match self {
+            Type::Class(name) => node("type", vec![("name", str_json(name.as_str()))], name.span),
+            _ => Json::object(vec![
+                ("node", str_json("type")),
+                ("name", str_json(self.as_str())),
+            ]),
+        }
+    }
+}
+
+impl ToJson for Stmt {
+    fn to_json(&self) -> Json {
+        let (kind, members) = match &self.kind {
+            StmtKind::Let { name, idx, expr } => (
+                "letStatement",
+                vec![
+                    ("name", name.to_json()),
+                    ("index", option(idx)),
+                    ("value", expr.to_json()),
+                ],
+            ),
+            StmtKind::If { test, then, else_ } => (
+                "ifStatement",
+                vec![
+                    ("condition", test.to_json()),
+                    ("then", array(then)),
+                    ("else", option(else_)),
+                ],
+            ),
+            StmtKind::While { test, body } => (
+                "whileStatement",
+                vec![("condition", test.to_json()), ("body", array(body))],
+            ),
+            StmtKind::Do(call) => ("doStatement", vec![("call", call.to_json())]),
+            StmtKind::Return(expr) => ("returnStatement", vec![("value", option(expr))]),
+        };
+        node(kind, members, self.span)
+    }
+}
+
+impl ToJson for Expr {
+    fn to_json(&self) -> Json {
+        let ops = self
+            .cdr
+            .iter()
+            .map(|(op, rhs)| {
+                Json::object(vec![("op", str_json(op.as_str())), ("rhs", rhs.to_json())])
+            })
+            .collect();
+        node(
+            "expression",
+            vec![("lhs", self.lhs.to_json()), ("ops", Json::Array(ops))],
+            self.span,
+        )
+    }
+}
+
+impl ToJson for Term {
+    fn to_json(&self) -> Json {
+        let (kind, members) = match &self.kind {
+            TermKind::IntegerConstant(num) => (
+                "integerConstant",
+                vec![("value", Json::Number(*num as i64))],
+            ),
+            TermKind::StringConstant(s) => ("stringConstant", vec![("value", str_json(s))]),
+            TermKind::KeywordConstant(kwd) => {
+                ("keywordConstant", vec![("value", str_json(kwd.as_str()))])
+            }
+            TermKind::ValName(name) => ("variable", vec![("name", name.to_json())]),
+            TermKind::WithUnary(op, term) => (
+                "unaryOp",
+                vec![("op", str_json(op.as_str())), ("term", term.to_json())],
+            ),
+            TermKind::WithIdx(name, idx) => (
+                "arrayAccess",
+                vec![("name", name.to_json()), ("index", idx.to_json())],
+            ),
+            TermKind::SubRoutineCall(call) => return call.to_json(),
+            TermKind::Expr(expr) => ("parenthesized", vec![("expression", expr.to_json())]),
+        };
+        node(kind, members, self.span)
+    }
+}
+
+impl ToJson for SubRoutineCall {
+    fn to_json(&self) -> Json {
+        node(
+            "subroutineCall",
+            vec![
+                ("object", option(&self.obj_name)),
+                ("name", self.routine_name.to_json()),
+                ("args", array(&self.args)),
+            ],
+            self.span,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{tokenize, Parser};
+    use super::*;
+
+    #[test]
+    fn test_write() {
+        let json = Json::object(vec![
+            ("a", Json::Array(vec![Json::Number(-1), Json::Bool(true)])),
+            ("b", str_json("\"x\"\n")),
+            (
+                "c",
+                Json::Array(vec![Json::object(vec![("d", Json::Null)])]),
+            ),
+        ]);
+        let expected = "{\n  \"a\": [-1, true],\n  \"b\": \"\\\"x\\\"\\n\",\n  \"c\": [\n    { \"d\": null }\n  ]\n}";
+        assert_eq!(json.to_string(), expected);
+    }
+
+    #[test]
+    fn test_parse() {
+        let text = r#"{"id": 1, "params": {"text": "a\"b\n\u00e9\ud83d\ude00", "xs": [true, null, -2.5]}}"#;
+        let json: Json = text.parse().unwrap();
+        assert_eq!(json.get("id").and_then(Json::as_i64), Some(1));
+        let params = json.get("params").unwrap();
+        assert_eq!(
+            params.get("text").and_then(Json::as_str),
+            Some("a\"b\n\u{e9}\u{1f600}")
+        );
+        assert_eq!(
+            params.get("xs").and_then(Json::as_array),
+            Some(&[Json::Bool(true), Json::Null, Json::Number(-2)][..])
+        );
+        assert_eq!(json.to_compact_string().parse::<Json>().unwrap(), json);
+        assert!("{\"a\": }".parse::<Json>().is_err());
+        assert!(r#""\ud800\u0041""#.parse::<Json>().is_err());
+    }
+
+    #[test]
+    fn test_spans() {
+        let jack = "class Main {\n  function void main() {\n    do Output.printInt(1 + x);\n    return;\n  }\n}\n";
+        let tokens = tokenize(jack.chars()).unwrap();
+        let ast = Parser::new(tokens.into_iter()).parse().unwrap();
+        let span = |l1, c1, l2, c2| Span {
+            start: Pos { line: l1, col: c1 },
+            end: Pos { line: l2, col: c2 },
+        };
+        assert_eq!(ast.span, span(1, 1, 6, 2));
+        let main = &ast.subroutine_decs[0];
+        assert_eq!(main.span, span(2, 3, 5, 4));
+        assert_eq!(main.body.stmts[0].span, span(3, 5, 3, 31));
+        let call = match &main.body.stmts[0].kind {
+            StmtKind::Do(call) => call,
+            _ => unreachable!(),
+        };
+        assert_eq!(call.span, span(3, 8, 3, 30));
+        assert_eq!(call.args[0].span, span(3, 24, 3, 29));
+
+        let json = ast.to_json().to_string();
+        assert!(json.starts_with("{\n  \"node\": \"class\",\n  \"name\": {\n    \"node\": \"identifier\",\n    \"name\": \"Main\","));
+        assert!(json.contains("\"node\": \"subroutineCall\""));
+        assert!(json.contains("\"ops\": [\n"));
+        assert!(json.contains("\"op\": \"+\""));
+    }
+}
@@ -1,152 +1,414 @@
 use std::borrow::Borrow;
+use std::iter::Peekable;
 use std::rc::Rc;
 
-use super::token::{Keyword, Symbol::*, Token};
+use super::diagnostic::Diagnostic;
+use super::token::{Comment, Keyword, Pos, Span, Symbol::*, Token, INT_MAX};
 
-pub fn tokenize<T, U>(strm: T) -> Result<Vec<Token>, String>
+/// Splits Jack source into tokens, each with the span it occupies in the source.
+pub fn tokenize<T, U>(strm: T) -> Result<Vec<(Token, Span)>, Diagnostic>
+where
+    T: Iterator<Item = U>,
+    U: Borrow<char>,
+{
+    tokenize_with_comments(strm).map(|(tokens, _)| tokens)
+}
+
+type TokensAndComments = (Vec<(Token, Span)>, Vec<Comment>);
+
+/// Like `tokenize`, but also returns the comments, which `tokenize` drops.
+pub fn tokenize_with_comments<T, U>(strm: T) -> Result<TokensAndComments, Diagnostic>
+where
+    T: Iterator<Item = U>,
+    U: Borrow<char>,
+{
+    lex(strm, false)
+}
+
+/// Like `tokenize`, but also accepts `'c'` character literals, lowered to integer
+/// constants, `\n`, `\"`, `\'` and `\\` escapes in strings and characters, and
+/// `0x`/`0b` integer literals.
+pub fn tokenize_extended<T, U>(strm: T) -> Result<Vec<(Token, Span)>, Diagnostic>
+where
+    T: Iterator<Item = U>,
+    U: Borrow<char>,
+{
+    lex(strm, true).map(|(tokens, _)| tokens)
+}
+
+/// Like `tokenize_extended`, but also returns the comments.
+pub fn tokenize_extended_with_comments<T, U>(strm: T) -> Result<TokensAndComments, Diagnostic>
+where
+    T: Iterator<Item = U>,
+    U: Borrow<char>,
+{
+    lex(strm, true)
+}
+
+/// Newline in the Hack character set.
+const NEW_LINE: char = '\u{80}';
+
+fn lex<T, U>(strm: T, extended: bool) -> Result<TokensAndComments, Diagnostic>
 where
     T: Iterator<Item = U>,
     U: Borrow<char>,
 {
     let mut ret = Vec::new();
-    let mut strm = strm.peekable();
-    let mut line_num = 1;
-    while let Some(c) = strm.next() {
-        match c.borrow() {
-            '\n' => {
-                line_num += 1;
-            }
+    let mut comments = Vec::new();
+    let mut strm = Cursor::new(strm);
+    loop {
+        let start = strm.pos();
+        let c = match strm.next() {
+            Some(c) => c,
+            None => break,
+        };
+        let token = match c {
             '"' => {
                 let mut s = String::new();
                 loop {
-                    if let Some(c) = strm.peek() {
-                        if *c.borrow() == '"' {
-                            strm.next().unwrap();
+                    match strm.peek() {
+                        Some('"') => {
+                            strm.next();
                             break;
-                        } else if *c.borrow() == '\n' {
-                            return Err(
-                                format! {"Error: line {}; Can't use line break in string literal", line_num},
-                            );
-                        } else {
-                            let c = strm.next().unwrap();
-                            s.push(*c.borrow());
                         }
-                    }
-                    if let None = strm.peek() {
-                        return Err(format! {"Error: line {}; Unclosed delimiter", line_num});
+                        Some('\n') => {
+                            return Err(Diagnostic::new(
+                                strm.span_from(start),
+                                "Can't use line break in string literal",
+                            ));
+                        }
+                        Some('\\') if extended => {
+                            strm.next();
+                            s.push(escape(&mut strm, start)?);
+                        }
+                        Some(c) => {
+                            s.push(c);
+                            strm.next();
+                        }
+                        None => {
+                            return Err(Diagnostic::new(
+                                strm.span_from(start),
+                                "Unclosed delimiter",
+                            ));
+                        }
                     }
                 }
-                ret.push(Token::StringConstant(Rc::new(s)));
+                Token::StringConstant(Rc::new(s))
             }
-            '{' => ret.push(Token::Symbol(LBrace)),
-            '}' => ret.push(Token::Symbol(RBrace)),
-            '(' => ret.push(Token::Symbol(LParen)),
-            ')' => ret.push(Token::Symbol(RParen)),
-            '[' => ret.push(Token::Symbol(LBracket)),
-            ']' => ret.push(Token::Symbol(RBracket)),
-            '.' => ret.push(Token::Symbol(Dot)),
-            ',' => ret.push(Token::Symbol(Comma)),
-            ';' => ret.push(Token::Symbol(SemiColon)),
-            '+' => ret.push(Token::Symbol(Plus)),
-            '-' => ret.push(Token::Symbol(Minus)),
-            '*' => ret.push(Token::Symbol(Star)),
-            '&' => ret.push(Token::Symbol(And)),
-            '|' => ret.push(Token::Symbol(Or)),
-            '<' => ret.push(Token::Symbol(LAngle)),
-            '>' => ret.push(Token::Symbol(RAngle)),
-            '=' => ret.push(Token::Symbol(Equal)),
-            '~' => ret.push(Token::Symbol(Tilde)),
-            '/' => {
-                if let Some(c) = strm.peek() {
-                    let c = *c.borrow();
-                    if c == '/' {
-                        skip_line(&mut strm);
-                        line_num += 1;
-                    } else if c == '*' {
-                        line_num += skip_multiline_comment(&mut strm)?;
-                    } else {
-                        ret.push(Token::Symbol(Slush))
+            '\'' if extended => {
+                let c = match strm.next() {
+                    Some('\\') => escape(&mut strm, start)?,
+                    Some(c) if (' '..='~').contains(&c) && c != '\'' => c,
+                    _ => {
+                        return Err(Diagnostic::new(
+                            strm.span_from(start),
+                            "Invalid character literal",
+                        ));
                     }
+                };
+                if strm.next() != Some('\'') {
+                    return Err(Diagnostic::new(
+                        strm.span_from(start),
+                        "Unclosed character literal",
+                    ));
                 }
+                Token::IntegerConstant(c as u16)
             }
-
+            '0' if extended && matches!(strm.peek(), Some('x') | Some('b')) => {
+                let radix = if strm.next() == Some('x') { 16 } else { 2 };
+                int_constant(&mut strm, start, String::new(), radix)?
+            }
+            // Extended syntax in a source lexed without it.
+            '\'' => {
+                return Err(Diagnostic::new(
+                    strm.span_from(start),
+                    "Character literals need the extended syntax",
+                ));
+            }
+            '0' if matches!(strm.peek(), Some('x') | Some('b')) => {
+                strm.next();
+                return Err(Diagnostic::new(
+                    strm.span_from(start),
+                    "Hex and binary integers need the extended syntax",
+                ));
+            }
+            '{' => Token::Symbol(LBrace),
+            '}' => Token::Symbol(RBrace),
+            '(' => Token::Symbol(LParen),
+            ')' => Token::Symbol(RParen),
+            '[' => Token::Symbol(LBracket),
+            ']' => Token::Symbol(RBracket),
+            '.' => Token::Symbol(Dot),
+            ',' => Token::Symbol(Comma),
+            ';' => Token::Symbol(SemiColon),
+            '+' => Token::Symbol(Plus),
+            '-' => Token::Symbol(Minus),
+            '*' => Token::Symbol(Star),
+            '&' => Token::Symbol(And),
+            '|' => Token::Symbol(Or),
+            '<' => Token::Symbol(LAngle),
+            '>' => Token::Symbol(RAngle),
+            '=' => Token::Symbol(Equal),
+            '~' => Token::Symbol(Tilde),
+            '/' => match strm.peek() {
+                Some('/') => {
+                    comments.push(line_comment(&mut strm, start));
+                    continue;
+                }
+                Some('*') => {
+                    comments.push(multiline_comment(&mut strm, start)?);
+                    continue;
+                }
+                _ => Token::Symbol(Slush),
+            },
             c if c.is_ascii_alphabetic() => {
                 let mut s = String::new();
-                s.push(*c.borrow());
-                loop {
-                    if let Some(c) = strm.peek() {
-                        if c.borrow().is_ascii_alphabetic()
-                            || c.borrow().is_digit(10)
-                            || *c.borrow() == '_'
-                        {
-                            s.push(*c.borrow());
-                            strm.next();
-                            continue;
-                        }
+                s.push(c);
+                while let Some(c) = strm.peek() {
+                    if c.is_ascii_alphabetic() || c.is_ascii_digit() || c == '_' {
+                        s.push(c);
+                        strm.next();
+                    } else {
+                        break;
                     }
-                    break;
                 }
-                if let Some(keyword) = Keyword::from_str(&s) {
-                    ret.push(Token::Keyword(keyword));
+                if let Ok(keyword) = s.parse::<Keyword>() {
+                    Token::Keyword(keyword)
                 } else {
-                    ret.push(Token::Ident(Rc::new(s)));
-                }
-            }
-            c if c.is_digit(10) => {
-                let mut num = c.to_digit(10).unwrap() as u16;
-                loop {
-                    if let Some(c) = strm.peek() {
-                        let c = *c.borrow();
-                        if c.is_digit(10) {
-                            strm.next().unwrap();
-                            num *= 10;
-                            num += c.borrow().to_digit(10).unwrap() as u16;
-                        } else {
-                            break;
-                        }
-                    }
+                    Token::Ident(Rc::new(s))
                 }
-                ret.push(Token::IntegerConstant(num));
             }
-            c if c.is_ascii_whitespace() => {}
+            c if c.is_ascii_digit() => int_constant(&mut strm, start, c.to_string(), 10)?,
+            c if c.is_ascii_whitespace() => continue,
             _ => {
-                return Err(format! {"Error: line {}; Unexpected Token", line_num});
+                return Err(Diagnostic::new(strm.span_from(start), "Unexpected Token"));
             }
+        };
+        ret.push((token, strm.span_from(start)));
+    }
+
+    Ok((ret, comments))
+}
+
+/// Character stream that keeps track of the position of the next character.
+struct Cursor<T>
+where
+    T: Iterator,
+{
+    strm: Peekable<T>,
+    pos: Pos,
+}
+
+impl<T, U> Cursor<T>
+where
+    T: Iterator<Item = U>,
+    U: Borrow<char>,
+{
+    fn new(strm: T) -> Self {
+        Self {
+            strm: strm.peekable(),
+            pos: Pos { line: 1, col: 1 },
+        }
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.strm.peek().map(|c| *c.borrow())
+    }
+
+    fn pos(&self) -> Pos {
+        self.pos
+    }
+
+    fn span_from(&self, start: Pos) -> Span {
+        Span {
+            start,
+            end: self.pos,
         }
     }
+}
+
+impl<T, U> Iterator for Cursor<T>
+where
+    T: Iterator<Item = U>,
+    U: Borrow<char>,
+{
+    type Item = char;
 
-    Ok(ret)
+    fn next(&mut self) -> Option<char> {
+        let c = *self.strm.next()?.borrow();
+        if c == '\n' {
+            self.pos.line += 1;
+            self.pos.col = 1;
+        } else {
+            self.pos.col += 1;
+        }
+        Some(c)
+    }
 }
 
-fn skip_line<T, U>(strm: &mut T)
+// Rest of an integer literal whose leading `digits` have already been consumed.
+// Its value can be at most 32768, for `-32768`; the parser checks the sign.
+fn int_constant<T, U>(
+    strm: &mut Cursor<T>,
+    start: Pos,
+    mut digits: String,
+    radix: u32,
+) -> Result<Token, Diagnostic>
 where
     T: Iterator<Item = U>,
     U: Borrow<char>,
 {
-    while let Some(c) = strm.next() {
-        if *c.borrow() == '\n' {
-            return;
+    while let Some(c) = strm.peek() {
+        if c.is_ascii_alphanumeric() || c == '_' {
+            digits.push(c);
+            strm.next();
+        } else {
+            break;
+        }
+    }
+    if digits.is_empty() || !digits.chars().all(|c| c.is_digit(radix)) {
+        return Err(Diagnostic::new(
+            strm.span_from(start),
+            "Invalid integer literal",
+        ));
+    }
+    let num = digits.chars().fold(0u32, |num, c| {
+        num.saturating_mul(radix)
+            .saturating_add(c.to_digit(radix).unwrap())
+    });
+    if num > INT_MAX as u32 + 1 {
+        // The literal as written, with the prefix the caller consumed.
+        let prefix = match radix {
+            16 => "0x",
+            2 => "0b",
+            _ => "",
+        };
+        return Err(Diagnostic::new(
+            strm.span_from(start),
+            format! {"Integer constant {}{} is out of range (0 to {})", prefix, digits, INT_MAX},
+        ));
+    }
+    Ok(Token::IntegerConstant(num as u16))
+}
+
+// Character escaped by a `\\`, which has already been consumed, as a Hack character.
+fn escape<T, U>(strm: &mut Cursor<T>, start: Pos) -> Result<char, Diagnostic>
+where
+    T: Iterator<Item = U>,
+    U: Borrow<char>,
+{
+    match strm.next() {
+        Some('n') => Ok(NEW_LINE),
+        Some(c @ '"') | Some(c @ '\'') | Some(c @ '\\') => Ok(c),
+        _ => Err(Diagnostic::new(
+            strm.span_from(start),
+            "Unknown escape sequence",
+        )),
+    }
+}
+
+// `start` is the position of the leading '/', which has already been consumed.
+fn line_comment<T, U>(strm: &mut Cursor<T>, start: Pos) -> Comment
+where
+    T: Iterator<Item = U>,
+    U: Borrow<char>,
+{
+    let mut text = "/".to_string();
+    while let Some(c) = strm.peek() {
+        if c == '\n' {
+            break;
         }
+        text.push(c);
+        strm.next();
+    }
+    let text = text.trim_end().to_string();
+    Comment {
+        text,
+        span: strm.span_from(start),
     }
 }
 
-fn skip_multiline_comment<T, U>(strm: &mut T) -> Result<usize, String>
+fn multiline_comment<T, U>(strm: &mut Cursor<T>, start: Pos) -> Result<Comment, Diagnostic>
 where
     T: Iterator<Item = U>,
     U: Borrow<char>,
 {
-    let mut line_count = 0;
+    let mut text = "/".to_string();
+    text.extend(strm.next());
     while let Some(c) = strm.next() {
-        if *c.borrow() == '*' {
-            if let Some(c) = strm.next() {
-                if *c.borrow() == '/' {
-                    return Ok(line_count);
-                }
-            }
+        text.push(c);
+        if c == '*' && strm.peek() == Some('/') {
+            text.extend(strm.next());
+            return Ok(Comment {
+                text,
+                span: strm.span_from(start),
+            });
+        }
+    }
+    Err(Diagnostic::new(
+        strm.span_from(start),
+        "Multi line Comment must be closed",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(src: &str) -> Vec<Token> {
+        let tokens = tokenize_extended(src.chars()).unwrap();
+        tokens.into_iter().map(|(token, _)| token).collect()
+    }
+
+    #[test]
+    fn test_extended() {
+        assert_eq!(
+            tokens(r#"'a' '\'' '\n' 0x80 0b101 "say \"hi\"\n\\""#),
+            [
+                Token::IntegerConstant(97),
+                Token::IntegerConstant(39),
+                Token::IntegerConstant(128),
+                Token::IntegerConstant(128),
+                Token::IntegerConstant(5),
+                Token::StringConstant(Rc::new("say \"hi\"\u{80}\\".to_string())),
+            ]
+        );
+        // Backslashes are plain characters in standard Jack.
+        let (tokens, _) = tokenize_with_comments(r#""a\n""#.chars()).unwrap();
+        assert_eq!(
+            tokens[0].0,
+            Token::StringConstant(Rc::new("a\\n".to_string()))
+        );
+
+        for src in &["'ab'", "'", "0x", "0b102", "0x8001", r#""\q""#] {
+            assert!(tokenize_extended(src.chars()).is_err(), "{}", src);
+        }
+        for (src, message) in &[
+            ("'a'", "Character literals need the extended syntax"),
+            ("0x80", "Hex and binary integers need the extended syntax"),
+        ] {
+            assert_eq!(tokenize(src.chars()).unwrap_err().message, *message);
+        }
+    }
+
+    #[test]
+    fn test_int_range() {
+        let (tokens, _) = tokenize_with_comments("32767 32768".chars()).unwrap();
+        assert_eq!(tokens[1].0, Token::IntegerConstant(32768));
+        for src in &["32769", "70000", "123456789012345678901234567890"] {
+            let diag = tokenize(src.chars()).unwrap_err();
+            assert_eq!(
+                diag.message,
+                format! {"Integer constant {} is out of range (0 to 32767)", src}
+            );
+            assert_eq!(diag.span.end.col, src.len() + 1);
         }
-        if *c.borrow() == '\n' {
-            line_count += 1;
+        for src in &["0x8001", "0b1000000000000001"] {
+            let diag = tokenize_extended(src.chars()).unwrap_err();
+            assert_eq!(
+                diag.message,
+                format! {"Integer constant {} is out of range (0 to 32767)", src}
+            );
         }
     }
-    return Err("Multi line Comment must be closed".to_string());
 }
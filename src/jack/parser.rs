@@ -1,126 +1,242 @@
 use std::iter::Peekable;
-use std::rc::Rc;
+use std::mem;
 
 use super::ast::*;
+use super::diagnostic::Diagnostic;
 use super::token::{Keyword::*, Symbol::*, *};
 
+type SubRoutineHeader = (SubRoutineKind, Type, Ident, Vec<(Type, Ident)>);
+
 pub struct Parser<T>
 where
-    T: Iterator<Item = Token>,
+    T: Iterator<Item = (Token, Span)>,
 {
     tokens: Peekable<T>,
+    // End of the last consumed token.
+    last_end: Pos,
+    diagnostics: Vec<Diagnostic>,
 }
 
 impl<T> Parser<T>
 where
-    T: Iterator<Item = Token>,
+    T: Iterator<Item = (Token, Span)>,
 {
     pub fn new(tokens: T) -> Self {
         Self {
             tokens: tokens.peekable(),
+            last_end: Pos { line: 1, col: 1 },
+            diagnostics: Vec::new(),
         }
     }
 
+    /// Parses a class, reporting every syntax error, one per line.
     pub fn parse(&mut self) -> Result<ClassDec, String> {
-        self.class_dec()
+        match self.parse_partial() {
+            (Some(class), diags) if diags.is_empty() => Ok(class),
+            (_, diags) => Err(diags
+                .iter()
+                .map(|diag| diag.to_string())
+                .collect::<Vec<_>>()
+                .join("\n")),
+        }
+    }
+
+    /// Parses a class, recovering from each syntax error by skipping to the next `;`,
+    /// `}` or statement or declaration keyword. Returns the class without the parts
+    /// that failed to parse (`None` if its header did) and all errors in source order.
+    pub fn parse_partial(&mut self) -> (Option<ClassDec>, Vec<Diagnostic>) {
+        let class = self.class_dec();
+        if self.peek().is_some() {
+            let diag = self.error("end of input");
+            self.diagnostics.push(diag);
+        }
+        (class, mem::take(&mut self.diagnostics))
     }
 
-    fn class_dec(&mut self) -> Result<ClassDec, String> {
+    fn class_dec(&mut self) -> Option<ClassDec> {
+        let start = self.start();
+        let name = match self.class_header() {
+            Ok(name) => Some(name),
+            Err(diag) => {
+                self.diagnostics.push(diag);
+                // Members are still parsed for their errors.
+                while !matches!(self.peek(), None | Some(Token::Symbol(LBrace)))
+                    && !self.at_member()
+                {
+                    self.next();
+                }
+                if let Some(Token::Symbol(LBrace)) = self.peek() {
+                    self.next();
+                }
+                None
+            }
+        };
+        let mut var_decs = Vec::new();
+        let mut subroutine_decs = Vec::new();
+        loop {
+            match self.peek() {
+                Some(Token::Keyword(Static)) | Some(Token::Keyword(Field)) => {
+                    if !subroutine_decs.is_empty() {
+                        let diag = self.error("subroutine declaration");
+                        self.diagnostics.push(diag);
+                    }
+                    match self.class_var_dec() {
+                        Ok(dec) => var_decs.push(dec),
+                        Err(diag) => self.recover(diag, false),
+                    }
+                }
+                Some(Token::Keyword(Constructor))
+                | Some(Token::Keyword(Function))
+                | Some(Token::Keyword(Method)) => {
+                    if let Some(dec) = self.subroutine_dec() {
+                        subroutine_decs.push(dec);
+                    }
+                }
+                Some(Token::Symbol(RBrace)) | None => break,
+                _ => {
+                    let diag = self.error("class member");
+                    self.recover(diag, true);
+                }
+            }
+        }
+        self.expect(Token::Symbol(RBrace));
+        let span = self.span_from(start);
+        name.map(|name| ClassDec::new(name, var_decs, subroutine_decs, span))
+    }
+
+    fn class_header(&mut self) -> Result<Ident, Diagnostic> {
         self.eat_assert(Token::Keyword(Keyword::Class))?;
         let name = self.eat_ident()?;
         self.eat_assert(Token::Symbol(Symbol::LBrace))?;
-        let var_decs = self.class_var_decs()?;
-        let subroutine_decs = self.subroutine_decs()?;
-        self.eat_assert(Token::Symbol(RBrace))?;
-        Ok(ClassDec::new(name, var_decs, subroutine_decs))
+        Ok(name)
     }
 
-    fn class_var_decs(&mut self) -> Result<Vec<ClassVarDec>, String> {
-        let mut ret = Vec::new();
-        loop {
-            let var_ty = match self.tokens.peek() {
-                Some(Token::Keyword(Keyword::Static)) => ClassVarType::Static,
-                Some(Token::Keyword(Keyword::Field)) => ClassVarType::Field,
-                _ => return Ok(ret),
-            };
-            self.tokens.next();
-            let ty = self.eat_type(false)?;
-            let mut names = Vec::new();
-            names.push(self.eat_ident()?);
-            while let Some(Token::Symbol(Comma)) = self.tokens.peek() {
-                self.eat_assert(Token::Symbol(Comma))?;
-                names.push(self.eat_ident()?);
+    fn class_var_dec(&mut self) -> Result<ClassVarDec, Diagnostic> {
+        let start = self.start();
+        let var_ty = match self.next() {
+            Some(Token::Keyword(Keyword::Static)) => ClassVarType::Static,
+            Some(Token::Keyword(Keyword::Field)) => ClassVarType::Field,
+            _ => unreachable!(),
+        };
+        let ty = self.eat_type(false)?;
+        let names = self.eat_names()?;
+        self.eat_assert(Token::Symbol(SemiColon))?;
+        Ok(ClassVarDec::new(var_ty, ty, names, self.span_from(start)))
+    }
+
+    fn subroutine_dec(&mut self) -> Option<SubRoutineDec> {
+        let start = self.start();
+        let header = match self.subroutine_header() {
+            Ok(header) => Some(header),
+            Err(diag) => {
+                self.diagnostics.push(diag);
+                // Skip to the body so that its errors are still reported.
+                while !matches!(
+                    self.peek(),
+                    None | Some(Token::Symbol(LBrace)) | Some(Token::Symbol(RBrace))
+                ) && !self.at_member()
+                {
+                    self.next();
+                }
+                None
             }
-            self.eat_assert(Token::Symbol(SemiColon))?;
-            ret.push(ClassVarDec::new(var_ty, ty, names));
-        }
+        };
+        let body = match self.subroutine_body() {
+            Ok(body) => body,
+            Err(diag) => {
+                if header.is_some() {
+                    self.diagnostics.push(diag);
+                }
+                return None;
+            }
+        };
+        let (kind, ret_ty, name, params) = header?;
+        Some(SubRoutineDec::new(
+            name,
+            kind,
+            ret_ty,
+            params,
+            body,
+            self.span_from(start),
+        ))
     }
 
-    fn subroutine_decs(&mut self) -> Result<Vec<SubRoutineDec>, String> {
-        let mut ret = Vec::new();
-        loop {
-            let ty = match self.tokens.peek() {
-                Some(Token::Keyword(Constructor)) => SubRoutineKind::Constructor,
-                Some(Token::Keyword(Function)) => SubRoutineKind::Function,
-                Some(Token::Keyword(Method)) => SubRoutineKind::Method,
-                _ => return Ok(ret),
-            };
-            self.tokens.next();
-            let ret_ty = self.eat_type(true)?;
+    fn subroutine_header(&mut self) -> Result<SubRoutineHeader, Diagnostic> {
+        let kind = match self.next() {
+            Some(Token::Keyword(Constructor)) => SubRoutineKind::Constructor,
+            Some(Token::Keyword(Function)) => SubRoutineKind::Function,
+            Some(Token::Keyword(Method)) => SubRoutineKind::Method,
+            _ => unreachable!(),
+        };
+        let ret_ty = self.eat_type(true)?;
+        let name = self.eat_ident()?;
+        self.eat_assert(Token::Symbol(LParen))?;
+        let mut params = Vec::new();
+        if let Ok(ty) = self.eat_type(false) {
             let name = self.eat_ident()?;
-            self.eat_assert(Token::Symbol(LParen))?;
-            let mut params = Vec::new();
-            if let Ok(ty) = self.eat_type(false) {
+            params.push((ty, name));
+            while let Some(Token::Symbol(Comma)) = self.peek() {
+                self.eat_assert(Token::Symbol(Comma))?;
+                let ty = self.eat_type(false)?;
                 let name = self.eat_ident()?;
                 params.push((ty, name));
-                while let Some(Token::Symbol(Comma)) = self.tokens.peek() {
-                    self.eat_assert(Token::Symbol(Comma))?;
-                    let ty = self.eat_type(false)?;
-                    let name = self.eat_ident()?;
-                    params.push((ty, name));
-                }
             }
-            self.eat_assert(Token::Symbol(RParen))?;
-            self.eat_assert(Token::Symbol(LBrace))?;
-            let mut var_decs = Vec::new();
-            while let Some(Token::Keyword(Var)) = self.tokens.peek() {
-                self.eat_assert(Token::Keyword(Var))?;
-                let ty = self.eat_type(false)?;
-                let mut names = Vec::new();
-                names.push(self.eat_ident()?);
-                while let Some(Token::Symbol(Comma)) = self.tokens.peek() {
-                    self.eat_assert(Token::Symbol(Comma))?;
-                    names.push(self.eat_ident()?);
-                }
-                self.eat_assert(Token::Symbol(SemiColon))?;
-                var_decs.push(VarDec::new(names, ty));
+        }
+        self.eat_assert(Token::Symbol(RParen))?;
+        Ok((kind, ret_ty, name, params))
+    }
+
+    fn subroutine_body(&mut self) -> Result<SubRoutineBody, Diagnostic> {
+        let start = self.start();
+        self.eat_assert(Token::Symbol(LBrace))?;
+        let mut var_decs = Vec::new();
+        while let Some(Token::Keyword(Var)) = self.peek() {
+            match self.var_dec() {
+                Ok(dec) => var_decs.push(dec),
+                Err(diag) => self.recover(diag, false),
             }
-            let stmts = self.stmts()?;
-            let body = SubRoutineBody::new(var_decs, stmts);
-            ret.push(SubRoutineDec::new(name, ty, ret_ty, params, body));
-            self.eat_assert(Token::Symbol(RBrace))?;
         }
+        let stmts = self.stmts();
+        self.expect(Token::Symbol(RBrace));
+        Ok(SubRoutineBody::new(var_decs, stmts, self.span_from(start)))
     }
 
-    fn stmts(&mut self) -> Result<Vec<Stmt>, String> {
+    fn var_dec(&mut self) -> Result<VarDec, Diagnostic> {
+        let start = self.start();
+        self.eat_assert(Token::Keyword(Var))?;
+        let ty = self.eat_type(false)?;
+        let names = self.eat_names()?;
+        self.eat_assert(Token::Symbol(SemiColon))?;
+        Ok(VarDec::new(names, ty, self.span_from(start)))
+    }
+
+    // Statements up to the `}` closing the block; those that fail to parse are left out.
+    fn stmts(&mut self) -> Vec<Stmt> {
         let mut stmts = Vec::new();
         loop {
-            match self.tokens.peek() {
-                Some(Token::Keyword(Let)) => stmts.push(self.let_stmt()?),
-                Some(Token::Keyword(If)) => stmts.push(self.if_stmt()?),
-                Some(Token::Keyword(While)) => stmts.push(self.while_stmt()?),
-                Some(Token::Keyword(Do)) => stmts.push(self.do_stmt()?),
-                Some(Token::Keyword(Return)) => stmts.push(self.return_stmt()?),
-                _ => break,
+            let stmt = match self.peek() {
+                Some(Token::Keyword(Let)) => self.let_stmt(),
+                Some(Token::Keyword(If)) => self.if_stmt(),
+                Some(Token::Keyword(While)) => self.while_stmt(),
+                Some(Token::Keyword(Do)) => self.do_stmt(),
+                Some(Token::Keyword(Return)) => self.return_stmt(),
+                Some(Token::Symbol(RBrace)) | None => break,
+                Some(token) if starts_member(token) => break,
+                _ => Err(self.error("statement")),
+            };
+            match stmt {
+                Ok(stmt) => stmts.push(stmt),
+                Err(diag) => self.recover(diag, false),
             }
         }
-        Ok(stmts)
+        stmts
     }
 
-    fn let_stmt(&mut self) -> Result<Stmt, String> {
+    fn let_stmt(&mut self) -> Result<Stmt, Diagnostic> {
+        let start = self.start();
         self.eat_assert(Token::Keyword(Let))?;
         let name = self.eat_ident()?;
-        let idx = if let Some(Token::Symbol(LBracket)) = self.tokens.peek() {
+        let idx = if let Some(Token::Symbol(LBracket)) = self.peek() {
             self.eat_assert(Token::Symbol(LBracket))?;
             let expr = self.expr()?;
             self.eat_assert(Token::Symbol(RBracket))?;
@@ -131,51 +247,64 @@ where
         self.eat_assert(Token::Symbol(Equal))?;
         let expr = self.expr()?;
         self.eat_assert(Token::Symbol(SemiColon))?;
-        Ok(Stmt::Let { name, idx, expr })
+        Ok(Stmt::new(
+            StmtKind::Let { name, idx, expr },
+            self.span_from(start),
+        ))
     }
 
-    fn if_stmt(&mut self) -> Result<Stmt, String> {
+    fn if_stmt(&mut self) -> Result<Stmt, Diagnostic> {
+        let start = self.start();
         self.eat_assert(Token::Keyword(If))?;
         self.eat_assert(Token::Symbol(LParen))?;
         let test = self.expr()?;
         self.eat_assert(Token::Symbol(RParen))?;
         self.eat_assert(Token::Symbol(LBrace))?;
-        let then = self.stmts()?;
-        self.eat_assert(Token::Symbol(RBrace))?;
-        let else_ = if let Some(Token::Keyword(Else)) = self.tokens.peek() {
+        let then = self.stmts();
+        self.expect(Token::Symbol(RBrace));
+        let else_ = if let Some(Token::Keyword(Else)) = self.peek() {
             self.eat_assert(Token::Keyword(Else))?;
             self.eat_assert(Token::Symbol(LBrace))?;
-            let else_ = self.stmts()?;
-            self.eat_assert(Token::Symbol(RBrace))?;
+            let else_ = self.stmts();
+            self.expect(Token::Symbol(RBrace));
             Some(else_)
         } else {
             None
         };
-        Ok(Stmt::If { test, then, else_ })
+        Ok(Stmt::new(
+            StmtKind::If { test, then, else_ },
+            self.span_from(start),
+        ))
     }
 
-    fn while_stmt(&mut self) -> Result<Stmt, String> {
+    fn while_stmt(&mut self) -> Result<Stmt, Diagnostic> {
+        let start = self.start();
         self.eat_assert(Token::Keyword(While))?;
         self.eat_assert(Token::Symbol(LParen))?;
         let test = self.expr()?;
         self.eat_assert(Token::Symbol(RParen))?;
         self.eat_assert(Token::Symbol(LBrace))?;
-        let body = self.stmts()?;
-        self.eat_assert(Token::Symbol(RBrace))?;
-        Ok(Stmt::While { test, body })
+        let body = self.stmts();
+        self.expect(Token::Symbol(RBrace));
+        Ok(Stmt::new(
+            StmtKind::While { test, body },
+            self.span_from(start),
+        ))
     }
 
-    fn do_stmt(&mut self) -> Result<Stmt, String> {
+    fn do_stmt(&mut self) -> Result<Stmt, Diagnostic> {
+        let start = self.start();
         self.eat_assert(Token::Keyword(Do))?;
         let name = self.eat_ident()?;
-        let do_stmt = Stmt::Do(self.subroutine_call(name)?);
+        let call = self.subroutine_call(name)?;
         self.eat_assert(Token::Symbol(SemiColon))?;
-        Ok(do_stmt)
+        Ok(Stmt::new(StmtKind::Do(call), self.span_from(start)))
     }
 
-    fn return_stmt(&mut self) -> Result<Stmt, String> {
+    fn return_stmt(&mut self) -> Result<Stmt, Diagnostic> {
+        let start = self.start();
         self.eat_assert(Token::Keyword(Return))?;
-        let expr = if let Some(Token::Symbol(SemiColon)) = self.tokens.peek() {
+        let expr = if let Some(Token::Symbol(SemiColon)) = self.peek() {
             self.eat_assert(Token::Symbol(SemiColon))?;
             None
         } else {
@@ -183,99 +312,104 @@ where
             self.eat_assert(Token::Symbol(SemiColon))?;
             Some(expr)
         };
-        Ok(Stmt::Return(expr))
+        Ok(Stmt::new(StmtKind::Return(expr), self.span_from(start)))
     }
 
-    fn expr(&mut self) -> Result<Expr, String> {
+    fn expr(&mut self) -> Result<Expr, Diagnostic> {
+        let start = self.start();
         let term = self.term()?;
-        let cdr = match self.tokens.peek() {
-            Some(Token::Symbol(Plus)) => {
-                self.eat_assert(Token::Symbol(Plus))?;
-                Some((Binop::Plus, Box::new(self.term()?)))
-            }
-            Some(Token::Symbol(Minus)) => {
-                self.eat_assert(Token::Symbol(Minus))?;
-                Some((Binop::Minus, Box::new(self.term()?)))
-            }
-            Some(Token::Symbol(Star)) => {
-                self.eat_assert(Token::Symbol(Star))?;
-                Some((Binop::Mul, Box::new(self.term()?)))
-            }
-            Some(Token::Symbol(Slush)) => {
-                self.eat_assert(Token::Symbol(Slush))?;
-                Some((Binop::Div, Box::new(self.term()?)))
-            }
-            Some(Token::Symbol(And)) => {
-                self.eat_assert(Token::Symbol(And))?;
-                Some((Binop::And, Box::new(self.term()?)))
-            }
-            Some(Token::Symbol(Or)) => {
-                self.eat_assert(Token::Symbol(Or))?;
-                Some((Binop::Or, Box::new(self.term()?)))
-            }
-            Some(Token::Symbol(LAngle)) => {
-                self.eat_assert(Token::Symbol(LAngle))?;
-                Some((Binop::Lt, Box::new(self.term()?)))
-            }
-            Some(Token::Symbol(RAngle)) => {
-                self.eat_assert(Token::Symbol(RAngle))?;
-                Some((Binop::Gt, Box::new(self.term()?)))
-            }
-            Some(Token::Symbol(Equal)) => {
-                self.eat_assert(Token::Symbol(Equal))?;
-                Some((Binop::Equal, Box::new(self.term()?)))
-            }
-            _ => None,
-        };
-        Ok(Expr::new(term, cdr))
+        let mut cdr = Vec::new();
+        while let Some(op) = self.peek().and_then(binop) {
+            self.next();
+            cdr.push((op, self.term()?));
+        }
+        Ok(Expr::new(term, cdr, self.span_from(start)))
     }
 
-    fn term(&mut self) -> Result<Term, String> {
-        if let Some(Token::Ident(_)) = self.tokens.peek() {
+    fn term(&mut self) -> Result<Term, Diagnostic> {
+        let start = self.start();
+        let kind = self.term_kind()?;
+        Ok(Term::new(kind, self.span_from(start)))
+    }
+
+    fn term_kind(&mut self) -> Result<TermKind, Diagnostic> {
+        if let Some(Token::Ident(_)) = self.peek() {
             let name = self.eat_ident()?;
-            match self.tokens.peek() {
+            match self.peek() {
                 Some(Token::Symbol(LBracket)) => {
                     self.eat_assert(Token::Symbol(LBracket))?;
                     let expr = self.expr()?;
                     self.eat_assert(Token::Symbol(RBracket))?;
-                    return Ok(Term::WithIdx(name, Box::new(expr)));
+                    return Ok(TermKind::WithIdx(name, Box::new(expr)));
                 }
                 Some(Token::Symbol(Dot)) | Some(Token::Symbol(LParen)) => {
                     let subroutine_call = self.subroutine_call(name)?;
-                    return Ok(Term::SubRoutineCall(subroutine_call));
+                    return Ok(TermKind::SubRoutineCall(subroutine_call));
                 }
                 _ => {
-                    return Ok(Term::ValName(name));
+                    return Ok(TermKind::ValName(name));
                 }
             }
         }
-        match self.tokens.next() {
-            Some(Token::IntegerConstant(num)) => Ok(Term::IntegerConstant(num)),
-            Some(Token::StringConstant(s)) => Ok(Term::StringConstant(s.clone())),
-            Some(Token::Keyword(True)) => Ok(Term::KeywordConstant(KeywordConstant::True)),
-            Some(Token::Keyword(False)) => Ok(Term::KeywordConstant(KeywordConstant::False)),
-            Some(Token::Keyword(Null)) => Ok(Term::KeywordConstant(KeywordConstant::Null)),
-            Some(Token::Keyword(This)) => Ok(Term::KeywordConstant(KeywordConstant::This)),
+        // Leave anything that can't start a term unconsumed, so errors point at it.
+        match self.peek() {
+            Some(Token::IntegerConstant(_))
+            | Some(Token::StringConstant(_))
+            | Some(Token::Keyword(True))
+            | Some(Token::Keyword(False))
+            | Some(Token::Keyword(Null))
+            | Some(Token::Keyword(This))
+            | Some(Token::Symbol(LParen))
+            | Some(Token::Symbol(Minus))
+            | Some(Token::Symbol(Tilde)) => {}
+            _ => return Err(self.error("expression")),
+        }
+        let span = self.next_span();
+        match self.next() {
+            Some(Token::IntegerConstant(num)) => {
+                if num > INT_MAX {
+                    self.diagnostics.push(Diagnostic::new(
+                        span,
+                        format! {"Integer constant {} is out of range (0 to {})", num, INT_MAX},
+                    ));
+                }
+                Ok(TermKind::IntegerConstant(num))
+            }
+            Some(Token::StringConstant(s)) => Ok(TermKind::StringConstant(s)),
+            Some(Token::Keyword(True)) => Ok(TermKind::KeywordConstant(KeywordConstant::True)),
+            Some(Token::Keyword(False)) => Ok(TermKind::KeywordConstant(KeywordConstant::False)),
+            Some(Token::Keyword(Null)) => Ok(TermKind::KeywordConstant(KeywordConstant::Null)),
+            Some(Token::Keyword(This)) => Ok(TermKind::KeywordConstant(KeywordConstant::This)),
             Some(Token::Symbol(LParen)) => {
                 let expr = self.expr()?;
                 self.eat_assert(Token::Symbol(RParen))?;
-                Ok(Term::Expr(Box::new(expr)))
+                Ok(TermKind::Expr(Box::new(expr)))
             }
             Some(Token::Symbol(Minus)) => {
-                let term = self.term()?;
-                Ok(Term::WithUnary(Unop::Minus, Box::new(term)))
+                let term = match self.peek() {
+                    // The one constant that is only in range negated.
+                    Some(Token::IntegerConstant(num)) if *num == INT_MAX + 1 => {
+                        let num = *num;
+                        let span = self.next_span();
+                        self.next();
+                        Term::new(TermKind::IntegerConstant(num), span)
+                    }
+                    _ => self.term()?,
+                };
+                Ok(TermKind::WithUnary(Unop::Minus, Box::new(term)))
             }
             Some(Token::Symbol(Tilde)) => {
                 let term = self.term()?;
-                Ok(Term::WithUnary(Unop::BitNot, Box::new(term)))
+                Ok(TermKind::WithUnary(Unop::BitNot, Box::new(term)))
             }
-            _ => Err("Error".to_string()),
+            _ => unreachable!(),
         }
     }
 
-    fn subroutine_call(&mut self, name: Rc<String>) -> Result<SubRoutineCall, String> {
+    fn subroutine_call(&mut self, name: Ident) -> Result<SubRoutineCall, Diagnostic> {
+        let start = name.span.start;
         let (obj_name, routine_name) = {
-            if let Some(Token::Symbol(Dot)) = self.tokens.peek() {
+            if let Some(Token::Symbol(Dot)) = self.peek() {
                 self.eat_assert(Token::Symbol(Dot))?;
                 let routine_name = self.eat_ident()?;
                 (Some(name), routine_name)
@@ -286,60 +420,264 @@ where
         self.eat_assert(Token::Symbol(LParen))?;
         let mut args = Vec::new();
         loop {
-            match self.tokens.peek() {
+            match self.peek() {
                 Some(Token::Symbol(RParen)) => {
                     self.eat_assert(Token::Symbol(RParen))?;
                     break;
                 }
                 _ => {
                     args.push(self.expr()?);
-                    if let Some(Token::Symbol(Comma)) = self.tokens.peek() {
+                    if let Some(Token::Symbol(Comma)) = self.peek() {
                         self.eat_assert(Token::Symbol(Comma))?;
                     }
                 }
             }
         }
-        Ok(SubRoutineCall::new(obj_name, routine_name, args))
+        Ok(SubRoutineCall::new(
+            obj_name,
+            routine_name,
+            args,
+            self.span_from(start),
+        ))
+    }
+
+    /// Span of the next token not consumed yet, or an empty span at the end of input.
+    /// After `parse` fails, this is where it stopped.
+    pub fn next_span(&mut self) -> Span {
+        match self.tokens.peek() {
+            Some((_, span)) => *span,
+            None => self.span_from(self.last_end),
+        }
+    }
+
+    fn peek(&mut self) -> Option<&Token> {
+        self.tokens.peek().map(|(token, _)| token)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let (token, span) = self.tokens.next()?;
+        self.last_end = span.end;
+        Some(token)
     }
 
-    fn eat_assert(&mut self, token: Token) -> Result<Token, String> {
+    // Start of the next token, or the end of input if there is none.
+    fn start(&mut self) -> Pos {
         match self.tokens.peek() {
-            Some(t) => {
-                if token == *t {
-                    Ok(self.tokens.next().unwrap())
-                } else {
-                    Err("Error".to_string())
+            Some((_, span)) => span.start,
+            None => self.last_end,
+        }
+    }
+
+    fn span_from(&self, start: Pos) -> Span {
+        Span {
+            start,
+            end: self.last_end,
+        }
+    }
+
+    fn error(&mut self, expected: &str) -> Diagnostic {
+        let found = match self.peek() {
+            Some(Token::StringConstant(_)) => "string constant".to_string(),
+            Some(token) => format! {"'{}'", token},
+            None => "end of input".to_string(),
+        };
+        let span = self.next_span();
+        Diagnostic::new(span, format! {"Expected {}, found {}", expected, found})
+    }
+
+    // Records `diag` and skips to where parsing can resume: after a `;`, or before a
+    // `}` closing the current block or a statement keyword, with blocks in between
+    // skipped whole. At class level only member declarations resume.
+    fn recover(&mut self, diag: Diagnostic, class_level: bool) {
+        self.diagnostics.push(diag);
+        let mut depth = 0;
+        loop {
+            match self.peek() {
+                None => return,
+                Some(Token::Symbol(RBrace)) if depth == 0 => return,
+                Some(token) if starts_member(token) => return,
+                Some(Token::Symbol(SemiColon)) if depth == 0 && !class_level => {
+                    self.next();
+                    return;
+                }
+                Some(Token::Keyword(Let))
+                | Some(Token::Keyword(If))
+                | Some(Token::Keyword(While))
+                | Some(Token::Keyword(Do))
+                | Some(Token::Keyword(Return))
+                    if depth == 0 && !class_level =>
+                {
+                    return
                 }
+                Some(Token::Symbol(LBrace)) => depth += 1,
+                Some(Token::Symbol(RBrace)) => depth -= 1,
+                _ => {}
             }
-            _ => Err("Error".to_string()),
+            self.next();
+        }
+    }
+
+    fn at_member(&mut self) -> bool {
+        self.peek().is_some_and(starts_member)
+    }
+
+    // Eats `token` if it is next, and otherwise records the error and goes on as if it was.
+    fn expect(&mut self, token: Token) {
+        if let Err(diag) = self.eat_assert(token) {
+            self.diagnostics.push(diag);
+        }
+    }
+
+    fn eat_assert(&mut self, token: Token) -> Result<Token, Diagnostic> {
+        match self.peek() {
+            Some(t) if *t == token => Ok(self.next().unwrap()),
+            _ => Err(self.error(&format! {"'{}'", token})),
         }
     }
 
-    fn eat_type(&mut self, allow_void: bool) -> Result<Type, String> {
-        let ty = match self.tokens.peek() {
+    fn eat_type(&mut self, allow_void: bool) -> Result<Type, Diagnostic> {
+        let ty = match self.peek() {
             Some(Token::Keyword(Int)) => Type::Int,
             Some(Token::Keyword(Char)) => Type::Char,
             Some(Token::Keyword(Boolean)) => Type::Boolean,
-            Some(Token::Ident(s)) => Type::Class(s.clone()),
-            Some(Token::Keyword(Void)) => {
-                if allow_void {
-                    Type::Void
-                } else {
-                    return Err("Error".to_string());
-                }
-            }
-            _ => return Err("Error".to_string()),
+            Some(Token::Ident(_)) => return Ok(Type::Class(self.eat_ident()?)),
+            Some(Token::Keyword(Void)) if allow_void => Type::Void,
+            _ => return Err(self.error("type")),
         };
-        self.tokens.next();
+        self.next();
         Ok(ty)
     }
 
-    fn eat_ident(&mut self) -> Result<Rc<String>, String> {
-        let name = match self.tokens.peek() {
+    fn eat_ident(&mut self) -> Result<Ident, Diagnostic> {
+        let start = self.start();
+        let name = match self.peek() {
             Some(Token::Ident(s)) => s.clone(),
-            _ => return Err("Error".to_string()),
+            _ => return Err(self.error("identifier")),
         };
-        self.tokens.next();
-        Ok(name)
+        self.next();
+        Ok(Ident::new(name, self.span_from(start)))
+    }
+
+    // `name (, name)*`
+    fn eat_names(&mut self) -> Result<Vec<Ident>, Diagnostic> {
+        let mut names = vec![self.eat_ident()?];
+        while let Some(Token::Symbol(Comma)) = self.peek() {
+            self.eat_assert(Token::Symbol(Comma))?;
+            names.push(self.eat_ident()?);
+        }
+        Ok(names)
+    }
+}
+
+// Binary operator of a symbol.
+fn binop(token: &Token) -> Option<Binop> {
+    Some(match token {
+        Token::Symbol(Plus) => Binop::Plus,
+        Token::Symbol(Minus) => Binop::Minus,
+        Token::Symbol(Star) => Binop::Mul,
+        Token::Symbol(Slush) => Binop::Div,
+        Token::Symbol(And) => Binop::And,
+        Token::Symbol(Or) => Binop::Or,
+        Token::Symbol(LAngle) => Binop::Lt,
+        Token::Symbol(RAngle) => Binop::Gt,
+        Token::Symbol(Equal) => Binop::Equal,
+        _ => return None,
+    })
+}
+
+// Keyword starting a class variable or subroutine declaration.
+fn starts_member(token: &Token) -> bool {
+    matches!(
+        token,
+        Token::Keyword(Static)
+            | Token::Keyword(Field)
+            | Token::Keyword(Constructor)
+            | Token::Keyword(Function)
+            | Token::Keyword(Method)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tokenize;
+    use super::*;
+
+    #[test]
+    fn test_recovery() {
+        let jack = "class Main {
+    field int x
+    field int y;
+    method void f() {
+        let x = ;
+        if (x { let x = 1; }
+        while (x) {
+            let x = 2
+            let y = 3;
+        }
+        return;
+    }
+    function int g(int a,) {
+        return a +;
+    }
+    function void h() {
+        return;
+    }
+}
+";
+        let tokens = tokenize(jack.chars()).unwrap();
+        let (class, diags) = Parser::new(tokens.into_iter()).parse_partial();
+        let diags: Vec<_> = diags
+            .iter()
+            .map(|diag| (diag.span.start.line, diag.message.as_str()))
+            .collect();
+        assert_eq!(
+            diags,
+            [
+                (3, "Expected ';', found 'field'"),
+                (5, "Expected expression, found ';'"),
+                (6, "Expected ')', found '{'"),
+                (9, "Expected ';', found 'let'"),
+                (13, "Expected type, found ')'"),
+                (14, "Expected expression, found ';'"),
+            ]
+        );
+
+        let class = class.unwrap();
+        assert_eq!(class.var_decs.len(), 1);
+        let names: Vec<_> = class
+            .subroutine_decs
+            .iter()
+            .map(|dec| dec.name.as_str())
+            .collect();
+        assert_eq!(names, ["f", "h"]);
+        // `let x = ;` and the `if` are left out.
+        let body = &class.subroutine_decs[0].body;
+        assert_eq!(body.stmts.len(), 2);
+    }
+
+    #[test]
+    fn test_int_range() {
+        let jack = "class Main { function int f() { let x = -32768; return 32768; } }";
+        let tokens = tokenize(jack.chars()).unwrap();
+        let (_, diags) = Parser::new(tokens.into_iter()).parse_partial();
+        assert_eq!(diags.len(), 1);
+        assert_eq!(
+            diags[0].message,
+            "Integer constant 32768 is out of range (0 to 32767)"
+        );
+        assert_eq!(diags[0].span.start.col, 56);
+    }
+
+    #[test]
+    fn test_binop_chain() {
+        let jack = "class Main { function int f() { return 1 - 2 * x < 3; } }";
+        let tokens = tokenize(jack.chars()).unwrap();
+        let class = Parser::new(tokens.into_iter()).parse().unwrap();
+        let expr = match &class.subroutine_decs[0].body.stmts[0].kind {
+            StmtKind::Return(Some(expr)) => expr,
+            stmt => panic!("{:?}", stmt),
+        };
+        let ops: Vec<_> = expr.cdr.iter().map(|(op, _)| *op).collect();
+        assert_eq!(ops, [Binop::Minus, Binop::Mul, Binop::Lt]);
     }
 }
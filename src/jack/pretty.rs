@@ -0,0 +1,462 @@
+use super::ast::*;
+use super::lexer::{tokenize_extended_with_comments, tokenize_with_comments};
+use super::parser::Parser;
+use super::token::{Comment, Pos, Span, Symbol, Token};
+
+const INDENT: &str = "    ";
+
+/// Lines longer than this get their subroutine calls split one argument per line.
+pub const MAX_WIDTH: usize = 100;
+
+/// Formats Jack source, keeping its comments and at most one blank line
+/// wherever the source has blank lines between statements or declarations.
+pub fn format_source(src: &str) -> Result<String, String> {
+    let (tokens, comments) = tokenize_with_comments(src.chars())?;
+    format_tokens(src, &tokens, comments)
+}
+
+/// Like `format_source` for the syntax of `tokenize_extended`. Character, hex and binary
+/// literals and escapes are kept as written.
+pub fn format_source_extended(src: &str) -> Result<String, String> {
+    let (tokens, comments) = tokenize_extended_with_comments(src.chars())?;
+    format_tokens(src, &tokens, comments)
+}
+
+fn format_tokens(
+    src: &str,
+    tokens: &[(Token, Span)],
+    comments: Vec<Comment>,
+) -> Result<String, String> {
+    let ast = Parser::new(tokens.iter().cloned()).parse()?;
+    let mut printer = Printer::new(tokens, comments);
+    printer.src = src.lines().collect();
+    printer.class(&ast);
+    Ok(printer.out)
+}
+
+/// Prints `ast` as Jack source in the layout of `format_source`, without comments.
+pub fn pretty_print(ast: &ClassDec) -> String {
+    let mut printer = Printer::new(&[], Vec::new());
+    printer.class(ast);
+    printer.out
+}
+
+struct Printer<'a> {
+    // Source tokens, used to find braces that the AST doesn't record, and source
+    // lines, from which constants are printed as written.
+    tokens: &'a [(Token, Span)],
+    src: Vec<&'a str>,
+    // Comments not printed yet, in reverse source order.
+    comments: Vec<Comment>,
+    out: String,
+    indent: usize,
+    // Source line of the last thing printed.
+    last_line: usize,
+    // Whether nothing has been printed yet in the current block.
+    block_start: bool,
+    // Whether the next line not at the start of a block must follow a blank line.
+    force_blank: bool,
+}
+
+impl<'a> Printer<'a> {
+    fn new(tokens: &'a [(Token, Span)], mut comments: Vec<Comment>) -> Self {
+        comments.reverse();
+        Self {
+            tokens,
+            src: Vec::new(),
+            comments,
+            out: String::new(),
+            indent: 0,
+            last_line: 0,
+            block_start: true,
+            force_blank: false,
+        }
+    }
+
+    fn class(&mut self, class: &ClassDec) {
+        self.item(class.span.start);
+        self.line(&format! {"class {} {{", class.name.as_str()});
+        self.open_block(self.find(class.name.span.end, Symbol::LBrace));
+        for dec in &class.var_decs {
+            self.item(dec.span.start);
+            self.line(&format! {
+                "{} {} {};",
+                dec.var_ty.as_str(),
+                dec.ty.as_str(),
+                names(&dec.names)
+            });
+            self.last_line = dec.span.end.line;
+        }
+        for dec in &class.subroutine_decs {
+            self.force_blank = true;
+            self.item(dec.span.start);
+            self.subroutine_dec(dec);
+        }
+        self.close_block(class.span.end);
+        self.comments_before(Pos {
+            line: usize::MAX,
+            col: 0,
+        });
+    }
+
+    fn subroutine_dec(&mut self, dec: &SubRoutineDec) {
+        let args: Vec<_> = dec
+            .args
+            .iter()
+            .map(|(ty, name)| format! {"{} {}", ty.as_str(), name.as_str()})
+            .collect();
+        self.line(&format! {
+            "{} {} {}({}) {{",
+            dec.kind.as_str(),
+            dec.ret.as_str(),
+            dec.name.as_str(),
+            args.join(", ")
+        });
+        self.open_block(dec.body.span.start);
+        for var_dec in &dec.body.var_decs {
+            self.item(var_dec.span.start);
+            self.line(&format! {"var {} {};", var_dec.ty.as_str(), names(&var_dec.names)});
+            self.last_line = var_dec.span.end.line;
+        }
+        for stmt in &dec.body.stmts {
+            self.stmt(stmt);
+        }
+        self.close_block(dec.body.span.end);
+    }
+
+    // Prints a block whose `{` is at `lbrace` and returns the position of its `}`.
+    fn block(&mut self, stmts: &[Stmt], lbrace: Pos) -> Pos {
+        self.open_block(lbrace);
+        let after = stmts.last().map_or(lbrace, |stmt| stmt.span.end);
+        for stmt in stmts {
+            self.stmt(stmt);
+        }
+        let rbrace = self.find(after, Symbol::RBrace);
+        self.close_block(rbrace);
+        rbrace
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        self.item(stmt.span.start);
+        match &stmt.kind {
+            StmtKind::Let { name, idx, expr } => {
+                let mut s = format! {"let {}", name.as_str()};
+                if let Some(idx) = idx {
+                    s.push('[');
+                    let idx = self.expr(idx, self.col(&s));
+                    s.push_str(&idx);
+                    s.push(']');
+                }
+                s.push_str(" = ");
+                let expr = self.expr(expr, self.col(&s));
+                s.push_str(&expr);
+                s.push(';');
+                self.line(&s);
+            }
+            StmtKind::If { test, then, else_ } => {
+                let test_str = self.expr(test, self.col("if ("));
+                self.line(&format! {"if ({}) {{", test_str});
+                let rbrace = self.block(then, self.find(test.span.end, Symbol::LBrace));
+                if let Some(else_) = else_ {
+                    self.out.truncate(self.out.len() - 1);
+                    self.out.push_str(" else {\n");
+                    self.block(else_, self.find(rbrace, Symbol::LBrace));
+                }
+            }
+            StmtKind::While { test, body } => {
+                let test_str = self.expr(test, self.col("while ("));
+                self.line(&format! {"while ({}) {{", test_str});
+                self.block(body, self.find(test.span.end, Symbol::LBrace));
+            }
+            StmtKind::Do(call) => {
+                let call = self.call(call, self.col("do "), self.indent);
+                self.line(&format! {"do {};", call});
+            }
+            StmtKind::Return(None) => self.line("return;"),
+            StmtKind::Return(Some(expr)) => {
+                let expr = self.expr(expr, self.col("return "));
+                self.line(&format! {"return {};", expr});
+            }
+        }
+        self.last_line = stmt.span.end.line;
+    }
+
+    fn expr(&self, expr: &Expr, col: usize) -> String {
+        self.expr_at(expr, col, self.indent)
+    }
+
+    fn expr_at(&self, expr: &Expr, col: usize, indent: usize) -> String {
+        let mut s = self.term(&expr.lhs, col, indent);
+        for (op, rhs) in &expr.cdr {
+            s.push(' ');
+            s.push_str(op.as_str());
+            s.push(' ');
+            let rhs = self.term(rhs, end_col(col, &s), indent);
+            s.push_str(&rhs);
+        }
+        s
+    }
+
+    fn term(&self, term: &Term, col: usize, indent: usize) -> String {
+        match &term.kind {
+            TermKind::IntegerConstant(num) => {
+                self.source(term.span).unwrap_or_else(|| num.to_string())
+            }
+            TermKind::StringConstant(s) => self
+                .source(term.span)
+                .unwrap_or_else(|| format! {"\"{}\"", s}),
+            TermKind::KeywordConstant(kwd) => kwd.as_str().to_string(),
+            TermKind::ValName(name) => name.as_str().to_string(),
+            TermKind::WithUnary(op, term) => {
+                op.as_str().to_string() + &self.term(term, col + 1, indent)
+            }
+            TermKind::WithIdx(name, idx) => {
+                let prefix = format! {"{}[", name.as_str()};
+                let idx = self.expr_at(idx, col + prefix.len(), indent);
+                format! {"{}{}]", prefix, idx}
+            }
+            TermKind::SubRoutineCall(call) => self.call(call, col, indent),
+            TermKind::Expr(expr) => format! {"({})", self.expr_at(expr, col + 1, indent)},
+        }
+    }
+
+    // Prints `call` on one line if it fits, otherwise one argument per line.
+    fn call(&self, call: &SubRoutineCall, col: usize, indent: usize) -> String {
+        let name = match &call.obj_name {
+            Some(obj_name) => format! {"{}.{}", obj_name.as_str(), call.routine_name.as_str()},
+            None => call.routine_name.as_str().to_string(),
+        };
+        let args: Vec<_> = call
+            .args
+            .iter()
+            .map(|arg| self.expr_at(arg, 0, indent))
+            .collect();
+        let flat = format! {"{}({})", name, args.join(", ")};
+        // One more column for the `;` or `)` that usually follows.
+        if args.is_empty() || (!flat.contains('\n') && col + flat.len() < MAX_WIDTH) {
+            return flat;
+        }
+        let arg_indent = INDENT.repeat(indent + 1);
+        let args: Vec<_> = call
+            .args
+            .iter()
+            .map(|arg| arg_indent.clone() + &self.expr_at(arg, arg_indent.len(), indent + 1))
+            .collect();
+        format! {"{}(\n{}\n{})", name, args.join(",\n"), INDENT.repeat(indent)}
+    }
+
+    // Column right after `prefix` printed at the start of a line.
+    fn col(&self, prefix: &str) -> usize {
+        self.indent * INDENT.len() + prefix.len()
+    }
+
+    // Prints comments and a preserved blank line that come before a statement
+    // or declaration starting at `start`.
+    fn item(&mut self, start: Pos) {
+        self.comments_before(start);
+        self.blank_line(start.line);
+        self.block_start = false;
+    }
+
+    fn open_block(&mut self, lbrace: Pos) {
+        self.indent += 1;
+        self.last_line = lbrace.line;
+        self.block_start = true;
+    }
+
+    fn close_block(&mut self, rbrace: Pos) {
+        self.comments_before(rbrace);
+        self.indent -= 1;
+        self.line("}");
+        self.last_line = rbrace.line;
+        self.block_start = false;
+    }
+
+    fn comments_before(&mut self, pos: Pos) {
+        while let Some(comment) = self.comments.last() {
+            if comment.span.start >= pos {
+                break;
+            }
+            let comment = self.comments.pop().unwrap();
+            if comment.span.start.line == self.last_line && !self.out.is_empty() {
+                // Trailing comment: keep it at the end of the line it was on.
+                self.out.truncate(self.out.len() - 1);
+                self.out.push(' ');
+                self.out.push_str(&comment.text);
+                self.out.push('\n');
+            } else {
+                // A comment right below the last line and followed by a blank line
+                // belongs to what is above it, so a forced blank line goes after it.
+                let next_line = match self.comments.last() {
+                    Some(next) if next.span.start < pos => next.span.start.line,
+                    _ => pos.line,
+                };
+                let attached = comment.span.start.line == self.last_line + 1
+                    && next_line > comment.span.end.line + 1;
+                let force_blank = self.force_blank;
+                if attached {
+                    self.force_blank = false;
+                }
+                self.blank_line(comment.span.start.line);
+                if attached {
+                    self.force_blank = force_blank;
+                }
+                self.block_start = false;
+                // Continuation lines of block comments either line up their `*`
+                // under the opening one or move along with the first line.
+                let shift = comment.span.start.col - 1;
+                for (i, line) in comment.text.lines().enumerate() {
+                    let trimmed = line.trim();
+                    if i == 0 {
+                        self.line(trimmed);
+                    } else if trimmed.starts_with('*') {
+                        self.line(&format! {" {}", trimmed});
+                    } else {
+                        let ws = line.len() - line.trim_start().len();
+                        self.line(line[ws.min(shift)..].trim_end());
+                    }
+                }
+            }
+            self.last_line = comment.span.end.line;
+        }
+    }
+
+    fn blank_line(&mut self, line: usize) {
+        if !self.block_start && (self.force_blank || line > self.last_line + 1) {
+            self.out.push('\n');
+        }
+        self.force_blank = false;
+    }
+
+    fn line(&mut self, s: &str) {
+        if !s.is_empty() {
+            self.out.push_str(&INDENT.repeat(self.indent));
+            self.out.push_str(s);
+        }
+        self.out.push('\n');
+    }
+
+    // Source text of a constant, which doesn't span lines.
+    fn source(&self, span: Span) -> Option<String> {
+        let line = self.src.get(span.start.line.checked_sub(1)?)?;
+        Some(
+            line.chars()
+                .skip(span.start.col - 1)
+                .take(span.end.col.checked_sub(span.start.col)?)
+                .collect(),
+        )
+    }
+
+    // Start of the first `symbol` at or after `after`, or `after` itself if
+    // there is none (as when printing without tokens).
+    fn find(&self, after: Pos, symbol: Symbol) -> Pos {
+        let i = self
+            .tokens
+            .binary_search_by(|(_, span)| span.start.cmp(&after))
+            .unwrap_or_else(|i| i);
+        self.tokens[i..]
+            .iter()
+            .find(|(token, _)| *token == Token::Symbol(symbol))
+            .map_or(after, |(_, span)| span.start)
+    }
+}
+
+fn names(names: &[Ident]) -> String {
+    let names: Vec<_> = names.iter().map(Ident::as_str).collect();
+    names.join(", ")
+}
+
+fn end_col(start: usize, s: &str) -> usize {
+    match s.rfind('\n') {
+        Some(i) => s.len() - i - 1,
+        None => start + s.len(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format() {
+        let src = "// Header\n\
+                   class Main{field int x,y; // point\n\
+                   \n\
+                   \n\
+                   /** Entry point.\n  *  Does nothing useful. */\n\
+                   function void main(){var int i;\n\
+                   let i=-1;if(i<0){ // negative\n\
+                   do Output.printInt(i);}else{\n\
+                   // nothing\n\
+                   }\n\
+                   while(~(i=0)){let i=i+1;}\n\n\n\
+                   return;}\n\
+                   method int get(){return x;}}\n";
+        let expected = "// Header\n\
+                        class Main {\n\
+                        \x20   field int x, y; // point\n\
+                        \n\
+                        \x20   /** Entry point.\n\
+                        \x20    *  Does nothing useful. */\n\
+                        \x20   function void main() {\n\
+                        \x20       var int i;\n\
+                        \x20       let i = -1;\n\
+                        \x20       if (i < 0) { // negative\n\
+                        \x20           do Output.printInt(i);\n\
+                        \x20       } else {\n\
+                        \x20           // nothing\n\
+                        \x20       }\n\
+                        \x20       while (~(i = 0)) {\n\
+                        \x20           let i = i + 1;\n\
+                        \x20       }\n\
+                        \n\
+                        \x20       return;\n\
+                        \x20   }\n\
+                        \n\
+                        \x20   method int get() {\n\
+                        \x20       return x;\n\
+                        \x20   }\n\
+                        }\n";
+        let formatted = format_source(src).unwrap();
+        assert_eq!(formatted, expected);
+        assert_eq!(format_source(&formatted).unwrap(), formatted);
+    }
+
+    #[test]
+    fn test_long_call() {
+        let src = "class Main { function void main() { \
+                   do Output.printString(\"The quick brown fox jumps over the lazy dog\", Main.f(1, 2), \"and then some more\"); \
+                   return; } }";
+        let expected = "class Main {\n\
+                        \x20   function void main() {\n\
+                        \x20       do Output.printString(\n\
+                        \x20           \"The quick brown fox jumps over the lazy dog\",\n\
+                        \x20           Main.f(1, 2),\n\
+                        \x20           \"and then some more\"\n\
+                        \x20       );\n\
+                        \x20       return;\n\
+                        \x20   }\n\
+                        }\n";
+        let formatted = format_source(src).unwrap();
+        assert_eq!(formatted, expected);
+        assert_eq!(format_source(&formatted).unwrap(), formatted);
+    }
+
+    #[test]
+    fn test_format_extended() {
+        let src = "class Main { function void main() { \
+                   let c = 'a'+0x1F-0b101; do Output.printString(\"say \\\"hi\\\"\\n\"); \
+                   return; } }";
+        let expected = "class Main {\n\
+                        \x20   function void main() {\n\
+                        \x20       let c = 'a' + 0x1F - 0b101;\n\
+                        \x20       do Output.printString(\"say \\\"hi\\\"\\n\");\n\
+                        \x20       return;\n\
+                        \x20   }\n\
+                        }\n";
+        let formatted = format_source_extended(src).unwrap();
+        assert_eq!(formatted, expected);
+        assert_eq!(format_source_extended(&formatted).unwrap(), formatted);
+        assert!(format_source(src).is_err());
+    }
+}
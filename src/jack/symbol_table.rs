@@ -2,11 +2,14 @@ use std::collections::HashMap;
 use std::rc::Rc;
 
 use super::ast::Type;
+use super::token::Span;
+use crate::vm::Segment;
 
 pub struct SymEntry {
     ty: SymType,
     kind: SymKind,
     index: usize,
+    def: Span,
 }
 
 pub struct SymbolTable {
@@ -46,25 +49,26 @@ impl SymbolTable {
         }
     }
 
-    pub fn insert(&mut self, name: Rc<String>, ty: SymType, kind: SymKind) {
+    /// Declares `name` at `def`, the span of its declaration.
+    pub fn insert(&mut self, name: Rc<String>, ty: SymType, kind: SymKind, def: Span) {
         match kind {
             SymKind::Static => {
-                let sym_info = SymEntry::new(ty, kind, self.static_count);
+                let sym_info = SymEntry::new(ty, kind, def, self.static_count);
                 self.class_table.insert(name, sym_info);
                 self.static_count += 1;
             }
             SymKind::Field => {
-                let sym_info = SymEntry::new(ty, kind, self.field_count);
+                let sym_info = SymEntry::new(ty, kind, def, self.field_count);
                 self.class_table.insert(name, sym_info);
                 self.field_count += 1;
             }
             SymKind::Argument => {
-                let sym_info = SymEntry::new(ty, kind, self.arg_count);
+                let sym_info = SymEntry::new(ty, kind, def, self.arg_count);
                 self.subroutine_table.insert(name, sym_info);
                 self.arg_count += 1;
             }
             SymKind::Var => {
-                let sym_info = SymEntry::new(ty, kind, self.var_count);
+                let sym_info = SymEntry::new(ty, kind, def, self.var_count);
                 self.subroutine_table.insert(name, sym_info);
                 self.var_count += 1;
             }
@@ -86,6 +90,16 @@ impl SymbolTable {
         }
     }
 
+    /// Every visible name, subroutine scope first, without the shadowed class-level ones.
+    pub fn iter(&self) -> impl Iterator<Item = (&Rc<String>, &SymEntry)> {
+        let shadowed = move |name: &Rc<String>| self.subroutine_table.contains_key(name);
+        self.subroutine_table.iter().chain(
+            self.class_table
+                .iter()
+                .filter(move |(name, _)| !shadowed(name)),
+        )
+    }
+
     pub fn clear(&mut self) {
         self.class_table.clear();
         self.subroutine_table.clear();
@@ -97,8 +111,13 @@ impl SymbolTable {
 }
 
 impl SymEntry {
-    fn new(ty: SymType, kind: SymKind, index: usize) -> Self {
-        Self { ty, kind, index }
+    fn new(ty: SymType, kind: SymKind, def: Span, index: usize) -> Self {
+        Self {
+            ty,
+            kind,
+            index,
+            def,
+        }
     }
 
     pub fn class_name(&self) -> Result<Rc<String>, &'static str> {
@@ -108,16 +127,28 @@ impl SymEntry {
         }
     }
 
+    pub fn ty(&self) -> &SymType {
+        &self.ty
+    }
+
+    pub fn kind(&self) -> SymKind {
+        self.kind
+    }
+
+    pub fn def(&self) -> Span {
+        self.def
+    }
+
     pub fn id(&self) -> usize {
         self.index
     }
 
-    pub fn reg_name(&self) -> &'static str {
+    pub fn segment(&self) -> Segment {
         match self.kind {
-            SymKind::Static => "static",
-            SymKind::Field => "this",
-            SymKind::Argument => "argument",
-            SymKind::Var => "local",
+            SymKind::Static => Segment::Static,
+            SymKind::Field => Segment::This,
+            SymKind::Argument => Segment::Argument,
+            SymKind::Var => Segment::Local,
         }
     }
 }
@@ -125,11 +156,32 @@ impl SymEntry {
 impl SymType {
     pub fn from_astty(ty: &Type) -> Result<Self, &'static str> {
         match ty {
-            Type::Class(name) => Ok(SymType::Class(name.clone())),
+            Type::Class(name) => Ok(SymType::Class(name.name.clone())),
             Type::Int => Ok(SymType::Int),
             Type::Char => Ok(SymType::Char),
             Type::Boolean => Ok(SymType::Boolean),
             Type::Void => Err("Error"),
         }
     }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            SymType::Class(name) => name,
+            SymType::Int => "int",
+            SymType::Char => "char",
+            SymType::Boolean => "boolean",
+        }
+    }
+}
+
+impl SymKind {
+    /// Keyword declaring this kind of variable; `argument` for parameters.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SymKind::Static => "static",
+            SymKind::Field => "field",
+            SymKind::Argument => "argument",
+            SymKind::Var => "var",
+        }
+    }
 }
@@ -1,8 +1,12 @@
+use std::fmt;
 use std::rc::Rc;
+use std::str::FromStr;
+
+use super::xml::XmlWriter;
 
 use Keyword::*;
 
-#[derive(PartialEq, Eq, Debug)]
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
 pub enum Keyword {
     Class,
     Constructor,
@@ -27,7 +31,7 @@ pub enum Keyword {
     Return,
 }
 
-#[derive(PartialEq, Eq, Debug)]
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
 pub enum Symbol {
     LBrace,
     RBrace,
@@ -50,7 +54,7 @@ pub enum Symbol {
     Tilde,
 }
 
-#[derive(PartialEq, Eq, Debug)]
+#[derive(PartialEq, Eq, Debug, Clone)]
 pub enum Token {
     Keyword(Keyword),
     Symbol(Symbol),
@@ -59,31 +63,194 @@ pub enum Token {
     Ident(Rc<String>),
 }
 
-impl Keyword {
-    pub fn from_str(s: &str) -> Option<Self> {
+/// Largest integer constant in Jack. `-32768` is also allowed, as a negated constant.
+pub const INT_MAX: u16 = 32767;
+
+/// 1-based line and column of a character in Jack source.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Pos {
+    pub line: usize,
+    pub col: usize,
+}
+
+/// Source range from `start` up to, but not including, `end`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Span {
+    pub start: Pos,
+    pub end: Pos,
+}
+
+/// `//` or `/* */` comment, delimiters included.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Comment {
+    pub text: String,
+    pub span: Span,
+}
+
+impl Span {
+    pub fn contains(&self, pos: Pos) -> bool {
+        self.start <= pos && pos < self.end
+    }
+}
+
+impl Token {
+    fn write(&self, w: &mut XmlWriter) {
+        match self {
+            Token::Keyword(keyword) => w.keyword(keyword.as_str()),
+            Token::Symbol(symbol) => w.symbol(symbol.as_str()),
+            Token::IntegerConstant(num) => w.leaf("integerConstant", &num.to_string()),
+            Token::StringConstant(s) => w.leaf("stringConstant", s),
+            Token::Ident(name) => w.ident(name),
+        }
+    }
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::Keyword(keyword) => f.write_str(keyword.as_str()),
+            Token::Symbol(symbol) => f.write_str(symbol.as_str()),
+            Token::IntegerConstant(num) => write! {f, "{}", num},
+            Token::StringConstant(s) => write! {f, "\"{}\"", s},
+            Token::Ident(name) => f.write_str(name),
+        }
+    }
+}
+
+/// Serializes tokens in the format of the `xxxT.xml` files of project 10.
+pub fn tokens_to_xml<'a>(tokens: impl IntoIterator<Item = &'a Token>) -> String {
+    let mut w = XmlWriter::with_indent(0);
+    w.open("tokens");
+    for token in tokens {
+        token.write(&mut w);
+    }
+    w.close("tokens");
+    w.finish()
+}
+
+impl FromStr for Keyword {
+    type Err = ();
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s {
-            "class" => Some(Class),
-            "constructor" => Some(Constructor),
-            "function" => Some(Function),
-            "method" => Some(Method),
-            "field" => Some(Field),
-            "static" => Some(Static),
-            "var" => Some(Var),
-            "int" => Some(Int),
-            "char" => Some(Char),
-            "boolean" => Some(Boolean),
-            "void" => Some(Void),
-            "true" => Some(True),
-            "false" => Some(False),
-            "null" => Some(Null),
-            "this" => Some(This),
-            "let" => Some(Let),
-            "do" => Some(Do),
-            "if" => Some(If),
-            "else" => Some(Else),
-            "while" => Some(While),
-            "return" => Some(Return),
-            _ => None,
+            "class" => Ok(Class),
+            "constructor" => Ok(Constructor),
+            "function" => Ok(Function),
+            "method" => Ok(Method),
+            "field" => Ok(Field),
+            "static" => Ok(Static),
+            "var" => Ok(Var),
+            "int" => Ok(Int),
+            "char" => Ok(Char),
+            "boolean" => Ok(Boolean),
+            "void" => Ok(Void),
+            "true" => Ok(True),
+            "false" => Ok(False),
+            "null" => Ok(Null),
+            "this" => Ok(This),
+            "let" => Ok(Let),
+            "do" => Ok(Do),
+            "if" => Ok(If),
+            "else" => Ok(Else),
+            "while" => Ok(While),
+            "return" => Ok(Return),
+            _ => Err(()),
+        }
+    }
+}
+
+impl Keyword {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Class => "class",
+            Constructor => "constructor",
+            Function => "function",
+            Method => "method",
+            Field => "field",
+            Static => "static",
+            Var => "var",
+            Int => "int",
+            Char => "char",
+            Boolean => "boolean",
+            Void => "void",
+            True => "true",
+            False => "false",
+            Null => "null",
+            This => "this",
+            Let => "let",
+            Do => "do",
+            If => "if",
+            Else => "else",
+            While => "while",
+            Return => "return",
+        }
+    }
+}
+
+impl Symbol {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Symbol::LBrace => "{",
+            Symbol::RBrace => "}",
+            Symbol::LParen => "(",
+            Symbol::RParen => ")",
+            Symbol::LBracket => "[",
+            Symbol::RBracket => "]",
+            Symbol::Dot => ".",
+            Symbol::Comma => ",",
+            Symbol::SemiColon => ";",
+            Symbol::Plus => "+",
+            Symbol::Minus => "-",
+            Symbol::Star => "*",
+            Symbol::Slush => "/",
+            Symbol::And => "&",
+            Symbol::Or => "|",
+            Symbol::LAngle => "<",
+            Symbol::RAngle => ">",
+            Symbol::Equal => "=",
+            Symbol::Tilde => "~",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tokenize;
+    use super::*;
+
+    #[test]
+    fn test_tokens_to_xml() {
+        let tokens = tokenize("x<\"&\"".chars()).unwrap();
+        let expected = "<tokens>\r\n\
+                        <identifier> x </identifier>\r\n\
+                        <symbol> &lt; </symbol>\r\n\
+                        <stringConstant> &amp; </stringConstant>\r\n\
+                        </tokens>\r\n";
+        assert_eq!(tokens_to_xml(tokens.iter().map(|(t, _)| t)), expected);
+    }
+
+    #[test]
+    fn test_reference_tokens() {
+        let cases = [
+            (
+                include_str!("../project09/Square/Main.jack"),
+                include_str!("../project10/Square/MainT.xml"),
+            ),
+            (
+                include_str!("../project09/Square/Square.jack"),
+                include_str!("../project10/Square/SquareT.xml"),
+            ),
+            (
+                include_str!("../project09/Square/SquareGame.jack"),
+                include_str!("../project10/Square/SquareGameT.xml"),
+            ),
+            (
+                include_str!("../project09/Average/Main.jack"),
+                include_str!("../project10/Average/MainT.xml"),
+            ),
+        ];
+        for (jack, xml) in cases.iter() {
+            let tokens = tokenize(jack.chars()).unwrap();
+            assert_eq!(tokens_to_xml(tokens.iter().map(|(t, _)| t)), *xml);
         }
     }
 }
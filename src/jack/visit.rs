@@ -0,0 +1,222 @@
+//! Read-only traversal of `jack::ast`.
+//!
+//! Every `visit_*` method defaults to the matching `walk_*` function, which
+//! visits the node's children in source order. Override a method to inspect
+//! a kind of node and call the `walk_*` function from it to keep descending.
+
+use super::ast::*;
+
+pub trait Visitor<'ast> {
+    fn visit_class(&mut self, class: &'ast ClassDec) {
+        walk_class(self, class);
+    }
+
+    fn visit_class_var_dec(&mut self, dec: &'ast ClassVarDec) {
+        walk_class_var_dec(self, dec);
+    }
+
+    fn visit_subroutine_dec(&mut self, dec: &'ast SubRoutineDec) {
+        walk_subroutine_dec(self, dec);
+    }
+
+    fn visit_subroutine_body(&mut self, body: &'ast SubRoutineBody) {
+        walk_subroutine_body(self, body);
+    }
+
+    fn visit_var_dec(&mut self, dec: &'ast VarDec) {
+        walk_var_dec(self, dec);
+    }
+
+    fn visit_stmt(&mut self, stmt: &'ast Stmt) {
+        walk_stmt(self, stmt);
+    }
+
+    fn visit_expr(&mut self, expr: &'ast Expr) {
+        walk_expr(self, expr);
+    }
+
+    fn visit_term(&mut self, term: &'ast Term) {
+        walk_term(self, term);
+    }
+
+    fn visit_subroutine_call(&mut self, call: &'ast SubRoutineCall) {
+        walk_subroutine_call(self, call);
+    }
+
+    fn visit_type(&mut self, ty: &'ast Type) {
+        walk_type(self, ty);
+    }
+
+    /// Called for declared names and uses alike.
+    fn visit_ident(&mut self, _ident: &'ast Ident) {}
+}
+
+pub fn walk_class<'ast, V: Visitor<'ast> + ?Sized>(v: &mut V, class: &'ast ClassDec) {
+    v.visit_ident(&class.name);
+    for dec in &class.var_decs {
+        v.visit_class_var_dec(dec);
+    }
+    for dec in &class.subroutine_decs {
+        v.visit_subroutine_dec(dec);
+    }
+}
+
+pub fn walk_class_var_dec<'ast, V: Visitor<'ast> + ?Sized>(v: &mut V, dec: &'ast ClassVarDec) {
+    v.visit_type(&dec.ty);
+    for name in &dec.names {
+        v.visit_ident(name);
+    }
+}
+
+pub fn walk_subroutine_dec<'ast, V: Visitor<'ast> + ?Sized>(v: &mut V, dec: &'ast SubRoutineDec) {
+    v.visit_type(&dec.ret);
+    v.visit_ident(&dec.name);
+    for (ty, name) in &dec.args {
+        v.visit_type(ty);
+        v.visit_ident(name);
+    }
+    v.visit_subroutine_body(&dec.body);
+}
+
+pub fn walk_subroutine_body<'ast, V: Visitor<'ast> + ?Sized>(
+    v: &mut V,
+    body: &'ast SubRoutineBody,
+) {
+    for dec in &body.var_decs {
+        v.visit_var_dec(dec);
+    }
+    for stmt in &body.stmts {
+        v.visit_stmt(stmt);
+    }
+}
+
+pub fn walk_var_dec<'ast, V: Visitor<'ast> + ?Sized>(v: &mut V, dec: &'ast VarDec) {
+    v.visit_type(&dec.ty);
+    for name in &dec.names {
+        v.visit_ident(name);
+    }
+}
+
+pub fn walk_stmt<'ast, V: Visitor<'ast> + ?Sized>(v: &mut V, stmt: &'ast Stmt) {
+    match &stmt.kind {
+        StmtKind::Let { name, idx, expr } => {
+            v.visit_ident(name);
+            if let Some(idx) = idx {
+                v.visit_expr(idx);
+            }
+            v.visit_expr(expr);
+        }
+        StmtKind::If { test, then, else_ } => {
+            v.visit_expr(test);
+            for stmt in then {
+                v.visit_stmt(stmt);
+            }
+            for stmt in else_.iter().flatten() {
+                v.visit_stmt(stmt);
+            }
+        }
+        StmtKind::While { test, body } => {
+            v.visit_expr(test);
+            for stmt in body {
+                v.visit_stmt(stmt);
+            }
+        }
+        StmtKind::Do(call) => v.visit_subroutine_call(call),
+        StmtKind::Return(expr) => {
+            if let Some(expr) = expr {
+                v.visit_expr(expr);
+            }
+        }
+    }
+}
+
+pub fn walk_expr<'ast, V: Visitor<'ast> + ?Sized>(v: &mut V, expr: &'ast Expr) {
+    v.visit_term(&expr.lhs);
+    for (_, rhs) in &expr.cdr {
+        v.visit_term(rhs);
+    }
+}
+
+pub fn walk_term<'ast, V: Visitor<'ast> + ?Sized>(v: &mut V, term: &'ast Term) {
+    match &term.kind {
+        TermKind::IntegerConstant(_)
+        | TermKind::StringConstant(_)
+        | TermKind::KeywordConstant(_) => {}
+        TermKind::ValName(name) => v.visit_ident(name),
+        TermKind::WithUnary(_, term) => v.visit_term(term),
+        TermKind::WithIdx(name, idx) => {
+            v.visit_ident(name);
+            v.visit_expr(idx);
+        }
+        TermKind::SubRoutineCall(call) => v.visit_subroutine_call(call),
+        TermKind::Expr(expr) => v.visit_expr(expr),
+    }
+}
+
+pub fn walk_subroutine_call<'ast, V: Visitor<'ast> + ?Sized>(
+    v: &mut V,
+    call: &'ast SubRoutineCall,
+) {
+    if let Some(obj_name) = &call.obj_name {
+        v.visit_ident(obj_name);
+    }
+    v.visit_ident(&call.routine_name);
+    for arg in &call.args {
+        v.visit_expr(arg);
+    }
+}
+
+pub fn walk_type<'ast, V: Visitor<'ast> + ?Sized>(v: &mut V, ty: &'ast Type) {
+    if let Type::Class(name) = ty {
+        v.visit_ident(name);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{tokenize, Parser};
+    use super::*;
+
+    // Collects every identifier and counts the calls.
+    #[derive(Default)]
+    struct Collector<'ast> {
+        idents: Vec<&'ast str>,
+        calls: usize,
+    }
+
+    impl<'ast> Visitor<'ast> for Collector<'ast> {
+        fn visit_subroutine_call(&mut self, call: &'ast SubRoutineCall) {
+            self.calls += 1;
+            walk_subroutine_call(self, call);
+        }
+
+        fn visit_ident(&mut self, ident: &'ast Ident) {
+            self.idents.push(ident.as_str());
+        }
+    }
+
+    #[test]
+    fn test_visitor() {
+        let jack = "class Main {\n\
+                    field Array a;\n\
+                    method void f(int i) {\n\
+                    var int x;\n\
+                    let x = a[i] + g(Math.abs(i));\n\
+                    if (x) { do f(x); } else { return; }\n\
+                    return;\n\
+                    }\n\
+                    }\n";
+        let tokens = tokenize(jack.chars()).unwrap();
+        let ast = Parser::new(tokens.into_iter()).parse().unwrap();
+        let mut collector = Collector::default();
+        collector.visit_class(&ast);
+        assert_eq!(
+            collector.idents,
+            [
+                "Main", "Array", "a", "f", "i", "x", "x", "a", "i", "g", "Math", "abs", "i", "x",
+                "f", "x"
+            ]
+        );
+        assert_eq!(collector.calls, 3);
+    }
+}
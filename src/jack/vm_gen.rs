@@ -1,24 +1,45 @@
 use super::ast::*;
+use super::flow::check_returns;
 use super::symbol_table::*;
+use super::token::{Span, INT_MAX};
+use crate::source_map::{SourceLoc, SourceMap};
+use crate::vm::{Command, Op, Segment};
 use std::rc::Rc;
 
 pub struct VmGen {
     sym_table: SymbolTable,
-    vm_code: String,
+    commands: Vec<Command>,
+    // Jack line of each command, and of the commands being generated.
+    lines: Vec<usize>,
+    line: usize,
     label_count: usize,
 }
 
+impl Default for VmGen {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl VmGen {
     pub fn new() -> Self {
         Self {
             sym_table: SymbolTable::new(),
-            vm_code: String::new(),
+            commands: Vec::new(),
+            lines: Vec::new(),
+            line: 0,
             label_count: 0,
         }
     }
 
-    pub fn gen(&mut self, ast: ClassDec) -> Result<&str, &'static str> {
+    pub fn gen(&mut self, ast: ClassDec) -> Result<&[Command], &'static str> {
+        // `check_returns` tells what is wrong and where.
+        if !check_returns(&ast).is_empty() {
+            return Err("Invalid return");
+        }
         self.sym_table.clear();
+        self.commands.clear();
+        self.lines.clear();
         self.label_count = 0;
         for class_var in &ast.var_decs {
             let kind = match class_var.var_ty {
@@ -27,7 +48,8 @@ impl VmGen {
             };
             let ty = SymType::from_astty(&class_var.ty)?;
             for name in &class_var.names {
-                self.sym_table.insert(name.clone(), ty.clone(), kind);
+                self.sym_table
+                    .insert(name.name.clone(), ty.clone(), kind, name.span);
             }
         }
 
@@ -38,32 +60,57 @@ impl VmGen {
             .flat_map(|var| &var.names)
             .fold(0, |acc, _| acc + 1);
         for subroutine_dec in &ast.subroutine_decs {
-            self.subroutine_dec(subroutine_dec, &ast.name, field_count)?;
+            self.subroutine_dec(subroutine_dec, &ast.name.name, field_count)?;
         }
-        Ok(&self.vm_code)
+        Ok(&self.commands)
+    }
+
+    /// Where the commands of the last `gen` come from, as lines of the Jack file `file`.
+    pub fn source_map(&self, file: &str) -> SourceMap {
+        let mut function = None;
+        let locs = self
+            .commands
+            .iter()
+            .zip(&self.lines)
+            .map(|(command, &line)| {
+                if let Command::Function(name, _) = command {
+                    function = Some(name.as_str());
+                }
+                Some(SourceLoc::new(file, line, function))
+            })
+            .collect();
+        SourceMap::new(locs)
     }
+
+    fn command(&mut self, command: Command) {
+        self.commands.push(command);
+        self.lines.push(self.line);
+    }
+
     fn subroutine_dec(
         &mut self,
         dec: &SubRoutineDec,
         class_name: &Rc<String>,
         field_count: usize,
     ) -> Result<(), &'static str> {
+        self.line = dec.span.start.line;
         self.sym_table.next_scope();
         if dec.kind == SubRoutineKind::Method {
             self.sym_table.insert(
                 Rc::new("this".to_string()),
                 SymType::Class(class_name.clone()),
                 SymKind::Argument,
+                Span::default(),
             );
         }
 
         for (ty, name) in &dec.args {
             let ty = SymType::from_astty(ty)?;
             self.sym_table
-                .insert(name.clone(), ty.clone(), SymKind::Argument);
+                .insert(name.name.clone(), ty.clone(), SymKind::Argument, name.span);
         }
 
-        self.subroutine_body(&dec.body, class_name, &dec.name, dec.kind, field_count)
+        self.subroutine_body(&dec.body, class_name, &dec.name.name, dec.kind, field_count)
     }
 
     fn subroutine_body(
@@ -79,22 +126,24 @@ impl VmGen {
             let ty = SymType::from_astty(&var.ty)?;
             for name in &var.names {
                 self.sym_table
-                    .insert(name.clone(), ty.clone(), SymKind::Var);
+                    .insert(name.name.clone(), ty.clone(), SymKind::Var, name.span);
                 locals_count += 1;
             }
         }
 
-        self.vm_code
-            .push_str(&format! {"function {}.{} {}\n", class_name, fn_name, locals_count});
+        self.command(Command::Function(
+            format! {"{}.{}", class_name, fn_name},
+            locals_count,
+        ));
         match kind {
             SubRoutineKind::Constructor => {
-                self.push("constant", field_count);
-                self.vm_code.push_str("call Memory.alloc 1\n");
-                self.pop("pointer", 0);
+                self.push(Segment::Constant, field_count);
+                self.call_raw("Memory.alloc", 1);
+                self.pop(Segment::Pointer, 0);
             }
             SubRoutineKind::Method => {
-                self.push("argument", 0);
-                self.pop("pointer", 0);
+                self.push(Segment::Argument, 0);
+                self.pop(Segment::Pointer, 0);
             }
             SubRoutineKind::Function => {}
         }
@@ -107,24 +156,27 @@ impl VmGen {
     }
 
     fn stmt(&mut self, stmt: &Stmt, class_name: &Rc<String>) -> Result<(), &'static str> {
-        match stmt {
-            Stmt::Let { name, idx, expr } => {
-                let var = self.sym_table.get(name).ok_or("Undefined variable")?;
-                let reg_name = var.reg_name();
+        // Jumps and labels after nested statements still belong to this one.
+        let line = stmt.span.start.line;
+        self.line = line;
+        match &stmt.kind {
+            StmtKind::Let { name, idx, expr } => {
+                let var = self.sym_table.get(&name.name).ok_or("Undefined variable")?;
+                let segment = var.segment();
                 let id = var.id();
                 self.expr(expr, class_name)?;
 
                 if let Some(idx) = idx {
                     self.expr(idx, class_name)?;
-                    self.push(reg_name, id);
+                    self.push(segment, id);
                     self.binop(Binop::Plus);
-                    self.pop("pointer", 1);
-                    self.pop("that", 0);
+                    self.pop(Segment::Pointer, 1);
+                    self.pop(Segment::That, 0);
                 } else {
-                    self.pop(reg_name, id);
+                    self.pop(segment, id);
                 }
             }
-            Stmt::If { test, then, else_ } => {
+            StmtKind::If { test, then, else_ } => {
                 let then_label = self.gen_label();
                 let end_label = self.gen_label();
                 self.expr(test, class_name)?;
@@ -132,14 +184,16 @@ impl VmGen {
                 for stmt in else_.as_ref().unwrap_or(&Vec::new()) {
                     self.stmt(stmt, class_name)?;
                 }
+                self.line = line;
                 self.goto(&end_label);
                 self.label(&then_label);
                 for stmt in then {
                     self.stmt(stmt, class_name)?;
                 }
+                self.line = line;
                 self.label(&end_label);
             }
-            Stmt::While { test, body } => {
+            StmtKind::While { test, body } => {
                 let stmt_label = self.gen_label();
                 let loop_label = self.gen_label();
                 let end_label = self.gen_label();
@@ -151,20 +205,21 @@ impl VmGen {
                 for stmt in body {
                     self.stmt(stmt, class_name)?;
                 }
+                self.line = line;
                 self.goto(&loop_label);
                 self.label(&end_label);
             }
-            Stmt::Do(call) => {
+            StmtKind::Do(call) => {
                 self.call(call, class_name)?;
-                self.pop("temp", 0);
+                self.pop(Segment::Temp, 0);
             }
-            Stmt::Return(expr) => {
+            StmtKind::Return(expr) => {
                 if let Some(expr) = expr {
                     self.expr(expr, class_name)?;
                 } else {
-                    self.push("constant", 0);
+                    self.push(Segment::Constant, 0);
                 }
-                self.vm_code.push_str("return\n");
+                self.command(Command::Return);
             }
         }
         Ok(())
@@ -172,134 +227,155 @@ impl VmGen {
 
     fn expr(&mut self, expr: &Expr, class_name: &Rc<String>) -> Result<(), &'static str> {
         self.term(&expr.lhs, class_name)?;
-        if let Some((op, ref term)) = expr.cdr {
+        for (op, term) in &expr.cdr {
             self.term(term, class_name)?;
-            self.binop(op);
+            self.binop(*op);
         }
         Ok(())
     }
 
     fn term(&mut self, term: &Term, class_name: &Rc<String>) -> Result<(), &'static str> {
-        match term {
-            Term::IntegerConstant(num) => self.push("constant", *num as usize),
-            Term::StringConstant(s) => self.string_constant(s),
-            Term::KeywordConstant(kwd) => match kwd {
+        match &term.kind {
+            TermKind::IntegerConstant(num) if *num > INT_MAX => {
+                return Err("Integer constant out of range")
+            }
+            TermKind::IntegerConstant(num) => self.push(Segment::Constant, *num as usize),
+            TermKind::StringConstant(s) => self.string_constant(s),
+            TermKind::KeywordConstant(kwd) => match kwd {
                 KeywordConstant::True => {
-                    self.push("constant", 0);
+                    self.push(Segment::Constant, 0);
                     self.unop(Unop::BitNot);
                 }
-                KeywordConstant::False | KeywordConstant::Null => self.push("constant", 0),
-                KeywordConstant::This => self.push("pointer", 0),
+                KeywordConstant::False | KeywordConstant::Null => self.push(Segment::Constant, 0),
+                KeywordConstant::This => self.push(Segment::Pointer, 0),
             },
-            Term::ValName(name) => {
-                let entry = self.sym_table.get(name).ok_or("Undefined variable")?;
-                let reg_name = entry.reg_name();
+            TermKind::ValName(name) => {
+                let entry = self.sym_table.get(&name.name).ok_or("Undefined variable")?;
+                let segment = entry.segment();
                 let id = entry.id();
-                self.push(reg_name, id);
+                self.push(segment, id);
+            }
+            TermKind::WithUnary(Unop::Minus, term)
+                if term.kind == TermKind::IntegerConstant(INT_MAX + 1) =>
+            {
+                // -32768 = -32767 - 1, as 32768 can't be pushed.
+                self.push(Segment::Constant, INT_MAX as usize);
+                self.unop(Unop::Minus);
+                self.push(Segment::Constant, 1);
+                self.binop(Binop::Minus);
             }
-            Term::WithUnary(unary, term) => {
+            TermKind::WithUnary(unary, term) => {
                 self.term(term, class_name)?;
                 self.unop(*unary);
             }
-            Term::WithIdx(name, expr) => {
+            TermKind::WithIdx(name, expr) => {
                 self.expr(expr, class_name)?;
-                let entry = self.sym_table.get(name).ok_or("Undefined variable")?;
-                let reg_name = entry.reg_name();
+                let entry = self.sym_table.get(&name.name).ok_or("Undefined variable")?;
+                let segment = entry.segment();
                 let id = entry.id();
-                self.push(reg_name, id);
+                self.push(segment, id);
                 self.binop(Binop::Plus);
-                self.pop("pointer", 1);
-                self.push("that", 0);
+                self.pop(Segment::Pointer, 1);
+                self.push(Segment::That, 0);
             }
-            Term::SubRoutineCall(subroutine_call) => {
+            TermKind::SubRoutineCall(subroutine_call) => {
                 self.call(subroutine_call, class_name)?;
             }
-            Term::Expr(expr) => {
+            TermKind::Expr(expr) => {
                 self.expr(expr, class_name)?;
             }
         }
         Ok(())
     }
 
-    fn push(&mut self, name: &str, index: usize) {
-        self.vm_code
-            .push_str(&format! {"push {} {}\n", name, index});
+    fn push(&mut self, segment: Segment, index: usize) {
+        self.command(Command::Push(segment, index as u16));
     }
 
-    fn pop(&mut self, name: &str, index: usize) {
-        self.vm_code.push_str(&format! {"pop {} {}\n", name, index});
+    fn pop(&mut self, segment: Segment, index: usize) {
+        self.command(Command::Pop(segment, index as u16));
     }
 
     fn call(&mut self, call: &SubRoutineCall, class_name: &Rc<String>) -> Result<(), &'static str> {
         let mut arg_num = call.args.len();
         let mangled_name = if let Some(obj_name) = &call.obj_name {
-            if let Some(sym) = self.sym_table.get(&obj_name) {
-                let reg_name = sym.reg_name();
+            if let Some(sym) = self.sym_table.get(&obj_name.name) {
+                let segment = sym.segment();
                 let id = sym.id();
                 let class_name = sym.class_name()?;
-                self.push(reg_name, id);
+                self.push(segment, id);
                 arg_num += 1;
                 class_name
             } else {
-                obj_name.clone()
+                obj_name.name.clone()
             }
         } else {
-            self.push("pointer", 0);
+            self.push(Segment::Pointer, 0);
             arg_num += 1;
             class_name.clone()
         };
 
         for expr in &call.args {
-            self.expr(expr, &class_name)?;
+            self.expr(expr, class_name)?;
         }
 
-        self.vm_code
-            .push_str(&format! {"call {}.{} {}\n", mangled_name, call.routine_name, arg_num});
+        self.call_raw(
+            &format! {"{}.{}", mangled_name, call.routine_name.as_str()},
+            arg_num,
+        );
         Ok(())
     }
 
+    fn call_raw(&mut self, name: &str, arg_num: usize) {
+        self.command(Command::Call(name.to_string(), arg_num as u16));
+    }
+
     fn if_goto(&mut self, label: &str) {
-        self.vm_code.push_str(&format! {"if-goto {}\n", label});
+        self.command(Command::IfGoto(label.to_string()));
     }
 
     fn goto(&mut self, label: &str) {
-        self.vm_code.push_str(&format! {"goto {}\n", label});
+        self.command(Command::Goto(label.to_string()));
     }
 
     fn label(&mut self, label: &str) {
-        self.vm_code.push_str(&format! {"label {}\n", label});
+        self.command(Command::Label(label.to_string()));
     }
 
     fn string_constant(&mut self, s: &str) {
-        self.push("constant", s.len());
-        self.vm_code.push_str("call String.new 1\n");
+        self.push(Segment::Constant, s.chars().count());
+        self.call_raw("String.new", 1);
         for c in s.chars() {
-            self.push("constant", c as usize);
-            self.vm_code.push_str("call String.appendChar 2\n");
+            self.push(Segment::Constant, c as usize);
+            self.call_raw("String.appendChar", 2);
         }
     }
 
     fn binop(&mut self, op: Binop) {
         match op {
-            Binop::Plus => self.vm_code.push_str("add\n"),
-            Binop::Minus => self.vm_code.push_str("sub\n"),
-            Binop::Mul => self.vm_code.push_str("call Math.multiply 2\n"),
-            Binop::Div => self.vm_code.push_str("call Math.divide 2\n"),
-            Binop::And => self.vm_code.push_str("and\n"),
-            Binop::Or => self.vm_code.push_str("or\n"),
-            Binop::Lt => self.vm_code.push_str("lt\n"),
-            Binop::Gt => self.vm_code.push_str("gt\n"),
-            Binop::Equal => self.vm_code.push_str("eq\n"),
+            Binop::Plus => self.arithmetic(Op::Add),
+            Binop::Minus => self.arithmetic(Op::Sub),
+            Binop::Mul => self.call_raw("Math.multiply", 2),
+            Binop::Div => self.call_raw("Math.divide", 2),
+            Binop::And => self.arithmetic(Op::And),
+            Binop::Or => self.arithmetic(Op::Or),
+            Binop::Lt => self.arithmetic(Op::Lt),
+            Binop::Gt => self.arithmetic(Op::Gt),
+            Binop::Equal => self.arithmetic(Op::Eq_),
         }
     }
 
     fn unop(&mut self, op: Unop) {
         match op {
-            Unop::Minus => self.vm_code.push_str("neg\n"),
-            Unop::BitNot => self.vm_code.push_str("not\n"),
+            Unop::Minus => self.arithmetic(Op::Neg),
+            Unop::BitNot => self.arithmetic(Op::Not),
         }
     }
 
+    fn arithmetic(&mut self, op: Op) {
+        self.command(Command::Arithmetic(op));
+    }
+
     fn gen_label(&mut self) -> String {
         let label = format! {"jmp_label_{}", self.label_count};
         self.label_count += 1;
@@ -0,0 +1,83 @@
+/// Line terminator of the reference XML files shipped with project 10.
+pub const LINE_END: &str = "\r\n";
+
+/// Writes XML in the layout of the project 10 reference files:
+/// one element per line, children indented by `indent` spaces and
+/// leaf text padded with a single space on each side.
+pub struct XmlWriter {
+    buf: String,
+    indent: usize,
+    depth: usize,
+}
+
+impl Default for XmlWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl XmlWriter {
+    pub fn new() -> Self {
+        Self::with_indent(2)
+    }
+
+    pub fn with_indent(indent: usize) -> Self {
+        Self {
+            buf: String::new(),
+            indent,
+            depth: 0,
+        }
+    }
+
+    pub fn open(&mut self, tag: &str) {
+        self.line(&format!("<{}>", tag));
+        self.depth += 1;
+    }
+
+    pub fn close(&mut self, tag: &str) {
+        self.depth -= 1;
+        self.line(&format!("</{}>", tag));
+    }
+
+    pub fn leaf(&mut self, tag: &str, text: &str) {
+        self.line(&format!("<{0}> {1} </{0}>", tag, escape(text)));
+    }
+
+    pub fn keyword(&mut self, keyword: &str) {
+        self.leaf("keyword", keyword);
+    }
+
+    pub fn symbol(&mut self, symbol: &str) {
+        self.leaf("symbol", symbol);
+    }
+
+    pub fn ident(&mut self, ident: &str) {
+        self.leaf("identifier", ident);
+    }
+
+    pub fn finish(self) -> String {
+        self.buf
+    }
+
+    fn line(&mut self, s: &str) {
+        for _ in 0..self.depth * self.indent {
+            self.buf.push(' ');
+        }
+        self.buf.push_str(s);
+        self.buf.push_str(LINE_END);
+    }
+}
+
+pub fn escape(s: &str) -> String {
+    let mut ret = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '<' => ret.push_str("&lt;"),
+            '>' => ret.push_str("&gt;"),
+            '&' => ret.push_str("&amp;"),
+            '"' => ret.push_str("&quot;"),
+            c => ret.push(c),
+        }
+    }
+    ret
+}
@@ -1,6 +1,12 @@
 mod asm_gen;
-mod code_gen;
+pub mod code_gen;
+pub mod emu;
+pub mod hdl;
 pub mod jack;
+pub mod os;
+pub mod source_map;
+pub mod tst;
+pub mod vm;
 
-pub use asm_gen::AsmGenerator;
+pub use asm_gen::{AsmGenerator, StackCheck, STACK_OVERFLOW_CODE, STACK_UNDERFLOW_CODE};
 pub use code_gen::gen_code;
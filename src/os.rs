@@ -0,0 +1,189 @@
+use std::collections::BTreeSet;
+
+use crate::vm::{parse_vm, Command, VmFile};
+
+/// Names and VM code of the classes of the bundled Jack OS, compiled from
+/// `src/project12` by the build script.
+pub const OS_CLASSES: &[(&str, &str)] = include!(concat!(env!("OUT_DIR"), "/os.rs"));
+
+/// VM code of the bundled OS class `name`.
+pub fn os_class(name: &str) -> Option<&'static str> {
+    OS_CLASSES
+        .iter()
+        .find(|(class, _)| *class == name)
+        .map(|(_, vm)| *vm)
+}
+
+/// Whether `file` holds the bundled VM code of its class, as `jackc` writes it.
+pub fn is_os_class(file: &VmFile) -> bool {
+    os_class(&file.name)
+        .is_some_and(|vm| parse_vm(vm.as_bytes()).is_ok_and(|commands| commands == file.commands))
+}
+
+/// Bundled OS classes that `files` need, in name order: those called from them,
+/// directly or through other OS classes, that none of them defines. A program with
+/// a `Main` class also needs `Sys`, whose `Sys.init` calls `Main.main`.
+pub fn os_dependencies(files: &[VmFile]) -> Vec<VmFile> {
+    let defined: BTreeSet<_> = files
+        .iter()
+        .flat_map(|file| &file.commands)
+        .filter_map(|command| match command {
+            Command::Function(name, _) => Some(class_of(name)),
+            _ => None,
+        })
+        .collect();
+    let mut needed = BTreeSet::new();
+    let mut pending: Vec<_> = files
+        .iter()
+        .flat_map(|file| calls(&file.commands))
+        .collect();
+    if defined.contains("Main") {
+        pending.push("Sys".to_string());
+    }
+    let mut linked = Vec::new();
+    while let Some(class) = pending.pop() {
+        if defined.contains(class.as_str()) || !needed.insert(class.clone()) {
+            continue;
+        }
+        if let Some(vm) = os_class(&class) {
+            let commands = parse_vm(vm.as_bytes()).expect("Invalid bundled OS code");
+            pending.extend(calls(&commands));
+            linked.push(VmFile {
+                name: class,
+                commands,
+            });
+        }
+    }
+    linked.sort_by(|a, b| a.name.cmp(&b.name));
+    linked
+}
+
+fn class_of(function: &str) -> &str {
+    function.split('.').next().unwrap_or(function)
+}
+
+// Classes of the functions `commands` call.
+fn calls(commands: &[Command]) -> Vec<String> {
+    commands
+        .iter()
+        .filter_map(|command| match command {
+            Command::Call(name, _) => Some(class_of(name).to_string()),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emu::{Vm, SP};
+
+    fn file(name: &str, vm: &str) -> VmFile {
+        VmFile {
+            name: name.to_string(),
+            commands: parse_vm(vm.as_bytes()).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_os_dependencies() {
+        let math = file(
+            "Math",
+            "function Math.multiply 0\npush constant 0\nreturn\n",
+        );
+        let main = file(
+            "Main",
+            "function Main.main 0\npush constant 2\npush constant 3\ncall Math.multiply 2\nreturn\n",
+        );
+        let names = |files: Vec<VmFile>| files.into_iter().map(|f| f.name).collect::<Vec<_>>();
+        // `Sys.init` initializes every class but `Array` and `String`, which other
+        // classes use.
+        let all: Vec<_> = OS_CLASSES
+            .iter()
+            .map(|(name, _)| name.to_string())
+            .collect();
+        assert_eq!(names(os_dependencies(std::slice::from_ref(&main))), all);
+        let all_but_math: Vec<_> = all.iter().filter(|name| *name != "Math").cloned().collect();
+        assert_eq!(names(os_dependencies(&[main, math])), all_but_math);
+
+        // `Math` reports errors through `Sys.error`, and so needs the classes that
+        // `Sys` uses.
+        let lib = file("Lib", "function Lib.f 0\ncall Math.abs 1\nreturn\n");
+        assert_eq!(names(os_dependencies(&[lib])), all);
+    }
+
+    #[test]
+    fn test_run_os() {
+        let sys = file(
+            "Sys",
+            "function Sys.init 0
+call Memory.init 0
+pop temp 0
+call Math.init 0
+pop temp 0
+push constant 123
+neg
+push constant 45
+call Math.multiply 2
+pop static 0
+push constant 5000
+neg
+push constant 7
+call Math.divide 2
+pop static 1
+push constant 3
+call String.new 1
+push constant 45
+call String.appendChar 2
+push constant 52
+call String.appendChar 2
+push constant 50
+call String.appendChar 2
+call String.intValue 1
+pop static 2
+label HALT
+goto HALT
+",
+        );
+        let mut files = os_dependencies(std::slice::from_ref(&sys));
+        files.insert(0, sys);
+        let mut vm = Vm::new(files).unwrap();
+        vm.ram[SP] = 256;
+        for _ in 0..100_000 {
+            vm.step().unwrap();
+        }
+        assert_eq!(
+            &vm.ram[16..19],
+            &[-5535i16 as u16, -714i16 as u16, -42i16 as u16]
+        );
+    }
+
+    #[test]
+    fn test_sys_error() {
+        let main = file(
+            "Main",
+            "function Main.main 0
+push constant 3
+call Sys.error 1
+pop temp 0
+push constant 7
+pop temp 1
+push constant 0
+return
+",
+        );
+        let mut files = os_dependencies(std::slice::from_ref(&main));
+        files.insert(0, main);
+        let mut vm = Vm::new(files).unwrap();
+        vm.ram[SP] = 256;
+        for _ in 0..1_000_000 {
+            if vm.current_function() == Some("Sys.halt") {
+                break;
+            }
+            vm.step().unwrap();
+        }
+        // `Sys.error` halts instead of returning to its caller.
+        assert_eq!(vm.current_function(), Some("Sys.halt"));
+        assert_eq!(vm.ram[6], 0);
+    }
+}
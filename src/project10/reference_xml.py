@@ -0,0 +1,243 @@
+#!/usr/bin/env python3
+"""Reference XML outputs of project 10, the token and parse tree files in this directory.
+
+Written from the course's Jack grammar (book chapter 10), independently of the crate.
+Usage: reference_xml.py FILE.jack OUTDIR -> writes OUTDIR/FILET.xml and OUTDIR/FILE.xml
+"""
+import os
+import re
+import sys
+
+KEYWORDS = {
+    "class", "constructor", "function", "method", "field", "static", "var",
+    "int", "char", "boolean", "void", "true", "false", "null", "this", "let",
+    "do", "if", "else", "while", "return",
+}
+SYMBOLS = set("{}()[].,;+-*/&|<>=~")
+OPS = set("+-*/&|<>=")
+ESC = {"<": "&lt;", ">": "&gt;", "&": "&amp;", '"': "&quot;"}
+NL = "\r\n"
+
+
+def tokenize(src):
+    src = re.sub(r"/\*.*?\*/", " ", src, flags=re.S)
+    src = re.sub(r"//[^\n]*", " ", src)
+    toks = []
+    i = 0
+    while i < len(src):
+        c = src[i]
+        if c.isspace():
+            i += 1
+        elif c in SYMBOLS:
+            toks.append(("symbol", c))
+            i += 1
+        elif c == '"':
+            j = src.index('"', i + 1)
+            toks.append(("stringConstant", src[i + 1:j]))
+            i = j + 1
+        elif c.isdigit():
+            m = re.match(r"\d+", src[i:])
+            toks.append(("integerConstant", m.group()))
+            i += len(m.group())
+        else:
+            m = re.match(r"[A-Za-z_][A-Za-z0-9_]*", src[i:])
+            w = m.group()
+            toks.append(("keyword" if w in KEYWORDS else "identifier", w))
+            i += len(w)
+    return toks
+
+
+def leaf(kind, val):
+    return "<%s> %s </%s>" % (kind, "".join(ESC.get(ch, ch) for ch in val), kind)
+
+
+class Engine:
+    def __init__(self, toks):
+        self.toks = toks
+        self.i = 0
+        self.out = []
+        self.depth = 0
+
+    def peek(self, k=0):
+        return self.toks[self.i + k] if self.i + k < len(self.toks) else (None, None)
+
+    def emit(self, line):
+        self.out.append("  " * self.depth + line)
+
+    def eat(self, val=None):
+        kind, v = self.toks[self.i]
+        if val is not None and v != val:
+            raise SyntaxError("expected %r, got %r at %d" % (val, v, self.i))
+        self.emit(leaf(kind, v))
+        self.i += 1
+
+    def open(self, tag):
+        self.emit("<%s>" % tag)
+        self.depth += 1
+
+    def close(self, tag):
+        self.depth -= 1
+        self.emit("</%s>" % tag)
+
+    def klass(self):
+        self.open("class")
+        self.eat("class")
+        self.eat()
+        self.eat("{")
+        while self.peek()[1] in ("static", "field"):
+            self.open("classVarDec")
+            while self.peek()[1] != ";":
+                self.eat()
+            self.eat(";")
+            self.close("classVarDec")
+        while self.peek()[1] in ("constructor", "function", "method"):
+            self.subroutine()
+        self.eat("}")
+        self.close("class")
+
+    def subroutine(self):
+        self.open("subroutineDec")
+        self.eat()
+        self.eat()
+        self.eat()
+        self.eat("(")
+        self.open("parameterList")
+        while self.peek()[1] != ")":
+            self.eat()
+        self.close("parameterList")
+        self.eat(")")
+        self.open("subroutineBody")
+        self.eat("{")
+        while self.peek()[1] == "var":
+            self.open("varDec")
+            while self.peek()[1] != ";":
+                self.eat()
+            self.eat(";")
+            self.close("varDec")
+        self.statements()
+        self.eat("}")
+        self.close("subroutineBody")
+        self.close("subroutineDec")
+
+    def statements(self):
+        self.open("statements")
+        while self.peek()[1] in ("let", "if", "while", "do", "return"):
+            getattr(self, "st_" + self.peek()[1])()
+        self.close("statements")
+
+    def block(self):
+        self.eat("{")
+        self.statements()
+        self.eat("}")
+
+    def st_let(self):
+        self.open("letStatement")
+        self.eat("let")
+        self.eat()
+        if self.peek()[1] == "[":
+            self.eat("[")
+            self.expression()
+            self.eat("]")
+        self.eat("=")
+        self.expression()
+        self.eat(";")
+        self.close("letStatement")
+
+    def st_if(self):
+        self.open("ifStatement")
+        self.eat("if")
+        self.eat("(")
+        self.expression()
+        self.eat(")")
+        self.block()
+        if self.peek()[1] == "else":
+            self.eat("else")
+            self.block()
+        self.close("ifStatement")
+
+    def st_while(self):
+        self.open("whileStatement")
+        self.eat("while")
+        self.eat("(")
+        self.expression()
+        self.eat(")")
+        self.block()
+        self.close("whileStatement")
+
+    def st_do(self):
+        self.open("doStatement")
+        self.eat("do")
+        self.call()
+        self.eat(";")
+        self.close("doStatement")
+
+    def st_return(self):
+        self.open("returnStatement")
+        self.eat("return")
+        if self.peek()[1] != ";":
+            self.expression()
+        self.eat(";")
+        self.close("returnStatement")
+
+    def call(self):
+        self.eat()
+        if self.peek()[1] == ".":
+            self.eat(".")
+            self.eat()
+        self.eat("(")
+        self.open("expressionList")
+        if self.peek()[1] != ")":
+            self.expression()
+            while self.peek()[1] == ",":
+                self.eat(",")
+                self.expression()
+        self.close("expressionList")
+        self.eat(")")
+
+    def expression(self):
+        self.open("expression")
+        self.term()
+        while self.peek()[0] == "symbol" and self.peek()[1] in OPS:
+            self.eat()
+            self.term()
+        self.close("expression")
+
+    def term(self):
+        self.open("term")
+        kind, v = self.peek()
+        if v == "(" and kind == "symbol":
+            self.eat("(")
+            self.expression()
+            self.eat(")")
+        elif kind == "symbol" and v in "-~":
+            self.eat()
+            self.term()
+        elif kind == "identifier" and self.peek(1)[1] in ("(", "."):
+            self.call()
+        elif kind == "identifier" and self.peek(1)[1] == "[":
+            self.eat()
+            self.eat("[")
+            self.expression()
+            self.eat("]")
+        else:
+            self.eat()
+        self.close("term")
+
+
+def main():
+    path, outdir = sys.argv[1], sys.argv[2]
+    name = os.path.splitext(os.path.basename(path))[0]
+    with open(path, newline="") as f:
+        toks = tokenize(f.read())
+    lines = ["<tokens>"] + [leaf(k, v) for k, v in toks] + ["</tokens>"]
+    with open(os.path.join(outdir, name + "T.xml"), "w", newline="") as f:
+        f.write(NL.join(lines) + NL)
+    eng = Engine(toks)
+    eng.klass()
+    assert eng.i == len(toks)
+    with open(os.path.join(outdir, name + ".xml"), "w", newline="") as f:
+        f.write(NL.join(eng.out) + NL)
+
+
+if __name__ == "__main__":
+    main()
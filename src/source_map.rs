@@ -0,0 +1,257 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+/// Line `line`, 1-based, of the source file `file`, in the VM function `function` if
+/// known.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SourceLoc {
+    pub file: String,
+    pub line: usize,
+    pub function: Option<String>,
+}
+
+impl SourceLoc {
+    pub fn new(file: &str, line: usize, function: Option<&str>) -> Self {
+        Self {
+            file: file.to_string(),
+            line,
+            function: function.map(str::to_string),
+        }
+    }
+}
+
+impl fmt::Display for SourceLoc {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.function {
+            Some(function) => write!(f, "{} ({}:{})", function, self.file, self.line),
+            None => write!(f, "{}:{}", self.file, self.line),
+        }
+    }
+}
+
+/// Source location of each line a compiler stage generated, or each ROM address for
+/// the assembler, by 0-based index.
+///
+/// Its text form, written to the generated file's path plus `.map`, has a line per
+/// index of `FILE:LINE` or `FILE:LINE FUNCTION`, or `-` for generated code without
+/// a source.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SourceMap {
+    locs: Vec<Option<SourceLoc>>,
+}
+
+impl SourceMap {
+    pub fn new(locs: Vec<Option<SourceLoc>>) -> Self {
+        Self { locs }
+    }
+
+    pub fn get(&self, index: usize) -> Option<&SourceLoc> {
+        self.locs.get(index).and_then(Option::as_ref)
+    }
+
+    pub fn len(&self) -> usize {
+        self.locs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.locs.is_empty()
+    }
+
+    pub fn parse(strm: impl BufRead) -> Result<Self, String> {
+        let mut locs = Vec::new();
+        for (line_num, line) in strm.lines().enumerate() {
+            let error = |message: &str| format! {"Error: line {}; {}", line_num + 1, message};
+            let line = line.map_err(|e| error(&format! {"{:?}", e}))?;
+            let line = line.trim();
+            if line == "-" {
+                locs.push(None);
+                continue;
+            }
+            let (loc, function) = match line.split_once(' ') {
+                Some((loc, function)) => (loc, Some(function.trim())),
+                None => (line, None),
+            };
+            let (file, source_line) = loc
+                .rsplit_once(':')
+                .and_then(|(file, source_line)| Some((file, source_line.parse().ok()?)))
+                .ok_or_else(|| error("Expected FILE:LINE"))?;
+            locs.push(Some(SourceLoc::new(file, source_line, function)));
+        }
+        Ok(Self { locs })
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        let file = File::open(path).map_err(|_| format! {"Can't open file: {:?}", path})?;
+        Self::parse(BufReader::new(file))
+    }
+}
+
+impl fmt::Display for SourceMap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for loc in &self.locs {
+            match loc {
+                Some(SourceLoc {
+                    file,
+                    line,
+                    function: Some(function),
+                }) => writeln!(f, "{}:{} {}", file, line, function)?,
+                Some(SourceLoc { file, line, .. }) => writeln!(f, "{}:{}", file, line)?,
+                None => writeln!(f, "-")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Follows source locations back through the maps of earlier compiler stages, such as
+/// from a `.asm` line to the `.vm` line it was translated from, and then to the
+/// `.jack` line that one was compiled from. The map of a file is read from the file's
+/// path plus `.map` the first time it's needed.
+#[derive(Default)]
+pub struct SourceResolver {
+    maps: HashMap<String, Option<SourceMap>>,
+}
+
+// Bounds the stages followed, in case maps refer to each other.
+const MAX_STAGES: usize = 8;
+
+impl SourceResolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Uses `map` as the map of `file` instead of reading it.
+    pub fn insert(&mut self, file: &str, map: SourceMap) {
+        self.maps.insert(file.to_string(), Some(map));
+    }
+
+    /// Location in the first stage with a map leading to it, keeping the function of
+    /// the latest stage that tells it.
+    pub fn resolve(&mut self, mut loc: SourceLoc) -> SourceLoc {
+        for _ in 0..MAX_STAGES {
+            let map = self
+                .maps
+                .entry(loc.file.clone())
+                .or_insert_with(|| SourceMap::load(format! {"{}.map", loc.file}).ok());
+            let source = match map
+                .as_ref()
+                .and_then(|map| map.get(loc.line.checked_sub(1)?))
+            {
+                Some(source) => source.clone(),
+                None => break,
+            };
+            let function = loc.function.take();
+            loc = source;
+            loc.function = loc.function.or(function);
+        }
+        loc
+    }
+
+    /// `map` with every location resolved.
+    pub fn resolve_map(&mut self, map: &SourceMap) -> SourceMap {
+        let locs = map
+            .locs
+            .iter()
+            .map(|loc| loc.clone().map(|loc| self.resolve(loc)))
+            .collect();
+        SourceMap::new(locs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emu::{assemble_with_symbols, Cpu, Vm, SP};
+    use crate::jack::{tokenize, Parser, VmGen};
+    use crate::vm::{print_vm, VmFile};
+    use crate::AsmGenerator;
+
+    #[test]
+    fn test_backtrace() {
+        let jack = "class Sys {
+    function void init() {
+        do Sys.f(2);
+        return;
+    }
+
+    function int f(int n) {
+        if (n = 0) {
+            while (true) {}
+        }
+        return Sys.f(n - 1);
+    }
+}
+";
+        let class = Parser::new(tokenize(jack.chars()).unwrap().into_iter())
+            .parse()
+            .unwrap();
+        let mut vm_gen = VmGen::new();
+        let commands = vm_gen.gen(class).unwrap().to_vec();
+        let vm_map = vm_gen.source_map("Sys.jack");
+        assert_eq!(
+            SourceMap::parse(vm_map.to_string().as_bytes()),
+            Ok(vm_map.clone())
+        );
+        let mut resolver = SourceResolver::new();
+        resolver.insert("Sys.vm", vm_map);
+
+        let mut asm_gen = AsmGenerator::new(false);
+        let lines: Vec<_> = (1..=commands.len()).collect();
+        asm_gen.gen_commands_with_source("Sys", commands.clone(), "Sys.vm", &lines);
+        resolver.insert("Sys.asm", asm_gen.source_map());
+        let asm = asm_gen.lines().join("\n");
+        let (program, symbols) = assemble_with_symbols(asm.as_bytes()).unwrap();
+        let rom_map = SourceMap::new(
+            symbols
+                .lines
+                .iter()
+                .map(|&line| Some(SourceLoc::new("Sys.asm", line, None)))
+                .collect(),
+        );
+        let rom_map = resolver.resolve_map(&rom_map);
+        let mut cpu = Cpu::new(&program);
+        for _ in 0..2000 {
+            cpu.step();
+        }
+        let expected = [
+            "Sys.f (Sys.jack:9)",
+            "Sys.f (Sys.jack:11)",
+            "Sys.f (Sys.jack:11)",
+            "Sys.init (Sys.jack:3)",
+        ];
+        let trace: Vec<_> = cpu
+            .backtrace()
+            .iter()
+            .filter_map(|&addr| rom_map.get(addr as usize))
+            .map(SourceLoc::to_string)
+            .collect();
+        // The outermost call is in the bootstrap code, which has no VM source.
+        assert_eq!(trace[..4], expected);
+        assert!(trace[4].starts_with("Sys.asm:"));
+
+        let file = VmFile {
+            name: "Sys".to_string(),
+            commands,
+        };
+        assert_eq!(
+            print_vm(&file.commands).lines().count(),
+            file.commands.len()
+        );
+        let mut vm = Vm::new(vec![file]).unwrap();
+        vm.ram[SP] = 256;
+        for _ in 0..200 {
+            vm.step().unwrap();
+        }
+        let trace: Vec<_> = vm
+            .backtrace()
+            .into_iter()
+            .filter_map(|pc| vm.source(pc))
+            .map(|loc| resolver.resolve(loc).to_string())
+            .collect();
+        assert_eq!(trace, expected);
+    }
+}
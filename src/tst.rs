@@ -0,0 +1,5 @@
+mod parser;
+mod runner;
+
+pub use parser::{parse_tst, CmpOp, Cond, OutputSpec, Stmt, TstCommand, Var};
+pub use runner::{TstRunner, Value};
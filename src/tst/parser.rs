@@ -0,0 +1,443 @@
+use std::iter::Peekable;
+use std::str::Chars;
+
+/// Simulator variable, `name` or `name[index]`. `name[]` is `name[0]`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Var {
+    pub name: String,
+    pub index: Option<usize>,
+}
+
+/// Column of `output-list`, `var%Fleft.width.right` with format `F` one of `B`
+/// (binary), `D` (decimal), `X` (hexadecimal) and `S` (string).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OutputSpec {
+    pub var: Var,
+    // Variable as written, for the header.
+    pub label: String,
+    pub format: char,
+    pub left: usize,
+    pub width: usize,
+    pub right: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CmpOp {
+    Eq,
+    Ne,
+    Lt,
+    Gt,
+    Le,
+    Ge,
+}
+
+/// Condition of `while`, e.g. `RAM[0] <> 0`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Cond {
+    pub var: Var,
+    pub op: CmpOp,
+    pub value: i32,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TstCommand {
+    /// `load` of a `.hdl`, `.hack`, `.asm` or `.vm` file, or of the script directory.
+    Load(Option<String>),
+    OutputFile(String),
+    CompareTo(String),
+    OutputList(Vec<OutputSpec>),
+    Set(Var, i32),
+    Eval,
+    Tick,
+    Tock,
+    TickTock,
+    VmStep,
+    Output,
+    Echo(String),
+    ClearEcho,
+    /// `<chip> load <file>`, e.g. `ROM32K load Add.hack`.
+    LoadMemory(String, String),
+    /// `repeat n { ... }`, forever without `n`.
+    Repeat(Option<usize>, Vec<Stmt>),
+    While(Cond, Vec<Stmt>),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Stmt {
+    pub command: TstCommand,
+    pub line: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Tok {
+    Word(String),
+    Str(String),
+    // `,`, `;`, `!`, `{` or `}`
+    Sym(char),
+}
+
+/// Parses a test script.
+pub fn parse_tst(src: &str) -> Result<Vec<Stmt>, String> {
+    let mut parser = TstParser {
+        toks: lex(src)?,
+        pos: 0,
+    };
+    let mut stmts = Vec::new();
+    while parser.peek().is_some() {
+        stmts.push(parser.stmt()?);
+    }
+    Ok(stmts)
+}
+
+fn lex(src: &str) -> Result<Vec<(Tok, usize)>, String> {
+    let mut toks = Vec::new();
+    let mut line = 1;
+    let mut chars: Peekable<Chars> = src.chars().peekable();
+    while let Some(c) = chars.next() {
+        let tok = match c {
+            '\n' => {
+                line += 1;
+                continue;
+            }
+            c if c.is_whitespace() => continue,
+            '/' if chars.peek() == Some(&'/') => {
+                while chars.peek().is_some_and(|&c| c != '\n') {
+                    chars.next();
+                }
+                continue;
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let start = line;
+                let mut prev = ' ';
+                loop {
+                    match chars.next() {
+                        Some('/') if prev == '*' => break,
+                        Some(c) => {
+                            if c == '\n' {
+                                line += 1;
+                            }
+                            prev = c;
+                        }
+                        None => {
+                            return Err(format! {"Error: line {}; Unclosed comment", start});
+                        }
+                    }
+                }
+                continue;
+            }
+            '"' => {
+                let mut s = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\n') | None => {
+                            return Err(format! {"Error: line {}; Unclosed string", line});
+                        }
+                        Some(c) => s.push(c),
+                    }
+                }
+                Tok::Str(s)
+            }
+            ',' | ';' | '!' | '{' | '}' => Tok::Sym(c),
+            c => {
+                let mut s = c.to_string();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || ",;!{}\"".contains(c) {
+                        break;
+                    }
+                    s.push(c);
+                    chars.next();
+                }
+                Tok::Word(s)
+            }
+        };
+        toks.push((tok, line));
+    }
+    Ok(toks)
+}
+
+struct TstParser {
+    toks: Vec<(Tok, usize)>,
+    pos: usize,
+}
+
+impl TstParser {
+    fn stmt(&mut self) -> Result<Stmt, String> {
+        let line = self.line();
+        let word = self.word()?;
+        let command = match word.as_str() {
+            "repeat" => {
+                let count = match self.peek() {
+                    Some(Tok::Word(_)) => {
+                        let word = self.word()?;
+                        Some(
+                            word.parse()
+                                .map_err(|_| self.error("Expected repeat count"))?,
+                        )
+                    }
+                    _ => None,
+                };
+                return Ok(Stmt {
+                    command: TstCommand::Repeat(count, self.block()?),
+                    line,
+                });
+            }
+            "while" => {
+                let cond = self.cond()?;
+                return Ok(Stmt {
+                    command: TstCommand::While(cond, self.block()?),
+                    line,
+                });
+            }
+            "load" => match self.peek() {
+                Some(Tok::Word(_)) => TstCommand::Load(Some(self.word()?)),
+                _ => TstCommand::Load(None),
+            },
+            "output-file" => TstCommand::OutputFile(self.word()?),
+            "compare-to" => TstCommand::CompareTo(self.word()?),
+            "output-list" => {
+                let mut specs = Vec::new();
+                while let Some(Tok::Word(_)) = self.peek() {
+                    let word = self.word()?;
+                    specs.push(self.output_spec(&word)?);
+                }
+                TstCommand::OutputList(specs)
+            }
+            "set" => {
+                let var = self.word()?;
+                let var = self.var(&var)?;
+                let value = self.word()?;
+                TstCommand::Set(var, self.value(&value)?)
+            }
+            "eval" => TstCommand::Eval,
+            "tick" => TstCommand::Tick,
+            "tock" => TstCommand::Tock,
+            "ticktock" => TstCommand::TickTock,
+            "vmstep" => TstCommand::VmStep,
+            "output" => TstCommand::Output,
+            "echo" => match self.next() {
+                Some(Tok::Str(s)) => TstCommand::Echo(s),
+                _ => return Err(self.error("Expected string")),
+            },
+            "clear-echo" => TstCommand::ClearEcho,
+            chip if self.peek() == Some(&Tok::Word("load".to_string())) => {
+                self.next();
+                TstCommand::LoadMemory(chip.to_string(), self.word()?)
+            }
+            _ => return Err(format! {"Error: line {}; Unknown command {}", line, word}),
+        };
+        match self.peek() {
+            Some(Tok::Sym(',')) | Some(Tok::Sym(';')) | Some(Tok::Sym('!')) => {
+                self.next();
+            }
+            // The last command may omit its terminator.
+            None => {}
+            _ => return Err(self.error("Expected ',', ';' or '!'")),
+        }
+        Ok(Stmt { command, line })
+    }
+
+    fn block(&mut self) -> Result<Vec<Stmt>, String> {
+        if self.next() != Some(Tok::Sym('{')) {
+            return Err(self.error("Expected '{'"));
+        }
+        let mut stmts = Vec::new();
+        loop {
+            match self.peek() {
+                Some(Tok::Sym('}')) => {
+                    self.next();
+                    return Ok(stmts);
+                }
+                None => return Err(self.error("Expected '}'")),
+                _ => stmts.push(self.stmt()?),
+            }
+        }
+    }
+
+    // Words up to `{`, which may or may not be spaced around the operator.
+    fn cond(&mut self) -> Result<Cond, String> {
+        let mut s = String::new();
+        while let Some(Tok::Word(_)) = self.peek() {
+            s.push_str(&self.word()?);
+        }
+        let start = s
+            .find(['<', '>', '='])
+            .ok_or_else(|| self.error("Expected comparison"))?;
+        let end = s[start..]
+            .find(|c| !"<>=".contains(c))
+            .map_or(s.len(), |len| start + len);
+        let op = match &s[start..end] {
+            "=" => CmpOp::Eq,
+            "<>" => CmpOp::Ne,
+            "<" => CmpOp::Lt,
+            ">" => CmpOp::Gt,
+            "<=" => CmpOp::Le,
+            ">=" => CmpOp::Ge,
+            _ => return Err(self.error("Invalid comparison")),
+        };
+        Ok(Cond {
+            var: self.var(&s[..start])?,
+            op,
+            value: self.value(&s[end..])?,
+        })
+    }
+
+    fn output_spec(&self, word: &str) -> Result<OutputSpec, String> {
+        let invalid = || self.error(&format! {"Invalid output format {}", word});
+        let (label, format) = word.split_at(word.find('%').ok_or_else(invalid)?);
+        let mut chars = format[1..].chars();
+        let format = chars
+            .next()
+            .filter(|c| "BDXS".contains(*c))
+            .ok_or_else(invalid)?;
+        let sizes = chars
+            .as_str()
+            .split('.')
+            .map(|n| n.parse())
+            .collect::<Result<Vec<usize>, _>>()
+            .map_err(|_| invalid())?;
+        match sizes[..] {
+            [left, width, right] => Ok(OutputSpec {
+                var: self.var(label)?,
+                label: label.to_string(),
+                format,
+                left,
+                width,
+                right,
+            }),
+            _ => Err(invalid()),
+        }
+    }
+
+    fn var(&self, s: &str) -> Result<Var, String> {
+        let (name, index) = match s.find('[') {
+            Some(open) if s.ends_with(']') => {
+                let index = &s[open + 1..s.len() - 1];
+                let index = if index.is_empty() {
+                    0
+                } else {
+                    index
+                        .parse()
+                        .map_err(|_| self.error(&format! {"Invalid index {}", s}))?
+                };
+                (&s[..open], Some(index))
+            }
+            _ => (s, None),
+        };
+        if name.is_empty() {
+            return Err(self.error("Expected variable"));
+        }
+        Ok(Var {
+            name: name.to_string(),
+            index,
+        })
+    }
+
+    // `%B`, `%X` or `%D` number, decimal by default.
+    fn value(&self, s: &str) -> Result<i32, String> {
+        let (digits, radix) = match s.get(..2) {
+            Some("%B") => (&s[2..], 2),
+            Some("%X") => (&s[2..], 16),
+            Some("%D") => (&s[2..], 10),
+            _ => (s, 10),
+        };
+        i32::from_str_radix(digits, radix)
+            .ok()
+            .filter(|value| (-32768..=65535).contains(value))
+            .ok_or_else(|| self.error(&format! {"Invalid value {}", s}))
+    }
+
+    fn peek(&self) -> Option<&Tok> {
+        self.toks.get(self.pos).map(|(tok, _)| tok)
+    }
+
+    fn next(&mut self) -> Option<Tok> {
+        let tok = self.toks.get(self.pos).map(|(tok, _)| tok.clone());
+        self.pos += 1;
+        tok
+    }
+
+    fn word(&mut self) -> Result<String, String> {
+        match self.peek() {
+            Some(Tok::Word(s)) => {
+                let s = s.clone();
+                self.next();
+                Ok(s)
+            }
+            _ => Err(self.error("Expected word")),
+        }
+    }
+
+    // Line of the next token, or of the last one at the end of the file.
+    fn line(&self) -> usize {
+        self.toks
+            .get(self.pos)
+            .or_else(|| self.toks.last())
+            .map_or(1, |(_, line)| *line)
+    }
+
+    fn error(&self, message: &str) -> String {
+        format! {"Error: line {}; {}", self.line(), message}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_tst() {
+        let stmts = parse_tst(
+            "load Max.asm, // comment
+output-list RAM[0]%D2.6.2 time%S1.4.1;
+set RAM[1] %B101,
+while RAM[0]<>-1 {
+    ticktock;
+}
+ROM32K load Max.hack",
+        )
+        .unwrap();
+        let var = |name: &str, index| Var {
+            name: name.to_string(),
+            index,
+        };
+        assert_eq!(
+            stmts[0].command,
+            TstCommand::Load(Some("Max.asm".to_string()))
+        );
+        match &stmts[1].command {
+            TstCommand::OutputList(specs) => {
+                assert_eq!(specs[0].var, var("RAM", Some(0)));
+                assert_eq!(specs[0].label, "RAM[0]");
+                assert_eq!((specs[1].format, specs[1].width), ('S', 4));
+            }
+            command => panic!("{:?}", command),
+        }
+        assert_eq!(stmts[2].command, TstCommand::Set(var("RAM", Some(1)), 5));
+        assert_eq!(
+            stmts[3],
+            Stmt {
+                command: TstCommand::While(
+                    Cond {
+                        var: var("RAM", Some(0)),
+                        op: CmpOp::Ne,
+                        value: -1,
+                    },
+                    vec![Stmt {
+                        command: TstCommand::TickTock,
+                        line: 5,
+                    }]
+                ),
+                line: 4,
+            }
+        );
+        assert_eq!(
+            stmts[4].command,
+            TstCommand::LoadMemory("ROM32K".to_string(), "Max.hack".to_string())
+        );
+
+        let err = parse_tst("set a 1\nset b 2;").unwrap_err();
+        assert_eq!(err, "Error: line 2; Expected ',', ';' or '!'");
+    }
+}
@@ -0,0 +1,516 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::parser::{parse_tst, CmpOp, Cond, OutputSpec, Stmt, TstCommand, Var};
+use crate::emu::{assemble, parse_hack, Cpu, Vm, ARG, LCL, MEMORY_SIZE, SP, THAT, THIS};
+use crate::hdl::{Chip, ChipLoader};
+
+/// Value of a script variable.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Value {
+    Int(i32),
+    Str(String),
+}
+
+// Simulator driven by a script. Commands a simulator doesn't have are errors.
+trait Target {
+    fn get(&mut self, var: &Var) -> Result<Value, String>;
+
+    fn set(&mut self, var: &Var, value: u16) -> Result<(), String>;
+
+    fn eval(&mut self) -> Result<(), String> {
+        Err(unsupported("eval"))
+    }
+
+    fn tick(&mut self) -> Result<(), String> {
+        Err(unsupported("tick"))
+    }
+
+    fn tock(&mut self) -> Result<(), String> {
+        Err(unsupported("tock"))
+    }
+
+    fn ticktock(&mut self) -> Result<(), String> {
+        self.tick()?;
+        self.tock()
+    }
+
+    fn vmstep(&mut self) -> Result<(), String> {
+        Err(unsupported("vmstep"))
+    }
+
+    fn memory(&mut self, chip: &str) -> Option<&mut [u16]> {
+        let _ = chip;
+        None
+    }
+}
+
+fn unsupported(command: &str) -> String {
+    format! {"{} is not supported by the loaded program", command}
+}
+
+fn unknown(var: &Var) -> String {
+    format! {"Unknown variable {}", var.name}
+}
+
+// Word of `memory` as a signed value.
+fn word(memory: &[u16], var: &Var) -> Result<Value, String> {
+    memory
+        .get(var.index.unwrap_or(0))
+        .map(|&word| Value::Int(word as i16 as i32))
+        .ok_or_else(|| format! {"Index of {} out of range", var.name})
+}
+
+fn word_mut<'a>(memory: &'a mut [u16], var: &Var) -> Result<&'a mut u16, String> {
+    memory
+        .get_mut(var.index.unwrap_or(0))
+        .ok_or_else(|| format! {"Index of {} out of range", var.name})
+}
+
+// Chip of an `.hdl` file. `time` counts clock cycles, shown as `t+` after a tick.
+struct Hardware {
+    chip: Chip,
+    time: usize,
+    ticked: bool,
+}
+
+impl Target for Hardware {
+    fn get(&mut self, var: &Var) -> Result<Value, String> {
+        if var.name == "time" {
+            let plus = if self.ticked { "+" } else { "" };
+            return Ok(Value::Str(format! {"{}{}", self.time, plus}));
+        }
+        if var.index.is_some() {
+            let memory = self.chip.memory(&var.name).ok_or_else(|| unknown(var))?;
+            return word(memory, var);
+        }
+        let value = self.chip.get(&var.name)?;
+        let width = self
+            .chip
+            .inputs()
+            .chain(self.chip.outputs())
+            .find(|(pin, _)| *pin == var.name)
+            .map_or(0, |(_, width)| width);
+        // Only full words are signed.
+        Ok(Value::Int(if width == 16 {
+            value as i16 as i32
+        } else {
+            value as i32
+        }))
+    }
+
+    fn set(&mut self, var: &Var, value: u16) -> Result<(), String> {
+        if var.index.is_some() {
+            let memory = self.chip.memory(&var.name).ok_or_else(|| unknown(var))?;
+            *word_mut(memory, var)? = value;
+            return Ok(());
+        }
+        self.chip.set(&var.name, value)
+    }
+
+    fn eval(&mut self) -> Result<(), String> {
+        self.chip.eval();
+        Ok(())
+    }
+
+    fn tick(&mut self) -> Result<(), String> {
+        self.chip.tick();
+        self.ticked = true;
+        Ok(())
+    }
+
+    fn tock(&mut self) -> Result<(), String> {
+        self.chip.tock();
+        self.time += 1;
+        self.ticked = false;
+        Ok(())
+    }
+
+    fn memory(&mut self, chip: &str) -> Option<&mut [u16]> {
+        self.chip.memory(chip)
+    }
+}
+
+// Program of a `.hack` or `.asm` file, running an instruction per `ticktock`.
+struct CpuTarget {
+    cpu: Cpu,
+    time: usize,
+}
+
+impl Target for CpuTarget {
+    fn get(&mut self, var: &Var) -> Result<Value, String> {
+        let cpu = &self.cpu;
+        Ok(Value::Int(match (var.name.as_str(), var.index) {
+            ("time", None) => self.time as i32,
+            ("A", None) => cpu.a as i16 as i32,
+            ("D", None) => cpu.d as i16 as i32,
+            ("PC", None) => cpu.pc as i32,
+            ("RAM", Some(_)) => return word(&cpu.ram, var),
+            ("ROM", Some(_)) => return word(&cpu.rom, var),
+            _ => return Err(unknown(var)),
+        }))
+    }
+
+    fn set(&mut self, var: &Var, value: u16) -> Result<(), String> {
+        let cpu = &mut self.cpu;
+        match (var.name.as_str(), var.index) {
+            ("A", None) => cpu.a = value,
+            ("D", None) => cpu.d = value,
+            ("PC", None) => cpu.pc = value % MEMORY_SIZE as u16,
+            ("RAM", Some(_)) => *word_mut(&mut cpu.ram, var)? = value,
+            ("ROM", Some(_)) => *word_mut(&mut cpu.rom, var)? = value,
+            _ => return Err(unknown(var)),
+        }
+        Ok(())
+    }
+
+    fn ticktock(&mut self) -> Result<(), String> {
+        self.cpu.step();
+        self.time += 1;
+        Ok(())
+    }
+}
+
+// Program of `.vm` files, running a command per `vmstep`.
+struct VmTarget {
+    vm: Vm,
+}
+
+impl VmTarget {
+    // RAM address of `var`, a register or a segment entry.
+    fn address(&self, var: &Var) -> Result<usize, String> {
+        let ram = &self.vm.ram;
+        let base = |reg: usize| var.index.map_or(reg, |i| ram[reg] as usize + i);
+        let address = match (var.name.as_str(), var.index) {
+            ("sp", None) => SP,
+            ("local", _) => base(LCL),
+            ("argument", _) => base(ARG),
+            ("this", _) => base(THIS),
+            ("that", _) => base(THAT),
+            ("temp", Some(i)) if i < 8 => 5 + i,
+            ("pointer", Some(i)) if i < 2 => THIS + i,
+            ("RAM", Some(i)) => i,
+            _ => return Err(unknown(var)),
+        };
+        if address < MEMORY_SIZE {
+            Ok(address)
+        } else {
+            Err(format! {"Index of {} out of range", var.name})
+        }
+    }
+}
+
+impl Target for VmTarget {
+    fn get(&mut self, var: &Var) -> Result<Value, String> {
+        let address = self.address(var)?;
+        Ok(Value::Int(self.vm.ram[address] as i16 as i32))
+    }
+
+    fn set(&mut self, var: &Var, value: u16) -> Result<(), String> {
+        let address = self.address(var)?;
+        self.vm.ram[address] = value;
+        Ok(())
+    }
+
+    fn vmstep(&mut self) -> Result<(), String> {
+        self.vm.step()
+    }
+}
+
+// Message of an error, without the `Error: ` that a script line number replaces.
+fn strip(err: String) -> String {
+    err.trim_start_matches("Error: ").to_string()
+}
+
+/// Runs test scripts for chips and programs. Files named by a script are relative
+/// to its directory, and chips are looked up there before the library directories.
+pub struct TstRunner {
+    dir: PathBuf,
+    lib: Vec<PathBuf>,
+    target: Option<Box<dyn Target>>,
+    output_list: Vec<OutputSpec>,
+    output: Vec<String>,
+    output_file: Option<PathBuf>,
+    compare: Option<Vec<String>>,
+    echo: Option<String>,
+}
+
+impl TstRunner {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            lib: Vec::new(),
+            target: None,
+            output_list: Vec::new(),
+            output: Vec::new(),
+            output_file: None,
+            compare: None,
+            echo: None,
+        }
+    }
+
+    /// Adds a directory of `.hdl` files for chips the script directory doesn't have.
+    pub fn lib(&mut self, dir: impl Into<PathBuf>) {
+        self.lib.push(dir.into());
+    }
+
+    /// Runs `script`, stopping at the first error or at the first output line that
+    /// differs from the `compare-to` file.
+    pub fn run(&mut self, script: &str) -> Result<(), String> {
+        let stmts = parse_tst(script)?;
+        self.stmts(&stmts)
+    }
+
+    /// Lines written by `output-list` and `output` so far.
+    pub fn output(&self) -> &[String] {
+        &self.output
+    }
+
+    /// Path given to `output-file`.
+    pub fn output_file(&self) -> Option<&Path> {
+        self.output_file.as_deref()
+    }
+
+    /// Message of the last `echo`, unless cleared.
+    pub fn echo(&self) -> Option<&str> {
+        self.echo.as_deref()
+    }
+
+    fn stmts(&mut self, stmts: &[Stmt]) -> Result<(), String> {
+        for stmt in stmts {
+            self.stmt(stmt)?;
+        }
+        Ok(())
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) -> Result<(), String> {
+        let error = |message: String| format! {"Error: line {}; {}", stmt.line, strip(message)};
+        match &stmt.command {
+            TstCommand::Repeat(count, body) => match count {
+                Some(count) => {
+                    for _ in 0..*count {
+                        self.stmts(body)?;
+                    }
+                }
+                None => loop {
+                    self.stmts(body)?;
+                },
+            },
+            TstCommand::While(cond, body) => {
+                while self.cond(cond).map_err(error)? {
+                    self.stmts(body)?;
+                }
+            }
+            TstCommand::Load(file) => self.load(file.as_deref())?,
+            TstCommand::OutputFile(file) => self.output_file = Some(self.dir.join(file)),
+            TstCommand::CompareTo(file) => {
+                let path = self.dir.join(file);
+                let cmp = fs::read_to_string(&path)
+                    .map_err(|_| error(format! {"Can't open file: {:?}", path}))?;
+                self.compare = Some(cmp.lines().map(str::to_string).collect());
+            }
+            TstCommand::OutputList(specs) => {
+                self.output_list = specs.clone();
+                let header = specs.iter().fold("|".to_string(), |mut line, spec| {
+                    line.push_str(&header(spec));
+                    line.push('|');
+                    line
+                });
+                self.write(header)?;
+            }
+            TstCommand::Output => {
+                let mut line = "|".to_string();
+                for spec in self.output_list.clone() {
+                    let value = self
+                        .target()
+                        .map_err(error)?
+                        .get(&spec.var)
+                        .map_err(error)?;
+                    line.push_str(&format_value(&spec, &value));
+                    line.push('|');
+                }
+                self.write(line)?;
+            }
+            TstCommand::Set(var, value) => {
+                let target = self.target().map_err(error)?;
+                target.set(var, *value as u16).map_err(error)?;
+            }
+            TstCommand::Eval => self.target().map_err(error)?.eval().map_err(error)?,
+            TstCommand::Tick => self.target().map_err(error)?.tick().map_err(error)?,
+            TstCommand::Tock => self.target().map_err(error)?.tock().map_err(error)?,
+            TstCommand::TickTock => self.target().map_err(error)?.ticktock().map_err(error)?,
+            TstCommand::VmStep => self.target().map_err(error)?.vmstep().map_err(error)?,
+            TstCommand::Echo(message) => self.echo = Some(message.clone()),
+            TstCommand::ClearEcho => self.echo = None,
+            TstCommand::LoadMemory(chip, file) => {
+                let program = self.program(file)?;
+                let memory = self
+                    .target()
+                    .map_err(error)?
+                    .memory(chip)
+                    .ok_or_else(|| error(format! {"No builtin chip {}", chip}))?;
+                if program.len() > memory.len() {
+                    return Err(error(format! {"{} is too large for {}", file, chip}));
+                }
+                memory[..program.len()].copy_from_slice(&program);
+            }
+        }
+        Ok(())
+    }
+
+    fn target(&mut self) -> Result<&mut Box<dyn Target>, String> {
+        self.target
+            .as_mut()
+            .ok_or_else(|| "Nothing is loaded".to_string())
+    }
+
+    fn cond(&mut self, cond: &Cond) -> Result<bool, String> {
+        let value = match self.target()?.get(&cond.var)? {
+            Value::Int(value) => value,
+            Value::Str(_) => return Err(format! {"{} is not a number", cond.var.name}),
+        };
+        Ok(match cond.op {
+            CmpOp::Eq => value == cond.value,
+            CmpOp::Ne => value != cond.value,
+            CmpOp::Lt => value < cond.value,
+            CmpOp::Gt => value > cond.value,
+            CmpOp::Le => value <= cond.value,
+            CmpOp::Ge => value >= cond.value,
+        })
+    }
+
+    // Loads the chip or program of `file`, or the `.vm` files of the script directory.
+    fn load(&mut self, file: Option<&str>) -> Result<(), String> {
+        let path = file.map_or(self.dir.clone(), |file| self.dir.join(file));
+        let target: Box<dyn Target> = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("hdl") => {
+                let name = path.file_stem().and_then(|s| s.to_str()).unwrap_or("");
+                let dirs = path.parent().into_iter().map(Path::to_path_buf);
+                let mut loader = ChipLoader::new(dirs.chain(self.lib.iter().cloned()));
+                Box::new(Hardware {
+                    chip: loader.load(name)?,
+                    time: 0,
+                    ticked: false,
+                })
+            }
+            Some("hack") | Some("asm") => Box::new(CpuTarget {
+                cpu: Cpu::new(&self.program(file.unwrap_or(""))?),
+                time: 0,
+            }),
+            _ => Box::new(VmTarget {
+                vm: Vm::load(&path)?,
+            }),
+        };
+        self.target = Some(target);
+        Ok(())
+    }
+
+    // Words of a `.hack` or `.asm` file.
+    fn program(&self, file: &str) -> Result<Vec<u16>, String> {
+        let path = self.dir.join(file);
+        let src = fs::read_to_string(&path).map_err(|_| format! {"Can't open file: {:?}", path})?;
+        let program = if file.ends_with(".asm") {
+            assemble(src.as_bytes())
+        } else {
+            parse_hack(src.as_bytes())
+        };
+        program.map_err(|e| format! {"{}: {}", file, e})
+    }
+
+    // Appends an output line, checking it against the compare file.
+    fn write(&mut self, line: String) -> Result<(), String> {
+        self.output.push(line);
+        let line_num = self.output.len();
+        let actual = self.output[line_num - 1].trim_end();
+        if let Some(expected) = self.compare.as_ref().and_then(|cmp| cmp.get(line_num - 1)) {
+            let expected = expected.trim_end();
+            let matches = expected.len() == actual.len()
+                && expected
+                    .chars()
+                    .zip(actual.chars())
+                    .all(|(e, a)| e == '*' || e == a);
+            if !matches {
+                return Err(format! {
+                    "Error: Comparison failure at line {}\nexpected: {}\nactual:   {}",
+                    line_num, expected, actual
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+// Label of `spec` centered in its column, cut to fit.
+fn header(spec: &OutputSpec) -> String {
+    let width = spec.left + spec.width + spec.right;
+    let label: String = spec.label.chars().take(width).collect();
+    let pad = width - label.chars().count();
+    format! {"{}{}{}", " ".repeat(pad / 2), label, " ".repeat(pad - pad / 2)}
+}
+
+fn format_value(spec: &OutputSpec, value: &Value) -> String {
+    let width = spec.width;
+    let s = match (spec.format, value) {
+        (_, Value::Str(s)) => format! {"{:<1$}", s, width},
+        ('S', Value::Int(n)) => format! {"{:<1$}", n, width},
+        ('D', Value::Int(n)) => format! {"{:>1$}", n, width},
+        // The low `width` digits
+        ('B', Value::Int(n)) => {
+            let bits = *n as u32 as u64 & ((1 << width.min(32)) - 1);
+            format! {"{:01$b}", bits, width}
+        }
+        (_, Value::Int(n)) => {
+            let bits = *n as u32 as u64 & ((1 << (4 * width).min(32)) - 1);
+            format! {"{:01$X}", bits, width}
+        }
+    };
+    format! {"{}{}{}", " ".repeat(spec.left), s, " ".repeat(spec.right)}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(dir: &str, script: &str, lib: &[&str]) -> TstRunner {
+        let src = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src");
+        let mut runner = TstRunner::new(src.join(dir));
+        for dir in lib {
+            runner.lib(src.join(dir));
+        }
+        let script = fs::read_to_string(src.join(dir).join(script)).unwrap();
+        runner.run(&script).unwrap();
+        runner
+    }
+
+    #[test]
+    fn test_scripts() {
+        let runner = run("project04/mult", "Mult.tst", &[]);
+        assert_eq!(runner.output().len(), 7);
+        assert_eq!(
+            runner.output_file(),
+            Some(Path::new(concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/src/project04/mult/Mult.out"
+            )))
+        );
+        run("project03/a", "RAM8.tst", &["project02", "project01"]);
+        run("project05", "CPU.tst", &["project02", "project01"]);
+        run("project05", "ComputerAdd.tst", &["project02", "project01"]);
+    }
+
+    #[test]
+    fn test_comparison_failure() {
+        let src = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/project04/mult");
+        let mut runner = TstRunner::new(src);
+        let err = runner
+            .run("load Mult.hack, compare-to Mult.cmp, output-list RAM[0]%D2.6.2 RAM[1]%D2.6.2 RAM[2]%D2.6.2;
+set RAM[0] 1, output;")
+            .unwrap_err();
+        assert_eq!(
+            err,
+            "Error: Comparison failure at line 2
+expected: |       0  |       0  |       0  |
+actual:   |       1  |       0  |       0  |"
+        );
+    }
+}
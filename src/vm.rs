@@ -0,0 +1,154 @@
+mod analysis;
+mod call_graph;
+mod optimizer;
+mod parser;
+
+use std::fmt;
+use std::str::FromStr;
+
+pub use analysis::{stack_depths, stack_usage, FunctionStats, StackUsage, FRAME_SIZE};
+pub use call_graph::{remove_dead_functions, CallGraph};
+pub use optimizer::optimize;
+pub use parser::{load_vm_file, parse_vm, parse_vm_lines, VmFile, VmLine};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Op {
+    Add,
+    Sub,
+    Neg,
+    Eq_,
+    Gt,
+    Lt,
+    And,
+    Or,
+    Not,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Command {
+    Arithmetic(Op),
+    Push(Segment, u16),
+    Pop(Segment, u16),
+    Label(String),
+    Goto(String),
+    IfGoto(String),
+    Function(String, u16),
+    Call(String, u16),
+    Return,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Segment {
+    Argument,
+    Local,
+    Static,
+    Constant,
+    This,
+    That,
+    Pointer,
+    Temp,
+}
+
+/// Prints commands one per line, in the format accepted by `parse_vm`.
+pub fn print_vm(commands: &[Command]) -> String {
+    let mut ret = String::new();
+    for command in commands {
+        ret.push_str(&command.to_string());
+        ret.push('\n');
+    }
+    ret
+}
+
+impl Op {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Op::Add => "add",
+            Op::Sub => "sub",
+            Op::Neg => "neg",
+            Op::Eq_ => "eq",
+            Op::Gt => "gt",
+            Op::Lt => "lt",
+            Op::And => "and",
+            Op::Or => "or",
+            Op::Not => "not",
+        }
+    }
+}
+
+impl Segment {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Segment::Argument => "argument",
+            Segment::Local => "local",
+            Segment::Static => "static",
+            Segment::Constant => "constant",
+            Segment::This => "this",
+            Segment::That => "that",
+            Segment::Pointer => "pointer",
+            Segment::Temp => "temp",
+        }
+    }
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl fmt::Display for Segment {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Command::Arithmetic(op) => write!(f, "{}", op),
+            Command::Push(seg, offset) => write!(f, "push {} {}", seg, offset),
+            Command::Pop(seg, offset) => write!(f, "pop {} {}", seg, offset),
+            Command::Label(label) => write!(f, "label {}", label),
+            Command::Goto(label) => write!(f, "goto {}", label),
+            Command::IfGoto(label) => write!(f, "if-goto {}", label),
+            Command::Function(name, n_locs) => write!(f, "function {} {}", name, n_locs),
+            Command::Call(name, arity) => write!(f, "call {} {}", name, arity),
+            Command::Return => write!(f, "return"),
+        }
+    }
+}
+
+impl FromStr for Op {
+    type Err = &'static str;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "add" => Ok(Op::Add),
+            "sub" => Ok(Op::Sub),
+            "neg" => Ok(Op::Neg),
+            "eq" => Ok(Op::Eq_),
+            "gt" => Ok(Op::Gt),
+            "lt" => Ok(Op::Lt),
+            "and" => Ok(Op::And),
+            "or" => Ok(Op::Or),
+            "not" => Ok(Op::Not),
+            _ => Err("Invalid VM code"),
+        }
+    }
+}
+
+impl FromStr for Segment {
+    type Err = &'static str;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "argument" => Ok(Segment::Argument),
+            "local" => Ok(Segment::Local),
+            "static" => Ok(Segment::Static),
+            "constant" => Ok(Segment::Constant),
+            "this" => Ok(Segment::This),
+            "that" => Ok(Segment::That),
+            "pointer" => Ok(Segment::Pointer),
+            "temp" => Ok(Segment::Temp),
+            _ => Err("Invalid VM code"),
+        }
+    }
+}
@@ -0,0 +1,208 @@
+use std::collections::{BTreeMap, HashMap};
+
+use super::{CallGraph, Command, Op::*};
+
+/// Words pushed by `call` to save the caller's frame.
+pub const FRAME_SIZE: usize = 5;
+
+/// Operand stack usage of a single VM function.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FunctionStats {
+    pub n_locals: usize,
+    /// Maximum operand stack height, not counting locals.
+    pub max_depth: usize,
+    /// Maximum operand stack height right before each callee is called, arguments included.
+    pub call_depths: BTreeMap<String, usize>,
+}
+
+/// Worst-case stack usage of a function including everything it calls.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StackUsage {
+    /// Words used by the function's locals, operand stack and callee frames.
+    Bounded(usize),
+    /// Some call path reaches a recursion cycle.
+    Recursive,
+}
+
+/// Computes operand stack statistics of every function.
+/// Stack heights must agree wherever control flow merges, as they do in code from `VmGen`.
+pub fn stack_depths(commands: &[Command]) -> Result<BTreeMap<String, FunctionStats>, String> {
+    let mut ret = BTreeMap::new();
+    let mut starts = commands
+        .iter()
+        .enumerate()
+        .filter(|(_, com)| matches!(com, Command::Function(_, _)))
+        .map(|(i, _)| i)
+        .peekable();
+    while let Some(start) = starts.next() {
+        let end = starts.peek().cloned().unwrap_or(commands.len());
+        let (name, n_locals) = match &commands[start] {
+            Command::Function(name, n_locals) => (name, *n_locals as usize),
+            _ => unreachable!(),
+        };
+        let mut stats = function_depths(&commands[start + 1..end])
+            .map_err(|e| format! {"Error: function {}; {}", name, e})?;
+        stats.n_locals = n_locals;
+        ret.insert(name.clone(), stats);
+    }
+    Ok(ret)
+}
+
+fn function_depths(body: &[Command]) -> Result<FunctionStats, String> {
+    let mut labels = HashMap::new();
+    for (i, com) in body.iter().enumerate() {
+        if let Command::Label(label) = com {
+            labels.insert(label.as_str(), i);
+        }
+    }
+    let target = |label: &str| {
+        labels
+            .get(label)
+            .cloned()
+            .ok_or(format! {"Undefined label {}", label})
+    };
+
+    let mut stats = FunctionStats::default();
+    let mut heights: Vec<Option<usize>> = vec![None; body.len() + 1];
+    let mut worklist = vec![(0, 0)];
+    while let Some((i, height)) = worklist.pop() {
+        match heights[i] {
+            Some(h) if h == height => continue,
+            Some(h) => {
+                return Err(
+                    format! {"Inconsistent stack height {} and {} at command {}", h, height, i + 1},
+                )
+            }
+            None => heights[i] = Some(height),
+        }
+        stats.max_depth = stats.max_depth.max(height);
+        let com = match body.get(i) {
+            Some(com) => com,
+            None => continue,
+        };
+        let (popped, pushed) = match com {
+            Command::Arithmetic(Neg) | Command::Arithmetic(Not) => (1, 1),
+            Command::Arithmetic(_) => (2, 1),
+            Command::Push(_, _) => (0, 1),
+            Command::Pop(_, _) | Command::IfGoto(_) | Command::Return => (1, 0),
+            Command::Call(callee, n_args) => {
+                let depth = stats.call_depths.entry(callee.clone()).or_insert(0);
+                *depth = (*depth).max(height);
+                (*n_args as usize, 1)
+            }
+            Command::Label(_) | Command::Goto(_) => (0, 0),
+            Command::Function(_, _) => unreachable!(),
+        };
+        let next = height
+            .checked_sub(popped)
+            .ok_or(format! {"Stack underflow at command {}", i + 1})?
+            + pushed;
+        match com {
+            Command::Goto(label) => worklist.push((target(label)?, next)),
+            Command::IfGoto(label) => {
+                worklist.push((target(label)?, next));
+                worklist.push((i + 1, next));
+            }
+            Command::Return => {}
+            _ => worklist.push((i + 1, next)),
+        }
+    }
+    Ok(stats)
+}
+
+/// Worst-case stack usage of `name`: its locals plus the deepest of its own operand stack
+/// and every call site's height, frame and callee usage.
+/// Functions missing from `stats` (e.g. OS functions not given as input) count as using nothing.
+pub fn stack_usage(
+    graph: &CallGraph,
+    stats: &BTreeMap<String, FunctionStats>,
+    name: &str,
+) -> StackUsage {
+    let mut memo = HashMap::new();
+    usage(graph, stats, name, &mut memo)
+}
+
+fn usage<'a>(
+    graph: &'a CallGraph,
+    stats: &'a BTreeMap<String, FunctionStats>,
+    name: &'a str,
+    memo: &mut HashMap<&'a str, Option<StackUsage>>,
+) -> StackUsage {
+    match memo.get(name) {
+        Some(Some(usage)) => return *usage,
+        // Still being computed, so `name` is on a cycle.
+        Some(None) => return StackUsage::Recursive,
+        None => {}
+    }
+    let func = match stats.get(name) {
+        Some(func) => func,
+        None => return StackUsage::Bounded(0),
+    };
+    memo.insert(name, None);
+    let mut max = func.max_depth;
+    let mut ret = None;
+    for callee in graph.callees(name) {
+        let height = func.call_depths.get(callee).cloned().unwrap_or(0);
+        match usage(graph, stats, callee, memo) {
+            StackUsage::Bounded(words) => max = max.max(height + FRAME_SIZE + words),
+            StackUsage::Recursive => ret = Some(StackUsage::Recursive),
+        }
+    }
+    let ret = ret.unwrap_or(StackUsage::Bounded(func.n_locals + max));
+    memo.insert(name, Some(ret));
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::parse_vm;
+    use super::*;
+
+    #[test]
+    fn test_stack_depths() {
+        let vm = "function Main.main 1\n\
+                  push constant 1\npush constant 2\npush constant 3\n\
+                  call Main.add 2\n\
+                  add\n\
+                  if-goto END\n\
+                  push constant 0\npop local 0\n\
+                  label END\n\
+                  push constant 0\n\
+                  return\n\
+                  function Main.add 0\n\
+                  push argument 0\npush argument 1\nadd\n\
+                  return\n";
+        let commands = parse_vm(vm.as_bytes()).unwrap();
+        let stats = stack_depths(&commands).unwrap();
+        let main = &stats["Main.main"];
+        assert_eq!(main.n_locals, 1);
+        assert_eq!(main.max_depth, 3);
+        assert_eq!(main.call_depths["Main.add"], 3);
+        assert_eq!(stats["Main.add"].max_depth, 2);
+
+        let graph = CallGraph::new(&commands);
+        // 1 local + 3 operands + frame + 2 operands of Main.add
+        assert_eq!(
+            stack_usage(&graph, &stats, "Main.main"),
+            StackUsage::Bounded(1 + 3 + FRAME_SIZE + 2)
+        );
+    }
+
+    #[test]
+    fn test_recursive() {
+        let vm = "function Main.f 0\npush constant 0\ncall Main.g 1\nreturn\n\
+                  function Main.g 0\npush constant 0\ncall Main.f 1\nreturn\n";
+        let commands = parse_vm(vm.as_bytes()).unwrap();
+        let stats = stack_depths(&commands).unwrap();
+        let graph = CallGraph::new(&commands);
+        assert_eq!(stack_usage(&graph, &stats, "Main.f"), StackUsage::Recursive);
+    }
+
+    #[test]
+    fn test_inconsistent() {
+        let vm = "function Main.f 0\npush constant 0\nif-goto L\npush constant 1\n\
+                  label L\npush constant 0\nreturn\n";
+        let commands = parse_vm(vm.as_bytes()).unwrap();
+        assert!(stack_depths(&commands).is_err());
+    }
+}
@@ -0,0 +1,225 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use super::{Command, VmFile};
+
+/// Caller to callee edges between VM functions.
+/// Every defined function has an entry, even if it calls nothing.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CallGraph {
+    pub edges: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl CallGraph {
+    pub fn new<'a>(commands: impl IntoIterator<Item = &'a Command>) -> Self {
+        let mut edges: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+        let mut current = None;
+        for com in commands {
+            match com {
+                Command::Function(name, _) => {
+                    edges.entry(name.clone()).or_default();
+                    current = Some(name.clone());
+                }
+                Command::Call(callee, _) => {
+                    if let Some(caller) = &current {
+                        edges.get_mut(caller).unwrap().insert(callee.clone());
+                    }
+                }
+                _ => {}
+            }
+        }
+        Self { edges }
+    }
+
+    pub fn from_files(files: &[VmFile]) -> Self {
+        Self::new(files.iter().flat_map(|file| &file.commands))
+    }
+
+    pub fn callees(&self, name: &str) -> impl Iterator<Item = &String> {
+        self.edges.get(name).into_iter().flatten()
+    }
+
+    /// Functions reachable from `entry`, including `entry` itself.
+    pub fn reachable_from(&self, entry: &str) -> BTreeSet<String> {
+        let mut visited = BTreeSet::new();
+        let mut stack = vec![entry.to_string()];
+        while let Some(name) = stack.pop() {
+            if !visited.insert(name.clone()) {
+                continue;
+            }
+            stack.extend(self.callees(&name).cloned());
+        }
+        visited
+    }
+
+    /// Recursion cycles, as strongly connected components with more than one function
+    /// or a function calling itself.
+    pub fn cycles(&self) -> Vec<Vec<String>> {
+        let mut tarjan = Tarjan {
+            graph: self,
+            index: BTreeMap::new(),
+            low: BTreeMap::new(),
+            stack: Vec::new(),
+            sccs: Vec::new(),
+        };
+        for name in self.edges.keys() {
+            if !tarjan.index.contains_key(name.as_str()) {
+                tarjan.visit(name);
+            }
+        }
+        tarjan
+            .sccs
+            .into_iter()
+            .filter(|scc| scc.len() > 1 || self.callees(&scc[0]).any(|callee| *callee == scc[0]))
+            .collect()
+    }
+
+    /// Graphviz DOT representation. Functions on recursion cycles are drawn in red
+    /// and functions called but never defined are dashed.
+    pub fn to_dot(&self) -> String {
+        let recursive: BTreeSet<_> = self.cycles().into_iter().flatten().collect();
+        let mut ret = String::from("digraph calls {\n");
+        for name in self.edges.keys() {
+            if recursive.contains(name) {
+                ret.push_str(&format! {"    \"{}\" [color=red];\n", name});
+            } else {
+                ret.push_str(&format! {"    \"{}\";\n", name});
+            }
+        }
+        let undefined: BTreeSet<_> = self
+            .edges
+            .values()
+            .flatten()
+            .filter(|callee| !self.edges.contains_key(*callee))
+            .collect();
+        for name in undefined {
+            ret.push_str(&format! {"    \"{}\" [style=dashed];\n", name});
+        }
+        for (caller, callees) in &self.edges {
+            for callee in callees {
+                ret.push_str(&format! {"    \"{}\" -> \"{}\";\n", caller, callee});
+            }
+        }
+        ret.push_str("}\n");
+        ret
+    }
+}
+
+struct Tarjan<'a> {
+    graph: &'a CallGraph,
+    index: BTreeMap<&'a str, usize>,
+    low: BTreeMap<&'a str, usize>,
+    stack: Vec<&'a str>,
+    sccs: Vec<Vec<String>>,
+}
+
+impl<'a> Tarjan<'a> {
+    fn visit(&mut self, name: &'a str) {
+        let index = self.index.len();
+        self.index.insert(name, index);
+        self.low.insert(name, index);
+        self.stack.push(name);
+        for callee in self.graph.callees(name) {
+            if !self.graph.edges.contains_key(callee) {
+                continue;
+            }
+            if !self.index.contains_key(callee.as_str()) {
+                self.visit(callee);
+                let low = self.low[name].min(self.low[callee.as_str()]);
+                self.low.insert(name, low);
+            } else if self.stack.contains(&callee.as_str()) {
+                let low = self.low[name].min(self.index[callee.as_str()]);
+                self.low.insert(name, low);
+            }
+        }
+        if self.low[name] == index {
+            let mut scc = Vec::new();
+            while let Some(top) = self.stack.pop() {
+                scc.push(top.to_string());
+                if top == name {
+                    break;
+                }
+            }
+            scc.reverse();
+            self.sccs.push(scc);
+        }
+    }
+}
+
+/// Removes every function not reachable from `entry` and returns the removed names,
+/// or an error, removing nothing, if no file defines `entry`.
+pub fn remove_dead_functions(files: &mut [VmFile], entry: &str) -> Result<Vec<String>, String> {
+    let graph = CallGraph::from_files(files);
+    if !graph.edges.contains_key(entry) {
+        return Err(format! {"Error: Entry function {} is not defined", entry});
+    }
+    let live = graph.reachable_from(entry);
+    let mut removed = Vec::new();
+    for file in files.iter_mut() {
+        let mut alive = true;
+        file.commands.retain(|com| {
+            if let Command::Function(name, _) = com {
+                alive = live.contains(name);
+                if !alive {
+                    removed.push(name.clone());
+                }
+            }
+            alive
+        });
+    }
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::parse_vm;
+    use super::*;
+
+    fn file(name: &str, vm: &str) -> VmFile {
+        VmFile {
+            name: name.to_string(),
+            commands: parse_vm(vm.as_bytes()).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_remove_dead_functions() {
+        let mut files = vec![
+            file(
+                "Sys",
+                "function Sys.init 0\ncall Main.main 0\nreturn\n\
+                 function Sys.halt 0\nreturn\n",
+            ),
+            file(
+                "Main",
+                "function Main.main 0\ncall Main.f 0\nreturn\n\
+                 function Main.f 0\ncall Main.f 0\nreturn\n\
+                 function Main.unused 0\ncall Sys.halt 0\nreturn\n",
+            ),
+        ];
+        assert!(remove_dead_functions(&mut files, "Main.mian").is_err());
+        assert_eq!(files[1].commands.len(), 9);
+        let removed = remove_dead_functions(&mut files, "Sys.init").unwrap();
+        assert_eq!(removed, vec!["Sys.halt", "Main.unused"]);
+        assert_eq!(files[0].commands.len(), 3);
+        assert_eq!(files[1].commands.len(), 6);
+    }
+
+    #[test]
+    fn test_cycles() {
+        let commands = parse_vm(
+            "function A.a 0\ncall A.b 0\nreturn\n\
+             function A.b 0\ncall A.a 0\ncall A.c 0\nreturn\n\
+             function A.c 0\ncall A.c 0\ncall Math.abs 1\nreturn\n\
+             function A.d 0\ncall A.c 0\nreturn\n"
+                .as_bytes(),
+        )
+        .unwrap();
+        let graph = CallGraph::new(&commands);
+        assert_eq!(graph.cycles(), vec![vec!["A.c"], vec!["A.a", "A.b"]]);
+        let dot = graph.to_dot();
+        assert!(dot.contains("\"A.a\" [color=red];"));
+        assert!(dot.contains("\"A.d\";"));
+        assert!(dot.contains("\"Math.abs\" [style=dashed];"));
+        assert!(dot.contains("\"A.d\" -> \"A.c\";"));
+    }
+}
@@ -0,0 +1,420 @@
+use std::collections::{HashMap, HashSet};
+
+use super::Command::{self, *};
+use super::Op::{self, *};
+use super::Segment::Constant;
+
+/// Runs constant folding, jump threading, unreachable code elimination and
+/// dead-label removal on every function until none of them changes anything.
+pub fn optimize(commands: Vec<Command>) -> Vec<Command> {
+    let mut ret = Vec::new();
+    for mut func in split_functions(commands) {
+        loop {
+            let before = func.clone();
+            func = fold_constants(func);
+            func = thread_jumps(func);
+            func = remove_unreachable(func);
+            func = remove_dead_labels(func);
+            if func == before {
+                break;
+            }
+        }
+        ret.extend(func);
+    }
+    ret
+}
+
+// Labels are scoped to functions, so every pass works on one function at a time.
+fn split_functions(commands: Vec<Command>) -> Vec<Vec<Command>> {
+    let mut ret = vec![Vec::new()];
+    for com in commands {
+        if let Function(_, _) = com {
+            ret.push(Vec::new());
+        }
+        ret.last_mut().unwrap().push(com);
+    }
+    ret.retain(|func| !func.is_empty());
+    ret
+}
+
+fn fold_constants(commands: Vec<Command>) -> Vec<Command> {
+    let mut ret = Vec::new();
+    for com in commands {
+        match com {
+            Arithmetic(op @ Neg) | Arithmetic(op @ Not) => {
+                if let Some((val, len)) = trailing_const(&ret) {
+                    ret.truncate(ret.len() - len);
+                    push_const(&mut ret, if op == Neg { val.wrapping_neg() } else { !val });
+                } else if ret.last() == Some(&Arithmetic(op)) {
+                    ret.pop();
+                } else {
+                    ret.push(com);
+                }
+            }
+            Arithmetic(op) => {
+                let (rhs, rhs_len) = match trailing_const(&ret) {
+                    Some(rhs) => rhs,
+                    None => {
+                        ret.push(com);
+                        continue;
+                    }
+                };
+                if let Some((lhs, lhs_len)) = trailing_const(&ret[..ret.len() - rhs_len]) {
+                    ret.truncate(ret.len() - rhs_len - lhs_len);
+                    push_const(&mut ret, eval_binop(op, lhs, rhs));
+                } else if is_identity(op, rhs) {
+                    ret.truncate(ret.len() - rhs_len);
+                } else {
+                    ret.push(com);
+                }
+            }
+            IfGoto(label) => {
+                if let Some((val, len)) = trailing_const(&ret) {
+                    ret.truncate(ret.len() - len);
+                    if val != 0 {
+                        ret.push(Goto(label));
+                    }
+                } else {
+                    ret.push(IfGoto(label));
+                }
+            }
+            _ => ret.push(com),
+        }
+    }
+    ret
+}
+
+// Recognizes `push constant n`, `push constant n; neg` and `push constant n; not`
+// at the end of `commands`, returning the value and the number of commands used.
+fn trailing_const(commands: &[Command]) -> Option<(i16, usize)> {
+    let len = commands.len();
+    match commands.last()? {
+        Push(Constant, n) => Some((*n as i16, 1)),
+        Arithmetic(op @ Neg) | Arithmetic(op @ Not) if len >= 2 => match &commands[len - 2] {
+            Push(Constant, n) if *op == Neg => Some(((*n as i16).wrapping_neg(), 2)),
+            Push(Constant, n) => Some((!(*n as i16), 2)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn push_const(commands: &mut Vec<Command>, val: i16) {
+    if val >= 0 {
+        commands.push(Push(Constant, val as u16));
+    } else if val == i16::MIN {
+        commands.push(Push(Constant, !val as u16));
+        commands.push(Arithmetic(Not));
+    } else {
+        commands.push(Push(Constant, -val as u16));
+        commands.push(Arithmetic(Neg));
+    }
+}
+
+fn eval_binop(op: Op, lhs: i16, rhs: i16) -> i16 {
+    let bool_val = |b| if b { -1 } else { 0 };
+    match op {
+        Add => lhs.wrapping_add(rhs),
+        Sub => lhs.wrapping_sub(rhs),
+        And => lhs & rhs,
+        Or => lhs | rhs,
+        Eq_ => bool_val(lhs == rhs),
+        Gt => bool_val(lhs > rhs),
+        Lt => bool_val(lhs < rhs),
+        Neg | Not => unreachable!(),
+    }
+}
+
+fn is_identity(op: Op, rhs: i16) -> bool {
+    match op {
+        Add | Sub | Or => rhs == 0,
+        And => rhs == -1,
+        _ => false,
+    }
+}
+
+fn thread_jumps(commands: Vec<Command>) -> Vec<Command> {
+    let mut label_pos = HashMap::new();
+    for (i, com) in commands.iter().enumerate() {
+        if let Label(label) = com {
+            label_pos.insert(label.clone(), i);
+        }
+    }
+    let resolve = |label: &String| -> String {
+        let mut label = label.clone();
+        let mut visited = HashSet::new();
+        while visited.insert(label.clone()) {
+            let pos = match label_pos.get(&label) {
+                Some(&pos) => pos,
+                None => break,
+            };
+            match commands[pos..].iter().find(|com| !matches!(com, Label(_))) {
+                Some(Goto(next)) => label = next.clone(),
+                _ => break,
+            }
+        }
+        label
+    };
+
+    let mut ret = Vec::new();
+    let mut i = 0;
+    while i < commands.len() {
+        match &commands[i] {
+            Goto(label) => {
+                let label = resolve(label);
+                if !falls_into(&commands[i + 1..], &label) {
+                    ret.push(Goto(label));
+                }
+            }
+            IfGoto(label) => {
+                let label = resolve(label);
+                // `if-goto A; goto B; label A` => `not; if-goto B; label A`, or drops the
+                // `not` that made the condition. Only for the 0 and -1 of comparisons, as
+                // `not` of any other true value isn't 0.
+                if let Some(Goto(other)) = commands.get(i + 1) {
+                    if falls_into(&commands[i + 2..], &label) && is_boolean(&ret) {
+                        if ret.last() == Some(&Arithmetic(Not)) {
+                            ret.pop();
+                        } else {
+                            ret.push(Arithmetic(Not));
+                        }
+                        ret.push(IfGoto(resolve(other)));
+                        i += 2;
+                        continue;
+                    }
+                }
+                ret.push(IfGoto(label));
+            }
+            com => ret.push(com.clone()),
+        }
+        i += 1;
+    }
+    ret
+}
+
+// Whether the last command of `commands` leaves 0 or -1 on the stack.
+fn is_boolean(commands: &[Command]) -> bool {
+    match commands.split_last() {
+        Some((Arithmetic(Eq_), _)) | Some((Arithmetic(Gt), _)) | Some((Arithmetic(Lt), _)) => true,
+        Some((Arithmetic(Not), rest)) => is_boolean(rest),
+        _ => false,
+    }
+}
+
+// Whether `label` is among the labels at the head of `commands`.
+fn falls_into(commands: &[Command], label: &str) -> bool {
+    commands
+        .iter()
+        .take_while(|com| matches!(com, Label(_)))
+        .any(|com| matches!(com, Label(l) if l == label))
+}
+
+fn remove_unreachable(commands: Vec<Command>) -> Vec<Command> {
+    let mut ret = Vec::new();
+    let mut reachable = true;
+    for com in commands {
+        match com {
+            Label(_) | Function(_, _) => reachable = true,
+            _ if !reachable => continue,
+            _ => {}
+        }
+        if let Goto(_) | Return = com {
+            reachable = false;
+        }
+        ret.push(com);
+    }
+    ret
+}
+
+fn remove_dead_labels(commands: Vec<Command>) -> Vec<Command> {
+    let used: HashSet<String> = commands
+        .iter()
+        .filter_map(|com| match com {
+            Goto(label) | IfGoto(label) => Some(label.clone()),
+            _ => None,
+        })
+        .collect();
+    commands
+        .into_iter()
+        .filter(|com| match com {
+            Label(label) => used.contains(label),
+            _ => true,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{parse_vm, print_vm, VmFile};
+    use super::*;
+    use crate::emu::{Vm, SP};
+    use crate::jack::{tokenize, Parser, VmGen};
+
+    fn optimize_str(vm: &str) -> String {
+        print_vm(&optimize(parse_vm(vm.as_bytes()).unwrap()))
+    }
+
+    #[test]
+    fn test_fold_constants() {
+        let vm = "function Main.f 0\n\
+                  push constant 3\npush constant 4\nadd\n\
+                  push constant 0\nnot\nnot\n\
+                  push constant 1\nneg\npush constant 1\nsub\n\
+                  push local 0\npush constant 0\nadd\n\
+                  return\n";
+        let expected = "function Main.f 0\n\
+                        push constant 7\n\
+                        push constant 0\n\
+                        push constant 2\nneg\n\
+                        push local 0\n\
+                        return\n";
+        assert_eq!(optimize_str(vm), expected);
+    }
+
+    #[test]
+    fn test_while_loop() {
+        // while (i < 10) { let i = i + 1; }
+        let vm = "function Main.f 1\n\
+                  label jmp_label_1\n\
+                  push local 0\npush constant 10\nlt\n\
+                  if-goto jmp_label_0\n\
+                  goto jmp_label_2\n\
+                  label jmp_label_0\n\
+                  push local 0\npush constant 1\nadd\npop local 0\n\
+                  goto jmp_label_1\n\
+                  label jmp_label_2\n\
+                  push constant 0\n\
+                  return\n\
+                  push constant 1\n";
+        let expected = "function Main.f 1\n\
+                        label jmp_label_1\n\
+                        push local 0\npush constant 10\nlt\nnot\n\
+                        if-goto jmp_label_2\n\
+                        push local 0\npush constant 1\nadd\npop local 0\n\
+                        goto jmp_label_1\n\
+                        label jmp_label_2\n\
+                        push constant 0\n\
+                        return\n";
+        assert_eq!(optimize_str(vm), expected);
+    }
+
+    #[test]
+    fn test_non_boolean_condition() {
+        // `if-goto` takes 1 as true, though `not` of it isn't 0.
+        let vm = "function Sys.init 0\n\
+                  push constant 1\npop temp 1\n\
+                  push temp 1\n\
+                  if-goto THEN\n\
+                  goto ELSE\n\
+                  label THEN\n\
+                  push constant 10\npop temp 0\n\
+                  label ELSE\n\
+                  label HALT\n\
+                  goto HALT\n";
+        let run = |commands| {
+            let file = VmFile {
+                name: "Sys".to_string(),
+                commands,
+            };
+            let mut vm = Vm::new(vec![file]).unwrap();
+            vm.ram[SP] = 256;
+            for _ in 0..20 {
+                vm.step().unwrap();
+            }
+            vm.ram[5]
+        };
+        let commands = parse_vm(vm.as_bytes()).unwrap();
+        assert_eq!(run(commands.clone()), 10);
+        assert_eq!(run(optimize(commands)), 10);
+    }
+
+    #[test]
+    fn test_negated_condition() {
+        // while (~(i = 10)) { let i = i + 1; }
+        let vm = "function Main.f 1\n\
+                  label L1\n\
+                  push local 0\npush constant 10\neq\nnot\n\
+                  if-goto L0\n\
+                  goto L2\n\
+                  label L0\n\
+                  push local 0\npush constant 1\nadd\npop local 0\n\
+                  goto L1\n\
+                  label L2\n\
+                  push constant 0\n\
+                  return\n";
+        let expected = "function Main.f 1\n\
+                        label L1\n\
+                        push local 0\npush constant 10\neq\n\
+                        if-goto L2\n\
+                        push local 0\npush constant 1\nadd\npop local 0\n\
+                        goto L1\n\
+                        label L2\n\
+                        push constant 0\n\
+                        return\n";
+        assert_eq!(optimize_str(vm), expected);
+    }
+
+    #[test]
+    fn test_no_growth() {
+        let programs = [
+            include_str!("../project09/Square/Main.jack"),
+            include_str!("../project09/Square/Square.jack"),
+            include_str!("../project09/Square/SquareGame.jack"),
+            include_str!("../project09/Average/Main.jack"),
+        ];
+        for jack in programs.iter() {
+            let tokens = tokenize(jack.chars()).unwrap();
+            let class = Parser::new(tokens.into_iter()).parse().unwrap();
+            let commands = VmGen::new().gen(class).unwrap().to_vec();
+            assert!(thread_jumps(commands.clone()).len() <= commands.len());
+        }
+    }
+
+    #[test]
+    fn test_constant_condition() {
+        // while (true) { do Main.g(); }
+        let vm = "function Main.f 0\n\
+                  label L1\n\
+                  push constant 0\nnot\n\
+                  if-goto L0\n\
+                  goto L2\n\
+                  label L0\n\
+                  call Main.g 0\npop temp 0\n\
+                  goto L1\n\
+                  label L2\n\
+                  push constant 0\n\
+                  return\n";
+        let expected = "function Main.f 0\n\
+                        label L0\n\
+                        call Main.g 0\npop temp 0\n\
+                        goto L0\n";
+        assert_eq!(optimize_str(vm), expected);
+    }
+
+    #[test]
+    fn test_jump_chain() {
+        let vm = "function Main.f 0\n\
+                  goto A\n\
+                  label A\n\
+                  push argument 0\n\
+                  if-goto B\n\
+                  push constant 1\n\
+                  return\n\
+                  label B\n\
+                  goto C\n\
+                  label C\n\
+                  goto D\n\
+                  label D\n\
+                  push constant 2\n\
+                  return\n";
+        let expected = "function Main.f 0\n\
+                        push argument 0\n\
+                        if-goto D\n\
+                        push constant 1\n\
+                        return\n\
+                        label D\n\
+                        push constant 2\n\
+                        return\n";
+        assert_eq!(optimize_str(vm), expected);
+    }
+}
@@ -0,0 +1,195 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use std::str::FromStr;
+
+use super::Command::{self, *};
+use super::Op;
+use super::Segment::{self, *};
+
+/// A source line of a VM file.
+/// Blank lines and comment-only lines are kept with `command` set to `None`,
+/// so that printing every `VmLine` gives back the lines of the file in order, with
+/// their commands and comments but not their indentation or spacing.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VmLine {
+    pub line_num: usize,
+    pub command: Option<Command>,
+    pub comment: Option<String>,
+}
+
+/// Commands of a single VM file.
+/// `name` is the file stem, which the VM spec uses to scope static variables.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VmFile {
+    pub name: String,
+    pub commands: Vec<Command>,
+}
+
+pub fn load_vm_file(path: impl AsRef<Path>) -> Result<VmFile, String> {
+    let path = path.as_ref();
+    let name = path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .ok_or("Invalid file path")?;
+    let file = File::open(path).map_err(|_| format! {"Can't open file: {:?}", path})?;
+    Ok(VmFile {
+        name: name.to_string(),
+        commands: parse_vm(BufReader::new(file))?,
+    })
+}
+
+pub fn parse_vm(strm: impl BufRead) -> Result<Vec<Command>, String> {
+    Ok(parse_vm_lines(strm)?
+        .into_iter()
+        .filter_map(|line| line.command)
+        .collect())
+}
+
+pub fn parse_vm_lines(strm: impl BufRead) -> Result<Vec<VmLine>, String> {
+    let mut ret = Vec::new();
+    for (line_num, line) in strm.lines().enumerate() {
+        let line_num = line_num + 1;
+        let line = line.map_err(|e| format! {"Error: line {}; {:?}", line_num, e})?;
+        let (code, comment) = match line.find("//") {
+            Some(pos) => (&line[..pos], Some(line[pos + 2..].to_string())),
+            None => (line.as_str(), None),
+        };
+        let command = if code.trim().is_empty() {
+            None
+        } else {
+            Some(parse_line(code).map_err(|e| format! {"Error: line {}; {}", line_num, e})?)
+        };
+        ret.push(VmLine {
+            line_num,
+            command,
+            comment,
+        });
+    }
+    Ok(ret)
+}
+
+fn parse_line(line: &str) -> Result<Command, &'static str> {
+    let mut words = line.split_whitespace();
+    let first = words.next().ok_or("Invalid VM code")?;
+    let command = match first {
+        "add" | "sub" | "neg" | "eq" | "gt" | "lt" | "and" | "or" | "not" => {
+            Arithmetic(Op::from_str(first)?)
+        }
+        "push" => {
+            let segment: Segment = next_as(&mut words)?;
+            let offset: u16 = next_as(&mut words)?;
+            Push(segment, offset)
+        }
+        "pop" => {
+            let segment: Segment = next_as(&mut words)?;
+            if let Constant = segment {
+                return Err("Invalid VM code");
+            }
+            let offset: u16 = next_as(&mut words)?;
+            Pop(segment, offset)
+        }
+        "label" => Label(next_as(&mut words)?),
+        "goto" => Goto(next_as(&mut words)?),
+        "if-goto" => IfGoto(next_as(&mut words)?),
+        "function" => {
+            let label: String = next_as(&mut words)?;
+            let arg_num: u16 = next_as(&mut words)?;
+            Function(label, arg_num)
+        }
+        "call" => {
+            let label: String = next_as(&mut words)?;
+            let arg_num: u16 = next_as(&mut words)?;
+            Call(label, arg_num)
+        }
+        "return" => Return,
+        _ => return Err("Invalid VM code"),
+    };
+
+    match words.next() {
+        None => Ok(command),
+        _ => Err("Invalid VM code"),
+    }
+}
+
+fn next_as<'a, T, U>(mut iter: U) -> Result<T, &'static str>
+where
+    T: FromStr,
+    U: Iterator<Item = &'a str>,
+{
+    match iter.next() {
+        Some(s) => s.parse().map_err(|_| "Invalid VM code"),
+        None => Err("Invalid VM code"),
+    }
+}
+
+impl FromStr for Command {
+    type Err = &'static str;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let code = match s.find("//") {
+            Some(pos) => &s[..pos],
+            None => s,
+        };
+        parse_line(code)
+    }
+}
+
+impl fmt::Display for VmLine {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (&self.command, &self.comment) {
+            (Some(command), Some(comment)) => write!(f, "{} //{}", command, comment),
+            (Some(command), None) => write!(f, "{}", command),
+            (None, Some(comment)) => write!(f, "//{}", comment),
+            (None, None) => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::print_vm;
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let vm = "function Main.main 2\n\
+                  push constant 7\n\
+                  pop local 1\n\
+                  label LOOP\n\
+                  push local 1\n\
+                  if-goto END\n\
+                  call Math.multiply 2\n\
+                  goto LOOP\n\
+                  label END\n\
+                  not\n\
+                  eq\n\
+                  return\n";
+        let commands = parse_vm(vm.as_bytes()).unwrap();
+        assert_eq!(commands.len(), 12);
+        assert_eq!(commands[1], Push(Constant, 7));
+        assert_eq!(commands[10], Arithmetic(Op::Eq_));
+        assert_eq!(print_vm(&commands), vm);
+    }
+
+    #[test]
+    fn test_comments() {
+        let vm = "// header\n\npush constant 1 // one\n  // indented\n";
+        let lines = parse_vm_lines(vm.as_bytes()).unwrap();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[0].command, None);
+        assert_eq!(lines[0].comment.as_deref(), Some(" header"));
+        assert_eq!(lines[2].line_num, 3);
+        assert_eq!(lines[2].command, Some(Push(Constant, 1)));
+        assert_eq!(lines[2].to_string(), "push constant 1 // one");
+        assert_eq!(lines[3].to_string(), "// indented");
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert!(parse_vm("pop constant 0".as_bytes()).is_err());
+        assert!(parse_vm("push local".as_bytes()).is_err());
+        assert!(parse_vm("push local 0 1".as_bytes()).is_err());
+        assert!("frobnicate".parse::<Command>().is_err());
+    }
+}